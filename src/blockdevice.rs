@@ -3,6 +3,8 @@
 //! Generic code for handling block devices, such as types for identifying
 //! a particular block on a block device by its index.
 
+use crate::trace;
+
 /// A standard 512 byte block (also known as a sector).
 ///
 /// IBM PC formatted 5.25" and 3.5" floppy disks, IDE/SATA Hard Drives up to
@@ -11,6 +13,7 @@
 /// This library does not support devices with a block size other than 512
 /// bytes.
 #[derive(Clone)]
+#[repr(transparent)]
 pub struct Block {
     /// The 512 bytes in this block (or sector).
     pub contents: [u8; Block::LEN],
@@ -48,6 +51,23 @@ pub trait BlockDevice {
     fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error>;
     /// Determine how many blocks this device can hold.
     fn num_blocks(&self) -> Result<BlockCount, Self::Error>;
+    /// Make sure every block written so far has reached the underlying media.
+    ///
+    /// The filesystem calls this when it has reached a consistent state, such
+    /// as when a file is flushed or a volume is closed. Devices which cache or
+    /// buffer writes should override this. The default does nothing.
+    fn flush(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    /// How many blocks this device would like to transfer at a time.
+    ///
+    /// The filesystem won't read or write more than this many blocks in one
+    /// call, so a device with a preferred erase or program size can say so
+    /// here. The default of one block means the device has no preference, in
+    /// which case the filesystem reads and writes up to a cluster at a time.
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        BlockCount(1)
+    }
 }
 
 impl Block {
@@ -61,10 +81,128 @@ impl Block {
 
     /// Create a new block full of zeros.
     pub fn new() -> Block {
+        Self::zeroed()
+    }
+
+    /// Create a new block full of zeros, in a `const` context.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::Block;
+    /// const EMPTY: Block = Block::zeroed();
+    /// assert!(EMPTY.contents.iter().all(|&b| b == 0));
+    /// ```
+    pub const fn zeroed() -> Block {
         Block {
             contents: [0u8; Self::LEN],
         }
     }
+
+    /// Create a block holding the given bytes.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::Block;
+    /// let block = Block::from_array([0xAA; Block::LEN]);
+    /// assert!(block.contents.iter().all(|&b| b == 0xAA));
+    /// let block: Block = [0x55; Block::LEN].into();
+    /// assert!(block.contents.iter().all(|&b| b == 0x55));
+    /// ```
+    pub const fn from_array(contents: [u8; Block::LEN]) -> Block {
+        Block { contents }
+    }
+
+    /// Create a block by copying a slice, which must be exactly
+    /// [`Block::LEN`] bytes long.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::Block;
+    /// let data = [0x12u8; 1024];
+    /// let block = Block::try_from_slice(&data[512..]).unwrap();
+    /// assert!(block.contents.iter().all(|&b| b == 0x12));
+    /// assert!(Block::try_from_slice(&data[..511]).is_err());
+    /// assert!(Block::try_from_slice(&data).is_err());
+    /// ```
+    pub fn try_from_slice(data: &[u8]) -> Result<Block, core::array::TryFromSliceError> {
+        Ok(Block {
+            contents: data.try_into()?,
+        })
+    }
+
+    /// Get the `index`-th record of `record_len` bytes in this block.
+    ///
+    /// Handy when a block holds an array of small fixed-size records, like
+    /// the 32-byte entries of a FAT directory.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::Block;
+    /// let mut block = Block::new();
+    /// block.record_mut(1, 32).fill(0xAA);
+    /// assert!(block.record(0, 32).iter().all(|&b| b == 0x00));
+    /// assert!(block.record(1, 32).iter().all(|&b| b == 0xAA));
+    /// assert_eq!(&block[32..64], block.record(1, 32));
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record_len` is zero, or if the record doesn't fit entirely
+    /// within the block.
+    pub fn record(&self, index: usize, record_len: usize) -> &[u8] {
+        let range = Self::record_range(index, record_len);
+        &self.contents[range]
+    }
+
+    /// Get the `index`-th record of `record_len` bytes in this block, mutably.
+    ///
+    /// See [`Block::record`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `record_len` is zero, or if the record doesn't fit entirely
+    /// within the block.
+    pub fn record_mut(&mut self, index: usize, record_len: usize) -> &mut [u8] {
+        let range = Self::record_range(index, record_len);
+        &mut self.contents[range]
+    }
+
+    /// Work out where a record is, checking it fits in a block.
+    fn record_range(index: usize, record_len: usize) -> core::ops::Range<usize> {
+        assert!(record_len > 0, "records can't be empty");
+        let start = index
+            .checked_mul(record_len)
+            .filter(|&start| record_len <= Self::LEN && start <= Self::LEN - record_len);
+        match start {
+            Some(start) => start..start + record_len,
+            None => panic!(
+                "record {} of {} bytes is outside the block",
+                index, record_len
+            ),
+        }
+    }
+
+    /// View some bytes as a slice of blocks, without copying them.
+    ///
+    /// The length of `data` must be a multiple of [`Block::LEN`].
+    pub(crate) fn slice_from_bytes(data: &[u8]) -> &[Block] {
+        assert!(data.len().is_multiple_of(Block::LEN));
+        // Safety: `Block` is `repr(transparent)` over `[u8; 512]`, so it has
+        // the same size and alignment, and every bit pattern is valid.
+        unsafe {
+            core::slice::from_raw_parts(data.as_ptr().cast::<Block>(), data.len() / Block::LEN)
+        }
+    }
+
+    /// View some bytes as a mutable slice of blocks, without copying them.
+    ///
+    /// The length of `data` must be a multiple of [`Block::LEN`].
+    pub(crate) fn slice_from_bytes_mut(data: &mut [u8]) -> &mut [Block] {
+        assert!(data.len().is_multiple_of(Block::LEN));
+        // Safety: as for `slice_from_bytes`
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                data.as_mut_ptr().cast::<Block>(),
+                data.len() / Block::LEN,
+            )
+        }
+    }
 }
 
 impl Default for Block {
@@ -73,6 +211,12 @@ impl Default for Block {
     }
 }
 
+impl From<[u8; Block::LEN]> for Block {
+    fn from(contents: [u8; Block::LEN]) -> Self {
+        Self::from_array(contents)
+    }
+}
+
 impl core::ops::Add<BlockCount> for BlockIdx {
     type Output = BlockIdx;
     fn add(self, rhs: BlockCount) -> BlockIdx {
@@ -125,6 +269,25 @@ impl core::ops::SubAssign<BlockCount> for BlockCount {
     }
 }
 
+impl core::ops::Sub<BlockIdx> for BlockIdx {
+    type Output = BlockCount;
+    fn sub(self, rhs: BlockIdx) -> BlockCount {
+        BlockCount(self.0 - rhs.0)
+    }
+}
+
+impl core::fmt::Display for BlockIdx {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl core::fmt::Display for BlockCount {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.0, f)
+    }
+}
+
 impl core::ops::Deref for Block {
     type Target = [u8; 512];
     fn deref(&self) -> &[u8; 512] {
@@ -172,6 +335,21 @@ impl BlockIdx {
     pub fn range(self, num: BlockCount) -> BlockIter {
         BlockIter::new(self, self + BlockCount(num.0))
     }
+
+    /// Add a number of blocks to this block index, returning `None` if the
+    /// result would not fit in a `u32`.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::{BlockCount, BlockIdx};
+    /// assert_eq!(BlockIdx(1).checked_add(BlockCount(2)), Some(BlockIdx(3)));
+    /// assert_eq!(BlockIdx(u32::MAX).checked_add(BlockCount(1)), None);
+    /// ```
+    pub const fn checked_add(self, rhs: BlockCount) -> Option<BlockIdx> {
+        match self.0.checked_add(rhs.0) {
+            Some(n) => Some(BlockIdx(n)),
+            None => None,
+        }
+    }
 }
 
 impl BlockCount {
@@ -198,6 +376,36 @@ impl BlockCount {
     pub fn offset_bytes(self, offset: u32) -> Self {
         BlockCount(self.0 + (offset / Block::LEN_U32))
     }
+
+    /// Like [`BlockCount::offset_bytes`], but returns `None` if the result
+    /// would not fit in a `u32`.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::BlockCount;
+    /// assert_eq!(BlockCount(1).checked_offset_bytes(1024), Some(BlockCount(3)));
+    /// assert_eq!(BlockCount(u32::MAX).checked_offset_bytes(512), None);
+    /// ```
+    pub const fn checked_offset_bytes(self, offset: u32) -> Option<Self> {
+        match self.0.checked_add(offset / Block::LEN_U32) {
+            Some(n) => Some(BlockCount(n)),
+            None => None,
+        }
+    }
+
+    /// Add two block counts, returning `None` if the result would not fit in
+    /// a `u32`.
+    ///
+    /// ```
+    /// # use embedded_sdmmc::BlockCount;
+    /// assert_eq!(BlockCount(1).checked_add(BlockCount(2)), Some(BlockCount(3)));
+    /// assert_eq!(BlockCount(u32::MAX).checked_add(BlockCount(1)), None);
+    /// ```
+    pub const fn checked_add(self, rhs: BlockCount) -> Option<BlockCount> {
+        match self.0.checked_add(rhs.0) {
+            Some(n) => Some(BlockCount(n)),
+            None => None,
+        }
+    }
 }
 
 impl BlockIter {
@@ -224,6 +432,302 @@ impl core::iter::Iterator for BlockIter {
     }
 }
 
+/// A [`BlockDevice`] which is a window onto part of another `BlockDevice`.
+///
+/// Block `0` of this device is block `start` of the parent device, and it is
+/// `len` blocks long. Useful if you have a disk image stored inside a
+/// partition of some other disk, for example.
+#[derive(Debug)]
+pub struct SubBlockDevice<D> {
+    device: D,
+    start: BlockIdx,
+    len: BlockCount,
+}
+
+/// The possible errors a [`SubBlockDevice`] can generate.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubBlockDeviceError<E> {
+    /// The parent device gave us an error
+    Device(E),
+    /// The blocks requested are beyond the end of the window, or their
+    /// index on the parent device doesn't fit in a `u32`
+    OutOfBounds,
+}
+
+impl<D> SubBlockDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Create a new `SubBlockDevice`, covering the `len` blocks of `device`
+    /// starting at `start`.
+    ///
+    /// The window isn't checked against the size of `device`, here or on
+    /// each access. Reading or writing past the end of `device` is left to
+    /// `device` to reject, and any error it gives comes back as
+    /// [`SubBlockDeviceError::Device`].
+    pub fn new(device: D, start: BlockIdx, len: BlockCount) -> SubBlockDevice<D> {
+        SubBlockDevice { device, start, len }
+    }
+
+    /// Get the parent device back.
+    pub fn free(self) -> D {
+        self.device
+    }
+
+    /// Get a reference to the parent device.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Convert a range of blocks in the window to an index on the parent
+    /// device.
+    fn translate(
+        &self,
+        start: BlockIdx,
+        count: usize,
+    ) -> Result<BlockIdx, SubBlockDeviceError<D::Error>> {
+        let count = u32::try_from(count).map_err(|_| SubBlockDeviceError::OutOfBounds)?;
+        let end = start
+            .checked_add(BlockCount(count))
+            .ok_or(SubBlockDeviceError::OutOfBounds)?;
+        if end.0 > self.len.0 {
+            return Err(SubBlockDeviceError::OutOfBounds);
+        }
+        self.start
+            .checked_add(BlockCount(start.0))
+            .ok_or(SubBlockDeviceError::OutOfBounds)
+    }
+}
+
+impl<D> BlockDevice for SubBlockDevice<D>
+where
+    D: BlockDevice,
+{
+    type Error = SubBlockDeviceError<D::Error>;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        let block_idx = self.translate(start_block_idx, blocks.len())?;
+        self.device
+            .read(blocks, block_idx)
+            .map_err(SubBlockDeviceError::Device)
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        let block_idx = self.translate(start_block_idx, blocks.len())?;
+        self.device
+            .write(blocks, block_idx)
+            .map_err(SubBlockDeviceError::Device)
+    }
+
+    /// The length of the window, or whatever of it fits on the parent device.
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        let parent_blocks = self
+            .device
+            .num_blocks()
+            .map_err(SubBlockDeviceError::Device)?;
+        let available = parent_blocks.0.saturating_sub(self.start.0);
+        Ok(BlockCount(available.min(self.len.0)))
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.device.flush().map_err(SubBlockDeviceError::Device)
+    }
+
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        self.device.optimal_transfer_blocks()
+    }
+}
+
+/// Counts of the operations an [`InstrumentedBlockDevice`] has seen.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct IoStats {
+    /// How many times `read` was called
+    pub reads: u32,
+    /// How many blocks were read, over all those calls
+    pub blocks_read: u32,
+    /// How many times `write` was called
+    pub writes: u32,
+    /// How many blocks were written, over all those calls
+    pub blocks_written: u32,
+    /// How many times `flush` was called
+    pub flushes: u32,
+}
+
+/// A [`BlockDevice`] which passes everything through to another
+/// `BlockDevice`, counting the reads and writes as it goes.
+///
+/// Useful for seeing how much I/O an operation costs. Each operation is also
+/// logged at trace level, if logging is enabled. Failed operations are
+/// counted too.
+#[derive(Debug)]
+pub struct InstrumentedBlockDevice<D> {
+    device: D,
+    stats: core::cell::Cell<IoStats>,
+}
+
+impl<D> InstrumentedBlockDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Create a new `InstrumentedBlockDevice`, wrapping `device`, with all
+    /// the counts at zero.
+    pub fn new(device: D) -> InstrumentedBlockDevice<D> {
+        InstrumentedBlockDevice {
+            device,
+            stats: core::cell::Cell::new(IoStats::default()),
+        }
+    }
+
+    /// Get the counts so far.
+    pub fn stats(&self) -> IoStats {
+        self.stats.get()
+    }
+
+    /// Set all the counts back to zero.
+    pub fn reset_stats(&self) {
+        self.stats.set(IoStats::default());
+    }
+
+    /// Get the wrapped device back.
+    pub fn free(self) -> D {
+        self.device
+    }
+
+    /// Get a reference to the wrapped device.
+    pub fn device(&self) -> &D {
+        &self.device
+    }
+
+    /// Change the counts.
+    fn update<F>(&self, func: F)
+    where
+        F: FnOnce(&mut IoStats),
+    {
+        let mut stats = self.stats.get();
+        func(&mut stats);
+        self.stats.set(stats);
+    }
+}
+
+impl<D> BlockDevice for InstrumentedBlockDevice<D>
+where
+    D: BlockDevice,
+{
+    type Error = D::Error;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        trace!("Read {} blocks from {}", blocks.len(), start_block_idx.0);
+        self.update(|stats| {
+            stats.reads = stats.reads.wrapping_add(1);
+            stats.blocks_read = stats.blocks_read.wrapping_add(blocks.len() as u32);
+        });
+        self.device.read(blocks, start_block_idx)
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        trace!("Write {} blocks to {}", blocks.len(), start_block_idx.0);
+        self.update(|stats| {
+            stats.writes = stats.writes.wrapping_add(1);
+            stats.blocks_written = stats.blocks_written.wrapping_add(blocks.len() as u32);
+        });
+        self.device.write(blocks, start_block_idx)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        self.device.num_blocks()
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        trace!("Flush");
+        self.update(|stats| stats.flushes = stats.flushes.wrapping_add(1));
+        self.device.flush()
+    }
+
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        self.device.optimal_transfer_blocks()
+    }
+}
+
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn block_arithmetic() {
+        let mut idx = BlockIdx(10);
+        idx += BlockCount(5);
+        assert_eq!(idx, BlockIdx(15));
+        idx -= BlockCount(3);
+        assert_eq!(idx, BlockIdx(12));
+        assert_eq!(idx + BlockCount(4), BlockIdx(16));
+        assert_eq!(idx - BlockCount(2), BlockIdx(10));
+        assert_eq!(BlockIdx(16) - idx, BlockCount(4));
+
+        let mut count = BlockCount(1);
+        count += BlockCount(2);
+        assert_eq!(count, BlockCount(3));
+        count -= BlockCount(1);
+        assert_eq!(count + BlockCount(1) - BlockCount(3), BlockCount(0));
+
+        assert_eq!(idx.checked_add(count), Some(BlockIdx(14)));
+        assert_eq!(BlockIdx(u32::MAX - 1).checked_add(count), None);
+    }
+
+    #[test]
+    fn block_records() {
+        let mut block = Block::new();
+        for (i, b) in block.contents.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        assert_eq!(block.record(0, 16), &block.contents[0..16]);
+        assert_eq!(block.record(31, 16), &block.contents[496..512]);
+        assert_eq!(block.record(0, Block::LEN), &block.contents[..]);
+        // Records don't have to divide the block evenly
+        assert_eq!(block.record(4, 100), &block.contents[400..500]);
+        block.record_mut(2, 3).copy_from_slice(&[0xAA, 0xBB, 0xCC]);
+        assert_eq!(&block.contents[5..10], &[5, 0xAA, 0xBB, 0xCC, 9]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn block_record_past_end() {
+        Block::new().record(32, 16);
+    }
+
+    #[test]
+    #[should_panic]
+    fn block_record_partly_past_end() {
+        Block::new().record_mut(5, 100);
+    }
+
+    #[test]
+    #[should_panic]
+    fn block_record_overflow() {
+        Block::new().record(usize::MAX, 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn block_record_empty() {
+        Block::new().record(0, 0);
+    }
+
+    #[test]
+    fn block_display() {
+        assert_eq!(format!("{}", BlockIdx(2048)), "2048");
+        assert_eq!(format!("{}", BlockCount(7)), "7");
+        assert_eq!(format!("[{:>6}]", BlockIdx(42)), "[    42]");
+    }
+}
+
 // ****************************************************************************
 //
 // End Of File
@@ -19,8 +19,12 @@ pub struct Bpb<'a> {
 impl<'a> Bpb<'a> {
     pub(crate) const FOOTER_VALUE: u16 = 0xAA55;
 
+    /// Where FAT32 volumes usually keep a backup of the boot sector, relative
+    /// to the start of the volume.
+    pub(crate) const DEFAULT_BACKUP_BOOT_BLOCK: u16 = 6;
+
     /// Attempt to parse a Boot Parameter Block from a 512 byte sector.
-    pub fn create_from_bytes(data: &[u8; 512]) -> Result<Bpb, &'static str> {
+    pub fn create_from_bytes(data: &[u8; 512]) -> Result<Bpb<'_>, &'static str> {
         let mut bpb = Bpb {
             data,
             fat_type: FatType::Fat16,
@@ -32,10 +36,18 @@ impl<'a> Bpb<'a> {
 
         let root_dir_blocks =
             BlockCount::from_bytes(u32::from(bpb.root_entries_count()) * OnDiskDirEntry::LEN_U32).0;
-        let non_data_blocks = u32::from(bpb.reserved_block_count())
-            + (u32::from(bpb.num_fats()) * bpb.fat_size())
-            + root_dir_blocks;
-        let data_blocks = bpb.total_blocks() - non_data_blocks;
+        let non_data_blocks = u32::from(bpb.num_fats())
+            .checked_mul(bpb.fat_size())
+            .and_then(|n| n.checked_add(u32::from(bpb.reserved_block_count())))
+            .and_then(|n| n.checked_add(root_dir_blocks))
+            .ok_or("Bad BPB: FAT region too large")?;
+        let data_blocks = bpb
+            .total_blocks()
+            .checked_sub(non_data_blocks)
+            .ok_or("Bad BPB: FAT region larger than volume")?;
+        if bpb.blocks_per_cluster() == 0 {
+            return Err("Bad BPB: zero blocks per cluster");
+        }
         bpb.cluster_count = data_blocks / u32::from(bpb.blocks_per_cluster());
         if bpb.cluster_count < 4085 {
             return Err("FAT12 is unsupported");
@@ -6,6 +6,7 @@
 pub const RESERVED_ENTRIES: u32 = 2;
 
 /// Indentifies the supported types of FAT format
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum FatType {
     /// FAT16 Format
@@ -14,15 +15,40 @@ pub enum FatType {
     Fat32,
 }
 
+/// Calculate the checksum of a short file name, as stored in each of the long
+/// file name (LFN) entries which belong to it.
+///
+/// If the checksum in the LFN entries doesn't match the short entry which
+/// follows them, the LFN entries are orphans (left behind by software which
+/// doesn't know about long file names, say) and should be ignored.
+pub fn lfn_checksum(name: &ShortFileName) -> u8 {
+    name.contents
+        .iter()
+        .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Holds one block, so that reading the same block again doesn't go back to
+/// the disk.
+///
+/// Changes made through [`BlockCache::read_mut`] are held back until
+/// [`BlockCache::write_back`] is called, or a different block is read, so
+/// several updates to one block cost a single write. Anything holding changes
+/// must be written back before it is dropped.
 pub(crate) struct BlockCache {
     block: Block,
     idx: Option<BlockIdx>,
+    dirty: bool,
+    /// Further copies of the block we hold, as the distance between copies and
+    /// how many there are. Set for blocks in the FAT, which we keep mirrored.
+    mirrors: Option<(BlockCount, u8)>,
 }
 impl BlockCache {
     pub fn empty() -> Self {
         BlockCache {
             block: Block::new(),
             idx: None,
+            dirty: false,
+            mirrors: None,
         }
     }
     pub(crate) fn read<D>(
@@ -34,13 +60,74 @@ impl BlockCache {
         D: BlockDevice,
     {
         if Some(block_idx) != self.idx {
-            self.idx = Some(block_idx);
+            self.write_back(block_device)?;
+            self.idx = None;
+            self.mirrors = None;
             block_device
                 .read(core::slice::from_mut(&mut self.block), block_idx)
                 .map_err(Error::DeviceError)?;
+            self.idx = Some(block_idx);
         }
         Ok(&self.block)
     }
+    /// Get a block to change, which will be written out later.
+    pub(crate) fn read_mut<D>(
+        &mut self,
+        block_device: &D,
+        block_idx: BlockIdx,
+    ) -> Result<&mut Block, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        self.read(block_device, block_idx)?;
+        self.dirty = true;
+        Ok(&mut self.block)
+    }
+    /// Get a block to change which has `copies` further copies, each `stride`
+    /// blocks after the last. They are all written out together later.
+    pub(crate) fn read_mut_mirrored<D>(
+        &mut self,
+        block_device: &D,
+        block_idx: BlockIdx,
+        stride: BlockCount,
+        copies: u8,
+    ) -> Result<&mut Block, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        self.read_mut(block_device, block_idx)?;
+        self.mirrors = Some((stride, copies));
+        Ok(&mut self.block)
+    }
+    /// Write out the block we hold, and any copies of it, if it has been
+    /// changed.
+    pub(crate) fn write_back<D>(&mut self, block_device: &D) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        if let (true, Some(block_idx)) = (self.dirty, self.idx) {
+            trace!("Writing back block {:?}", block_idx);
+            block_device
+                .write(core::slice::from_ref(&self.block), block_idx)
+                .map_err(Error::DeviceError)?;
+            if let Some((stride, copies)) = self.mirrors {
+                let mut copy_idx = block_idx;
+                for _ in 0..copies {
+                    copy_idx = copy_idx
+                        .0
+                        .checked_add(stride.0)
+                        .map(BlockIdx)
+                        .ok_or(Error::ConversionError)?;
+                    trace!("Writing back copy at {:?}", copy_idx);
+                    block_device
+                        .write(core::slice::from_ref(&self.block), copy_idx)
+                        .map_err(Error::DeviceError)?;
+                }
+            }
+            self.dirty = false;
+        }
+        Ok(())
+    }
 }
 
 mod bpb;
@@ -51,9 +138,11 @@ mod volume;
 pub use bpb::Bpb;
 pub use info::{Fat16Info, Fat32Info, FatSpecificInfo, InfoSector};
 pub use ondiskdirentry::OnDiskDirEntry;
-pub use volume::{parse_volume, FatVolume, VolumeName};
+pub use volume::{parse_volume, FatVolume, FsckReport, VolumeName};
 
-use crate::{Block, BlockDevice, BlockIdx, Error};
+pub(crate) use volume::DirPosition;
+
+use crate::{trace, Block, BlockCount, BlockDevice, BlockIdx, Error, ShortFileName};
 
 // ****************************************************************************
 //
@@ -145,6 +234,7 @@ mod test {
                 },
                 mtime: Timestamp::from_calendar(2015, 11, 21, 19, 35, 18).unwrap(),
                 ctime: Timestamp::from_calendar(2015, 11, 21, 19, 35, 18).unwrap(),
+                atime: Timestamp::from_calendar(2015, 11, 21, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::VOLUME),
                 cluster: ClusterId(0),
                 size: 0,
@@ -163,6 +253,7 @@ mod test {
                 name: ShortFileName::create_from_str("OVERLAYS").unwrap(),
                 mtime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 54).unwrap(),
                 ctime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 54).unwrap(),
+                atime: Timestamp::from_calendar(2016, 3, 1, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::DIRECTORY),
                 cluster: ClusterId(3),
                 size: 0,
@@ -188,6 +279,7 @@ mod test {
                 name: ShortFileName::create_from_str("BCM270~1.DTB").unwrap(),
                 mtime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 34).unwrap(),
                 ctime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 34).unwrap(),
+                atime: Timestamp::from_calendar(2016, 3, 1, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::ARCHIVE),
                 cluster: ClusterId(9),
                 size: 11120,
@@ -205,6 +297,7 @@ mod test {
                 name: ShortFileName::create_from_str("COPYIN~1.LIN").unwrap(),
                 mtime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 30).unwrap(),
                 ctime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 30).unwrap(),
+                atime: Timestamp::from_calendar(2016, 3, 1, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::ARCHIVE),
                 cluster: ClusterId(5),
                 size: 18693,
@@ -230,6 +323,7 @@ mod test {
                 name: ShortFileName::create_from_str("LICENC~1.BRO").unwrap(),
                 mtime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 34).unwrap(),
                 ctime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 34).unwrap(),
+                atime: Timestamp::from_calendar(2016, 3, 1, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::ARCHIVE),
                 cluster: ClusterId(8),
                 size: 1494,
@@ -255,6 +349,7 @@ mod test {
                 name: ShortFileName::create_from_str("BCM270~4.DTB").unwrap(),
                 mtime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 36).unwrap(),
                 ctime: Timestamp::from_calendar(2016, 3, 1, 19, 56, 36).unwrap(),
+                atime: Timestamp::from_calendar(2016, 3, 1, 0, 0, 0).unwrap(),
                 attributes: Attributes::create_from_fat(Attributes::ARCHIVE),
                 cluster: ClusterId(15),
                 size: 12108,
@@ -303,6 +398,36 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_lowercase_dir_entry() {
+        // `config.txt`, as created by Linux. Byte 12 flags the base name and
+        // extension as lower-case.
+        let raw_data = r#"
+        434f4e4649472020545854201800699c754775470000699c7547050063060000 CONFIG  TXT ..i.uGuG..i.uG..c...
+        "#;
+        let data = parse(raw_data);
+        let on_disk_entry = OnDiskDirEntry::new(&data);
+        let parsed_entry = on_disk_entry.get_entry(FatType::Fat16, BlockIdx(0), 0);
+        assert_eq!(parsed_entry.name.to_string(), "config.txt");
+        assert_eq!(
+            parsed_entry.name,
+            ShortFileName::create_from_str("CONFIG.TXT").unwrap()
+        );
+        assert_eq!(parsed_entry.size, 1635);
+        // Writing it back out must preserve the flags
+        assert_eq!(&parsed_entry.serialize(FatType::Fat16)[..], &data[..]);
+        // And creating an all lower-case name must set them
+        let entry = DirEntry::new(
+            ShortFileName::create_from_str("config.txt").unwrap(),
+            Attributes::create_from_fat(Attributes::ARCHIVE),
+            ClusterId(5),
+            Timestamp::from_calendar(2015, 11, 21, 19, 35, 18).unwrap(),
+            BlockIdx(0),
+            0,
+        );
+        assert_eq!(entry.serialize(FatType::Fat16)[12], 0x18);
+    }
+
     #[test]
     fn test_bpb() {
         // Taken from a Raspberry Pi bootable SD-Card
@@ -57,7 +57,7 @@ impl<'a> OnDiskDirEntry<'a> {
 
     /// Create a new on-disk directory entry from a block of 32 bytes read
     /// from a directory file.
-    pub fn new(data: &[u8]) -> OnDiskDirEntry {
+    pub fn new(data: &[u8]) -> OnDiskDirEntry<'_> {
         OnDiskDirEntry { data }
     }
 
@@ -79,51 +79,39 @@ impl<'a> OnDiskDirEntry<'a> {
 
     /// If this is an LFN, get the contents so we can re-assemble the filename.
     pub fn lfn_contents(&self) -> Option<(bool, u8, [char; 13])> {
+        let (is_start, sequence, units) = self.lfn_units()?;
+        // LFNs store UCS-2, so we can map from 16-bit char to 32-bit char, except
+        // that a stray surrogate half isn't a valid char.
+        let buffer = units.map(|unit| {
+            core::char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER)
+        });
+        Some((is_start, sequence, buffer))
+    }
+
+    /// If this is an LFN, get its raw UTF-16 code units, along with whether
+    /// it is the first entry of its name, and its sequence number.
+    pub fn lfn_units(&self) -> Option<(bool, u8, [u16; 13])> {
         if self.is_lfn() {
-            let mut buffer = [' '; 13];
+            // The thirteen code units are split across three fields
+            const OFFSETS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
             let is_start = (self.data[0] & 0x40) != 0;
             let sequence = self.data[0] & 0x1F;
-            // LFNs store UCS-2, so we can map from 16-bit char to 32-bit char without problem.
-            buffer[0] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[1..=2]))).unwrap();
-            buffer[1] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[3..=4]))).unwrap();
-            buffer[2] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[5..=6]))).unwrap();
-            buffer[3] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[7..=8]))).unwrap();
-            buffer[4] = core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[9..=10])))
-                .unwrap();
-            buffer[5] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[14..=15])))
-                    .unwrap();
-            buffer[6] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[16..=17])))
-                    .unwrap();
-            buffer[7] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[18..=19])))
-                    .unwrap();
-            buffer[8] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[20..=21])))
-                    .unwrap();
-            buffer[9] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[22..=23])))
-                    .unwrap();
-            buffer[10] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[24..=25])))
-                    .unwrap();
-            buffer[11] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[28..=29])))
-                    .unwrap();
-            buffer[12] =
-                core::char::from_u32(u32::from(LittleEndian::read_u16(&self.data[30..=31])))
-                    .unwrap();
-            Some((is_start, sequence, buffer))
+            let units =
+                OFFSETS.map(|offset| LittleEndian::read_u16(&self.data[offset..offset + 2]));
+            Some((is_start, sequence, units))
         } else {
             None
         }
     }
 
+    /// If this is an LFN, get the checksum of the short file name it belongs
+    /// to.
+    ///
+    /// See [`lfn_checksum`](crate::fat::lfn_checksum).
+    pub fn lfn_checksum(&self) -> Option<u8> {
+        self.is_lfn().then_some(self.data[13])
+    }
+
     /// Does this on-disk entry match the given filename?
     pub fn matches(&self, sfn: &ShortFileName) -> bool {
         self.data[0..11] == sfn.contents
@@ -153,9 +141,12 @@ impl<'a> OnDiskDirEntry<'a> {
         let mut result = DirEntry {
             name: ShortFileName {
                 contents: [0u8; 11],
+                case_flags: self.data[12]
+                    & (ShortFileName::LOWERCASE_BASE | ShortFileName::LOWERCASE_EXT),
             },
             mtime: Timestamp::from_fat(self.write_date(), self.write_time()),
             ctime: Timestamp::from_fat(self.create_date(), self.create_time()),
+            atime: Timestamp::from_fat(self.last_access_data(), 0),
             attributes,
             cluster: {
                 let cluster = if fat_type == FatType::Fat32 {
@@ -11,9 +11,47 @@ use crate::{
     DirectoryInfo, Error, ShortFileName, TimeSource, VolumeType,
 };
 use byteorder::{ByteOrder, LittleEndian};
-use core::convert::TryFrom;
+use core::{convert::TryFrom, ops::ControlFlow};
 
-use super::BlockCache;
+use super::{lfn_checksum, BlockCache};
+
+/// How many UCS-2 characters of a long file name each LFN entry holds.
+const LFN_CHARS_PER_ENTRY: usize = 13;
+
+/// Does this part of a long file name match the name we are looking for?
+///
+/// LFN entry `sequence` (which counts from one) holds the thirteen UTF-16
+/// code units starting at `(sequence - 1) * 13`. If the name ends within
+/// this part, it must be followed by a NUL.
+fn lfn_part_matches(long_name: &str, sequence: u8, contents: &[u16; 13]) -> bool {
+    let mut name = long_name
+        .encode_utf16()
+        .skip((usize::from(sequence) - 1) * LFN_CHARS_PER_ENTRY);
+    for &on_disk in contents {
+        match name.next() {
+            Some(unit) if lfn_units_match(unit, on_disk) => {}
+            Some(_) => return false,
+            None => return on_disk == 0,
+        }
+    }
+    true
+}
+
+/// Do these two UTF-16 code units match, ignoring case?
+///
+/// Surrogate halves only match themselves.
+fn lfn_units_match(a: u16, b: u16) -> bool {
+    if a == b {
+        return true;
+    }
+    match (
+        core::char::from_u32(u32::from(a)),
+        core::char::from_u32(u32::from(b)),
+    ) {
+        (Some(a), Some(b)) => a.to_lowercase().eq(b.to_lowercase()),
+        _ => false,
+    }
+}
 
 /// An MS-DOS 11 character volume label.
 ///
@@ -102,6 +140,7 @@ impl VolumeName {
     pub unsafe fn to_short_filename(self) -> ShortFileName {
         ShortFileName {
             contents: self.contents,
+            case_flags: 0,
         }
     }
 }
@@ -133,6 +172,84 @@ impl core::fmt::Debug for VolumeName {
     }
 }
 
+/// What [`VolumeManager::check_volume`](crate::VolumeManager::check_volume)
+/// found wrong with a volume.
+///
+/// A volume with nothing wrong gives a report full of zeros.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct FsckReport {
+    /// The number of chains of clusters which are allocated in the FAT, but
+    /// which no directory entry uses.
+    ///
+    /// These are usually left behind when a file is deleted or created but
+    /// the card loses power before the FAT is fully updated.
+    pub lost_chains: u32,
+    /// The number of clusters in those lost chains.
+    pub lost_clusters: u32,
+    /// The number of times a file or directory uses a cluster which some
+    /// other file or directory already uses.
+    pub cross_linked_clusters: u32,
+    /// The number of directory entries whose first cluster is free in the
+    /// FAT.
+    pub entries_on_free_clusters: u32,
+    /// The number of files or directories whose cluster chain runs off the
+    /// end of the volume, into a free or bad cluster, or back on itself.
+    pub broken_chains: u32,
+}
+
+impl FsckReport {
+    /// Did the check find nothing wrong with the volume?
+    pub fn is_clean(&self) -> bool {
+        *self == FsckReport::default()
+    }
+}
+
+/// How a cluster chain looked when we walked it, checking the volume.
+enum ChainCheck {
+    /// The chain ended with an end-of-chain marker.
+    Good,
+    /// The first cluster of the chain is free.
+    StartsFree,
+    /// The chain ends any other way.
+    Broken,
+}
+
+/// How many clusters we look for in each pass when checking a volume. We
+/// keep one bit per cluster in each of two blocks.
+const CHECK_WINDOW: u32 = Block::LEN_U32 * 8;
+
+/// How many levels of sub-directory we go into when checking a volume. This
+/// stops a directory which contains itself sending us round in circles.
+const MAX_CHECK_DEPTH: usize = 16;
+
+/// Called with each directory entry we find when checking a volume.
+type CheckEntryFn<'a, E> = dyn FnMut(&DirEntry) -> Result<(), Error<E>> + 'a;
+
+/// A place in a directory to start reading entries from.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct DirPosition {
+    /// The cluster holding the entry, or [`ClusterId::ROOT_DIR`] for the
+    /// root directory (which, on FAT16, isn't made of clusters).
+    cluster: ClusterId,
+    /// Which block the entry is in, counting from the start of `cluster`.
+    block: u32,
+    /// Which entry in that block.
+    entry: usize,
+}
+
+impl DirPosition {
+    /// The first entry of the directory starting at `cluster`.
+    pub(crate) const fn start(cluster: ClusterId) -> DirPosition {
+        DirPosition {
+            cluster,
+            block: 0,
+            entry: 0,
+        }
+    }
+}
+
 /// Identifies a FAT16 or FAT32 Volume on the disk.
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Eq)]
@@ -151,12 +268,18 @@ pub struct FatVolume {
     /// The block the FAT starts in. Relative to start of partition (so add
     /// `self.lba_offset` before passing to volume manager)
     pub(crate) fat_start: BlockCount,
+    /// Number of blocks in each copy of the FAT
+    pub(crate) fat_size: BlockCount,
+    /// Number of copies of the FAT
+    pub(crate) num_fats: u8,
     /// Expected number of free clusters
     pub(crate) free_clusters_count: Option<u32>,
     /// Number of the next expected free cluster
     pub(crate) next_free_cluster: Option<ClusterId>,
     /// Total number of clusters
     pub(crate) cluster_count: u32,
+    /// Most blocks we read when searching or listing a directory
+    pub(crate) max_dir_scan_blocks: Option<u32>,
     /// Type of FAT
     pub(crate) fat_specific_info: FatSpecificInfo,
 }
@@ -204,27 +327,58 @@ impl FatVolume {
         }
     }
 
+    /// Find the block holding the FAT entry for the given cluster, and the
+    /// byte offset of the entry within that block.
+    ///
+    /// The byte offset into the FAT is calculated as a `u64`, so that it is
+    /// correct across the whole cluster range.
+    fn fat_entry_location<E>(&self, cluster: ClusterId) -> Result<(BlockIdx, usize), Error<E>>
+    where
+        E: core::fmt::Debug,
+    {
+        let entry_len: u64 = match &self.fat_specific_info {
+            FatSpecificInfo::Fat16(_fat16_info) => 2,
+            FatSpecificInfo::Fat32(_fat32_info) => 4,
+        };
+        let fat_offset = u64::from(cluster.0) * entry_len;
+        let fat_block = u32::try_from(fat_offset / u64::from(Block::LEN_U32))
+            .map_err(|_| Error::ConversionError)?;
+        let this_fat_block_num = self
+            .lba_start
+            .checked_add(self.fat_start)
+            .and_then(|b| b.checked_add(BlockCount(fat_block)))
+            .ok_or(Error::ConversionError)?;
+        // Always less than 512, so this cast is fine
+        let this_fat_ent_offset = (fat_offset % u64::from(Block::LEN_U32)) as usize;
+        Ok((this_fat_block_num, this_fat_ent_offset))
+    }
+
     /// Write a new entry in the FAT
+    ///
+    /// The change is made in `fat_block_cache`, and only reaches the disk when
+    /// the cache is written back or moves on to another block, so updates to
+    /// neighbouring entries share one write.
     fn update_fat<D>(
         &mut self,
         block_device: &D,
         cluster: ClusterId,
         new_value: ClusterId,
+        fat_block_cache: &mut BlockCache,
     ) -> Result<(), Error<D::Error>>
     where
         D: BlockDevice,
     {
-        let mut blocks = [Block::new()];
-        let this_fat_block_num;
+        let (this_fat_block_num, this_fat_ent_offset) = self.fat_entry_location(cluster)?;
+        trace!("Updating FAT");
+        // Keep every copy of the FAT the same
+        let block = fat_block_cache.read_mut_mirrored(
+            block_device,
+            this_fat_block_num,
+            self.fat_size,
+            self.num_fats.saturating_sub(1),
+        )?;
         match &self.fat_specific_info {
             FatSpecificInfo::Fat16(_fat16_info) => {
-                let fat_offset = cluster.0 * 2;
-                this_fat_block_num = self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                let this_fat_ent_offset = (fat_offset % Block::LEN_U32) as usize;
-                trace!("Reading FAT for update");
-                block_device
-                    .read(&mut blocks, this_fat_block_num)
-                    .map_err(Error::DeviceError)?;
                 // See <https://en.wikipedia.org/wiki/Design_of_the_FAT_file_system>
                 let entry = match new_value {
                     ClusterId::INVALID => 0xFFF6,
@@ -234,43 +388,33 @@ impl FatVolume {
                     _ => new_value.0 as u16,
                 };
                 LittleEndian::write_u16(
-                    &mut blocks[0][this_fat_ent_offset..=this_fat_ent_offset + 1],
+                    &mut block[this_fat_ent_offset..=this_fat_ent_offset + 1],
                     entry,
                 );
             }
             FatSpecificInfo::Fat32(_fat32_info) => {
-                // FAT32 => 4 bytes per entry
-                let fat_offset = cluster.0 * 4;
-                this_fat_block_num = self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                let this_fat_ent_offset = (fat_offset % Block::LEN_U32) as usize;
-                trace!("Reading FAT for update");
-                block_device
-                    .read(&mut blocks, this_fat_block_num)
-                    .map_err(Error::DeviceError)?;
                 let entry = match new_value {
                     ClusterId::INVALID => 0x0FFF_FFF6,
                     ClusterId::BAD => 0x0FFF_FFF7,
                     ClusterId::EMPTY => 0x0000_0000,
                     _ => new_value.0,
                 };
-                let existing = LittleEndian::read_u32(
-                    &blocks[0][this_fat_ent_offset..=this_fat_ent_offset + 3],
-                );
+                let existing =
+                    LittleEndian::read_u32(&block[this_fat_ent_offset..=this_fat_ent_offset + 3]);
                 let new = (existing & 0xF000_0000) | (entry & 0x0FFF_FFFF);
                 LittleEndian::write_u32(
-                    &mut blocks[0][this_fat_ent_offset..=this_fat_ent_offset + 3],
+                    &mut block[this_fat_ent_offset..=this_fat_ent_offset + 3],
                     new,
                 );
             }
         }
-        trace!("Updating FAT");
-        block_device
-            .write(&blocks, this_fat_block_num)
-            .map_err(Error::DeviceError)?;
         Ok(())
     }
 
     /// Look in the FAT to see which cluster comes next.
+    ///
+    /// Gives `Error::BadCluster` if `cluster`, or the cluster the FAT says
+    /// comes next, is beyond the end of the volume.
     pub(crate) fn next_cluster<D>(
         &self,
         block_device: &D,
@@ -280,37 +424,36 @@ impl FatVolume {
     where
         D: BlockDevice,
     {
-        if cluster.0 > (u32::MAX / 4) {
-            panic!("next_cluster called on invalid cluster {:x?}", cluster);
+        if !self.is_data_cluster(cluster) {
+            return Err(Error::BadCluster);
         }
+        let (this_fat_block_num, this_fat_ent_offset) = self.fat_entry_location(cluster)?;
         match &self.fat_specific_info {
             FatSpecificInfo::Fat16(_fat16_info) => {
-                let fat_offset = cluster.0 * 2;
-                let this_fat_block_num = self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                let this_fat_ent_offset = (fat_offset % Block::LEN_U32) as usize;
                 trace!("Walkng FAT");
                 let block = fat_block_cache.read(block_device, this_fat_block_num)?;
                 let fat_entry =
                     LittleEndian::read_u16(&block[this_fat_ent_offset..=this_fat_ent_offset + 1]);
                 match fat_entry {
-                    0xFFF7 => {
-                        // Bad cluster
-                        Err(Error::BadCluster)
+                    0x0000 => {
+                        // Jumped to free space
+                        Err(Error::UnterminatedFatChain)
                     }
                     0xFFF8..=0xFFFF => {
                         // There is no next cluster
                         Err(Error::EndOfFile)
                     }
-                    f => {
+                    f if self.is_data_cluster(ClusterId(u32::from(f))) => {
                         // Seems legit
                         Ok(ClusterId(u32::from(f)))
                     }
+                    _ => {
+                        // Bad cluster, or beyond the end of the volume
+                        Err(Error::BadCluster)
+                    }
                 }
             }
             FatSpecificInfo::Fat32(_fat32_info) => {
-                let fat_offset = cluster.0 * 4;
-                let this_fat_block_num = self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                let this_fat_ent_offset = (fat_offset % Block::LEN_U32) as usize;
                 trace!("Walking FAT");
                 let block = fat_block_cache.read(block_device, this_fat_block_num)?;
                 let fat_entry =
@@ -321,18 +464,18 @@ impl FatVolume {
                         // Jumped to free space
                         Err(Error::UnterminatedFatChain)
                     }
-                    0x0FFF_FFF7 => {
-                        // Bad cluster
-                        Err(Error::BadCluster)
-                    }
                     0x0000_0001 | 0x0FFF_FFF8..=0x0FFF_FFFF => {
                         // There is no next cluster
                         Err(Error::EndOfFile)
                     }
-                    f => {
+                    f if self.is_data_cluster(ClusterId(f)) => {
                         // Seems legit
                         Ok(ClusterId(f))
                     }
+                    _ => {
+                        // Bad cluster, or beyond the end of the volume
+                        Err(Error::BadCluster)
+                    }
                 }
             }
         }
@@ -387,149 +530,152 @@ impl FatVolume {
         D: BlockDevice,
         T: TimeSource,
     {
-        match &self.fat_specific_info {
-            FatSpecificInfo::Fat16(fat16_info) => {
-                // Root directories on FAT16 have a fixed size, because they use
-                // a specially reserved space on disk (see
-                // `first_root_dir_block`). Other directories can have any size
-                // as they are made of regular clusters.
-                let mut current_cluster = Some(dir_cluster);
-                let mut first_dir_block_num = match dir_cluster {
-                    ClusterId::ROOT_DIR => self.lba_start + fat16_info.first_root_dir_block,
-                    _ => self.cluster_to_block(dir_cluster),
-                };
-                let dir_size = match dir_cluster {
-                    ClusterId::ROOT_DIR => {
-                        let len_bytes =
-                            u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
-                        BlockCount::from_bytes(len_bytes)
-                    }
-                    _ => BlockCount(u32::from(self.blocks_per_cluster)),
-                };
+        let fat_type = self.get_fat_type();
+        let ctime = time_source.get_timestamp();
+        let mut new_entry = None;
+        self.fill_free_dir_entries(block_device, dir_cluster, 1, |block, offset, bytes| {
+            let entry = DirEntry::new(
+                name.clone(),
+                attributes,
+                ClusterId::EMPTY,
+                ctime,
+                block,
+                offset,
+            );
+            bytes.copy_from_slice(&entry.serialize(fat_type)[..]);
+            new_entry = Some(entry);
+        })?;
+        new_entry.ok_or(Error::NotEnoughSpace)
+    }
 
-                // Walk the directory
-                let mut blocks = [Block::new()];
-                while let Some(cluster) = current_cluster {
-                    for block in first_dir_block_num.range(dir_size) {
-                        trace!("Reading directory");
-                        block_device
-                            .read(&mut blocks, block)
-                            .map_err(Error::DeviceError)?;
-                        for (i, dir_entry_bytes) in
-                            blocks[0].chunks_exact_mut(OnDiskDirEntry::LEN).enumerate()
-                        {
-                            let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
-                            // 0x00 or 0xE5 represents a free entry
-                            if !dir_entry.is_valid() {
-                                let ctime = time_source.get_timestamp();
-                                let entry = DirEntry::new(
-                                    name,
-                                    attributes,
-                                    ClusterId::EMPTY,
-                                    ctime,
-                                    block,
-                                    (i * OnDiskDirEntry::LEN) as u32,
-                                );
-                                dir_entry_bytes
-                                    .copy_from_slice(&entry.serialize(FatType::Fat16)[..]);
-                                trace!("Updating directory");
-                                block_device
-                                    .write(&blocks, block)
-                                    .map_err(Error::DeviceError)?;
-                                return Ok(entry);
-                            }
-                        }
-                    }
-                    if cluster != ClusterId::ROOT_DIR {
-                        let mut block_cache = BlockCache::empty();
-                        current_cluster =
-                            match self.next_cluster(block_device, cluster, &mut block_cache) {
-                                Ok(n) => {
-                                    first_dir_block_num = self.cluster_to_block(n);
-                                    Some(n)
-                                }
-                                Err(Error::EndOfFile) => {
-                                    let c =
-                                        self.alloc_cluster(block_device, Some(cluster), true)?;
-                                    first_dir_block_num = self.cluster_to_block(c);
-                                    Some(c)
-                                }
-                                _ => None,
-                            };
-                    } else {
-                        current_cluster = None;
-                    }
+    /// Writes a new, empty, entry for each of the given names into the free
+    /// entries of a directory, in order, allocating new clusters as needed.
+    ///
+    /// The directory is only scanned once, and each block is only written
+    /// once, however many of the new entries it holds.
+    pub(crate) fn write_new_directory_entries<D, T, F>(
+        &mut self,
+        block_device: &D,
+        time_source: &T,
+        dir_cluster: ClusterId,
+        count: usize,
+        mut entry_for: F,
+    ) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+        T: TimeSource,
+        F: FnMut(usize) -> (ShortFileName, Attributes),
+    {
+        let fat_type = self.get_fat_type();
+        let ctime = time_source.get_timestamp();
+        let mut idx = 0;
+        self.fill_free_dir_entries(block_device, dir_cluster, count, |block, offset, bytes| {
+            let (name, attributes) = entry_for(idx);
+            let entry = DirEntry::new(name, attributes, ClusterId::EMPTY, ctime, block, offset);
+            bytes.copy_from_slice(&entry.serialize(fat_type)[..]);
+            idx += 1;
+        })
+    }
+
+    /// Calls `fill` for each of the first `count` free entries in a
+    /// directory, growing the directory if it runs out of room.
+    ///
+    /// `fill` gets the block and offset of the entry, and the entry's bytes
+    /// to overwrite. Each block we change is written back once, after all of
+    /// its free entries have been filled.
+    fn fill_free_dir_entries<D, F>(
+        &mut self,
+        block_device: &D,
+        dir_cluster: ClusterId,
+        count: usize,
+        mut fill: F,
+    ) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+        F: FnMut(BlockIdx, u32, &mut [u8]),
+    {
+        let mut remaining = count;
+        if remaining == 0 {
+            return Ok(());
+        }
+        let cluster_size = BlockCount(u32::from(self.blocks_per_cluster));
+        let (mut current_cluster, mut first_dir_block_num, dir_size) =
+            match (&self.fat_specific_info, dir_cluster) {
+                // Root directories on FAT16 have a fixed size, because they
+                // use a specially reserved space on disk (see
+                // `first_root_dir_block`).
+                (FatSpecificInfo::Fat16(fat16_info), ClusterId::ROOT_DIR) => {
+                    let len_bytes =
+                        u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
+                    (
+                        ClusterId::ROOT_DIR,
+                        self.lba_start + fat16_info.first_root_dir_block,
+                        BlockCount::from_bytes(len_bytes),
+                    )
                 }
-                Err(Error::NotEnoughSpace)
-            }
-            FatSpecificInfo::Fat32(fat32_info) => {
-                // All directories on FAT32 have a cluster chain but the root
-                // dir starts in a specified cluster.
-                let mut current_cluster = match dir_cluster {
-                    ClusterId::ROOT_DIR => Some(fat32_info.first_root_dir_cluster),
-                    _ => Some(dir_cluster),
-                };
-                let mut first_dir_block_num = self.cluster_to_block(dir_cluster);
-                let mut blocks = [Block::new()];
+                // The FAT32 root dir has a cluster chain, but it starts in a
+                // specified cluster.
+                (FatSpecificInfo::Fat32(fat32_info), ClusterId::ROOT_DIR) => (
+                    fat32_info.first_root_dir_cluster,
+                    self.cluster_to_block(dir_cluster),
+                    cluster_size,
+                ),
+                // Other directories can have any size as they are made of
+                // regular clusters.
+                _ => (
+                    dir_cluster,
+                    self.cluster_to_block(dir_cluster),
+                    cluster_size,
+                ),
+            };
 
-                let dir_size = BlockCount(u32::from(self.blocks_per_cluster));
-                // Walk the cluster chain until we run out of clusters
-                while let Some(cluster) = current_cluster {
-                    // Loop through the blocks in the cluster
-                    for block in first_dir_block_num.range(dir_size) {
-                        // Read a block of directory entries
-                        trace!("Reading directory");
-                        block_device
-                            .read(&mut blocks, block)
-                            .map_err(Error::DeviceError)?;
-                        // Are any entries in the block we just loaded blank? If so
-                        // we can use them.
-                        for (i, dir_entry_bytes) in
-                            blocks[0].chunks_exact_mut(OnDiskDirEntry::LEN).enumerate()
-                        {
-                            let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
-                            // 0x00 or 0xE5 represents a free entry
-                            if !dir_entry.is_valid() {
-                                let ctime = time_source.get_timestamp();
-                                let entry = DirEntry::new(
-                                    name,
-                                    attributes,
-                                    ClusterId(0),
-                                    ctime,
-                                    block,
-                                    (i * OnDiskDirEntry::LEN) as u32,
-                                );
-                                dir_entry_bytes
-                                    .copy_from_slice(&entry.serialize(FatType::Fat32)[..]);
-                                trace!("Updating directory");
-                                block_device
-                                    .write(&blocks, block)
-                                    .map_err(Error::DeviceError)?;
-                                return Ok(entry);
-                            }
-                        }
+        let mut blocks = [Block::new()];
+        loop {
+            for block in first_dir_block_num.range(dir_size) {
+                trace!("Reading directory");
+                block_device
+                    .read(&mut blocks, block)
+                    .map_err(Error::DeviceError)?;
+                let mut changed = false;
+                for (i, dir_entry_bytes) in
+                    blocks[0].chunks_exact_mut(OnDiskDirEntry::LEN).enumerate()
+                {
+                    // 0x00 or 0xE5 represents a free entry
+                    if remaining > 0 && !OnDiskDirEntry::new(dir_entry_bytes).is_valid() {
+                        fill(block, (i * OnDiskDirEntry::LEN) as u32, dir_entry_bytes);
+                        remaining -= 1;
+                        changed = true;
                     }
-                    // Well none of the blocks in that cluster had any space in
-                    // them, let's fetch another one.
-                    let mut block_cache = BlockCache::empty();
-                    current_cluster =
-                        match self.next_cluster(block_device, cluster, &mut block_cache) {
-                            Ok(n) => {
-                                first_dir_block_num = self.cluster_to_block(n);
-                                Some(n)
-                            }
-                            Err(Error::EndOfFile) => {
-                                let c = self.alloc_cluster(block_device, Some(cluster), true)?;
-                                first_dir_block_num = self.cluster_to_block(c);
-                                Some(c)
-                            }
-                            _ => None,
-                        };
                 }
-                // We ran out of clusters in the chain, and apparently we weren't
-                // able to make the chain longer, so the disk must be full.
-                Err(Error::NotEnoughSpace)
+                if changed {
+                    trace!("Updating directory");
+                    block_device
+                        .write(&blocks, block)
+                        .map_err(Error::DeviceError)?;
+                }
+                if remaining == 0 {
+                    return Ok(());
+                }
             }
+            if current_cluster == ClusterId::ROOT_DIR {
+                // A FAT16 root directory can't grow
+                return Err(Error::NotEnoughSpace);
+            }
+            // Well none of the blocks in that cluster had enough space in
+            // them, let's fetch another one.
+            let mut block_cache = BlockCache::empty();
+            current_cluster =
+                match self.next_cluster(block_device, current_cluster, &mut block_cache) {
+                    Ok(n) => n,
+                    Err(Error::EndOfFile) => {
+                        self.alloc_cluster(block_device, Some(current_cluster), true)?
+                    }
+                    // We ran out of clusters in the chain, and apparently we
+                    // weren't able to make the chain longer, so the disk must be
+                    // full.
+                    _ => return Err(Error::NotEnoughSpace),
+                };
+            first_dir_block_num = self.cluster_to_block(current_cluster);
         }
     }
 
@@ -539,130 +685,72 @@ impl FatVolume {
         &self,
         block_device: &D,
         dir_info: &DirectoryInfo,
-        func: F,
+        mut func: F,
     ) -> Result<(), Error<D::Error>>
     where
         F: FnMut(&DirEntry),
         D: BlockDevice,
     {
-        match &self.fat_specific_info {
-            FatSpecificInfo::Fat16(fat16_info) => {
-                self.iterate_fat16(dir_info, fat16_info, block_device, func)
-            }
-            FatSpecificInfo::Fat32(fat32_info) => {
-                self.iterate_fat32(dir_info, fat32_info, block_device, func)
-            }
-        }
+        self.iterate_dir_until(block_device, dir_info, |entry| {
+            func(entry);
+            ControlFlow::<()>::Continue(())
+        })?;
+        Ok(())
     }
 
-    fn iterate_fat16<D, F>(
+    /// Like [`iterate_dir`](Self::iterate_dir), but stops as soon as `func`
+    /// breaks, giving back the value it broke with.
+    pub(crate) fn iterate_dir_until<D, F, T>(
         &self,
-        dir_info: &DirectoryInfo,
-        fat16_info: &Fat16Info,
         block_device: &D,
-        mut func: F,
-    ) -> Result<(), Error<D::Error>>
+        dir_info: &DirectoryInfo,
+        func: F,
+    ) -> Result<Option<T>, Error<D::Error>>
     where
-        F: FnMut(&DirEntry),
+        F: FnMut(&DirEntry) -> ControlFlow<T>,
         D: BlockDevice,
     {
-        // Root directories on FAT16 have a fixed size, because they use
-        // a specially reserved space on disk (see
-        // `first_root_dir_block`). Other directories can have any size
-        // as they are made of regular clusters.
-        let mut current_cluster = Some(dir_info.cluster);
-        let mut first_dir_block_num = match dir_info.cluster {
-            ClusterId::ROOT_DIR => self.lba_start + fat16_info.first_root_dir_block,
-            _ => self.cluster_to_block(dir_info.cluster),
-        };
-        let dir_size = match dir_info.cluster {
-            ClusterId::ROOT_DIR => {
-                let len_bytes = u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
-                BlockCount::from_bytes(len_bytes)
-            }
-            _ => BlockCount(u32::from(self.blocks_per_cluster)),
-        };
-
-        let mut block_cache = BlockCache::empty();
-        while let Some(cluster) = current_cluster {
-            for block_idx in first_dir_block_num.range(dir_size) {
-                trace!("Reading directory");
-                let block = block_cache.read(block_device, block_idx)?;
-                for (i, dir_entry_bytes) in block.chunks_exact(OnDiskDirEntry::LEN).enumerate() {
-                    let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
-                    if dir_entry.is_end() {
-                        // Can quit early
-                        return Ok(());
-                    } else if dir_entry.is_valid() && !dir_entry.is_lfn() {
-                        // Block::LEN always fits on a u32
-                        let start = (i * OnDiskDirEntry::LEN) as u32;
-                        let entry = dir_entry.get_entry(FatType::Fat16, block_idx, start);
-                        func(&entry);
-                    }
-                }
-            }
-            if cluster != ClusterId::ROOT_DIR {
-                current_cluster = match self.next_cluster(block_device, cluster, &mut block_cache) {
-                    Ok(n) => {
-                        first_dir_block_num = self.cluster_to_block(n);
-                        Some(n)
-                    }
-                    _ => None,
-                };
-            } else {
-                current_cluster = None;
-            }
-        }
-        Ok(())
+        let found =
+            self.iterate_dir_from(block_device, DirPosition::start(dir_info.cluster), func)?;
+        Ok(found.map(|(result, _)| result))
     }
 
-    fn iterate_fat32<D, F>(
+    /// Like [`iterate_dir_until`](Self::iterate_dir_until), but starts at
+    /// `from` rather than at the start of the directory.
+    ///
+    /// If `func` breaks, we also give back the position just after the entry
+    /// it broke on, so a later call can carry on from there.
+    pub(crate) fn iterate_dir_from<D, F, T>(
         &self,
-        dir_info: &DirectoryInfo,
-        fat32_info: &Fat32Info,
         block_device: &D,
+        from: DirPosition,
         mut func: F,
-    ) -> Result<(), Error<D::Error>>
+    ) -> Result<Option<(T, DirPosition)>, Error<D::Error>>
     where
-        F: FnMut(&DirEntry),
+        F: FnMut(&DirEntry) -> ControlFlow<T>,
         D: BlockDevice,
     {
-        // All directories on FAT32 have a cluster chain but the root
-        // dir starts in a specified cluster.
-        let mut current_cluster = match dir_info.cluster {
-            ClusterId::ROOT_DIR => Some(fat32_info.first_root_dir_cluster),
-            _ => Some(dir_info.cluster),
-        };
-        let mut blocks = [Block::new()];
-        let mut block_cache = BlockCache::empty();
-        while let Some(cluster) = current_cluster {
-            let block_idx = self.cluster_to_block(cluster);
-            for block in block_idx.range(BlockCount(u32::from(self.blocks_per_cluster))) {
-                trace!("Reading directory");
-                block_device
-                    .read(&mut blocks, block)
-                    .map_err(Error::DeviceError)?;
-                for (i, dir_entry_bytes) in
-                    blocks[0].chunks_exact_mut(OnDiskDirEntry::LEN).enumerate()
-                {
-                    let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
-                    if dir_entry.is_end() {
-                        // Can quit early
-                        return Ok(());
-                    } else if dir_entry.is_valid() && !dir_entry.is_lfn() {
-                        // Block::LEN always fits on a u32
-                        let start = (i * OnDiskDirEntry::LEN) as u32;
-                        let entry = dir_entry.get_entry(FatType::Fat32, block, start);
-                        func(&entry);
-                    }
-                }
+        let fat_type = self.get_fat_type();
+        self.walk_dir_entries_from(block_device, from, |dir_entry, block, start| {
+            if dir_entry.is_valid() && !dir_entry.is_lfn() {
+                func(&dir_entry.get_entry(fat_type, block, start))
+            } else {
+                ControlFlow::Continue(())
             }
-            current_cluster = match self.next_cluster(block_device, cluster, &mut block_cache) {
-                Ok(n) => Some(n),
-                _ => None,
-            };
+        })
+    }
+
+    /// Count another block read while scanning a directory, failing if that
+    /// takes us past the volume's `max_dir_scan_blocks` limit.
+    fn count_dir_scan_block<E>(&self, blocks_scanned: &mut u32) -> Result<(), Error<E>>
+    where
+        E: core::fmt::Debug,
+    {
+        *blocks_scanned += 1;
+        match self.max_dir_scan_blocks {
+            Some(limit) if *blocks_scanned > limit => Err(Error::DirectoryScanLimitExceeded),
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     /// Get an entry from the given directory
@@ -696,8 +784,10 @@ impl FatVolume {
                 };
 
                 let mut block_cache = BlockCache::empty();
+                let mut blocks_scanned = 0;
                 while let Some(cluster) = current_cluster {
                     for block in first_dir_block_num.range(dir_size) {
+                        self.count_dir_scan_block(&mut blocks_scanned)?;
                         match self.find_entry_in_block(
                             block_device,
                             FatType::Fat16,
@@ -729,9 +819,11 @@ impl FatVolume {
                     _ => Some(dir_info.cluster),
                 };
                 let mut block_cache = BlockCache::empty();
+                let mut blocks_scanned = 0;
                 while let Some(cluster) = current_cluster {
                     let block_idx = self.cluster_to_block(cluster);
                     for block in block_idx.range(BlockCount(u32::from(self.blocks_per_cluster))) {
+                        self.count_dir_scan_block(&mut blocks_scanned)?;
                         match self.find_entry_in_block(
                             block_device,
                             FatType::Fat32,
@@ -742,11 +834,9 @@ impl FatVolume {
                             x => return x,
                         }
                     }
-                    current_cluster =
-                        match self.next_cluster(block_device, cluster, &mut block_cache) {
-                            Ok(n) => Some(n),
-                            _ => None,
-                        }
+                    current_cluster = self
+                        .next_cluster(block_device, cluster, &mut block_cache)
+                        .ok()
                 }
                 Err(Error::NotFound)
             }
@@ -784,48 +874,270 @@ impl FatVolume {
         Err(Error::NotFound)
     }
 
-    /// Delete an entry from the given directory
-    pub(crate) fn delete_directory_entry<D>(
+    /// Get an entry from the given directory by its long file name.
+    ///
+    /// Like with short file names, the comparison ignores case. The entries
+    /// holding the long file name must come immediately before the short
+    /// entry they belong to.
+    pub(crate) fn find_lfn_directory_entry<D>(
         &self,
         block_device: &D,
         dir_info: &DirectoryInfo,
-        match_name: &ShortFileName,
-    ) -> Result<(), Error<D::Error>>
+        long_name: &str,
+    ) -> Result<DirEntry, Error<D::Error>>
     where
         D: BlockDevice,
     {
-        match &self.fat_specific_info {
-            FatSpecificInfo::Fat16(fat16_info) => {
-                // Root directories on FAT16 have a fixed size, because they use
-                // a specially reserved space on disk (see
-                // `first_root_dir_block`). Other directories can have any size
-                // as they are made of regular clusters.
-                let mut current_cluster = Some(dir_info.cluster);
-                let mut first_dir_block_num = match dir_info.cluster {
-                    ClusterId::ROOT_DIR => self.lba_start + fat16_info.first_root_dir_block,
-                    _ => self.cluster_to_block(dir_info.cluster),
+        let fat_type = self.get_fat_type();
+        let num_lfn_entries = long_name
+            .encode_utf16()
+            .count()
+            .div_ceil(LFN_CHARS_PER_ENTRY);
+        // The sequence number of the LFN entry we want next, if the ones so
+        // far have matched. Zero means we want the short entry.
+        let mut wanted: Option<u8> = None;
+        // The short name checksum in the LFN entries so far. They must all
+        // agree, and match the short entry.
+        let mut checksum: Option<u8> = None;
+        let found = self.walk_dir_entries(block_device, dir_info, |dir_entry, block, start| {
+            if !dir_entry.is_valid() {
+                wanted = None;
+            } else if let Some((is_start, sequence, contents)) = dir_entry.lfn_units() {
+                let in_sequence = if is_start {
+                    checksum = dir_entry.lfn_checksum();
+                    usize::from(sequence) == num_lfn_entries
+                } else {
+                    wanted == Some(sequence) && checksum == dir_entry.lfn_checksum()
                 };
-                let dir_size = match dir_info.cluster {
-                    ClusterId::ROOT_DIR => {
-                        let len_bytes =
-                            u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
-                        BlockCount::from_bytes(len_bytes)
-                    }
-                    _ => BlockCount(u32::from(self.blocks_per_cluster)),
+                wanted = if sequence != 0
+                    && in_sequence
+                    && lfn_part_matches(long_name, sequence, &contents)
+                {
+                    Some(sequence - 1)
+                } else {
+                    None
                 };
+            } else if wanted == Some(0) {
+                let entry = dir_entry.get_entry(fat_type, block, start);
+                if checksum == Some(lfn_checksum(&entry.name)) {
+                    return ControlFlow::Break(entry);
+                }
+                wanted = None;
+            } else {
+                wanted = None;
+            }
+            ControlFlow::Continue(())
+        })?;
+        found.ok_or(Error::NotFound)
+    }
 
-                // Walk the directory
-                while let Some(cluster) = current_cluster {
-                    // Scan the cluster / root dir a block at a time
-                    for block in first_dir_block_num.range(dir_size) {
-                        match self.delete_entry_in_block(block_device, match_name, block) {
-                            Err(Error::NotFound) => {
-                                // Carry on
-                            }
-                            x => {
-                                // Either we deleted it OK, or there was some
-                                // catastrophic error reading/writing the disk.
-                                return x;
+    /// Pick a name for a new entry in the given directory, based on `desired`.
+    ///
+    /// If nothing in the directory has that name, it is returned unchanged.
+    /// Otherwise, like other FAT drivers, we add the lowest `~N` numeric tail
+    /// that isn't taken, so `REPORT.TXT` becomes `REPORT~1.TXT`, then
+    /// `REPORT~2.TXT`, and so on, shortening the base name when the tail
+    /// gets longer (`REPOR~10.TXT`).
+    pub(crate) fn generate_unique_short_name<D>(
+        &self,
+        block_device: &D,
+        dir_info: &DirectoryInfo,
+        desired: &ShortFileName,
+    ) -> Result<ShortFileName, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        // How many tails we check in each scan of the directory
+        const TAILS_PER_SCAN: u32 = u128::BITS;
+        let fat_type = self.get_fat_type();
+        let mut first_tail = 1;
+        while first_tail <= ShortFileName::MAX_NUMERIC_TAIL {
+            let tails = first_tail..first_tail + TAILS_PER_SCAN;
+            let mut collides = false;
+            let mut taken: u128 = 0;
+            self.walk_dir_entries(block_device, dir_info, |dir_entry, block, start| {
+                if dir_entry.is_valid() && !dir_entry.is_lfn() {
+                    let name = dir_entry.get_entry(fat_type, block, start).name;
+                    if name.contents == desired.contents {
+                        collides = true;
+                    } else if let Some(n) = name.numeric_tail().filter(|n| tails.contains(n)) {
+                        if name.contents == desired.with_numeric_tail(n).contents {
+                            taken |= 1 << (n - first_tail);
+                        }
+                    }
+                }
+                ControlFlow::<()>::Continue(())
+            })?;
+            if first_tail == 1 && !collides {
+                return Ok(desired.clone());
+            }
+            let n = first_tail + taken.trailing_ones();
+            if n < tails.end && n <= ShortFileName::MAX_NUMERIC_TAIL {
+                return Ok(desired.with_numeric_tail(n));
+            }
+            first_tail = tails.end;
+        }
+        Err(Error::FileAlreadyExists)
+    }
+
+    /// Calls `func` with every on-disk entry in the given directory, including
+    /// LFN and deleted entries, until `func` breaks or we reach the end of the
+    /// directory.
+    ///
+    /// `func` is given the entry, along with the block it is in and its
+    /// offset within that block.
+    fn walk_dir_entries<D, F, T>(
+        &self,
+        block_device: &D,
+        dir_info: &DirectoryInfo,
+        func: F,
+    ) -> Result<Option<T>, Error<D::Error>>
+    where
+        D: BlockDevice,
+        F: FnMut(&OnDiskDirEntry, BlockIdx, u32) -> ControlFlow<T>,
+    {
+        let found =
+            self.walk_dir_entries_from(block_device, DirPosition::start(dir_info.cluster), func)?;
+        Ok(found.map(|(result, _)| result))
+    }
+
+    /// Like [`walk_dir_entries`](Self::walk_dir_entries), but starts at
+    /// `from`, and if `func` breaks, also gives back the position just after
+    /// the entry it broke on.
+    fn walk_dir_entries_from<D, F, T>(
+        &self,
+        block_device: &D,
+        from: DirPosition,
+        mut func: F,
+    ) -> Result<Option<(T, DirPosition)>, Error<D::Error>>
+    where
+        D: BlockDevice,
+        F: FnMut(&OnDiskDirEntry, BlockIdx, u32) -> ControlFlow<T>,
+    {
+        let blocks_per_cluster = BlockCount(u32::from(self.blocks_per_cluster));
+        // Root directories on FAT16 have a fixed size, because they use a
+        // specially reserved space on disk. Everything else is made of
+        // clusters.
+        let (mut current_cluster, mut first_dir_block_num, dir_size) =
+            match (&self.fat_specific_info, from.cluster) {
+                (FatSpecificInfo::Fat16(fat16_info), ClusterId::ROOT_DIR) => {
+                    let len_bytes =
+                        u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
+                    (
+                        None,
+                        self.lba_start + fat16_info.first_root_dir_block,
+                        BlockCount::from_bytes(len_bytes),
+                    )
+                }
+                (FatSpecificInfo::Fat32(fat32_info), ClusterId::ROOT_DIR) => {
+                    let cluster = fat32_info.first_root_dir_cluster;
+                    (
+                        Some(cluster),
+                        self.cluster_to_block(cluster),
+                        blocks_per_cluster,
+                    )
+                }
+                (_, cluster) => (
+                    Some(cluster),
+                    self.cluster_to_block(cluster),
+                    blocks_per_cluster,
+                ),
+            };
+        // Where to start within the first cluster
+        let mut skip_blocks = from.block;
+        let mut skip_entries = from.entry;
+
+        let mut block_cache = BlockCache::empty();
+        let mut blocks_scanned = 0;
+        loop {
+            for (block_num, block_idx) in first_dir_block_num.range(dir_size).enumerate() {
+                // There are only ever a few blocks in a cluster
+                let block_num = block_num as u32;
+                if block_num < skip_blocks {
+                    continue;
+                }
+                self.count_dir_scan_block(&mut blocks_scanned)?;
+                trace!("Reading directory");
+                let block = block_cache.read(block_device, block_idx)?;
+                for (i, dir_entry_bytes) in block
+                    .chunks_exact(OnDiskDirEntry::LEN)
+                    .enumerate()
+                    .skip(skip_entries)
+                {
+                    let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
+                    if dir_entry.is_end() {
+                        // Can quit early
+                        return Ok(None);
+                    }
+                    // Block::LEN always fits on a u32
+                    let start = (i * OnDiskDirEntry::LEN) as u32;
+                    if let ControlFlow::Break(result) = func(&dir_entry, block_idx, start) {
+                        let next = DirPosition {
+                            cluster: current_cluster.unwrap_or(ClusterId::ROOT_DIR),
+                            block: block_num,
+                            entry: i + 1,
+                        };
+                        return Ok(Some((result, next)));
+                    }
+                }
+                skip_entries = 0;
+            }
+            skip_blocks = 0;
+            let Some(cluster) = current_cluster else {
+                return Ok(None);
+            };
+            match self.next_cluster(block_device, cluster, &mut block_cache) {
+                Ok(n) => {
+                    current_cluster = Some(n);
+                    first_dir_block_num = self.cluster_to_block(n);
+                }
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    /// Delete an entry from the given directory
+    pub(crate) fn delete_directory_entry<D>(
+        &self,
+        block_device: &D,
+        dir_info: &DirectoryInfo,
+        match_name: &ShortFileName,
+    ) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        match &self.fat_specific_info {
+            FatSpecificInfo::Fat16(fat16_info) => {
+                // Root directories on FAT16 have a fixed size, because they use
+                // a specially reserved space on disk (see
+                // `first_root_dir_block`). Other directories can have any size
+                // as they are made of regular clusters.
+                let mut current_cluster = Some(dir_info.cluster);
+                let mut first_dir_block_num = match dir_info.cluster {
+                    ClusterId::ROOT_DIR => self.lba_start + fat16_info.first_root_dir_block,
+                    _ => self.cluster_to_block(dir_info.cluster),
+                };
+                let dir_size = match dir_info.cluster {
+                    ClusterId::ROOT_DIR => {
+                        let len_bytes =
+                            u32::from(fat16_info.root_entries_count) * OnDiskDirEntry::LEN_U32;
+                        BlockCount::from_bytes(len_bytes)
+                    }
+                    _ => BlockCount(u32::from(self.blocks_per_cluster)),
+                };
+
+                // Walk the directory
+                while let Some(cluster) = current_cluster {
+                    // Scan the cluster / root dir a block at a time
+                    for block in first_dir_block_num.range(dir_size) {
+                        match self.delete_entry_in_block(block_device, match_name, block) {
+                            Err(Error::NotFound) => {
+                                // Carry on
+                            }
+                            x => {
+                                // Either we deleted it OK, or there was some
+                                // catastrophic error reading/writing the disk.
+                                return x;
                             }
                         }
                     }
@@ -872,11 +1184,9 @@ impl FatVolume {
                     }
                     // Find the next cluster
                     let mut block_cache = BlockCache::empty();
-                    current_cluster =
-                        match self.next_cluster(block_device, cluster, &mut block_cache) {
-                            Ok(n) => Some(n),
-                            _ => None,
-                        }
+                    current_cluster = self
+                        .next_cluster(block_device, cluster, &mut block_cache)
+                        .ok()
                 }
                 // Ok, give up
             }
@@ -923,17 +1233,374 @@ impl FatVolume {
         Err(Error::NotFound)
     }
 
+    /// Call `func` with each cluster in the chain starting at `first_cluster`.
+    ///
+    /// The walk gives up with `Error::FormatError` if the chain is longer
+    /// than the volume, which means it must loop back on itself.
+    fn walk_cluster_chain<D, F>(
+        &self,
+        block_device: &D,
+        first_cluster: ClusterId,
+        mut func: F,
+    ) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+        F: FnMut(ClusterId),
+    {
+        let mut current_cluster = match (first_cluster, &self.fat_specific_info) {
+            (ClusterId::EMPTY, _) => return Ok(()),
+            // The FAT16 root directory isn't stored in clusters
+            (ClusterId::ROOT_DIR, FatSpecificInfo::Fat16(_)) => return Ok(()),
+            (ClusterId::ROOT_DIR, FatSpecificInfo::Fat32(fat32_info)) => {
+                fat32_info.first_root_dir_cluster
+            }
+            (cluster, _) => cluster,
+        };
+        let mut block_cache = BlockCache::empty();
+        for _ in 0..self.cluster_count {
+            func(current_cluster);
+            match self.next_cluster(block_device, current_cluster, &mut block_cache) {
+                Ok(next_cluster) => current_cluster = next_cluster,
+                Err(Error::EndOfFile) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(Error::FormatError("FAT chain is longer than the volume"))
+    }
+
+    /// Count the clusters in the chain starting at `first_cluster`.
+    pub(crate) fn cluster_chain_len<D>(
+        &self,
+        block_device: &D,
+        first_cluster: ClusterId,
+    ) -> Result<u32, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut len = 0;
+        self.walk_cluster_chain(block_device, first_cluster, |_| len += 1)?;
+        Ok(len)
+    }
+
+    /// Check whether each cluster in the chain starting at `first_cluster` is
+    /// immediately followed by the next one on disk.
+    pub(crate) fn is_contiguous<D>(
+        &self,
+        block_device: &D,
+        first_cluster: ClusterId,
+    ) -> Result<bool, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut previous: Option<ClusterId> = None;
+        let mut contiguous = true;
+        self.walk_cluster_chain(block_device, first_cluster, |cluster| {
+            if let Some(previous) = previous {
+                if cluster.0 != previous.0.wrapping_add(1) {
+                    contiguous = false;
+                }
+            }
+            previous = Some(cluster);
+        })?;
+        Ok(contiguous)
+    }
+
+    /// Is this the number of a cluster in the data region of the volume?
+    fn is_data_cluster(&self, cluster: ClusterId) -> bool {
+        cluster.0 >= RESERVED_ENTRIES && cluster.0 - RESERVED_ENTRIES < self.cluster_count
+    }
+
+    /// Read the FAT entry for `cluster`.
+    ///
+    /// FAT16 bad cluster and end-of-chain markers are converted to their
+    /// FAT32 equivalents.
+    fn read_fat_entry<D>(
+        &self,
+        block_device: &D,
+        cluster: ClusterId,
+        fat_block_cache: &mut BlockCache,
+    ) -> Result<u32, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let (this_fat_block_num, this_fat_ent_offset) = self.fat_entry_location(cluster)?;
+        let block = fat_block_cache.read(block_device, this_fat_block_num)?;
+        match &self.fat_specific_info {
+            FatSpecificInfo::Fat16(_fat16_info) => {
+                let fat_entry = u32::from(LittleEndian::read_u16(
+                    &block[this_fat_ent_offset..=this_fat_ent_offset + 1],
+                ));
+                if fat_entry >= 0xFFF7 {
+                    Ok(fat_entry | 0x0FFF_0000)
+                } else {
+                    Ok(fat_entry)
+                }
+            }
+            FatSpecificInfo::Fat32(_fat32_info) => Ok(LittleEndian::read_u32(
+                &block[this_fat_ent_offset..=this_fat_ent_offset + 3],
+            ) & 0x0FFF_FFFF),
+        }
+    }
+
+    /// Check every cluster chain and directory on the volume, without
+    /// changing anything.
+    ///
+    /// We only have room to remember [`CHECK_WINDOW`] clusters at a time, so
+    /// we walk the whole directory tree and FAT once for each window of
+    /// clusters. `root` says which directory to start from.
+    pub(crate) fn check<D>(
+        &self,
+        block_device: &D,
+        root: &DirectoryInfo,
+    ) -> Result<FsckReport, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut report = FsckReport::default();
+        let first_cluster = RESERVED_ENTRIES;
+        let end_cluster = self.cluster_count + RESERVED_ENTRIES;
+        let mut fat_block_cache = BlockCache::empty();
+        let mut window_start = first_cluster;
+        while window_start < end_cluster {
+            let window_end = window_start.saturating_add(CHECK_WINDOW).min(end_cluster);
+            let first_pass = window_start == first_cluster;
+            // One bit for each cluster in the window
+            let mut used = Block::new();
+            let mut pointed_to = Block::new();
+            let bit = |cluster: ClusterId| -> Option<(usize, u8)> {
+                if cluster.0 >= window_start && cluster.0 < window_end {
+                    let n = (cluster.0 - window_start) as usize;
+                    Some((n / 8, 1 << (n % 8)))
+                } else {
+                    None
+                }
+            };
+
+            // Mark which of the clusters in the window are used
+            let mut mark_chain = |start: ClusterId| -> Result<(), Error<D::Error>> {
+                let outcome =
+                    self.check_chain(block_device, start, &mut fat_block_cache, |cluster| {
+                        if let Some((byte, mask)) = bit(cluster) {
+                            if used[byte] & mask != 0 {
+                                report.cross_linked_clusters += 1;
+                            }
+                            used[byte] |= mask;
+                        }
+                    })?;
+                if first_pass {
+                    match outcome {
+                        ChainCheck::Good => {}
+                        ChainCheck::StartsFree => report.entries_on_free_clusters += 1,
+                        ChainCheck::Broken => report.broken_chains += 1,
+                    }
+                }
+                Ok(())
+            };
+            if let FatSpecificInfo::Fat32(fat32_info) = &self.fat_specific_info {
+                mark_chain(fat32_info.first_root_dir_cluster)?;
+            }
+            self.check_dir(block_device, root, 0, &mut |entry| {
+                mark_chain(entry.cluster)
+            })?;
+
+            // Mark which of the clusters in the window follow some other
+            // cluster, so we can tell which lost clusters start a chain
+            for cluster in first_cluster..end_cluster {
+                let next = ClusterId(self.read_fat_entry(
+                    block_device,
+                    ClusterId(cluster),
+                    &mut fat_block_cache,
+                )?);
+                if let Some((byte, mask)) = bit(next) {
+                    pointed_to[byte] |= mask;
+                }
+            }
+
+            // Anything allocated which isn't used is lost
+            for cluster in window_start..window_end {
+                let entry =
+                    self.read_fat_entry(block_device, ClusterId(cluster), &mut fat_block_cache)?;
+                if entry == 0 || entry == 0x0FFF_FFF7 {
+                    // Free or bad
+                    continue;
+                }
+                if let Some((byte, mask)) = bit(ClusterId(cluster)) {
+                    if used[byte] & mask == 0 {
+                        report.lost_clusters += 1;
+                        if pointed_to[byte] & mask == 0 {
+                            report.lost_chains += 1;
+                        }
+                    }
+                }
+            }
+
+            window_start = window_end;
+        }
+        Ok(report)
+    }
+
+    /// Call `func` with each cluster in the chain starting at `start`, and
+    /// say how the chain ended.
+    fn check_chain<D, F>(
+        &self,
+        block_device: &D,
+        start: ClusterId,
+        fat_block_cache: &mut BlockCache,
+        mut func: F,
+    ) -> Result<ChainCheck, Error<D::Error>>
+    where
+        D: BlockDevice,
+        F: FnMut(ClusterId),
+    {
+        let mut cluster = start;
+        for idx in 0..self.cluster_count {
+            if !self.is_data_cluster(cluster) {
+                return Ok(ChainCheck::Broken);
+            }
+            match self.next_cluster(block_device, cluster, fat_block_cache) {
+                Ok(next_cluster) => {
+                    func(cluster);
+                    cluster = next_cluster;
+                }
+                Err(Error::EndOfFile) => {
+                    func(cluster);
+                    return Ok(ChainCheck::Good);
+                }
+                Err(Error::UnterminatedFatChain) if idx == 0 => {
+                    return Ok(ChainCheck::StartsFree);
+                }
+                Err(Error::UnterminatedFatChain | Error::BadCluster) => {
+                    return Ok(ChainCheck::Broken);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        // Longer than the volume, so it must loop back on itself
+        Ok(ChainCheck::Broken)
+    }
+
+    /// Call `func` with every entry in the given directory which has a
+    /// cluster, and in all of its sub-directories, depth first.
+    fn check_dir<D>(
+        &self,
+        block_device: &D,
+        dir_info: &DirectoryInfo,
+        depth: usize,
+        func: &mut CheckEntryFn<'_, D::Error>,
+    ) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut result = Ok(());
+        self.iterate_dir(block_device, dir_info, |entry| {
+            if result.is_err()
+                || entry.cluster.is_free()
+                || entry.name == ShortFileName::this_dir()
+                || entry.name == ShortFileName::parent_dir()
+            {
+                return;
+            }
+            result = func(entry);
+            if result.is_ok()
+                && entry.attributes.is_directory()
+                && self.is_data_cluster(entry.cluster)
+            {
+                result = if depth >= MAX_CHECK_DEPTH {
+                    Err(Error::FormatError("Directory tree is too deep to check"))
+                } else {
+                    let sub_dir_info = DirectoryInfo {
+                        cluster: entry.cluster,
+                        ..dir_info.clone()
+                    };
+                    self.check_dir(block_device, &sub_dir_info, depth + 1, func)
+                };
+            }
+        })?;
+        result
+    }
+
+    /// Check that every copy of the FAT matches the first one, block by
+    /// block.
+    ///
+    /// Gives `Error::FatMirrorMismatch` with the first block of the first
+    /// FAT which doesn't match its copy.
+    pub(crate) fn verify_fats<D>(&self, block_device: &D) -> Result<(), Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let first_fat_block = self
+            .lba_start
+            .checked_add(self.fat_start)
+            .ok_or(Error::ConversionError)?;
+        let mut primary = [Block::new()];
+        let mut copy = [Block::new()];
+        for offset in 0..self.fat_size.0 {
+            let primary_block = first_fat_block + BlockCount(offset);
+            trace!("Reading FAT");
+            block_device
+                .read(&mut primary, primary_block)
+                .map_err(Error::DeviceError)?;
+            for fat_idx in 1..u32::from(self.num_fats) {
+                let copy_block = fat_idx
+                    .checked_mul(self.fat_size.0)
+                    .and_then(|n| primary_block.checked_add(BlockCount(n)))
+                    .ok_or(Error::ConversionError)?;
+                trace!("Reading FAT copy");
+                block_device
+                    .read(&mut copy, copy_block)
+                    .map_err(Error::DeviceError)?;
+                if primary[0].contents != copy[0].contents {
+                    warn!("FAT copy {} differs at block {:?}", fat_idx, primary_block);
+                    return Err(Error::FatMirrorMismatch(primary_block));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Count the free clusters, by looking at every entry in the FAT.
+    pub(crate) fn count_free_clusters<D>(&self, block_device: &D) -> Result<u32, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut block_cache = BlockCache::empty();
+        let mut count = 0;
+        for cluster in RESERVED_ENTRIES..(self.cluster_count + RESERVED_ENTRIES) {
+            let (this_fat_block_num, this_fat_ent_offset) =
+                self.fat_entry_location(ClusterId(cluster))?;
+            let block = block_cache.read(block_device, this_fat_block_num)?;
+            let is_free = match &self.fat_specific_info {
+                FatSpecificInfo::Fat16(_fat16_info) => {
+                    LittleEndian::read_u16(&block[this_fat_ent_offset..=this_fat_ent_offset + 1])
+                        == 0
+                }
+                FatSpecificInfo::Fat32(_fat32_info) => {
+                    (LittleEndian::read_u32(&block[this_fat_ent_offset..=this_fat_ent_offset + 3])
+                        & 0x0FFF_FFFF)
+                        == 0
+                }
+            };
+            if is_free {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
     /// Finds the next free cluster after the start_cluster and before end_cluster
+    ///
+    /// The FAT is read through `fat_block_cache`, so this sees any updates
+    /// held there which haven't been written back yet.
     pub(crate) fn find_next_free_cluster<D>(
         &self,
         block_device: &D,
         start_cluster: ClusterId,
         end_cluster: ClusterId,
+        fat_block_cache: &mut BlockCache,
     ) -> Result<ClusterId, Error<D::Error>>
     where
         D: BlockDevice,
     {
-        let mut blocks = [Block::new()];
         let mut current_cluster = start_cluster;
         match &self.fat_specific_info {
             FatSpecificInfo::Fat16(_fat16_info) => {
@@ -943,21 +1610,14 @@ impl FatVolume {
                         current_cluster,
                         end_cluster
                     );
-                    let fat_offset = current_cluster.0 * 2;
-                    trace!("fat_offset = {:?}", fat_offset);
-                    let this_fat_block_num =
-                        self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                    trace!("this_fat_block_num = {:?}", this_fat_block_num);
-                    let mut this_fat_ent_offset = usize::try_from(fat_offset % Block::LEN_U32)
-                        .map_err(|_| Error::ConversionError)?;
+                    let (this_fat_block_num, mut this_fat_ent_offset) =
+                        self.fat_entry_location(current_cluster)?;
                     trace!("Reading block {:?}", this_fat_block_num);
-                    block_device
-                        .read(&mut blocks, this_fat_block_num)
-                        .map_err(Error::DeviceError)?;
+                    let block = fat_block_cache.read(block_device, this_fat_block_num)?;
 
                     while this_fat_ent_offset <= Block::LEN - 2 {
                         let fat_entry = LittleEndian::read_u16(
-                            &blocks[0][this_fat_ent_offset..=this_fat_ent_offset + 1],
+                            &block[this_fat_ent_offset..=this_fat_ent_offset + 1],
                         );
                         if fat_entry == 0 {
                             return Ok(current_cluster);
@@ -974,21 +1634,14 @@ impl FatVolume {
                         current_cluster,
                         end_cluster
                     );
-                    let fat_offset = current_cluster.0 * 4;
-                    trace!("fat_offset = {:?}", fat_offset);
-                    let this_fat_block_num =
-                        self.lba_start + self.fat_start.offset_bytes(fat_offset);
-                    trace!("this_fat_block_num = {:?}", this_fat_block_num);
-                    let mut this_fat_ent_offset = usize::try_from(fat_offset % Block::LEN_U32)
-                        .map_err(|_| Error::ConversionError)?;
+                    let (this_fat_block_num, mut this_fat_ent_offset) =
+                        self.fat_entry_location(current_cluster)?;
                     trace!("Reading block {:?}", this_fat_block_num);
-                    block_device
-                        .read(&mut blocks, this_fat_block_num)
-                        .map_err(Error::DeviceError)?;
+                    let block = fat_block_cache.read(block_device, this_fat_block_num)?;
 
                     while this_fat_ent_offset <= Block::LEN - 4 {
                         let fat_entry = LittleEndian::read_u32(
-                            &blocks[0][this_fat_ent_offset..=this_fat_ent_offset + 3],
+                            &block[this_fat_ent_offset..=this_fat_ent_offset + 3],
                         ) & 0x0FFF_FFFF;
                         if fat_entry == 0 {
                             return Ok(current_cluster);
@@ -1024,52 +1677,71 @@ impl FatVolume {
             start_cluster,
             end_cluster
         );
-        let new_cluster =
-            match self.find_next_free_cluster(block_device, start_cluster, end_cluster) {
-                Ok(cluster) => cluster,
-                Err(_) if start_cluster.0 > RESERVED_ENTRIES => {
-                    debug!(
-                        "Retrying, finding next free between {:?}..={:?}",
-                        ClusterId(RESERVED_ENTRIES),
-                        end_cluster
-                    );
-                    self.find_next_free_cluster(
-                        block_device,
-                        ClusterId(RESERVED_ENTRIES),
-                        end_cluster,
-                    )?
-                }
-                Err(e) => return Err(e),
-            };
-        self.update_fat(block_device, new_cluster, ClusterId::END_OF_FILE)?;
+        let mut fat_block_cache = BlockCache::empty();
+        let new_cluster = match self.find_next_free_cluster(
+            block_device,
+            start_cluster,
+            end_cluster,
+            &mut fat_block_cache,
+        ) {
+            Ok(cluster) => cluster,
+            Err(_) if start_cluster.0 > RESERVED_ENTRIES => {
+                debug!(
+                    "Retrying, finding next free between {:?}..={:?}",
+                    ClusterId(RESERVED_ENTRIES),
+                    end_cluster
+                );
+                self.find_next_free_cluster(
+                    block_device,
+                    ClusterId(RESERVED_ENTRIES),
+                    end_cluster,
+                    &mut fat_block_cache,
+                )?
+            }
+            Err(e) => return Err(e),
+        };
+        self.update_fat(
+            block_device,
+            new_cluster,
+            ClusterId::END_OF_FILE,
+            &mut fat_block_cache,
+        )?;
         if let Some(cluster) = prev_cluster {
             trace!(
                 "Updating old cluster {:?} to {:?} in FAT",
                 cluster,
                 new_cluster
             );
-            self.update_fat(block_device, cluster, new_cluster)?;
+            self.update_fat(block_device, cluster, new_cluster, &mut fat_block_cache)?;
         }
+        // The two entries are usually in the same block, so this is
+        // usually one write
+        fat_block_cache.write_back(block_device)?;
         trace!(
             "Finding next free between {:?}..={:?}",
             new_cluster,
             end_cluster
         );
-        self.next_free_cluster =
-            match self.find_next_free_cluster(block_device, new_cluster, end_cluster) {
-                Ok(cluster) => Some(cluster),
-                Err(_) if new_cluster.0 > RESERVED_ENTRIES => {
-                    match self.find_next_free_cluster(
-                        block_device,
-                        ClusterId(RESERVED_ENTRIES),
-                        end_cluster,
-                    ) {
-                        Ok(cluster) => Some(cluster),
-                        Err(e) => return Err(e),
-                    }
+        self.next_free_cluster = match self.find_next_free_cluster(
+            block_device,
+            new_cluster,
+            end_cluster,
+            &mut fat_block_cache,
+        ) {
+            Ok(cluster) => Some(cluster),
+            Err(_) if new_cluster.0 > RESERVED_ENTRIES => {
+                match self.find_next_free_cluster(
+                    block_device,
+                    ClusterId(RESERVED_ENTRIES),
+                    end_cluster,
+                    &mut fat_block_cache,
+                ) {
+                    Ok(cluster) => Some(cluster),
+                    Err(e) => return Err(e),
                 }
-                Err(e) => return Err(e),
-            };
+            }
+            Err(e) => return Err(e),
+        };
         debug!("Next free cluster is {:?}", self.next_free_cluster);
         if let Some(ref mut number_free_cluster) = self.free_clusters_count {
             *number_free_cluster -= 1;
@@ -1089,6 +1761,199 @@ impl FatVolume {
         Ok(new_cluster)
     }
 
+    /// Finds the first run of `count` free clusters after the start_cluster
+    /// and before end_cluster, if there is one.
+    fn find_free_run<D>(
+        &self,
+        block_device: &D,
+        start_cluster: ClusterId,
+        end_cluster: ClusterId,
+        count: u32,
+    ) -> Result<Option<ClusterId>, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut block_cache = BlockCache::empty();
+        let mut run_start = start_cluster;
+        let mut run_len = 0;
+        let mut current_cluster = start_cluster;
+        while current_cluster.0 < end_cluster.0 {
+            let (fat_block_num, fat_ent_offset) = self.fat_entry_location(current_cluster)?;
+            let block = block_cache.read(block_device, fat_block_num)?;
+            let fat_entry = match &self.fat_specific_info {
+                FatSpecificInfo::Fat16(_fat16_info) => u32::from(LittleEndian::read_u16(
+                    &block[fat_ent_offset..=fat_ent_offset + 1],
+                )),
+                FatSpecificInfo::Fat32(_fat32_info) => {
+                    LittleEndian::read_u32(&block[fat_ent_offset..=fat_ent_offset + 3])
+                        & 0x0FFF_FFFF
+                }
+            };
+            current_cluster += 1;
+            if fat_entry != 0 {
+                run_start = current_cluster;
+                run_len = 0;
+                continue;
+            }
+            run_len += 1;
+            if run_len == count {
+                return Ok(Some(run_start));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Tries to allocate `count` clusters, chaining them together (and onto
+    /// `prev_cluster`, if given).
+    ///
+    /// We look for a run of `count` free clusters first. If there isn't one,
+    /// each new cluster is the first free one after the last, so the chain is
+    /// as close to contiguous as the free space allows. Returns the first new
+    /// cluster. If the volume fills up part way through, the
+    /// clusters already allocated are left on the chain and
+    /// `Error::NotEnoughSpace` is returned. The free cluster count is not
+    /// consulted first, as it is only a hint and may be out of date.
+    ///
+    /// Each FAT block is written once, after all of its entries have been
+    /// updated, rather than once per cluster.
+    pub(crate) fn alloc_clusters<D>(
+        &mut self,
+        block_device: &D,
+        count: u32,
+        prev_cluster: Option<ClusterId>,
+    ) -> Result<ClusterId, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        debug!(
+            "Allocating {} clusters, prev_cluster={:?}",
+            count, prev_cluster
+        );
+        if count == 0 {
+            return Err(Error::Unsupported);
+        }
+        let end_cluster = ClusterId(self.cluster_count + RESERVED_ENTRIES);
+        let mut search_from = match self.next_free_cluster {
+            Some(cluster) if cluster.0 < end_cluster.0 => cluster,
+            _ => ClusterId(RESERVED_ENTRIES),
+        };
+        let run = match self.find_free_run(block_device, search_from, end_cluster, count)? {
+            Some(cluster) => Some(cluster),
+            None if search_from.0 > RESERVED_ENTRIES => self.find_free_run(
+                block_device,
+                ClusterId(RESERVED_ENTRIES),
+                end_cluster,
+                count,
+            )?,
+            None => None,
+        };
+        if let Some(cluster) = run {
+            trace!("Found a run of {} free clusters at {:?}", count, cluster);
+            search_from = cluster;
+        }
+        // The updates are held in `fat_block_cache` and written once per FAT
+        // block. Searching has its own cache, so that finding a cluster in
+        // the next block doesn't push out the block where the previous
+        // cluster needs linking to it. The search only looks beyond the
+        // clusters we've allocated so far, so it can't see the updates it's
+        // missing - until it wraps around, when we write them out first.
+        let mut fat_block_cache = BlockCache::empty();
+        let mut search_cache = BlockCache::empty();
+        let mut first_cluster = None;
+        let mut prev_cluster = prev_cluster;
+        for _ in 0..count {
+            let found = if search_from.0 < end_cluster.0 {
+                self.find_next_free_cluster(
+                    block_device,
+                    search_from,
+                    end_cluster,
+                    &mut search_cache,
+                )
+            } else {
+                Err(Error::NotEnoughSpace)
+            };
+            let found = match found {
+                Err(_) if search_from.0 > RESERVED_ENTRIES => {
+                    fat_block_cache.write_back(block_device)?;
+                    search_cache = BlockCache::empty();
+                    self.find_next_free_cluster(
+                        block_device,
+                        ClusterId(RESERVED_ENTRIES),
+                        end_cluster,
+                        &mut search_cache,
+                    )
+                }
+                found => found,
+            };
+            let new_cluster = match found {
+                Ok(cluster) => cluster,
+                Err(e) => {
+                    // Keep the clusters we did manage to allocate
+                    fat_block_cache.write_back(block_device)?;
+                    return Err(e);
+                }
+            };
+            // Link the previous cluster first, so that when the chain moves
+            // into the next FAT block we're done with the last one
+            if let Some(cluster) = prev_cluster {
+                self.update_fat(block_device, cluster, new_cluster, &mut fat_block_cache)?;
+            }
+            self.update_fat(
+                block_device,
+                new_cluster,
+                ClusterId::END_OF_FILE,
+                &mut fat_block_cache,
+            )?;
+            // The count is only a hint, and might have been too low
+            if let Some(ref mut number_free_cluster) = self.free_clusters_count {
+                *number_free_cluster = number_free_cluster.saturating_sub(1);
+            };
+            first_cluster.get_or_insert(new_cluster);
+            prev_cluster = Some(new_cluster);
+            search_from = new_cluster + 1;
+            // Only a hint - `alloc_cluster` searches onwards from here
+            self.next_free_cluster = Some(search_from);
+        }
+        // Each FAT block we touched has been written once, as we moved past
+        // it, except for this last one
+        fat_block_cache.write_back(block_device)?;
+        debug!("All done, returning {:?}", first_cluster);
+        first_cluster.ok_or(Error::NotEnoughSpace)
+    }
+
+    /// Make sure the chain starting at `first_cluster` is at least
+    /// `clusters` long, allocating more on the end if it isn't.
+    ///
+    /// Pass `ClusterId::EMPTY` for a file that has no clusters yet. Returns
+    /// the (possibly new) first cluster of the chain.
+    pub(crate) fn preallocate<D>(
+        &mut self,
+        block_device: &D,
+        first_cluster: ClusterId,
+        clusters: u32,
+    ) -> Result<ClusterId, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let mut len = 0;
+        let mut last_cluster = None;
+        if first_cluster.0 >= RESERVED_ENTRIES {
+            self.walk_cluster_chain(block_device, first_cluster, |cluster| {
+                len += 1;
+                last_cluster = Some(cluster);
+            })?;
+        }
+        if len >= clusters {
+            return Ok(first_cluster);
+        }
+        let new_cluster = self.alloc_clusters(block_device, clusters - len, last_cluster)?;
+        Ok(if last_cluster.is_some() {
+            first_cluster
+        } else {
+            new_cluster
+        })
+    }
+
     /// Marks the input cluster as an EOF and all the subsequent clusters in the chain as free
     pub(crate) fn truncate_cluster_chain<D>(
         &mut self,
@@ -1102,13 +1967,12 @@ impl FatVolume {
             // file doesn't have any valid cluster allocated, there is nothing to do
             return Ok(());
         }
-        let mut next = {
-            let mut block_cache = BlockCache::empty();
-            match self.next_cluster(block_device, cluster, &mut block_cache) {
-                Ok(n) => n,
-                Err(Error::EndOfFile) => return Ok(()),
-                Err(e) => return Err(e),
-            }
+        // Reads and updates share this, so each FAT block is written once
+        let mut fat_block_cache = BlockCache::empty();
+        let mut next = match self.next_cluster(block_device, cluster, &mut fat_block_cache) {
+            Ok(n) => n,
+            Err(Error::EndOfFile) => return Ok(()),
+            Err(e) => return Err(e),
         };
         if let Some(ref mut next_free_cluster) = self.next_free_cluster {
             if next_free_cluster.0 > next.0 {
@@ -1117,25 +1981,88 @@ impl FatVolume {
         } else {
             self.next_free_cluster = Some(next);
         }
-        self.update_fat(block_device, cluster, ClusterId::END_OF_FILE)?;
+        self.update_fat(
+            block_device,
+            cluster,
+            ClusterId::END_OF_FILE,
+            &mut fat_block_cache,
+        )?;
         loop {
-            let mut block_cache = BlockCache::empty();
-            match self.next_cluster(block_device, next, &mut block_cache) {
-                Ok(n) => {
-                    self.update_fat(block_device, next, ClusterId::EMPTY)?;
-                    next = n;
-                }
-                Err(Error::EndOfFile) => {
-                    self.update_fat(block_device, next, ClusterId::EMPTY)?;
-                    break;
+            let following = match self.next_cluster(block_device, next, &mut fat_block_cache) {
+                Ok(n) => Some(n),
+                Err(Error::EndOfFile) => None,
+                Err(e) => {
+                    fat_block_cache.write_back(block_device)?;
+                    return Err(e);
                 }
-                Err(e) => return Err(e),
-            }
+            };
+            self.update_fat(block_device, next, ClusterId::EMPTY, &mut fat_block_cache)?;
             if let Some(ref mut number_free_cluster) = self.free_clusters_count {
                 *number_free_cluster += 1;
             };
+            match following {
+                Some(n) => next = n,
+                None => break,
+            }
         }
-        Ok(())
+        fat_block_cache.write_back(block_device)
+    }
+
+    /// Release any clusters at the end of a directory which only hold free
+    /// entries.
+    ///
+    /// The first cluster, which holds `.` and `..`, is always kept. Returns
+    /// the number of clusters released. The FAT16 root directory has a fixed
+    /// size, so there is nothing to release there.
+    pub(crate) fn compact_dir<D>(
+        &mut self,
+        block_device: &D,
+        dir_info: &DirectoryInfo,
+    ) -> Result<u32, Error<D::Error>>
+    where
+        D: BlockDevice,
+    {
+        let first_cluster = match (&self.fat_specific_info, dir_info.cluster) {
+            (FatSpecificInfo::Fat16(_), ClusterId::ROOT_DIR) => return Ok(0),
+            (FatSpecificInfo::Fat32(fat32_info), ClusterId::ROOT_DIR) => {
+                fat32_info.first_root_dir_cluster
+            }
+            (_, cluster) => cluster,
+        };
+
+        // Find the last cluster with an entry in use
+        let mut last_used_cluster = first_cluster;
+        let mut current_cluster = first_cluster;
+        let mut block_cache = BlockCache::empty();
+        'chain: for _ in 0..self.cluster_count {
+            let first_block = self.cluster_to_block(current_cluster);
+            for block_idx in first_block.range(BlockCount(u32::from(self.blocks_per_cluster))) {
+                let block = block_cache.read(block_device, block_idx)?;
+                for dir_entry_bytes in block.chunks_exact(OnDiskDirEntry::LEN) {
+                    let dir_entry = OnDiskDirEntry::new(dir_entry_bytes);
+                    if dir_entry.is_end() {
+                        // Everything after this is free
+                        break 'chain;
+                    } else if dir_entry.is_valid() {
+                        last_used_cluster = current_cluster;
+                    }
+                }
+            }
+            match self.next_cluster(block_device, current_cluster, &mut block_cache) {
+                Ok(n) => current_cluster = n,
+                Err(Error::EndOfFile) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let old_len = self.cluster_chain_len(block_device, first_cluster)?;
+        self.truncate_cluster_chain(block_device, last_used_cluster)?;
+        let new_len = self.cluster_chain_len(block_device, first_cluster)?;
+        debug!(
+            "Compacted directory from {} to {} clusters",
+            old_len, new_len
+        );
+        Ok(old_len - new_len)
     }
 
     /// Writes a Directory Entry to the disk
@@ -1159,7 +2086,9 @@ impl FatVolume {
         let block = &mut blocks[0];
 
         let start = usize::try_from(entry.entry_offset).map_err(|_| Error::ConversionError)?;
-        block[start..start + 32].copy_from_slice(&entry.serialize(fat_type)[..]);
+        block
+            .record_mut(start / OnDiskDirEntry::LEN, OnDiskDirEntry::LEN)
+            .copy_from_slice(&entry.serialize(fat_type)[..]);
 
         trace!("Updating directory");
         block_device
@@ -1185,21 +2114,60 @@ where
     block_device
         .read(&mut blocks, lba_start)
         .map_err(Error::DeviceError)?;
+    if let Err(primary_error) = Bpb::create_from_bytes(&blocks[0]) {
+        // FAT32 keeps a backup copy of the boot sector. The primary is
+        // damaged, but if its pointer to the backup looks sane we believe it,
+        // otherwise we use the standard location.
+        let backup_boot_block = match LittleEndian::read_u16(&blocks[0][50..52]) {
+            0 | 0xFFFF => Bpb::DEFAULT_BACKUP_BOOT_BLOCK,
+            n => n,
+        };
+        warn!(
+            "Bad BPB ({}), trying backup at block {}",
+            primary_error, backup_boot_block
+        );
+        let backup_block_idx = lba_start
+            .checked_add(BlockCount(u32::from(backup_boot_block)))
+            .ok_or(Error::FormatError(primary_error))?;
+        block_device
+            .read(&mut blocks, backup_block_idx)
+            .map_err(Error::DeviceError)?;
+        match Bpb::create_from_bytes(&blocks[0]) {
+            Ok(bpb) if bpb.fat_type == FatType::Fat32 => {
+                warn!("Using backup BPB");
+            }
+            _ => return Err(Error::FormatError(primary_error)),
+        }
+    }
     let block = &blocks[0];
     let bpb = Bpb::create_from_bytes(block).map_err(Error::FormatError)?;
+    // Everything we access is relative to `lba_start`, so make sure the whole
+    // filesystem is addressable.
+    if lba_start
+        .checked_add(BlockCount(bpb.total_blocks()))
+        .is_none()
+    {
+        return Err(Error::FormatError("Volume extends beyond end of disk"));
+    }
+    let fat_region_size = u32::from(bpb.num_fats())
+        .checked_mul(bpb.fat_size())
+        .map(BlockCount)
+        .ok_or(Error::FormatError("FAT region too large"))?;
     match bpb.fat_type {
         FatType::Fat16 => {
             if bpb.bytes_per_block() as usize != Block::LEN {
                 return Err(Error::BadBlockSize(bpb.bytes_per_block()));
             }
             // FirstDataSector = BPB_ResvdSecCnt + (BPB_NumFATs * FATSz) + RootDirSectors;
-            let root_dir_blocks = ((u32::from(bpb.root_entries_count()) * OnDiskDirEntry::LEN_U32)
-                + (Block::LEN_U32 - 1))
-                / Block::LEN_U32;
+            let root_dir_blocks = (u32::from(bpb.root_entries_count()) * OnDiskDirEntry::LEN_U32)
+                .div_ceil(Block::LEN_U32);
             let fat_start = BlockCount(u32::from(bpb.reserved_block_count()));
-            let first_root_dir_block =
-                fat_start + BlockCount(u32::from(bpb.num_fats()) * bpb.fat_size());
-            let first_data_block = first_root_dir_block + BlockCount(root_dir_blocks);
+            let first_root_dir_block = fat_start
+                .checked_add(fat_region_size)
+                .ok_or(Error::FormatError("FAT region too large"))?;
+            let first_data_block = first_root_dir_block
+                .checked_add(BlockCount(root_dir_blocks))
+                .ok_or(Error::FormatError("Root directory too large"))?;
             let volume = FatVolume {
                 lba_start,
                 num_blocks,
@@ -1209,9 +2177,12 @@ where
                 blocks_per_cluster: bpb.blocks_per_cluster(),
                 first_data_block: (first_data_block),
                 fat_start: BlockCount(u32::from(bpb.reserved_block_count())),
+                fat_size: BlockCount(bpb.fat_size()),
+                num_fats: bpb.num_fats(),
                 free_clusters_count: None,
                 next_free_cluster: None,
                 cluster_count: bpb.total_clusters(),
+                max_dir_scan_blocks: None,
                 fat_specific_info: FatSpecificInfo::Fat16(Fat16Info {
                     root_entries_count: bpb.root_entries_count(),
                     first_root_dir_block,
@@ -1221,15 +2192,19 @@ where
         }
         FatType::Fat32 => {
             // FirstDataSector = BPB_ResvdSecCnt + (BPB_NumFATs * FATSz);
-            let first_data_block = u32::from(bpb.reserved_block_count())
-                + (u32::from(bpb.num_fats()) * bpb.fat_size());
+            let first_data_block = BlockCount(u32::from(bpb.reserved_block_count()))
+                .checked_add(fat_region_size)
+                .ok_or(Error::FormatError("FAT region too large"))?;
 
             // Safe to unwrap since this is a Fat32 Type
             let info_location = bpb.fs_info_block().unwrap();
+            let info_location = lba_start
+                .checked_add(info_location)
+                .ok_or(Error::FormatError("Info sector beyond end of disk"))?;
             let mut info_blocks = [Block::new()];
             trace!("Reading info block");
             block_device
-                .read(&mut info_blocks, lba_start + info_location)
+                .read(&mut info_blocks, info_location)
                 .map_err(Error::DeviceError)?;
             let info_block = &info_blocks[0];
             let info_sector =
@@ -1242,13 +2217,16 @@ where
                     contents: bpb.volume_label(),
                 },
                 blocks_per_cluster: bpb.blocks_per_cluster(),
-                first_data_block: BlockCount(first_data_block),
+                first_data_block,
                 fat_start: BlockCount(u32::from(bpb.reserved_block_count())),
+                fat_size: BlockCount(bpb.fat_size()),
+                num_fats: bpb.num_fats(),
                 free_clusters_count: info_sector.free_clusters_count(),
                 next_free_cluster: info_sector.next_free_cluster(),
                 cluster_count: bpb.total_clusters(),
+                max_dir_scan_blocks: None,
                 fat_specific_info: FatSpecificInfo::Fat32(Fat32Info {
-                    info_location: lba_start + info_location,
+                    info_location,
                     first_root_dir_cluster: ClusterId(bpb.first_root_dir_cluster()),
                 }),
             };
@@ -1260,6 +2238,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{filesystem::Handle, RawDirectory, RawVolume};
 
     #[test]
     fn volume_name() {
@@ -1268,6 +2247,384 @@ mod tests {
         };
         assert_eq!(sfn, VolumeName::create_from_str("Hello £99").unwrap())
     }
+
+    /// A block device where every block contains the same data
+    struct RepeatingBlockDevice {
+        block: Block,
+        last_read: core::cell::Cell<Option<BlockIdx>>,
+    }
+
+    impl RepeatingBlockDevice {
+        fn new(block: Block) -> RepeatingBlockDevice {
+            RepeatingBlockDevice {
+                block,
+                last_read: core::cell::Cell::new(None),
+            }
+        }
+    }
+
+    impl BlockDevice for RepeatingBlockDevice {
+        type Error = ();
+
+        fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), ()> {
+            for block in blocks.iter_mut() {
+                block.contents = self.block.contents;
+            }
+            self.last_read.set(Some(start_block_idx));
+            Ok(())
+        }
+
+        fn write(&self, _blocks: &[Block], _start_block_idx: BlockIdx) -> Result<(), ()> {
+            panic!("RepeatingBlockDevice is read-only");
+        }
+
+        fn num_blocks(&self) -> Result<BlockCount, ()> {
+            Ok(BlockCount(u32::MAX))
+        }
+    }
+
+    /// Make a FAT32 BPB with 32 reserved blocks and two FATs.
+    fn make_fat32_bpb(fat_size: u32, total_blocks: u32) -> Block {
+        let mut block = Block::new();
+        LittleEndian::write_u16(&mut block[11..13], 512);
+        block[13] = 8;
+        LittleEndian::write_u16(&mut block[14..16], 32);
+        block[16] = 2;
+        LittleEndian::write_u32(&mut block[32..36], total_blocks);
+        LittleEndian::write_u32(&mut block[36..40], fat_size);
+        LittleEndian::write_u32(&mut block[44..48], 2);
+        LittleEndian::write_u16(&mut block[48..50], 1);
+        LittleEndian::write_u16(&mut block[510..512], Bpb::FOOTER_VALUE);
+        block
+    }
+
+    #[test]
+    fn bpb_fat_region_overflow() {
+        // Two FATs of this size cannot be counted in a u32
+        let block = make_fat32_bpb(0x8000_0001, 0x0100_0000);
+        assert!(Bpb::create_from_bytes(&block.contents).is_err());
+        let block_device = RepeatingBlockDevice::new(block);
+        let result = parse_volume(&block_device, BlockIdx(0), BlockCount(0x0100_0000));
+        assert!(matches!(result, Err(Error::FormatError(_))));
+    }
+
+    #[test]
+    fn bpb_fat_region_larger_than_volume() {
+        let block = make_fat32_bpb(0x4000, 0x4000);
+        assert!(Bpb::create_from_bytes(&block.contents).is_err());
+    }
+
+    #[test]
+    fn volume_beyond_end_of_disk() {
+        let block = make_fat32_bpb(0x4000, 0x0100_0000);
+        assert!(Bpb::create_from_bytes(&block.contents).is_ok());
+        let block_device = RepeatingBlockDevice::new(block);
+        let result = parse_volume(
+            &block_device,
+            BlockIdx(u32::MAX - 10),
+            BlockCount(0x0100_0000),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::FormatError("Volume extends beyond end of disk"))
+        ));
+    }
+
+    /// Make a FAT32 volume with the largest possible number of clusters.
+    fn make_huge_fat32_volume(lba_start: BlockIdx) -> FatVolume {
+        make_fat32_volume(lba_start, 0x0FFF_FFF0)
+    }
+
+    /// Make a FAT32 volume with one block per cluster and 32 reserved blocks.
+    fn make_fat32_volume(lba_start: BlockIdx, cluster_count: u32) -> FatVolume {
+        FatVolume {
+            lba_start,
+            num_blocks: BlockCount(u32::MAX - lba_start.0),
+            name: VolumeName {
+                contents: *b"NO NAME    ",
+            },
+            blocks_per_cluster: 1,
+            first_data_block: BlockCount(32 + (2 * 0x0020_0000)),
+            fat_start: BlockCount(32),
+            fat_size: BlockCount(0x0020_0000),
+            num_fats: 2,
+            free_clusters_count: None,
+            next_free_cluster: None,
+            cluster_count,
+            max_dir_scan_blocks: None,
+            fat_specific_info: FatSpecificInfo::Fat32(Fat32Info {
+                first_root_dir_cluster: ClusterId(2),
+                info_location: lba_start + BlockCount(1),
+            }),
+        }
+    }
+
+    #[test]
+    fn fat32_high_cluster_location() {
+        let mut block = Block::new();
+        // Cluster 0x0FFF_FFEF is at byte 0x3FFF_FFBC of the FAT, which is
+        // block 0x1F_FFFF, offset 0x1BC.
+        LittleEndian::write_u32(&mut block[0x1BC..0x1C0], 0x0000_1234);
+        let block_device = RepeatingBlockDevice::new(block);
+        // The byte offset of this FAT entry from the start of the disk is
+        // well above `u32::MAX`.
+        let volume = make_huge_fat32_volume(BlockIdx(0x0100_0000));
+        let mut block_cache = BlockCache::empty();
+        let next = volume
+            .next_cluster(&block_device, ClusterId(0x0FFF_FFEF), &mut block_cache)
+            .unwrap();
+        assert_eq!(next, ClusterId(0x0000_1234));
+        assert_eq!(
+            block_device.last_read.get(),
+            Some(BlockIdx(0x0100_0000 + 32 + 0x1F_FFFF))
+        );
+    }
+
+    #[test]
+    fn fat32_cluster_location_overflow() {
+        let block_device = RepeatingBlockDevice::new(Block::new());
+        // The FAT entry for this cluster would be beyond block `u32::MAX`
+        let volume = make_huge_fat32_volume(BlockIdx(u32::MAX - 0x1000));
+        let mut block_cache = BlockCache::empty();
+        let result = volume.next_cluster(&block_device, ClusterId(0x0FFF_FFEF), &mut block_cache);
+        assert!(matches!(result, Err(Error::ConversionError)));
+        assert_eq!(block_device.last_read.get(), None);
+    }
+
+    #[test]
+    fn next_cluster_out_of_range() {
+        let mut block = Block::new();
+        // Cluster 2 points at cluster 1001, cluster 3 at cluster 1000.
+        LittleEndian::write_u32(&mut block[8..12], 1001);
+        LittleEndian::write_u32(&mut block[12..16], 1000);
+        let block_device = RepeatingBlockDevice::new(block.clone());
+        // Clusters 2 to 1001 hold data.
+        let volume = make_fat32_volume(BlockIdx(0), 1000);
+        let mut block_cache = BlockCache::empty();
+        for cluster in [
+            ClusterId(0),
+            ClusterId(1),
+            ClusterId(1002),
+            ClusterId::ROOT_DIR,
+        ] {
+            assert!(matches!(
+                volume.next_cluster(&block_device, cluster, &mut block_cache),
+                Err(Error::BadCluster)
+            ));
+        }
+        // We didn't look in the FAT for any of those
+        assert_eq!(block_device.last_read.get(), None);
+        assert_eq!(
+            volume
+                .next_cluster(&block_device, ClusterId(3), &mut block_cache)
+                .unwrap(),
+            ClusterId(1000)
+        );
+        assert_eq!(
+            volume
+                .next_cluster(&block_device, ClusterId(2), &mut block_cache)
+                .unwrap(),
+            ClusterId(1001)
+        );
+        // The FAT pointing off the end of the volume is as bad as us asking
+        LittleEndian::write_u32(&mut block[8..12], 1002);
+        let block_device = RepeatingBlockDevice::new(block);
+        let mut block_cache = BlockCache::empty();
+        assert!(matches!(
+            volume.next_cluster(&block_device, ClusterId(2), &mut block_cache),
+            Err(Error::BadCluster)
+        ));
+    }
+
+    /// Make a FAT block where entry `n` points at entry `n + 1`, and the last
+    /// entry holds `last_entry`.
+    ///
+    /// Every block of the FAT is the same, so the chain starting at cluster 2
+    /// is contiguous, but chains starting in later FAT blocks jump back to
+    /// cluster 3.
+    fn make_chain_fat_block(last_entry: u32) -> Block {
+        let mut block = Block::new();
+        for (n, entry) in block.chunks_exact_mut(4).enumerate() {
+            LittleEndian::write_u32(entry, n as u32 + 1);
+        }
+        LittleEndian::write_u32(&mut block[508..512], last_entry);
+        block
+    }
+
+    #[test]
+    fn cluster_chain_len() {
+        let block_device = RepeatingBlockDevice::new(make_chain_fat_block(0x0FFF_FFFF));
+        let volume = make_fat32_volume(BlockIdx(0), 1000);
+        assert_eq!(
+            volume
+                .cluster_chain_len(&block_device, ClusterId(2))
+                .unwrap(),
+            126
+        );
+        assert!(volume.is_contiguous(&block_device, ClusterId(2)).unwrap());
+        // Cluster 130 is entry 2 of the second FAT block, so it jumps to 3
+        assert_eq!(
+            volume
+                .cluster_chain_len(&block_device, ClusterId(130))
+                .unwrap(),
+            126
+        );
+        assert!(!volume.is_contiguous(&block_device, ClusterId(130)).unwrap());
+        assert_eq!(
+            volume
+                .cluster_chain_len(&block_device, ClusterId(127))
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            volume
+                .cluster_chain_len(&block_device, ClusterId::EMPTY)
+                .unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn cyclic_cluster_chain() {
+        // Cluster 127 points back to cluster 2
+        let block_device = RepeatingBlockDevice::new(make_chain_fat_block(2));
+        let volume = make_fat32_volume(BlockIdx(0), 1000);
+        assert!(matches!(
+            volume.cluster_chain_len(&block_device, ClusterId(2)),
+            Err(Error::FormatError(_))
+        ));
+        assert!(matches!(
+            volume.is_contiguous(&block_device, ClusterId(2)),
+            Err(Error::FormatError(_))
+        ));
+    }
+
+    /// A FAT32 root directory holding `bcm2708-rpi-b-plus.dtb`, with its
+    /// short name `BCM270~1.DTB`.
+    fn make_lfn_dir_block() -> Block {
+        let entries = [
+            "422d0070006c00750073000f00792e006400740062000000ffff0000ffffffff",
+            "01620063006d00320037000f0079300038002d0072007000690000002d006200",
+            "42434d3237307e31445442200064119f614861480000119f61480900702b0000",
+        ];
+        let mut block = Block::new();
+        for (entry, chunk) in entries.iter().zip(block.chunks_exact_mut(32)) {
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&entry[i * 2..i * 2 + 2], 16).unwrap();
+            }
+        }
+        block
+    }
+
+    /// A FAT32 volume, and the root directory on it that
+    /// [`make_lfn_dir_block`] belongs to.
+    fn root_dir_info() -> (FatVolume, DirectoryInfo) {
+        let dir_info = DirectoryInfo {
+            raw_directory: RawDirectory(Handle(0)),
+            raw_volume: RawVolume(Handle(1)),
+            cluster: ClusterId::ROOT_DIR,
+        };
+        (make_fat32_volume(BlockIdx(0), 1000), dir_info)
+    }
+
+    #[test]
+    fn find_lfn_entry() {
+        let block_device = RepeatingBlockDevice::new(make_lfn_dir_block());
+        let (volume, dir_info) = root_dir_info();
+        let expected = ShortFileName::create_from_str("BCM270~1.DTB").unwrap();
+        for name in ["bcm2708-rpi-b-plus.dtb", "BCM2708-RPI-B-Plus.DTB"] {
+            let entry = volume
+                .find_lfn_directory_entry(&block_device, &dir_info, name)
+                .unwrap();
+            assert_eq!(entry.name, expected);
+            assert_eq!(entry.entry_offset, 64);
+        }
+        for name in [
+            "bcm2708-rpi-b-plus.dt",
+            "bcm2708-rpi-b-plus.dtbx",
+            "bcm2708-rpi-b.dtb",
+        ] {
+            assert!(matches!(
+                volume.find_lfn_directory_entry(&block_device, &dir_info, name),
+                Err(Error::NotFound)
+            ));
+        }
+    }
+
+    #[test]
+    fn find_lfn_entry_surrogate_pair() {
+        let (volume, dir_info) = root_dir_info();
+        // Swap the "us" in "-plus.dtb" for a character outside the BMP, which
+        // takes two UTF-16 code units
+        let mut block = make_lfn_dir_block();
+        block[7..9].copy_from_slice(&0xD83Du16.to_le_bytes());
+        block[9..11].copy_from_slice(&0xDE00u16.to_le_bytes());
+        let block_device = RepeatingBlockDevice::new(block);
+        let entry = volume
+            .find_lfn_directory_entry(&block_device, &dir_info, "bcm2708-rpi-b-pl\u{1F600}.dtb")
+            .unwrap();
+        assert_eq!(entry.entry_offset, 64);
+        assert!(matches!(
+            volume.find_lfn_directory_entry(
+                &block_device,
+                &dir_info,
+                "bcm2708-rpi-b-pl\u{1F601}.dtb"
+            ),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn lfn_checksum() {
+        let block = make_lfn_dir_block();
+        let name = ShortFileName::create_from_str("BCM270~1.DTB").unwrap();
+        let checksum = super::lfn_checksum(&name);
+        assert_eq!(checksum, 0x79);
+        for lfn_entry in block.chunks_exact(OnDiskDirEntry::LEN).take(2) {
+            assert_eq!(
+                OnDiskDirEntry::new(lfn_entry).lfn_checksum(),
+                Some(checksum)
+            );
+        }
+        let short_entry = &block[2 * OnDiskDirEntry::LEN..3 * OnDiskDirEntry::LEN];
+        assert_eq!(OnDiskDirEntry::new(short_entry).lfn_checksum(), None);
+    }
+
+    #[test]
+    fn find_lfn_entry_bad_checksum() {
+        let (volume, dir_info) = root_dir_info();
+        // Corrupt the checksum in the first LFN entry, then in the second
+        for entry_idx in 0..2 {
+            let mut block = make_lfn_dir_block();
+            block[(entry_idx * OnDiskDirEntry::LEN) + 13] ^= 0x01;
+            let block_device = RepeatingBlockDevice::new(block);
+            assert!(matches!(
+                volume.find_lfn_directory_entry(&block_device, &dir_info, "bcm2708-rpi-b-plus.dtb"),
+                Err(Error::NotFound)
+            ));
+        }
+        // Or rename the short entry, orphaning the LFN entries
+        let mut block = make_lfn_dir_block();
+        block[(2 * OnDiskDirEntry::LEN) + 7] = b'2';
+        let block_device = RepeatingBlockDevice::new(block);
+        assert!(matches!(
+            volume.find_lfn_directory_entry(&block_device, &dir_info, "bcm2708-rpi-b-plus.dtb"),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn find_lfn_entry_out_of_sequence() {
+        // Delete the first LFN entry, which leaves the second one orphaned
+        let mut block = make_lfn_dir_block();
+        block[32] = 0xE5;
+        let block_device = RepeatingBlockDevice::new(block);
+        let (volume, dir_info) = root_dir_info();
+        assert!(matches!(
+            volume.find_lfn_directory_entry(&block_device, &dir_info, "bcm2708-rpi-b-plus.dtb"),
+            Err(Error::NotFound)
+        ));
+    }
 }
 
 // ****************************************************************************
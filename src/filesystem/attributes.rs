@@ -1,6 +1,5 @@
 /// Indicates whether a directory entry is read-only, a directory, a volume
 /// label, etc.
-#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Attributes(pub(crate) u8);
 
@@ -28,6 +27,51 @@ impl Attributes {
         Attributes(value)
     }
 
+    /// Create an `Attributes` value with no flags set.
+    ///
+    /// Use the builder methods to set flags:
+    ///
+    /// ```
+    /// # use embedded_sdmmc::Attributes;
+    /// let attributes = Attributes::new().directory().hidden();
+    /// assert!(attributes.is_directory());
+    /// assert!(attributes.is_hidden());
+    /// assert!(!attributes.is_read_only());
+    /// ```
+    pub const fn new() -> Attributes {
+        Attributes(0)
+    }
+
+    /// Get the `u8` stored in a FAT16/FAT32 Directory Entry.
+    pub const fn to_fat(self) -> u8 {
+        self.0
+    }
+
+    /// Set the read-only flag.
+    pub const fn read_only(self) -> Attributes {
+        Attributes(self.0 | Self::READ_ONLY)
+    }
+
+    /// Set the hidden flag.
+    pub const fn hidden(self) -> Attributes {
+        Attributes(self.0 | Self::HIDDEN)
+    }
+
+    /// Set the system flag.
+    pub const fn system(self) -> Attributes {
+        Attributes(self.0 | Self::SYSTEM)
+    }
+
+    /// Set the directory flag.
+    pub const fn directory(self) -> Attributes {
+        Attributes(self.0 | Self::DIRECTORY)
+    }
+
+    /// Set the archive flag.
+    pub const fn archive(self) -> Attributes {
+        Attributes(self.0 | Self::ARCHIVE)
+    }
+
     pub(crate) fn set_archive(&mut self, flag: bool) {
         let archive = if flag { 0x20 } else { 0x00 };
         self.0 |= archive;
@@ -67,35 +111,73 @@ impl Attributes {
     pub fn is_lfn(self) -> bool {
         (self.0 & Self::LFN) == Self::LFN
     }
+
+    /// Spell out these attributes as letters, like `DRHSVA` or `LFN`.
+    fn letters(self, buf: &mut [u8; 6]) -> &str {
+        if self.is_lfn() {
+            return "LFN";
+        }
+        let flags = [
+            (true, if self.is_directory() { b'D' } else { b'F' }),
+            (self.is_read_only(), b'R'),
+            (self.is_hidden(), b'H'),
+            (self.is_system(), b'S'),
+            (self.is_volume(), b'V'),
+            (self.is_archive(), b'A'),
+        ];
+        let mut len = 0;
+        for (_, letter) in flags.iter().filter(|(set, _)| *set) {
+            buf[len] = *letter;
+            len += 1;
+        }
+        // Everything we wrote is ASCII
+        core::str::from_utf8(&buf[..len]).unwrap()
+    }
 }
 
 impl core::fmt::Debug for Attributes {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
-        if self.is_lfn() {
-            write!(f, "LFN")?;
-        } else {
-            if self.is_directory() {
-                write!(f, "D")?;
-            } else {
-                write!(f, "F")?;
-            }
-            if self.is_read_only() {
-                write!(f, "R")?;
-            }
-            if self.is_hidden() {
-                write!(f, "H")?;
-            }
-            if self.is_system() {
-                write!(f, "S")?;
-            }
-            if self.is_volume() {
-                write!(f, "V")?;
-            }
-            if self.is_archive() {
-                write!(f, "A")?;
-            }
-        }
-        Ok(())
+        f.write_str(self.letters(&mut [0; 6]))
+    }
+}
+
+#[cfg(feature = "defmt-log")]
+impl defmt::Format for Attributes {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.letters(&mut [0; 6]))
+    }
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        assert_eq!(Attributes::new().to_fat(), 0x00);
+        assert_eq!(Attributes::new().directory().to_fat(), 0x10);
+        assert_eq!(Attributes::new().read_only().archive().to_fat(), 0x21);
+        assert_eq!(
+            Attributes::new()
+                .directory()
+                .read_only()
+                .hidden()
+                .system()
+                .to_fat(),
+            0x17
+        );
+        // Setting a flag twice is harmless
+        assert_eq!(Attributes::new().hidden().hidden().to_fat(), 0x02);
+        assert_eq!(
+            Attributes::new().read_only().hidden().system().archive(),
+            Attributes::create_from_fat(0x27)
+        );
     }
 }
 
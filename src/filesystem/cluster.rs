@@ -18,6 +18,55 @@ impl ClusterId {
     pub const ROOT_DIR: ClusterId = ClusterId(0xFFFF_FFFC);
     /// Magic value indicating that the cluster is allocated and is the final cluster for the file
     pub const END_OF_FILE: ClusterId = ClusterId(0xFFFF_FFFF);
+
+    /// Is this the end-of-chain marker, which follows the last cluster of a
+    /// file or directory?
+    ///
+    /// As well as [`ClusterId::END_OF_FILE`], this accepts the values a FAT16
+    /// (`0xFFF8` and above) or FAT32 (`0x0FFF_FFF8` and above) table stores
+    /// on disk. Note that on a large FAT32 volume, `0xFFF8` can also be the
+    /// number of a real cluster.
+    pub const fn is_end_of_chain(self) -> bool {
+        matches!(self.0, 0xFFF8..=0xFFFF | 0x0FFF_FFF8..=0x0FFF_FFFF)
+            || self.0 == Self::END_OF_FILE.0
+    }
+
+    /// Is this the empty cluster?
+    ///
+    /// In the FAT, this marks a free cluster. In a directory entry, it means
+    /// the file has no clusters allocated to it (because it is empty).
+    pub const fn is_free(self) -> bool {
+        self.0 == Self::EMPTY.0
+    }
+
+    /// Is this the marker for a bad cluster?
+    ///
+    /// As well as [`ClusterId::BAD`], this accepts the values a FAT16
+    /// (`0xFFF7`) or FAT32 (`0x0FFF_FFF7`) table stores on disk.
+    pub const fn is_bad(self) -> bool {
+        matches!(self.0, 0xFFF7 | 0x0FFF_FFF7) || self.0 == Self::BAD.0
+    }
+
+    /// Is this one of the magic values above, rather than the number of a
+    /// cluster holding data?
+    pub const fn is_special(self) -> bool {
+        matches!(
+            self,
+            Self::INVALID | Self::BAD | Self::EMPTY | Self::ROOT_DIR | Self::END_OF_FILE
+        )
+    }
+}
+
+impl From<u32> for ClusterId {
+    fn from(value: u32) -> ClusterId {
+        ClusterId(value)
+    }
+}
+
+impl From<ClusterId> for u32 {
+    fn from(value: ClusterId) -> u32 {
+        value.0
+    }
 }
 
 impl core::ops::Add<u32> for ClusterId {
@@ -61,6 +110,74 @@ impl core::fmt::Debug for ClusterId {
     }
 }
 
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn end_of_chain() {
+        assert!(ClusterId::END_OF_FILE.is_end_of_chain());
+        assert!(!ClusterId::BAD.is_end_of_chain());
+        assert!(!ClusterId::EMPTY.is_end_of_chain());
+        assert!(!ClusterId(2).is_end_of_chain());
+        // On-disk values from FAT16 and FAT32 tables
+        for raw in [0xFFF8, 0xFFFF, 0x0FFF_FFF8, 0x0FFF_FFFF] {
+            assert!(ClusterId(raw).is_end_of_chain(), "{:#x}", raw);
+        }
+        assert!(!ClusterId(0xFFF7).is_end_of_chain());
+        assert!(!ClusterId(0x0FFF_FFF7).is_end_of_chain());
+        assert!(!ClusterId::ROOT_DIR.is_end_of_chain());
+    }
+
+    #[test]
+    fn free() {
+        assert!(ClusterId::EMPTY.is_free());
+        assert!(!ClusterId::END_OF_FILE.is_free());
+        assert!(!ClusterId::ROOT_DIR.is_free());
+        assert!(!ClusterId(2).is_free());
+    }
+
+    #[test]
+    fn bad() {
+        assert!(ClusterId::BAD.is_bad());
+        assert!(!ClusterId::INVALID.is_bad());
+        assert!(!ClusterId::END_OF_FILE.is_bad());
+        // On-disk values from FAT16 and FAT32 tables
+        assert!(ClusterId(0xFFF7).is_bad());
+        assert!(ClusterId(0x0FFF_FFF7).is_bad());
+        assert!(!ClusterId(0xFFF8).is_bad());
+        assert!(!ClusterId(0x0FFF_FFF8).is_bad());
+    }
+
+    #[test]
+    fn special() {
+        for id in [
+            ClusterId::INVALID,
+            ClusterId::BAD,
+            ClusterId::EMPTY,
+            ClusterId::ROOT_DIR,
+            ClusterId::END_OF_FILE,
+        ] {
+            assert!(id.is_special(), "{:?}", id);
+        }
+        assert!(!ClusterId(2).is_special());
+        assert!(!ClusterId(0x0FFF_FFEF).is_special());
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(ClusterId::from(1234), ClusterId(1234));
+        assert_eq!(u32::from(ClusterId(1234)), 1234);
+        assert_eq!(u32::from(ClusterId::END_OF_FILE), 0xFFFF_FFFF);
+    }
+}
+
 // ****************************************************************************
 //
 // End Of File
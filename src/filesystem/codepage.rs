@@ -0,0 +1,186 @@
+//! Code pages for short file names
+
+/// How the bytes of a short file name map to Unicode characters.
+///
+/// ASCII bytes (`0x00` to `0x7F`) are the same in every code page. The bytes
+/// above that depend on the OEM code page of the computer which wrote the
+/// name. Long file names are stored as UCS-2 and don't need one.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CodePage {
+    /// The original IBM PC code page, used by MS-DOS in the US. This is the
+    /// default.
+    #[default]
+    Cp437,
+    /// The MS-DOS Western European code page.
+    Cp850,
+    /// ISO-8859-1, where each byte is the Unicode code point of the same
+    /// value.
+    Iso8859_1,
+}
+
+impl CodePage {
+    /// Convert a byte from a short file name into a character.
+    pub fn to_char(self, byte: u8) -> char {
+        if byte < 0x80 {
+            return char::from(byte);
+        }
+        let idx = usize::from(byte - 0x80);
+        match self {
+            CodePage::Cp437 => CP437_HIGH[idx],
+            CodePage::Cp850 => CP850_HIGH[idx],
+            CodePage::Iso8859_1 => char::from(byte),
+        }
+    }
+
+    /// Convert a character into a byte for a short file name.
+    ///
+    /// Returns `None` if this code page can't represent the character.
+    pub fn from_char(self, ch: char) -> Option<u8> {
+        if ch.is_ascii() {
+            return Some(ch as u8);
+        }
+        let table = match self {
+            CodePage::Cp437 => &CP437_HIGH,
+            CodePage::Cp850 => &CP850_HIGH,
+            CodePage::Iso8859_1 => return u8::try_from(u32::from(ch)).ok(),
+        };
+        let idx = table.iter().position(|&c| c == ch)?;
+        Some(0x80 + idx as u8)
+    }
+
+    /// Convert a byte from a short file name to upper-case.
+    ///
+    /// The byte is unchanged if it isn't a lower-case letter, or if this
+    /// code page doesn't have the upper-case letter.
+    pub fn to_uppercase(self, byte: u8) -> u8 {
+        let mut upper = self.to_char(byte).to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(ch), None) => self.from_char(ch).unwrap_or(byte),
+            _ => byte,
+        }
+    }
+
+    /// Convert a byte from a short file name to lower-case.
+    ///
+    /// The byte is unchanged if it isn't an upper-case letter, or if this
+    /// code page doesn't have the lower-case letter.
+    pub fn to_lowercase(self, byte: u8) -> u8 {
+        let mut lower = self.to_char(byte).to_lowercase();
+        match (lower.next(), lower.next()) {
+            (Some(ch), None) => self.from_char(ch).unwrap_or(byte),
+            _ => byte,
+        }
+    }
+}
+
+/// Bytes `0x80` to `0xFF` of code page 437.
+const CP437_HIGH: [char; 128] = [
+    // 0x80
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    // 0x90
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00A2}', '\u{00A3}', '\u{00A5}', '\u{20A7}', '\u{0192}',
+    // 0xA0
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{2310}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    // 0xB0
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{2561}', '\u{2562}', '\u{2556}',
+    '\u{2555}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{255C}', '\u{255B}', '\u{2510}',
+    // 0xC0
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{255E}', '\u{255F}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{2567}',
+    // 0xD0
+    '\u{2568}', '\u{2564}', '\u{2565}', '\u{2559}', '\u{2558}', '\u{2552}', '\u{2553}', '\u{256B}',
+    '\u{256A}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{258C}', '\u{2590}', '\u{2580}',
+    // 0xE0
+    '\u{03B1}', '\u{00DF}', '\u{0393}', '\u{03C0}', '\u{03A3}', '\u{03C3}', '\u{00B5}', '\u{03C4}',
+    '\u{03A6}', '\u{0398}', '\u{03A9}', '\u{03B4}', '\u{221E}', '\u{03C6}', '\u{03B5}', '\u{2229}',
+    // 0xF0
+    '\u{2261}', '\u{00B1}', '\u{2265}', '\u{2264}', '\u{2320}', '\u{2321}', '\u{00F7}', '\u{2248}',
+    '\u{00B0}', '\u{2219}', '\u{00B7}', '\u{221A}', '\u{207F}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
+/// Bytes `0x80` to `0xFF` of code page 850.
+const CP850_HIGH: [char; 128] = [
+    // 0x80
+    '\u{00C7}', '\u{00FC}', '\u{00E9}', '\u{00E2}', '\u{00E4}', '\u{00E0}', '\u{00E5}', '\u{00E7}',
+    '\u{00EA}', '\u{00EB}', '\u{00E8}', '\u{00EF}', '\u{00EE}', '\u{00EC}', '\u{00C4}', '\u{00C5}',
+    // 0x90
+    '\u{00C9}', '\u{00E6}', '\u{00C6}', '\u{00F4}', '\u{00F6}', '\u{00F2}', '\u{00FB}', '\u{00F9}',
+    '\u{00FF}', '\u{00D6}', '\u{00DC}', '\u{00F8}', '\u{00A3}', '\u{00D8}', '\u{00D7}', '\u{0192}',
+    // 0xA0
+    '\u{00E1}', '\u{00ED}', '\u{00F3}', '\u{00FA}', '\u{00F1}', '\u{00D1}', '\u{00AA}', '\u{00BA}',
+    '\u{00BF}', '\u{00AE}', '\u{00AC}', '\u{00BD}', '\u{00BC}', '\u{00A1}', '\u{00AB}', '\u{00BB}',
+    // 0xB0
+    '\u{2591}', '\u{2592}', '\u{2593}', '\u{2502}', '\u{2524}', '\u{00C1}', '\u{00C2}', '\u{00C0}',
+    '\u{00A9}', '\u{2563}', '\u{2551}', '\u{2557}', '\u{255D}', '\u{00A2}', '\u{00A5}', '\u{2510}',
+    // 0xC0
+    '\u{2514}', '\u{2534}', '\u{252C}', '\u{251C}', '\u{2500}', '\u{253C}', '\u{00E3}', '\u{00C3}',
+    '\u{255A}', '\u{2554}', '\u{2569}', '\u{2566}', '\u{2560}', '\u{2550}', '\u{256C}', '\u{00A4}',
+    // 0xD0
+    '\u{00F0}', '\u{00D0}', '\u{00CA}', '\u{00CB}', '\u{00C8}', '\u{0131}', '\u{00CD}', '\u{00CE}',
+    '\u{00CF}', '\u{2518}', '\u{250C}', '\u{2588}', '\u{2584}', '\u{00A6}', '\u{00CC}', '\u{2580}',
+    // 0xE0
+    '\u{00D3}', '\u{00DF}', '\u{00D4}', '\u{00D2}', '\u{00F5}', '\u{00D5}', '\u{00B5}', '\u{00FE}',
+    '\u{00DE}', '\u{00DA}', '\u{00DB}', '\u{00D9}', '\u{00FD}', '\u{00DD}', '\u{00AF}', '\u{00B4}',
+    // 0xF0
+    '\u{00AD}', '\u{00B1}', '\u{2017}', '\u{00BE}', '\u{00B6}', '\u{00A7}', '\u{00F7}', '\u{00B8}',
+    '\u{00B0}', '\u{00A8}', '\u{00B7}', '\u{00B9}', '\u{00B3}', '\u{00B2}', '\u{25A0}', '\u{00A0}',
+];
+
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        for code_page in [CodePage::Cp437, CodePage::Cp850, CodePage::Iso8859_1] {
+            for byte in 0..=255u8 {
+                assert_eq!(code_page.from_char(code_page.to_char(byte)), Some(byte));
+            }
+        }
+    }
+
+    #[test]
+    fn high_bytes() {
+        assert_eq!(CodePage::Cp437.to_char(0x81), '\u{00FC}');
+        assert_eq!(CodePage::Cp437.to_char(0x9B), '\u{00A2}');
+        assert_eq!(CodePage::Cp850.to_char(0x9B), '\u{00F8}');
+        assert_eq!(CodePage::Iso8859_1.to_char(0x9B), '\u{009B}');
+        // Not every code page has every character
+        assert_eq!(CodePage::Cp437.from_char('\u{00F8}'), None);
+        assert_eq!(CodePage::Cp850.from_char('\u{00F8}'), Some(0x9B));
+        assert_eq!(CodePage::Iso8859_1.from_char('\u{20AC}'), None);
+    }
+
+    #[test]
+    fn change_case() {
+        // u with diaeresis
+        assert_eq!(CodePage::Cp437.to_uppercase(0x81), 0x9A);
+        assert_eq!(CodePage::Cp437.to_lowercase(0x9A), 0x81);
+        assert_eq!(CodePage::Cp850.to_uppercase(0x81), 0x9A);
+        assert_eq!(CodePage::Iso8859_1.to_uppercase(0xFC), 0xDC);
+        // Code page 850 has o with stroke in both cases, 437 has neither
+        assert_eq!(CodePage::Cp850.to_uppercase(0x9B), 0x9D);
+        // y with diaeresis has no upper-case form in code page 437
+        assert_eq!(CodePage::Cp437.to_uppercase(0x98), 0x98);
+        // Sharp s would be two letters in upper-case
+        assert_eq!(CodePage::Cp437.to_uppercase(0xE1), 0xE1);
+        assert_eq!(CodePage::Cp437.to_uppercase(b'a'), b'A');
+        assert_eq!(CodePage::Cp437.to_lowercase(b'1'), b'1');
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -1,6 +1,8 @@
 use crate::blockdevice::BlockIdx;
-use crate::fat::{FatType, OnDiskDirEntry};
-use crate::filesystem::{Attributes, ClusterId, Handle, ShortFileName, Timestamp};
+use crate::fat::{DirPosition, FatType, OnDiskDirEntry};
+use crate::filesystem::{
+    Attributes, ClusterId, Handle, ShortFileName, ShortFileNamePattern, Timestamp,
+};
 use crate::{Error, RawVolume, VolumeManager};
 
 use super::ToShortFileName;
@@ -15,6 +17,9 @@ pub struct DirEntry {
     pub mtime: Timestamp,
     /// When the file was first created
     pub ctime: Timestamp,
+    /// When the file was last accessed. FAT only records the date, so the
+    /// time is always midnight.
+    pub atime: Timestamp,
     /// The file attributes (Read Only, Archive, etc)
     pub attributes: Attributes,
     /// The starting cluster of the file. The FAT tells us the following Clusters.
@@ -27,6 +32,52 @@ pub struct DirEntry {
     pub entry_offset: u32,
 }
 
+/// The kind of thing a directory entry refers to.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    /// The entry is a file
+    File,
+    /// The entry is a directory
+    Directory,
+}
+
+/// What [`VolumeManager::walk_dir`] should do after visiting an entry.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WalkControl {
+    /// Keep walking, descending into this entry if it is a directory
+    Continue,
+    /// Keep walking, but don't descend into this entry
+    SkipDir,
+    /// Stop walking
+    Stop,
+}
+
+/// Where a [`VolumeManager::find_first`] search has got to.
+///
+/// Pass this to [`VolumeManager::find_next`] to get the next matching entry.
+/// It doesn't hold the directory open, or borrow the Volume Manager, so you
+/// are free to do other work between calls.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirScan {
+    /// The directory we are searching
+    pub(crate) directory: RawDirectory,
+    /// What we are searching for
+    pub(crate) pattern: ShortFileNamePattern,
+    /// Where to carry on reading the directory from, or `None` once we have
+    /// reached the end
+    pub(crate) next: Option<DirPosition>,
+}
+
+impl DirScan {
+    /// Get the directory this search is looking in.
+    pub fn directory(&self) -> RawDirectory {
+        self.directory
+    }
+}
+
 /// A handle for an open directory on disk.
 ///
 /// Do NOT drop this object! It doesn't hold a reference to the Volume Manager
@@ -55,10 +106,11 @@ impl RawDirectory {
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
     >(
         self,
-        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
     where
         D: crate::BlockDevice,
         T: crate::TimeSource,
@@ -82,16 +134,24 @@ pub struct Directory<
     const MAX_DIRS: usize,
     const MAX_FILES: usize,
     const MAX_VOLUMES: usize,
+    const MAX_CHECKPOINTS: usize = 0,
 > where
     D: crate::BlockDevice,
     T: crate::TimeSource,
 {
     raw_directory: RawDirectory,
-    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -99,8 +159,8 @@ where
     /// Create a new `Directory` from a `RawDirectory`
     pub fn new(
         raw_directory: RawDirectory,
-        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
+        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
         Directory {
             raw_directory,
             volume_mgr,
@@ -113,7 +173,10 @@ where
     pub fn open_dir<N>(
         &self,
         name: N,
-    ) -> Result<Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, Error<D::Error>>
+    ) -> Result<
+        Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        Error<D::Error>,
+    >
     where
         N: ToShortFileName,
     {
@@ -121,6 +184,42 @@ where
         Ok(d.to_directory(self.volume_mgr))
     }
 
+    /// Get the directory entry for this directory.
+    ///
+    /// See [`VolumeManager::dir_metadata`].
+    pub fn metadata(&self) -> Result<DirEntry, Error<D::Error>> {
+        self.volume_mgr.dir_metadata(self.raw_directory)
+    }
+
+    /// Open a directory using an entry found in this directory.
+    ///
+    /// This avoids scanning this directory again to find the entry by name.
+    pub fn open_dir_from_entry(
+        &self,
+        entry: &DirEntry,
+    ) -> Result<
+        Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        Error<D::Error>,
+    > {
+        let d = self
+            .volume_mgr
+            .open_dir_from_entry(self.raw_directory, entry)?;
+        Ok(d.to_directory(self.volume_mgr))
+    }
+
+    /// Open the parent of this directory.
+    ///
+    /// The parent of the root directory is the root directory.
+    pub fn open_parent_dir(
+        &self,
+    ) -> Result<
+        Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        Error<D::Error>,
+    > {
+        let d = self.volume_mgr.open_parent_dir(self.raw_directory)?;
+        Ok(d.to_directory(self.volume_mgr))
+    }
+
     /// Change to a directory, mutating this object.
     ///
     /// You can then read the directory entries with `iterate_dir` and `open_file_in_dir`.
@@ -143,6 +242,46 @@ where
             .find_directory_entry(self.raw_directory, name)
     }
 
+    /// Walk this directory and its sub-directories, depth first.
+    ///
+    /// See [`VolumeManager::walk_dir`] for details.
+    pub fn walk_dir<F>(&self, max_depth: usize, func: F) -> Result<(), Error<D::Error>>
+    where
+        F: FnMut(&[ShortFileName], &DirEntry) -> WalkControl,
+    {
+        self.volume_mgr
+            .walk_dir(self.raw_directory, max_depth, func)
+    }
+
+    /// Does this directory contain an entry with the given name?
+    pub fn exists<N>(&self, name: N) -> Result<bool, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        self.volume_mgr.exists(self.raw_directory, name)
+    }
+
+    /// Pick a short file name for a new entry in this directory.
+    ///
+    /// See [`VolumeManager::generate_unique_short_name`] for details.
+    pub fn generate_unique_short_name<N>(&self, name: N) -> Result<ShortFileName, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        self.volume_mgr
+            .generate_unique_short_name(self.raw_directory, name)
+    }
+
+    /// Is the named entry in this directory a file or a directory?
+    ///
+    /// Returns `Ok(None)` if there is no such entry.
+    pub fn entry_kind<N>(&self, name: N) -> Result<Option<EntryKind>, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        self.volume_mgr.entry_kind(self.raw_directory, name)
+    }
+
     /// Call a callback function for each directory entry in a directory.
     ///
     /// <div class="warning">
@@ -159,12 +298,53 @@ where
         self.volume_mgr.iterate_dir(self.raw_directory, func)
     }
 
+    /// Copy directory entries into `out`, until it is full or we reach the
+    /// end of the directory, and return how many were copied.
+    ///
+    /// See [`VolumeManager::collect_entries`] for details.
+    pub fn collect_entries(&self, out: &mut [Option<DirEntry>]) -> Result<usize, Error<D::Error>> {
+        self.volume_mgr.collect_entries(self.raw_directory, out)
+    }
+
+    /// Call a callback function for each directory entry whose name matches
+    /// a wildcard pattern, such as `*.TXT`.
+    ///
+    /// See [`VolumeManager::iterate_dir_glob`] for details.
+    pub fn iterate_dir_glob<F>(&self, pattern: &str, func: F) -> Result<(), Error<D::Error>>
+    where
+        F: FnMut(&DirEntry),
+    {
+        self.volume_mgr
+            .iterate_dir_glob(self.raw_directory, pattern, func)
+    }
+
+    /// Start a search for entries whose name matches a wildcard pattern,
+    /// such as `*.TXT`.
+    ///
+    /// See [`VolumeManager::find_first`] for details.
+    pub fn find_first(
+        &self,
+        pattern: &str,
+    ) -> Result<(DirScan, Option<DirEntry>), Error<D::Error>> {
+        self.volume_mgr.find_first(self.raw_directory, pattern)
+    }
+
+    /// Carry on a search started with [`Directory::find_first`].
+    ///
+    /// See [`VolumeManager::find_next`] for details.
+    pub fn find_next(&self, scan: &mut DirScan) -> Result<Option<DirEntry>, Error<D::Error>> {
+        self.volume_mgr.find_next(scan)
+    }
+
     /// Open a file with the given full path. A file can only be opened once.
     pub fn open_file_in_dir<N>(
         &self,
         name: N,
         mode: crate::Mode,
-    ) -> Result<crate::File<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, crate::Error<D::Error>>
+    ) -> Result<
+        crate::File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        crate::Error<D::Error>,
+    >
     where
         N: super::ToShortFileName,
     {
@@ -174,6 +354,80 @@ where
         Ok(f.to_file(self.volume_mgr))
     }
 
+    /// Open a file for appending, creating it if it doesn't exist.
+    ///
+    /// See [`VolumeManager::append_file_in_dir`] for details.
+    pub fn append_file_in_dir<N>(
+        &self,
+        name: N,
+    ) -> Result<
+        crate::File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        crate::Error<D::Error>,
+    >
+    where
+        N: super::ToShortFileName,
+    {
+        let f = self
+            .volume_mgr
+            .append_file_in_dir(self.raw_directory, name)?;
+        Ok(f.to_file(self.volume_mgr))
+    }
+
+    /// Append some bytes to the end of a file, creating it if it doesn't
+    /// exist.
+    ///
+    /// See [`VolumeManager::append_to_file`] for details.
+    pub fn append_to_file<N>(&self, name: N, buffer: &[u8]) -> Result<(), Error<D::Error>>
+    where
+        N: super::ToShortFileName,
+    {
+        self.volume_mgr
+            .append_to_file(self.raw_directory, name, buffer)
+    }
+
+    /// Open a file with the given full path, using the given options. A file
+    /// can only be opened once.
+    pub fn open_file_in_dir_with_opts<N>(
+        &self,
+        name: N,
+        mode: crate::Mode,
+        options: crate::FileOpts,
+    ) -> Result<
+        crate::File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        crate::Error<D::Error>,
+    >
+    where
+        N: super::ToShortFileName,
+    {
+        let f =
+            self.volume_mgr
+                .open_file_in_dir_with_opts(self.raw_directory, name, mode, options)?;
+        Ok(f.to_file(self.volume_mgr))
+    }
+
+    /// Open a file with the given full path, giving it the given attributes
+    /// if opening it creates it. A file can only be opened once.
+    pub fn open_file_in_dir_with_attributes<N>(
+        &self,
+        name: N,
+        mode: crate::Mode,
+        attributes: Attributes,
+    ) -> Result<
+        crate::File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        crate::Error<D::Error>,
+    >
+    where
+        N: super::ToShortFileName,
+    {
+        let f = self.volume_mgr.open_file_in_dir_with_attributes(
+            self.raw_directory,
+            name,
+            mode,
+            attributes,
+        )?;
+        Ok(f.to_file(self.volume_mgr))
+    }
+
     /// Delete a closed file with the given filename, if it exists.
     pub fn delete_file_in_dir<N>(&self, name: N) -> Result<(), Error<D::Error>>
     where
@@ -182,6 +436,16 @@ where
         self.volume_mgr.delete_file_in_dir(self.raw_directory, name)
     }
 
+    /// Create several empty files in this directory at once.
+    ///
+    /// See [`VolumeManager::create_files`].
+    pub fn create_files<N>(&self, files: &[(N, Attributes)]) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName + Clone,
+    {
+        self.volume_mgr.create_files(self.raw_directory, files)
+    }
+
     /// Make a directory inside this directory
     pub fn make_dir_in_dir<N>(&self, name: N) -> Result<(), Error<D::Error>>
     where
@@ -190,6 +454,48 @@ where
         self.volume_mgr.make_dir_in_dir(self.raw_directory, name)
     }
 
+    /// Rename a directory inside this directory.
+    ///
+    /// See [`VolumeManager::rename_dir`] for details.
+    pub fn rename_dir<N, M>(&self, old_name: N, new_name: M) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName,
+        M: ToShortFileName,
+    {
+        self.volume_mgr
+            .rename_dir(self.raw_directory, old_name, new_name)
+    }
+
+    /// Move a closed file from this directory into `destination`, giving it
+    /// a new name.
+    ///
+    /// See [`VolumeManager::move_file`] for details.
+    pub fn move_file<N, M>(
+        &self,
+        name: N,
+        destination: &Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        new_name: M,
+    ) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName,
+        M: ToShortFileName,
+    {
+        self.volume_mgr.move_file(
+            self.raw_directory,
+            name,
+            destination.raw_directory,
+            new_name,
+        )
+    }
+
+    /// Release any clusters at the end of this directory which only hold
+    /// deleted or unused entries.
+    ///
+    /// See [`VolumeManager::compact_dir`] for details.
+    pub fn compact_dir(&self) -> Result<u32, Error<D::Error>> {
+        self.volume_mgr.compact_dir(self.raw_directory)
+    }
+
     /// Convert back to a raw directory
     pub fn to_raw_directory(self) -> RawDirectory {
         let d = self.raw_directory;
@@ -208,8 +514,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> Drop
-    for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Drop for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -219,8 +532,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    core::fmt::Debug for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > core::fmt::Debug for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -231,8 +551,15 @@ where
 }
 
 #[cfg(feature = "defmt-log")]
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    defmt::Format for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > defmt::Format for Directory<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -255,14 +582,26 @@ pub(crate) struct DirectoryInfo {
 }
 
 impl DirEntry {
+    /// Is this entry a file or a directory?
+    pub fn kind(&self) -> EntryKind {
+        if self.attributes.is_directory() {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        }
+    }
+
     pub(crate) fn serialize(&self, fat_type: FatType) -> [u8; OnDiskDirEntry::LEN] {
         let mut data = [0u8; OnDiskDirEntry::LEN];
         data[0..11].copy_from_slice(&self.name.contents);
         data[11] = self.attributes.0;
-        // 12: Reserved. Must be set to zero
+        // 12: Reserved by Microsoft, but Windows NT uses it to flag
+        // lower-case names
+        data[12] = self.name.case_flags;
         // 13: CrtTimeTenth, not supported, set to zero
         data[14..18].copy_from_slice(&self.ctime.serialize_to_fat()[..]);
-        // 0 + 18: LastAccDate, not supported, set to zero
+        // 18: LastAccDate (the time part is dropped)
+        data[18..20].copy_from_slice(&self.atime.serialize_to_fat()[2..4]);
         let cluster_number = self.cluster.0;
         let cluster_hi = if fat_type == FatType::Fat16 {
             [0u8; 2]
@@ -291,6 +630,7 @@ impl DirEntry {
             name,
             mtime: ctime,
             ctime,
+            atime: ctime.date_only(),
             attributes,
             cluster,
             size: 0,
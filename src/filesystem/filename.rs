@@ -1,6 +1,7 @@
 //! Filename related types
 
 use crate::fat::VolumeName;
+use crate::filesystem::CodePage;
 
 /// Various filename related errors that can occur.
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
@@ -10,18 +11,72 @@ pub enum FilenameError {
     InvalidCharacter,
     /// Tried to create a file with no file name.
     FilenameEmpty,
-    /// Given name was too long (we are limited to 8.3).
+    /// Given name was too long, even for a long file name (which is limited to
+    /// 255 characters).
     NameTooLong,
-    /// Can't start a file with a period, or after 8 characters.
+    /// A wildcard pattern can't start with a period, or have more than one.
     MisplacedPeriod,
     /// Can't extract utf8 from file name
     Utf8Error,
+    /// Given name contains a `*` or `?` wildcard, which is only allowed in a
+    /// [`ShortFileNamePattern`].
+    ContainsWildcard,
+    /// Given name is valid, but can't be a short file name. It doesn't fit in
+    /// 8.3, or has something only a long file name can have: a space, one of
+    /// `+ , ; = [ ]`, a character the code page doesn't have, or a period at
+    /// the start or more than one period.
+    ///
+    /// It might still be the long file name of an existing entry.
+    TooLongForShortName,
+}
+
+impl core::fmt::Display for FilenameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FilenameError::InvalidCharacter => write!(f, "invalid character"),
+            FilenameError::FilenameEmpty => write!(f, "empty file name"),
+            FilenameError::NameTooLong => write!(f, "name too long"),
+            FilenameError::MisplacedPeriod => write!(f, "misplaced period"),
+            FilenameError::Utf8Error => write!(f, "invalid UTF-8"),
+            FilenameError::ContainsWildcard => write!(f, "contains a wildcard"),
+            FilenameError::TooLongForShortName => write!(f, "too long for an 8.3 name"),
+        }
+    }
 }
 
+#[cfg(feature = "core-error")]
+impl core::error::Error for FilenameError {}
+
 /// Describes things we can convert to short 8.3 filenames
 pub trait ToShortFileName {
     /// Try and convert this value into a [`ShortFileName`].
     fn to_short_filename(self) -> Result<ShortFileName, FilenameError>;
+
+    /// Try and convert this value into a [`ShortFileName`], storing any
+    /// characters outside ASCII using the given code page.
+    ///
+    /// The volume manager uses this, with the volume's code page, rather than
+    /// [`ToShortFileName::to_short_filename`]. The default ignores the code
+    /// page.
+    fn to_short_filename_with_code_page(
+        self,
+        code_page: CodePage,
+    ) -> Result<ShortFileName, FilenameError>
+    where
+        Self: Sized,
+    {
+        let _ = code_page;
+        self.to_short_filename()
+    }
+
+    /// Get this value as a string, if it is one.
+    ///
+    /// If the string is too long to be a short file name, we use it to look
+    /// for an entry with a matching long file name instead. The default gives
+    /// `None`.
+    fn long_name(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl ToShortFileName for ShortFileName {
@@ -40,26 +95,54 @@ impl ToShortFileName for &str {
     fn to_short_filename(self) -> Result<ShortFileName, FilenameError> {
         ShortFileName::create_from_str(self)
     }
+
+    fn to_short_filename_with_code_page(
+        self,
+        code_page: CodePage,
+    ) -> Result<ShortFileName, FilenameError> {
+        ShortFileName::create_from_str_with_code_page(self, code_page)
+    }
+
+    fn long_name(&self) -> Option<&str> {
+        Some(self)
+    }
 }
 
 /// An MS-DOS 8.3 filename.
 ///
-/// ISO-8859-1 encoding is assumed. All lower-case is converted to upper-case by
+/// Bytes above `0x7F` are taken to be in code page 437 (see [`CodePage`])
+/// unless you create the name with
+/// [`ShortFileName::create_from_str_with_code_page`] or display it with
+/// [`ShortFileName::display`]. All lower-case is converted to upper-case by
 /// default.
-#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
-#[derive(PartialEq, Eq, Clone)]
+///
+/// If the base name or the extension was entirely lower-case, this is
+/// remembered (using the same flags Windows NT stores in byte 12 of a
+/// directory entry) and used when the name is displayed. The flags are ignored
+/// when comparing two names, as FAT names are not case-sensitive.
+#[derive(Clone)]
 pub struct ShortFileName {
     pub(crate) contents: [u8; Self::TOTAL_LEN],
+    pub(crate) case_flags: u8,
 }
 
 impl ShortFileName {
     const BASE_LEN: usize = 8;
     const TOTAL_LEN: usize = 11;
 
+    /// The longest long file name, in UCS-2 characters.
+    const MAX_LONG_NAME_LEN: usize = 255;
+
+    /// Flag in the NT case byte meaning the base name is lower-case.
+    pub(crate) const LOWERCASE_BASE: u8 = 0x08;
+    /// Flag in the NT case byte meaning the extension is lower-case.
+    pub(crate) const LOWERCASE_EXT: u8 = 0x10;
+
     /// Get a short file name containing "..", which means "parent directory".
     pub const fn parent_dir() -> Self {
         Self {
             contents: *b"..         ",
+            case_flags: 0,
         }
     }
 
@@ -67,29 +150,83 @@ impl ShortFileName {
     pub const fn this_dir() -> Self {
         Self {
             contents: *b".          ",
+            case_flags: 0,
         }
     }
 
+    /// Should the base name be displayed in lower-case?
+    pub fn is_base_name_lowercase(&self) -> bool {
+        (self.case_flags & Self::LOWERCASE_BASE) != 0
+    }
+
+    /// Should the extension be displayed in lower-case?
+    pub fn is_extension_lowercase(&self) -> bool {
+        (self.case_flags & Self::LOWERCASE_EXT) != 0
+    }
+
     /// Get base name (without extension) of the file.
+    ///
+    /// The name is always upper-case here, whatever the case flags say. A
+    /// volume label doesn't have a base name and extension, so convert it with
+    /// [`ShortFileName::to_volume_label`] and use [`VolumeName::name`] instead.
     pub fn base_name(&self) -> &[u8] {
         Self::bytes_before_space(&self.contents[..Self::BASE_LEN])
     }
 
     /// Get extension of the file (without base name).
+    ///
+    /// As with [`ShortFileName::base_name`], this is always upper-case.
     pub fn extension(&self) -> &[u8] {
         Self::bytes_before_space(&self.contents[Self::BASE_LEN..])
     }
 
+    /// Does this file have the given extension, ignoring case?
+    ///
+    /// The extension may be given with or without a leading period, so `wav`
+    /// and `.WAV` both match `SOUND.WAV`. An empty string matches a file with
+    /// no extension.
+    pub fn eq_ignore_case_ext(&self, ext: &str) -> bool {
+        let ext = ext.strip_prefix('.').unwrap_or(ext);
+        self.extension().eq_ignore_ascii_case(ext.as_bytes())
+    }
+
     fn bytes_before_space(bytes: &[u8]) -> &[u8] {
         bytes.split(|b| *b == b' ').next().unwrap_or(&[])
     }
 
     /// Create a new MS-DOS 8.3 space-padded file name as stored in the directory entry.
     ///
-    /// The output uses ISO-8859-1 encoding.
+    /// Characters outside ASCII are stored using code page 437, and any that
+    /// code page 437 doesn't have are invalid.
     pub fn create_from_str(name: &str) -> Result<ShortFileName, FilenameError> {
+        Self::parse(name, false, CodePage::default())
+    }
+
+    /// Create a new MS-DOS 8.3 space-padded file name, storing characters
+    /// outside ASCII using the given code page.
+    ///
+    /// Any characters the code page doesn't have are invalid. Letters are
+    /// converted to upper-case where the code page has the upper-case
+    /// letter.
+    pub fn create_from_str_with_code_page(
+        name: &str,
+        code_page: CodePage,
+    ) -> Result<ShortFileName, FilenameError> {
+        Self::parse(name, false, code_page)
+    }
+
+    /// Parse a name, optionally allowing the `?` and `*` wildcards.
+    ///
+    /// A `?` is stored as-is, and a `*` fills the rest of the base name or
+    /// extension with `?`.
+    fn parse(
+        name: &str,
+        wildcards: bool,
+        code_page: CodePage,
+    ) -> Result<ShortFileName, FilenameError> {
         let mut sfn = ShortFileName {
             contents: [b' '; Self::TOTAL_LEN],
+            case_flags: 0,
         };
 
         // Special case `..`, which means "parent directory".
@@ -102,67 +239,216 @@ impl ShortFileName {
             return Ok(ShortFileName::this_dir());
         }
 
+        if name.encode_utf16().count() > Self::MAX_LONG_NAME_LEN {
+            return Err(FilenameError::NameTooLong);
+        }
+
         let mut idx = 0;
         let mut seen_dot = false;
+        // Is the name valid, but only as a long file name?
+        let mut needs_lfn = false;
+        // Long file names can have some things 8.3 names can't, but patterns
+        // only ever match short file names, so there they are just invalid.
+        let lfn_only = |e: FilenameError| if wildcards { Err(e) } else { Ok(()) };
+        // Track the case of the letters in the base name and extension
+        let mut base_lower = false;
+        let mut base_upper = false;
+        let mut ext_lower = false;
+        let mut ext_upper = false;
         for ch in name.chars() {
             match ch {
+                '*' | '?' if !wildcards => {
+                    return Err(FilenameError::ContainsWildcard);
+                }
+                '*' | '?' => {
+                    let end = if seen_dot {
+                        Self::TOTAL_LEN
+                    } else {
+                        Self::BASE_LEN
+                    };
+                    if idx >= end {
+                        needs_lfn = true;
+                    } else if ch == '*' {
+                        // Matches everything up to the end of this part
+                        sfn.contents[idx..end].fill(b'?');
+                        idx = end;
+                    } else {
+                        sfn.contents[idx] = b'?';
+                        idx += 1;
+                    }
+                }
                 // Microsoft say these are the invalid characters
-                '\u{0000}'..='\u{001F}'
-                | '"'
-                | '*'
-                | '+'
-                | ','
-                | '/'
-                | ':'
-                | ';'
-                | '<'
-                | '='
-                | '>'
-                | '?'
-                | '['
-                | '\\'
-                | ']'
-                | ' '
-                | '|' => {
+                '\u{0000}'..='\u{001F}' | '"' | '/' | ':' | '<' | '>' | '\\' | '|' => {
                     return Err(FilenameError::InvalidCharacter);
                 }
-                x if x > '\u{00FF}' => {
-                    // We only handle ISO-8859-1 which is Unicode Code Points
-                    // \U+0000 to \U+00FF. This is above that.
-                    return Err(FilenameError::InvalidCharacter);
+                // These are fine in a long file name, but not in 8.3
+                '+' | ',' | ';' | '=' | '[' | ']' | ' ' => {
+                    lfn_only(FilenameError::InvalidCharacter)?;
+                    needs_lfn = true;
+                }
+                '.' if idx == 0 || seen_dot => {
+                    // Only a long file name can start with a period, or have
+                    // more than one
+                    lfn_only(FilenameError::MisplacedPeriod)?;
+                    needs_lfn = true;
                 }
                 '.' => {
                     // Denotes the start of the file extension
-                    if (1..=Self::BASE_LEN).contains(&idx) {
-                        idx = Self::BASE_LEN;
-                        seen_dot = true;
-                    } else {
-                        return Err(FilenameError::MisplacedPeriod);
-                    }
+                    idx = Self::BASE_LEN;
+                    seen_dot = true;
                 }
                 _ => {
-                    let b = ch.to_ascii_uppercase() as u8;
-                    if seen_dot {
-                        if (Self::BASE_LEN..Self::TOTAL_LEN).contains(&idx) {
-                            sfn.contents[idx] = b;
-                        } else {
-                            return Err(FilenameError::NameTooLong);
-                        }
-                    } else if idx < Self::BASE_LEN {
+                    let (lower, upper) = if seen_dot {
+                        (&mut ext_lower, &mut ext_upper)
+                    } else {
+                        (&mut base_lower, &mut base_upper)
+                    };
+                    *lower |= ch.is_lowercase();
+                    *upper |= ch.is_uppercase();
+                    let Some(b) = code_page.from_char(ch).map(|b| code_page.to_uppercase(b)) else {
+                        // Not in the code page, but a long file name can
+                        // have any character
+                        lfn_only(FilenameError::InvalidCharacter)?;
+                        needs_lfn = true;
+                        continue;
+                    };
+                    let end = if seen_dot {
+                        Self::TOTAL_LEN
+                    } else {
+                        Self::BASE_LEN
+                    };
+                    if idx < end {
                         sfn.contents[idx] = b;
+                        idx += 1;
                     } else {
-                        return Err(FilenameError::NameTooLong);
+                        // Keep going, in case there is an invalid character
+                        needs_lfn = true;
                     }
-                    idx += 1;
                 }
             }
         }
+        if needs_lfn {
+            return Err(FilenameError::TooLongForShortName);
+        }
         if idx == 0 {
             return Err(FilenameError::FilenameEmpty);
         }
+        // Mixed-case parts cannot be represented, and so are left upper-case
+        if base_lower && !base_upper {
+            sfn.case_flags |= Self::LOWERCASE_BASE;
+        }
+        if ext_lower && !ext_upper {
+            sfn.case_flags |= Self::LOWERCASE_EXT;
+        }
         Ok(sfn)
     }
 
+    /// Is this a valid name which is too long to be a short file name?
+    pub(crate) fn is_too_long(name: &str, code_page: CodePage) -> bool {
+        matches!(
+            Self::create_from_str_with_code_page(name, code_page),
+            Err(FilenameError::TooLongForShortName)
+        )
+    }
+
+    /// The largest number we can put in a `~N` numeric tail.
+    pub(crate) const MAX_NUMERIC_TAIL: u32 = 999_999;
+
+    /// Make a copy of this name with a `~N` numeric tail on the base name,
+    /// shortening the base name if needed to make room, so `LONGNAME.TXT`
+    /// with tail 12 becomes `LONGN~12.TXT`.
+    ///
+    /// `n` must be between 1 and [`ShortFileName::MAX_NUMERIC_TAIL`].
+    pub(crate) fn with_numeric_tail(&self, n: u32) -> ShortFileName {
+        debug_assert!((1..=Self::MAX_NUMERIC_TAIL).contains(&n));
+        let mut digits = [0u8; 6];
+        let mut num_digits = 0;
+        let mut value = n;
+        while value > 0 && num_digits < digits.len() {
+            digits[num_digits] = b'0' + (value % 10) as u8;
+            num_digits += 1;
+            value /= 10;
+        }
+        let base = self.base_name();
+        let keep = base.len().min(Self::BASE_LEN - 1 - num_digits);
+        let mut contents = [b' '; Self::TOTAL_LEN];
+        contents[..keep].copy_from_slice(&base[..keep]);
+        contents[keep] = b'~';
+        for (dest, digit) in contents[keep + 1..]
+            .iter_mut()
+            .zip(digits[..num_digits].iter().rev())
+        {
+            *dest = *digit;
+        }
+        contents[Self::BASE_LEN..].copy_from_slice(&self.contents[Self::BASE_LEN..]);
+        ShortFileName {
+            contents,
+            case_flags: 0,
+        }
+    }
+
+    /// The number in this name's `~N` numeric tail, if it has one.
+    pub(crate) fn numeric_tail(&self) -> Option<u32> {
+        let base = self.base_name();
+        let tilde = base.iter().rposition(|&b| b == b'~')?;
+        let digits = &base[tilde + 1..];
+        if digits.is_empty() || digits[0] == b'0' || !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        Some(
+            digits
+                .iter()
+                .fold(0, |acc, &d| acc * 10 + u32::from(d - b'0')),
+        )
+    }
+
+    /// Display this name, taking bytes above `0x7F` to be in the given code
+    /// page.
+    ///
+    /// The `Display` impl for `ShortFileName` uses the default,
+    /// [`CodePage::Cp437`].
+    pub fn display(&self, code_page: CodePage) -> ShortFileNameDisplay<'_> {
+        ShortFileNameDisplay {
+            name: self,
+            code_page,
+        }
+    }
+
+    /// Write this name into `buf` in `NAME.EXT` form, and get it back as a
+    /// `&str`.
+    ///
+    /// This is the same as the `Display` output, case flags and all, but
+    /// without needing a formatter - handy for comparing names or logging
+    /// them. A name without an extension doesn't get a trailing `.`. Twelve
+    /// bytes only has room for one byte per character, so any bytes above
+    /// `0x7F` come out as `?`, which a short file name can't contain; use
+    /// [`ShortFileName::display`] to see those.
+    pub fn as_formatted<'b>(&self, buf: &'b mut [u8; 12]) -> &'b str {
+        let mut len = 0;
+        for (i, &c) in self.contents.iter().enumerate() {
+            if c == b' ' {
+                continue;
+            }
+            let lowercase = if i < Self::BASE_LEN {
+                self.is_base_name_lowercase()
+            } else {
+                if i == Self::BASE_LEN {
+                    buf[len] = b'.';
+                    len += 1;
+                }
+                self.is_extension_lowercase()
+            };
+            buf[len] = match c {
+                0x80.. => b'?',
+                c if lowercase => c.to_ascii_lowercase(),
+                c => c,
+            };
+            len += 1;
+        }
+        // Everything we wrote is ASCII
+        core::str::from_utf8(&buf[..len]).unwrap()
+    }
+
     /// Convert a Short File Name to a Volume Label.
     ///
     /// # Safety
@@ -177,20 +463,105 @@ impl ShortFileName {
     }
 }
 
+/// A pattern to match MS-DOS 8.3 filenames against.
+///
+/// A `?` matches any single character, including the space padding at the end
+/// of the base name or extension, so `TEST?.DAT` matches both `TEST1.DAT` and
+/// `TEST.DAT`. A `*` matches the rest of the base name or extension. As with
+/// MS-DOS, `*.*` matches every name, whereas `*` only matches names without an
+/// extension.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShortFileNamePattern {
+    contents: [u8; ShortFileName::TOTAL_LEN],
+}
+
+impl ShortFileNamePattern {
+    /// Create a new pattern from a string, which may contain `?` and `*`.
+    ///
+    /// Apart from the wildcards, the same rules apply as for
+    /// [`ShortFileName::create_from_str`].
+    pub fn create_from_str(pattern: &str) -> Result<ShortFileNamePattern, FilenameError> {
+        Self::create_from_str_with_code_page(pattern, CodePage::default())
+    }
+
+    /// Create a new pattern from a string, storing characters outside ASCII
+    /// using the given code page.
+    ///
+    /// See [`ShortFileName::create_from_str_with_code_page`].
+    pub fn create_from_str_with_code_page(
+        pattern: &str,
+        code_page: CodePage,
+    ) -> Result<ShortFileNamePattern, FilenameError> {
+        let sfn = ShortFileName::parse(pattern, true, code_page)?;
+        Ok(ShortFileNamePattern {
+            contents: sfn.contents,
+        })
+    }
+
+    /// Does the given name match this pattern?
+    pub fn matches(&self, name: &ShortFileName) -> bool {
+        self.contents
+            .iter()
+            .zip(name.contents.iter())
+            .all(|(p, n)| *p == b'?' || p == n)
+    }
+}
+
+impl PartialEq for ShortFileName {
+    fn eq(&self, other: &Self) -> bool {
+        self.contents == other.contents
+    }
+}
+
+impl Eq for ShortFileName {}
+
 impl core::fmt::Display for ShortFileName {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.display(CodePage::default()), f)
+    }
+}
+
+/// Displays a [`ShortFileName`] using a particular [`CodePage`].
+///
+/// Made by [`ShortFileName::display`].
+#[derive(Debug, Clone)]
+pub struct ShortFileNameDisplay<'a> {
+    name: &'a ShortFileName,
+    code_page: CodePage,
+}
+
+impl ShortFileNameDisplay<'_> {
+    /// The characters of the name, with a `.` before any extension.
+    fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        let name = self.name;
+        name.contents
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c != b' ')
+            .flat_map(move |(i, &c)| {
+                let dot = (i == ShortFileName::BASE_LEN).then_some('.');
+                let lowercase = if i < ShortFileName::BASE_LEN {
+                    name.is_base_name_lowercase()
+                } else {
+                    name.is_extension_lowercase()
+                };
+                let c = if lowercase {
+                    self.code_page.to_lowercase(c)
+                } else {
+                    c
+                };
+                dot.into_iter().chain(Some(self.code_page.to_char(c)))
+            })
+    }
+}
+
+impl core::fmt::Display for ShortFileNameDisplay<'_> {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let mut printed = 0;
-        for (i, &c) in self.contents.iter().enumerate() {
-            if c != b' ' {
-                if i == Self::BASE_LEN {
-                    write!(f, ".")?;
-                    printed += 1;
-                }
-                // converting a byte to a codepoint means you are assuming
-                // ISO-8859-1 encoding, because that's how Unicode was designed.
-                write!(f, "{}", c as char)?;
-                printed += 1;
-            }
+        for c in self.chars() {
+            write!(f, "{}", c)?;
+            printed += 1;
         }
         if let Some(mut width) = f.width() {
             if width > printed {
@@ -210,6 +581,17 @@ impl core::fmt::Debug for ShortFileName {
     }
 }
 
+#[cfg(feature = "defmt-log")]
+impl defmt::Format for ShortFileName {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "ShortFileName(\"");
+        for c in self.display(CodePage::default()).chars() {
+            defmt::write!(f, "{=char}", c);
+        }
+        defmt::write!(f, "\")");
+    }
+}
+
 // ****************************************************************************
 //
 // Unit Tests
@@ -220,10 +602,28 @@ impl core::fmt::Debug for ShortFileName {
 mod test {
     use super::*;
 
+    /// Only has to compile - there's no defmt logger to run it with.
+    #[cfg(feature = "defmt-log")]
+    #[allow(dead_code)]
+    fn log_dir_entry(entry: &crate::DirEntry) {
+        defmt::info!("{}", entry);
+    }
+
+    #[cfg(feature = "defmt-log")]
+    #[test]
+    fn defmt_format() {
+        fn assert_format<T: defmt::Format>() {}
+        assert_format::<crate::DirEntry>();
+        assert_format::<crate::Timestamp>();
+        assert_format::<crate::Attributes>();
+        assert_format::<ShortFileName>();
+    }
+
     #[test]
     fn filename_no_extension() {
         let sfn = ShortFileName {
             contents: *b"HELLO      ",
+            case_flags: 0,
         };
         assert_eq!(format!("{}", &sfn), "HELLO");
         assert_eq!(sfn, ShortFileName::create_from_str("HELLO").unwrap());
@@ -236,6 +636,7 @@ mod test {
     fn filename_extension() {
         let sfn = ShortFileName {
             contents: *b"HELLO   TXT",
+            case_flags: 0,
         };
         assert_eq!(format!("{}", &sfn), "HELLO.TXT");
         assert_eq!(sfn, ShortFileName::create_from_str("HELLO.TXT").unwrap());
@@ -251,6 +652,34 @@ mod test {
         assert_eq!(sfn.extension(), "A".as_bytes());
     }
 
+    #[test]
+    fn filename_eq_ignore_case_ext() {
+        let sfn = ShortFileName::create_from_str("sound.wav").unwrap();
+        assert!(sfn.eq_ignore_case_ext("WAV"));
+        assert!(sfn.eq_ignore_case_ext("wav"));
+        assert!(sfn.eq_ignore_case_ext(".Wav"));
+        assert!(!sfn.eq_ignore_case_ext("WA"));
+        assert!(!sfn.eq_ignore_case_ext("WAVE"));
+        assert!(!sfn.eq_ignore_case_ext(""));
+        let sfn = ShortFileName::create_from_str("README").unwrap();
+        assert!(sfn.eq_ignore_case_ext(""));
+        assert!(sfn.eq_ignore_case_ext("."));
+        assert!(!sfn.eq_ignore_case_ext("TXT"));
+    }
+
+    #[test]
+    fn filename_volume_label() {
+        // A volume label uses all 11 bytes as one field
+        let sfn = ShortFileName {
+            contents: *b"BOOTDISK001",
+            case_flags: 0,
+        };
+        assert_eq!(sfn.base_name(), b"BOOTDISK");
+        assert_eq!(sfn.extension(), b"001");
+        let label = unsafe { sfn.to_volume_label() };
+        assert_eq!(label.name(), b"BOOTDISK001");
+    }
+
     #[test]
     fn filename_get_base_name() {
         let mut sfn = ShortFileName::create_from_str("hello.txt").unwrap();
@@ -265,6 +694,7 @@ mod test {
     fn filename_fulllength() {
         let sfn = ShortFileName {
             contents: *b"12345678TXT",
+            case_flags: 0,
         };
         assert_eq!(format!("{}", &sfn), "12345678.TXT");
         assert_eq!(sfn, ShortFileName::create_from_str("12345678.TXT").unwrap());
@@ -274,6 +704,7 @@ mod test {
     fn filename_short_extension() {
         let sfn = ShortFileName {
             contents: *b"12345678C  ",
+            case_flags: 0,
         };
         assert_eq!(format!("{}", &sfn), "12345678.C");
         assert_eq!(sfn, ShortFileName::create_from_str("12345678.C").unwrap());
@@ -283,11 +714,35 @@ mod test {
     fn filename_short() {
         let sfn = ShortFileName {
             contents: *b"1       C  ",
+            case_flags: 0,
         };
         assert_eq!(format!("{}", &sfn), "1.C");
         assert_eq!(sfn, ShortFileName::create_from_str("1.C").unwrap());
     }
 
+    #[test]
+    fn filename_lowercase() {
+        let sfn = ShortFileName::create_from_str("config.txt").unwrap();
+        assert_eq!(format!("{}", &sfn), "config.txt");
+        assert!(sfn.is_base_name_lowercase());
+        assert!(sfn.is_extension_lowercase());
+        assert_eq!(sfn, ShortFileName::create_from_str("CONFIG.TXT").unwrap());
+
+        let sfn = ShortFileName::create_from_str("README.md").unwrap();
+        assert_eq!(format!("{}", &sfn), "README.md");
+        assert!(!sfn.is_base_name_lowercase());
+        assert!(sfn.is_extension_lowercase());
+
+        // Mixed case can't be stored, so we fall back to upper-case
+        let sfn = ShortFileName::create_from_str("HeLlO.tXt").unwrap();
+        assert_eq!(format!("{}", &sfn), "HELLO.TXT");
+        assert_eq!(sfn.case_flags, 0);
+
+        // Digits don't count as either case
+        let sfn = ShortFileName::create_from_str("file1.a1").unwrap();
+        assert_eq!(format!("{}", &sfn), "file1.a1");
+    }
+
     #[test]
     fn filename_empty() {
         assert_eq!(
@@ -301,6 +756,184 @@ mod test {
         assert!(ShortFileName::create_from_str(" ").is_err());
         assert!(ShortFileName::create_from_str("123456789").is_err());
         assert!(ShortFileName::create_from_str("12345678.ABCD").is_err());
+        assert!(matches!(
+            ShortFileName::create_from_str("*.TXT"),
+            Err(FilenameError::ContainsWildcard)
+        ));
+        assert!(matches!(
+            ShortFileName::create_from_str("TEST?.TXT"),
+            Err(FilenameError::ContainsWildcard)
+        ));
+    }
+
+    #[test]
+    fn filename_too_long() {
+        // Valid names, which only fit in a long file name
+        for name in [
+            "123456789",
+            "12345678.ABCD",
+            "bcm2708-rpi-b-plus.dtb",
+            "My Notes.txt",
+            "a+b.txt",
+            "my.config.json",
+            ".gitignore",
+            "\u{041F}\u{0440}\u{0438}.txt",
+        ] {
+            assert!(matches!(
+                ShortFileName::create_from_str(name),
+                Err(FilenameError::TooLongForShortName)
+            ));
+            assert!(ShortFileName::is_too_long(name, CodePage::Cp437));
+        }
+        // Invalid characters take priority, even after the 8.3 limit
+        assert!(matches!(
+            ShortFileName::create_from_str("123456789|.TXT"),
+            Err(FilenameError::InvalidCharacter)
+        ));
+        assert!(!ShortFileName::is_too_long(
+            "123456789|.TXT",
+            CodePage::Cp437
+        ));
+        assert!(!ShortFileName::is_too_long("README.TXT", CodePage::Cp437));
+        // Too long for anything
+        let name = [b'A'; 256];
+        let name = core::str::from_utf8(&name).unwrap();
+        assert!(matches!(
+            ShortFileName::create_from_str(name),
+            Err(FilenameError::NameTooLong)
+        ));
+    }
+
+    #[test]
+    fn numeric_tails() {
+        let sfn = ShortFileName::create_from_str("report.txt").unwrap();
+        assert_eq!(sfn.with_numeric_tail(1).to_string(), "REPORT~1.TXT");
+        assert_eq!(sfn.with_numeric_tail(10).to_string(), "REPOR~10.TXT");
+        assert_eq!(sfn.with_numeric_tail(999_999).to_string(), "R~999999.TXT");
+        let sfn = ShortFileName::create_from_str("A").unwrap();
+        assert_eq!(sfn.with_numeric_tail(42).to_string(), "A~42");
+        for (name, tail) in [
+            ("REPORT~1.TXT", Some(1)),
+            ("REPOR~10.TXT", Some(10)),
+            ("REPORT.TXT", None),
+            ("REPORT~.TXT", None),
+            ("REPO~01.TXT", None),
+            ("RE~1X.TXT", None),
+        ] {
+            let sfn = ShortFileName::create_from_str(name).unwrap();
+            assert_eq!(sfn.numeric_tail(), tail, "{}", name);
+        }
+    }
+
+    #[test]
+    fn pattern_contents() {
+        let pattern = ShortFileNamePattern::create_from_str("*.dat").unwrap();
+        assert_eq!(&pattern.contents, b"????????DAT");
+        let pattern = ShortFileNamePattern::create_from_str("README.*").unwrap();
+        assert_eq!(&pattern.contents, b"README  ???");
+        let pattern = ShortFileNamePattern::create_from_str("TE?T*.?").unwrap();
+        assert_eq!(&pattern.contents, b"TE?T?????  ");
+        assert!(ShortFileNamePattern::create_from_str("*A.TXT").is_err());
+        assert!(ShortFileNamePattern::create_from_str("123456789?").is_err());
+        assert!(ShortFileNamePattern::create_from_str(".*").is_err());
+        // Patterns only match short file names, so there's no long file name
+        // to fall back on
+        assert!(matches!(
+            ShortFileNamePattern::create_from_str("A B.*"),
+            Err(FilenameError::InvalidCharacter)
+        ));
+        assert!(matches!(
+            ShortFileNamePattern::create_from_str("*.A.B"),
+            Err(FilenameError::MisplacedPeriod)
+        ));
+    }
+
+    #[test]
+    fn pattern_matches() {
+        let name = |s| ShortFileName::create_from_str(s).unwrap();
+        let pattern = |s| ShortFileNamePattern::create_from_str(s).unwrap();
+        assert!(pattern("*.*").matches(&name("README.TXT")));
+        assert!(pattern("*.*").matches(&name("TEST")));
+        assert!(pattern("*").matches(&name("TEST")));
+        assert!(!pattern("*").matches(&name("README.TXT")));
+        assert!(pattern("*.dat").matches(&name("EMPTY.DAT")));
+        assert!(!pattern("*.DAT").matches(&name("README.TXT")));
+        assert!(pattern("TEST?.DAT").matches(&name("TEST1.DAT")));
+        assert!(pattern("TEST?.DAT").matches(&name("TEST.DAT")));
+        assert!(!pattern("TEST?.DAT").matches(&name("TEST12.DAT")));
+        assert!(!pattern("README").matches(&name("README.TXT")));
+    }
+
+    #[test]
+    fn code_pages() {
+        // 0x9A is 'Ü' and 0x81 is 'ü' in both CP437 and CP850
+        let sfn = ShortFileName {
+            contents: *b"\x9aBER    \x81  ",
+            case_flags: 0,
+        };
+        assert_eq!(format!("{}", sfn), "\u{00DC}BER.\u{00FC}");
+        assert_eq!(
+            format!("{}", sfn.display(CodePage::Iso8859_1)),
+            "\u{009A}BER.\u{0081}"
+        );
+        // 0x9B is where the code pages differ
+        let sfn = ShortFileName {
+            contents: *b"\x9b       TXT",
+            case_flags: 0,
+        };
+        assert_eq!(format!("{}", sfn), "\u{00A2}.TXT");
+        assert_eq!(format!("{}", sfn.display(CodePage::Cp850)), "\u{00F8}.TXT");
+        // Names are stored in CP437, in upper-case
+        let sfn = ShortFileName::create_from_str("\u{00DC}BER.\u{00FC}").unwrap();
+        assert_eq!(sfn.contents, *b"\x9aBER    \x9a  ");
+        assert_eq!(format!("{}", sfn), "\u{00DC}BER.\u{00FC}");
+        // A character CP437 doesn't have can only be in a long file name
+        assert!(matches!(
+            ShortFileName::create_from_str("\u{00F8}.TXT"),
+            Err(FilenameError::TooLongForShortName)
+        ));
+        // Unless we ask for another code page
+        let sfn =
+            ShortFileName::create_from_str_with_code_page("\u{00F8}.txt", CodePage::Cp850).unwrap();
+        assert_eq!(sfn.contents, *b"\x9d       TXT");
+        assert_eq!(format!("{}", sfn.display(CodePage::Cp850)), "\u{00F8}.txt");
+        let sfn =
+            ShortFileName::create_from_str_with_code_page("\u{00FC}ber.txt", CodePage::Iso8859_1)
+                .unwrap();
+        assert_eq!(sfn.contents, *b"\xdcBER    TXT");
+        assert_eq!(
+            format!("{}", sfn.display(CodePage::Iso8859_1)),
+            "\u{00FC}ber.txt"
+        );
+    }
+
+    #[test]
+    fn as_formatted() {
+        let mut buf = [0u8; 12];
+        let sfn = ShortFileName::create_from_str("README.TXT").unwrap();
+        assert_eq!(sfn.as_formatted(&mut buf), "README.TXT");
+        let sfn = ShortFileName::create_from_str("LONGNAME.EXT").unwrap();
+        assert_eq!(sfn.as_formatted(&mut buf), "LONGNAME.EXT");
+        let sfn = ShortFileName::create_from_str("A.B").unwrap();
+        assert_eq!(sfn.as_formatted(&mut buf), "A.B");
+        // No extension means no dot
+        let sfn = ShortFileName::create_from_str("MAKEFILE").unwrap();
+        assert_eq!(sfn.as_formatted(&mut buf), "MAKEFILE");
+        assert_eq!(ShortFileName::parent_dir().as_formatted(&mut buf), "..");
+        assert_eq!(ShortFileName::this_dir().as_formatted(&mut buf), ".");
+        // The case flags are honoured, as with Display
+        let sfn = ShortFileName {
+            contents: *b"README  TXT",
+            case_flags: ShortFileName::LOWERCASE_EXT,
+        };
+        assert_eq!(sfn.as_formatted(&mut buf), "README.txt");
+        assert_eq!(sfn.as_formatted(&mut buf), format!("{}", sfn));
+        // Bytes outside ASCII don't fit
+        let sfn = ShortFileName {
+            contents: *b"\x9aBER    \x81  ",
+            case_flags: 0,
+        };
+        assert_eq!(sfn.as_formatted(&mut buf), "?BER.?");
     }
 }
 
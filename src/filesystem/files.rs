@@ -1,9 +1,9 @@
 use super::TimeSource;
 use crate::{
-    filesystem::{ClusterId, DirEntry, Handle},
+    filesystem::{Attributes, ClusterId, DirEntry, Handle, Timestamp},
     BlockDevice, Error, RawVolume, VolumeManager,
 };
-use embedded_io::{ErrorType, Read, Seek, SeekFrom, Write};
+use embedded_io::{ErrorType, Read, ReadReady, Seek, SeekFrom, Write, WriteReady};
 
 /// A handle for an open file on disk.
 ///
@@ -27,10 +27,17 @@ pub struct RawFile(pub(crate) Handle);
 
 impl RawFile {
     /// Convert a raw file into a droppable [`File`]
-    pub fn to_file<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>(
+    pub fn to_file<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    >(
         self,
-        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> File<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
     where
         D: crate::BlockDevice,
         T: crate::TimeSource,
@@ -47,17 +54,31 @@ impl RawFile {
 /// If you drop a value of this type, it closes the file automatically, and but
 /// error that may occur will be ignored. To handle potential errors, use
 /// the [`File::close`] method.
-pub struct File<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-where
+pub struct File<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+    const MAX_CHECKPOINTS: usize = 0,
+> where
     D: crate::BlockDevice,
     T: crate::TimeSource,
 {
     raw_file: RawFile,
-    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -65,8 +86,8 @@ where
     /// Create a new `File` from a `RawFile`
     pub fn new(
         raw_file: RawFile,
-        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
+        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
         File {
             raw_file,
             volume_mgr,
@@ -80,11 +101,39 @@ where
         self.volume_mgr.read(self.raw_file, buffer)
     }
 
+    /// Read from the file, starting at the given offset, without moving the
+    /// file's current position.
+    ///
+    /// Returns how many bytes were read, or an error.
+    pub fn read_at(&self, offset: u32, buffer: &mut [u8]) -> Result<usize, crate::Error<D::Error>> {
+        self.volume_mgr.read_at(self.raw_file, offset, buffer)
+    }
+
     /// Write to the file
     pub fn write(&self, buffer: &[u8]) -> Result<(), crate::Error<D::Error>> {
         self.volume_mgr.write(self.raw_file, buffer)
     }
 
+    /// Append a length-prefixed record to the end of the file.
+    ///
+    /// See [`VolumeManager::write_record`].
+    pub fn write_record(&self, record: &[u8]) -> Result<(), crate::Error<D::Error>> {
+        self.volume_mgr.write_record(self.raw_file, record)
+    }
+
+    /// Make the file `length` bytes long, without writing the new space yet.
+    ///
+    /// See [`VolumeManager::file_extend`].
+    pub fn extend(&self, length: u32) -> Result<(), crate::Error<D::Error>> {
+        self.volume_mgr.file_extend(self.raw_file, length)
+    }
+
+    /// Make sure the file has room for `bytes` bytes without allocating on
+    /// write. The file's length doesn't change.
+    pub fn preallocate(&self, bytes: u32) -> Result<(), crate::Error<D::Error>> {
+        self.volume_mgr.file_preallocate(self.raw_file, bytes)
+    }
+
     /// Check if a file is at End Of File.
     pub fn is_eof(&self) -> bool {
         self.volume_mgr
@@ -108,6 +157,14 @@ where
         self.volume_mgr.file_seek_from_end(self.raw_file, offset)
     }
 
+    /// Seek a file with a signed offset relative to the end of the file.
+    ///
+    /// See [`VolumeManager::file_seek_relative_to_end`] for details.
+    pub fn seek_relative_to_end(&self, offset: i64) -> Result<(), crate::Error<D::Error>> {
+        self.volume_mgr
+            .file_seek_relative_to_end(self.raw_file, offset)
+    }
+
     /// Get the length of a file
     pub fn length(&self) -> u32 {
         self.volume_mgr
@@ -122,6 +179,79 @@ where
             .expect("Corrupt file ID")
     }
 
+    /// Get the current position in the file, as a number of bytes from the
+    /// start.
+    ///
+    /// This is the same as [`File::offset`].
+    pub fn position(&self) -> u32 {
+        self.offset()
+    }
+
+    /// Get the number of bytes between the current position and the end of
+    /// the file.
+    pub fn remaining(&self) -> u32 {
+        self.volume_mgr
+            .file_remaining(self.raw_file)
+            .expect("Corrupt file ID")
+    }
+
+    /// Get the mode the file was opened in
+    pub fn mode(&self) -> Mode {
+        self.volume_mgr
+            .file_mode(self.raw_file)
+            .expect("Corrupt file ID")
+    }
+
+    /// Write the path of this file, relative to the root directory, into
+    /// `buffer`.
+    ///
+    /// See [`VolumeManager::get_file_path`] for details.
+    pub fn path<'b>(&self, buffer: &'b mut [u8]) -> Result<&'b str, Error<D::Error>> {
+        self.volume_mgr.get_file_path(self.raw_file, buffer)
+    }
+
+    /// Get a snapshot of the metadata for a file
+    pub fn info(&self) -> FileMeta {
+        self.volume_mgr
+            .file_info(self.raw_file)
+            .expect("Corrupt file ID")
+    }
+
+    /// Get an iterator over the bytes in the file, starting at the current
+    /// offset.
+    ///
+    /// The file is read a few bytes at a time, not one byte at a time, so
+    /// while the iterator exists the file offset may be ahead of the bytes
+    /// it has returned. When the iterator is dropped, the file offset is
+    /// moved back to just after the last byte it returned. If a read fails,
+    /// the iterator returns the error and then stops.
+    pub fn bytes(
+        &self,
+    ) -> FileBytes<'_, 'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
+        FileBytes {
+            file: self,
+            buffer: [0u8; FILE_BYTES_BUFFER_LEN],
+            pos: 0,
+            len: 0,
+            done: false,
+        }
+    }
+
+    /// Get an iterator over the file, in chunks of `N` bytes, starting at the
+    /// current offset.
+    ///
+    /// Every chunk is `N` bytes long, except the last one, which holds
+    /// whatever was left in the file. If a read fails, the iterator returns
+    /// the error and then stops.
+    pub fn chunks<const N: usize>(
+        &self,
+    ) -> FileChunks<'_, 'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, N, MAX_CHECKPOINTS> {
+        FileChunks {
+            file: self,
+            done: false,
+        }
+    }
+
     /// Convert back to a raw file
     pub fn to_raw_file(self) -> RawFile {
         let f = self.raw_file;
@@ -145,8 +275,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> Drop
-    for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Drop for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -156,8 +293,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    core::fmt::Debug for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > core::fmt::Debug for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -173,7 +317,8 @@ impl<
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
-    > ErrorType for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        const MAX_CHECKPOINTS: usize,
+    > ErrorType for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 {
     type Error = crate::Error<D::Error>;
 }
@@ -184,13 +329,14 @@ impl<
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
-    > Read for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        const MAX_CHECKPOINTS: usize,
+    > Read for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         if buf.is_empty() {
             Ok(0)
         } else {
-            self.read(buf)
+            self.volume_mgr.read(self.raw_file, buf)
         }
     }
 }
@@ -201,19 +347,53 @@ impl<
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
-    > Write for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        const MAX_CHECKPOINTS: usize,
+    > Write for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         if buf.is_empty() {
             Ok(0)
         } else {
-            self.write(buf)?;
+            self.volume_mgr.write(self.raw_file, buf)?;
             Ok(buf.len())
         }
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
-        Self::flush(self)
+        self.volume_mgr.flush_file(self.raw_file)
+    }
+}
+
+impl<
+        D: BlockDevice,
+        T: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > ReadReady for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
+{
+    /// There's something to read unless we're at the end of the file.
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.volume_mgr.file_eof(self.raw_file)?)
+    }
+}
+
+impl<
+        D: BlockDevice,
+        T: TimeSource,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > WriteReady for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
+{
+    /// We can write if the file was opened for writing, and hasn't reached
+    /// [`MAX_FILE_SIZE`](crate::MAX_FILE_SIZE).
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        let info = self.volume_mgr.file_info(self.raw_file)?;
+        let offset = self.volume_mgr.file_offset(self.raw_file)?;
+        Ok(info.mode != Mode::ReadOnly && offset < crate::MAX_FILE_SIZE)
     }
 }
 
@@ -223,16 +403,15 @@ impl<
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
-    > Seek for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        const MAX_CHECKPOINTS: usize,
+    > Seek for File<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
         match pos {
             SeekFrom::Start(offset) => {
                 self.seek_from_start(offset.try_into().map_err(|_| Error::InvalidOffset)?)?
             }
-            SeekFrom::End(offset) => {
-                self.seek_from_end((-offset).try_into().map_err(|_| Error::InvalidOffset)?)?
-            }
+            SeekFrom::End(offset) => self.seek_relative_to_end(offset)?,
             SeekFrom::Current(offset) => {
                 self.seek_from_current(offset.try_into().map_err(|_| Error::InvalidOffset)?)?
             }
@@ -242,8 +421,15 @@ impl<
 }
 
 #[cfg(feature = "defmt-log")]
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    defmt::Format for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > defmt::Format for File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -253,6 +439,182 @@ where
     }
 }
 
+/// How many bytes a [`FileBytes`] iterator reads from the file at a time.
+const FILE_BYTES_BUFFER_LEN: usize = 64;
+
+/// An iterator over the bytes in a [`File`].
+///
+/// Created with [`File::bytes`].
+pub struct FileBytes<
+    'f,
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+    const MAX_CHECKPOINTS: usize = 0,
+> where
+    D: crate::BlockDevice,
+    T: crate::TimeSource,
+{
+    file: &'f File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    buffer: [u8; FILE_BYTES_BUFFER_LEN],
+    pos: usize,
+    len: usize,
+    done: bool,
+}
+
+impl<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Iterator for FileBytes<'_, '_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
+where
+    D: crate::BlockDevice,
+    T: crate::TimeSource,
+{
+    type Item = Result<u8, Error<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.len {
+            if self.done {
+                return None;
+            }
+            match self.file.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.done = true;
+                    return None;
+                }
+                Ok(n) => {
+                    self.pos = 0;
+                    self.len = n;
+                }
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        let b = self.buffer[self.pos];
+        self.pos += 1;
+        Some(Ok(b))
+    }
+}
+
+impl<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Drop for FileBytes<'_, '_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
+where
+    D: crate::BlockDevice,
+    T: crate::TimeSource,
+{
+    fn drop(&mut self) {
+        // Give back the bytes we read ahead but never returned. There are at
+        // most FILE_BYTES_BUFFER_LEN of them, so this can't overflow.
+        let unread = self.len - self.pos;
+        if unread != 0 {
+            let _ = self.file.seek_from_current(-(unread as i32));
+        }
+    }
+}
+
+/// An iterator over fixed-size chunks of a [`File`].
+///
+/// Created with [`File::chunks`].
+pub struct FileChunks<
+    'f,
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+    const N: usize,
+    const MAX_CHECKPOINTS: usize = 0,
+> where
+    D: crate::BlockDevice,
+    T: crate::TimeSource,
+{
+    file: &'f File<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    done: bool,
+}
+
+impl<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const N: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Iterator for FileChunks<'_, '_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, N, MAX_CHECKPOINTS>
+where
+    D: crate::BlockDevice,
+    T: crate::TimeSource,
+{
+    type Item = Result<heapless::Vec<u8, N>, Error<D::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut buffer = [0u8; N];
+        let mut len = 0;
+        // A read may stop at a block boundary, so keep going until the chunk
+        // is full or we run out of file.
+        while len < N {
+            match self.file.read(&mut buffer[len..]) {
+                Ok(0) => {
+                    self.done = true;
+                    break;
+                }
+                Ok(n) => len += n,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        if len == 0 {
+            None
+        } else {
+            // Can't fail, as `len <= N`
+            Some(Ok(heapless::Vec::from_slice(&buffer[..len]).unwrap()))
+        }
+    }
+}
+
+/// A snapshot of the metadata for an open file.
+///
+/// This reflects any writes made through the open file, even if they haven't
+/// yet been flushed to disk.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMeta {
+    /// The size of the file in bytes
+    pub size: u32,
+    /// What mode the file was opened in
+    pub mode: Mode,
+    /// The file attributes (Read Only, Archive, etc)
+    pub attributes: Attributes,
+    /// When the file was first created
+    pub ctime: Timestamp,
+    /// When the file was last modified
+    pub mtime: Timestamp,
+    /// The first cluster of the file, or `ClusterId::EMPTY` if the file has
+    /// no clusters yet
+    pub first_cluster: ClusterId,
+}
+
 /// Errors related to file operations
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -279,10 +641,33 @@ pub enum Mode {
     ReadWriteCreateOrAppend,
 }
 
+impl Mode {
+    /// Can a file opened in this mode be written to?
+    pub fn is_writable(self) -> bool {
+        self != Mode::ReadOnly
+    }
+
+    /// Does opening an existing file in this mode throw away its contents?
+    pub fn is_truncating(self) -> bool {
+        matches!(
+            self,
+            Mode::ReadWriteTruncate | Mode::ReadWriteCreateOrTruncate
+        )
+    }
+
+    /// Does opening a file in this mode create it, if it doesn't exist?
+    pub fn creates_if_missing(self) -> bool {
+        matches!(
+            self,
+            Mode::ReadWriteCreate | Mode::ReadWriteCreateOrTruncate | Mode::ReadWriteCreateOrAppend
+        )
+    }
+}
+
 /// Internal metadata about an open file
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, Clone)]
-pub(crate) struct FileInfo {
+pub(crate) struct FileInfo<const MAX_CHECKPOINTS: usize = 0> {
     /// Handle for this file
     pub(crate) raw_file: RawFile,
     /// The handle for the volume this directory is on
@@ -300,9 +685,23 @@ pub(crate) struct FileInfo {
     pub(crate) entry: DirEntry,
     /// Did we write to this file?
     pub(crate) dirty: bool,
+    /// Should we write the directory entry to disk after every write?
+    pub(crate) sync_entry_on_write: bool,
+    /// The directory that holds this file's directory entry
+    pub(crate) dir_cluster: ClusterId,
+    /// Where the space added by [`VolumeManager::file_extend`] starts, if
+    /// any of it hasn't been written yet.
+    ///
+    /// Everything from here to the end of the file reads as zeros, whatever
+    /// is on the disk. The zeros are only written when something is written
+    /// after them, or when the file is flushed.
+    pub(crate) zeros_from: Option<u32>,
+    /// Places we've been to in the FAT chain, so seeking backwards doesn't
+    /// have to go all the way back to the start.
+    pub(crate) checkpoints: ChainCheckpoints<MAX_CHECKPOINTS>,
 }
 
-impl FileInfo {
+impl<const MAX_CHECKPOINTS: usize> FileInfo<MAX_CHECKPOINTS> {
     /// Are we at the end of the file?
     pub fn eof(&self) -> bool {
         self.current_offset == self.entry.size
@@ -331,6 +730,19 @@ impl FileInfo {
         Ok(())
     }
 
+    /// Seek to a new position in the file, given as a signed offset from the
+    /// end of the file.
+    pub fn seek_relative_to_end(&mut self, offset: i64) -> Result<(), FileError> {
+        let new_offset = i64::from(self.entry.size)
+            .checked_add(offset)
+            .ok_or(FileError::InvalidOffset)?;
+        if new_offset < 0 || new_offset > i64::from(self.entry.size) {
+            return Err(FileError::InvalidOffset);
+        }
+        self.current_offset = new_offset as u32;
+        Ok(())
+    }
+
     /// Seek to a new position in the file, relative to the current position.
     pub fn seek_from_current(&mut self, offset: i32) -> Result<(), FileError> {
         let new_offset = i64::from(self.current_offset) + i64::from(offset);
@@ -342,8 +754,22 @@ impl FileInfo {
     }
 
     /// Amount of file left to read.
+    ///
+    /// This is zero if we are at, or somehow beyond, the end of the file.
     pub fn left(&self) -> u32 {
-        self.entry.size - self.current_offset
+        self.entry.size.saturating_sub(self.current_offset)
+    }
+
+    /// Get a snapshot of the file's metadata.
+    pub fn meta(&self) -> FileMeta {
+        FileMeta {
+            size: self.entry.size,
+            mode: self.mode,
+            attributes: self.entry.attributes,
+            ctime: self.entry.ctime,
+            mtime: self.entry.mtime,
+            first_cluster: self.entry.cluster,
+        }
     }
 
     /// Update the file's length.
@@ -352,6 +778,123 @@ impl FileInfo {
     }
 }
 
+/// How many clusters apart the checkpoints in a [`ChainCheckpoints`] are.
+pub(crate) const CHECKPOINT_INTERVAL: u32 = 64;
+
+/// Up to `N` places in a file's FAT chain, every [`CHECKPOINT_INTERVAL`]
+/// clusters, each with how far into the file (in bytes) that cluster starts.
+///
+/// The FAT is only a singly-linked list, so without these, going backwards in
+/// a file means walking the chain from its very first cluster.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChainCheckpoints<const N: usize> {
+    /// Kept in order of offset
+    points: heapless::Vec<(u32, ClusterId), N>,
+}
+
+impl<const N: usize> ChainCheckpoints<N> {
+    /// Find the checkpoint closest to `offset`, without going past it.
+    pub(crate) fn nearest(&self, offset: u32) -> Option<(u32, ClusterId)> {
+        self.points
+            .iter()
+            .take_while(|(point_offset, _)| *point_offset <= offset)
+            .last()
+            .copied()
+    }
+
+    /// Note that the cluster starting `offset` bytes into the file is
+    /// `cluster`.
+    ///
+    /// Only every [`CHECKPOINT_INTERVAL`]th cluster is kept, and only while
+    /// there is room.
+    pub(crate) fn record(&mut self, offset: u32, cluster: ClusterId, bytes_per_cluster: u32) {
+        if offset == 0 || !(offset / bytes_per_cluster).is_multiple_of(CHECKPOINT_INTERVAL) {
+            return;
+        }
+        let idx = self
+            .points
+            .iter()
+            .position(|(point_offset, _)| *point_offset >= offset)
+            .unwrap_or(self.points.len());
+        if self.points.get(idx).is_some_and(|(o, _)| *o == offset) {
+            return;
+        }
+        // If it's full, we just don't remember this one
+        let _ = self.points.insert(idx, (offset, cluster));
+    }
+}
+
+#[cfg(feature = "defmt-log")]
+impl<const N: usize> defmt::Format for ChainCheckpoints<N> {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "ChainCheckpoints({}/{})", self.points.len(), N)
+    }
+}
+
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mode_predicates() {
+        // (mode, writable, truncating, creates)
+        let cases = [
+            (Mode::ReadOnly, false, false, false),
+            (Mode::ReadWriteAppend, true, false, false),
+            (Mode::ReadWriteTruncate, true, true, false),
+            (Mode::ReadWriteCreate, true, false, true),
+            (Mode::ReadWriteCreateOrTruncate, true, true, true),
+            (Mode::ReadWriteCreateOrAppend, true, false, true),
+        ];
+        for (mode, writable, truncating, creates) in cases {
+            assert_eq!(mode.is_writable(), writable, "{:?}", mode);
+            assert_eq!(mode.is_truncating(), truncating, "{:?}", mode);
+            assert_eq!(mode.creates_if_missing(), creates, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn chain_checkpoints() {
+        const BPC: u32 = 2048;
+        const STEP: u32 = BPC * CHECKPOINT_INTERVAL;
+        let mut checkpoints = ChainCheckpoints::<2>::default();
+        // Only every 64th cluster is worth remembering, and never the first
+        checkpoints.record(0, ClusterId(2), BPC);
+        checkpoints.record(BPC, ClusterId(3), BPC);
+        assert_eq!(checkpoints.nearest(STEP * 4), None);
+        // Recorded out of order, but found in order
+        checkpoints.record(STEP * 2, ClusterId(200), BPC);
+        checkpoints.record(STEP, ClusterId(100), BPC);
+        checkpoints.record(STEP, ClusterId(999), BPC);
+        assert_eq!(checkpoints.nearest(STEP - 1), None);
+        assert_eq!(checkpoints.nearest(STEP), Some((STEP, ClusterId(100))));
+        assert_eq!(
+            checkpoints.nearest(STEP * 2 - 1),
+            Some((STEP, ClusterId(100)))
+        );
+        assert_eq!(
+            checkpoints.nearest(STEP * 5),
+            Some((STEP * 2, ClusterId(200)))
+        );
+        // Full, so this one is forgotten
+        checkpoints.record(STEP * 3, ClusterId(300), BPC);
+        assert_eq!(
+            checkpoints.nearest(STEP * 5),
+            Some((STEP * 2, ClusterId(200)))
+        );
+        // And with no room at all, nothing is kept
+        let mut none = ChainCheckpoints::<0>::default();
+        none.record(STEP, ClusterId(100), BPC);
+        assert_eq!(none.nearest(STEP), None);
+    }
+}
+
 // ****************************************************************************
 //
 // End Of File
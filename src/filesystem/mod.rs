@@ -8,6 +8,7 @@ pub const MAX_FILE_SIZE: u32 = u32::MAX;
 
 mod attributes;
 mod cluster;
+mod codepage;
 mod directory;
 mod filename;
 mod files;
@@ -16,14 +17,17 @@ mod timestamp;
 
 pub use self::attributes::Attributes;
 pub use self::cluster::ClusterId;
-pub use self::directory::{DirEntry, Directory, RawDirectory};
-pub use self::filename::{FilenameError, ShortFileName, ToShortFileName};
-pub use self::files::{File, FileError, Mode, RawFile};
+pub use self::codepage::CodePage;
+pub use self::directory::{DirEntry, DirScan, Directory, EntryKind, RawDirectory, WalkControl};
+pub use self::filename::{
+    FilenameError, ShortFileName, ShortFileNameDisplay, ShortFileNamePattern, ToShortFileName,
+};
+pub use self::files::{File, FileBytes, FileChunks, FileError, FileMeta, Mode, RawFile};
 pub use self::handles::{Handle, HandleGenerator};
-pub use self::timestamp::{TimeSource, Timestamp};
+pub use self::timestamp::{FnTimeSource, NullTimeSource, TimeSource, Timestamp};
 
 pub(crate) use self::directory::DirectoryInfo;
-pub(crate) use self::files::FileInfo;
+pub(crate) use self::files::{ChainCheckpoints, FileInfo};
 
 // ****************************************************************************
 //
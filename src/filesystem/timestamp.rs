@@ -4,11 +4,100 @@ pub trait TimeSource {
     fn get_timestamp(&self) -> Timestamp;
 }
 
+/// A [`TimeSource`] which always gives midnight on 1 January 1980.
+///
+/// That's the earliest time a FAT directory entry can hold. Useful for tests
+/// and prototypes, or on systems that don't have a clock.
+///
+/// ```
+/// use embedded_sdmmc::{BlockDevice, NullTimeSource, TimeSource, Timestamp, VolumeManager};
+///
+/// fn make_volume_manager<D>(block_device: D) -> VolumeManager<D, NullTimeSource>
+/// where
+///     D: BlockDevice,
+/// {
+///     VolumeManager::new(block_device, NullTimeSource)
+/// }
+///
+/// assert_eq!(
+///     NullTimeSource.get_timestamp(),
+///     Timestamp::from_calendar(1980, 1, 1, 0, 0, 0).unwrap()
+/// );
+/// ```
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct NullTimeSource;
+
+impl TimeSource for NullTimeSource {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 10,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// A [`TimeSource`] which calls a closure to get the time.
+///
+/// This saves writing a type and a trait impl when your clock is a single
+/// function call.
+///
+/// ```
+/// use embedded_sdmmc::{BlockDevice, FnTimeSource, TimeSource, Timestamp, VolumeManager};
+///
+/// fn read_rtc() -> Timestamp {
+///     Timestamp::from_calendar(2024, 3, 14, 15, 9, 26).unwrap()
+/// }
+///
+/// fn make_volume_manager<D>(block_device: D) -> VolumeManager<D, FnTimeSource<fn() -> Timestamp>>
+/// where
+///     D: BlockDevice,
+/// {
+///     VolumeManager::new(block_device, FnTimeSource::new(read_rtc))
+/// }
+///
+/// let time_source = FnTimeSource::new(|| Timestamp::from_calendar(2024, 3, 14, 0, 0, 0).unwrap());
+/// assert_eq!(
+///     time_source.get_timestamp(),
+///     Timestamp::from_calendar(2024, 3, 14, 0, 0, 0).unwrap()
+/// );
+/// ```
+#[derive(Debug, Default, Copy, Clone)]
+pub struct FnTimeSource<F> {
+    func: F,
+}
+
+impl<F> FnTimeSource<F>
+where
+    F: Fn() -> Timestamp,
+{
+    /// Make a time source which calls `func` every time it needs the time.
+    pub const fn new(func: F) -> FnTimeSource<F> {
+        FnTimeSource { func }
+    }
+}
+
+impl<F> TimeSource for FnTimeSource<F>
+where
+    F: Fn() -> Timestamp,
+{
+    fn get_timestamp(&self) -> Timestamp {
+        (self.func)()
+    }
+}
+
 /// A Gregorian Calendar date/time, in the local time zone.
 ///
+/// Timestamps are ordered chronologically, so you can sort directory entries
+/// with `entries.sort_by_key(|e| e.mtime)`. This relies on the fields being
+/// declared from most to least significant, so don't re-order them.
+///
 /// TODO: Consider replacing this with POSIX time as a `u32`, which would save
 /// two bytes at the expense of some maths.
-#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Copy, Clone, PartialOrd, Ord, PartialEq, Eq)]
 pub struct Timestamp {
     /// Add 1970 to this file to get the calendar year
@@ -66,6 +155,18 @@ impl Timestamp {
         data
     }
 
+    /// Get the same day, at midnight.
+    ///
+    /// This is all a FAT last access date can hold.
+    pub(crate) const fn date_only(self) -> Timestamp {
+        Timestamp {
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+            ..self
+        }
+    }
+
     /// Create a `Timestamp` from year/month/day/hour/minute/second.
     ///
     /// Values should be given as you'd write then (i.e. 1980, 01, 01, 13, 30,
@@ -111,6 +212,118 @@ impl Timestamp {
             },
         })
     }
+
+    /// Get the current time from a [`TimeSource`], moved by the given
+    /// number of seconds.
+    ///
+    /// Handy if your clock runs in UTC but you want local time on your
+    /// files, or if you know your clock is off by a fixed amount. Gives
+    /// `None` if the result can't be held in a `Timestamp`.
+    ///
+    /// ```
+    /// use embedded_sdmmc::{NullTimeSource, Timestamp};
+    ///
+    /// assert_eq!(
+    ///     Timestamp::now_from(&NullTimeSource, 90 * 60),
+    ///     Timestamp::from_calendar(1980, 1, 1, 1, 30, 0).ok()
+    /// );
+    /// ```
+    pub fn now_from<T>(time_source: &T, offset_seconds: i32) -> Option<Timestamp>
+    where
+        T: TimeSource,
+    {
+        time_source
+            .get_timestamp()
+            .checked_add_seconds(offset_seconds)
+    }
+
+    /// Move this timestamp forwards (or, if negative, backwards) by the given
+    /// number of seconds, rolling over into the next or previous day, month
+    /// and year as required.
+    ///
+    /// Gives `None` if the result can't be held in a `Timestamp`.
+    pub fn checked_add_seconds(self, seconds: i32) -> Option<Timestamp> {
+        const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+        let time_of_day = i64::from(self.hours) * 3600
+            + i64::from(self.minutes) * 60
+            + i64::from(u16::from(self.seconds));
+        let total = self.days_since_epoch() * SECONDS_PER_DAY + time_of_day + i64::from(seconds);
+        let time_of_day = total.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, day) = Self::civil_from_days(total.div_euclid(SECONDS_PER_DAY));
+        if !(1970..=(1970 + 255)).contains(&year) {
+            return None;
+        }
+        Some(Timestamp {
+            year_since_1970: (year - 1970) as u8,
+            zero_indexed_month: (month - 1) as u8,
+            zero_indexed_day: (day - 1) as u8,
+            hours: (time_of_day / 3600) as u8,
+            minutes: ((time_of_day / 60) % 60) as u8,
+            seconds: (time_of_day % 60) as u8,
+        })
+    }
+
+    /// Write this timestamp into `buf` as `YYYY-MM-DDTHH:MM:SS`, and get it
+    /// back as a `&str`.
+    ///
+    /// This fills the first 19 bytes of `buf`. Each field other than the year
+    /// always gets two digits, so a field set out of range by hand only shows
+    /// its last two.
+    pub fn format_iso8601<'b>(&self, buf: &'b mut [u8; 20]) -> &'b str {
+        fn two_digits(buf: &mut [u8], value: u16) {
+            buf[0] = b'0' + ((value / 10) % 10) as u8;
+            buf[1] = b'0' + (value % 10) as u8;
+        }
+        let year = u16::from(self.year_since_1970) + 1970;
+        buf[0] = b'0' + (year / 1000) as u8;
+        buf[1] = b'0' + ((year / 100) % 10) as u8;
+        buf[2] = b'0' + ((year / 10) % 10) as u8;
+        buf[3] = b'0' + (year % 10) as u8;
+        buf[4] = b'-';
+        two_digits(&mut buf[5..7], u16::from(self.zero_indexed_month) + 1);
+        buf[7] = b'-';
+        two_digits(&mut buf[8..10], u16::from(self.zero_indexed_day) + 1);
+        buf[10] = b'T';
+        two_digits(&mut buf[11..13], u16::from(self.hours));
+        buf[13] = b':';
+        two_digits(&mut buf[14..16], u16::from(self.minutes));
+        buf[16] = b':';
+        two_digits(&mut buf[17..19], u16::from(self.seconds));
+        // Everything we wrote is ASCII
+        core::str::from_utf8(&buf[..19]).unwrap()
+    }
+
+    /// The number of days from 1970-01-01 to the date of this timestamp.
+    ///
+    /// Uses Howard Hinnant's `days_from_civil` algorithm.
+    fn days_since_epoch(&self) -> i64 {
+        let month = i64::from(self.zero_indexed_month) + 1;
+        let day = i64::from(self.zero_indexed_day) + 1;
+        let year = i64::from(self.year_since_1970) + 1970 - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+
+    /// Convert a number of days since 1970-01-01 to a (year, month, day), with
+    /// month and day counting from one.
+    ///
+    /// Uses Howard Hinnant's `civil_from_days` algorithm.
+    fn civil_from_days(days: i64) -> (i64, i64, i64) {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = year_of_era + era * 400 + i64::from(month <= 2);
+        (year, month, day)
+    }
 }
 
 impl core::fmt::Debug for Timestamp {
@@ -119,6 +332,22 @@ impl core::fmt::Debug for Timestamp {
     }
 }
 
+#[cfg(feature = "defmt-log")]
+impl defmt::Format for Timestamp {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(
+            f,
+            "Timestamp({=u16}-{=u16:02}-{=u16:02} {=u8:02}:{=u8:02}:{=u8:02})",
+            u16::from(self.year_since_1970) + 1970,
+            u16::from(self.zero_indexed_month) + 1,
+            u16::from(self.zero_indexed_day) + 1,
+            self.hours,
+            self.minutes,
+            self.seconds
+        )
+    }
+}
+
 impl core::fmt::Display for Timestamp {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
@@ -134,6 +363,145 @@ impl core::fmt::Display for Timestamp {
     }
 }
 
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ordering_across_year_boundary() {
+        let old = Timestamp::from_calendar(2023, 12, 31, 23, 59, 59).unwrap();
+        let new = Timestamp::from_calendar(2024, 1, 1, 0, 0, 0).unwrap();
+        assert!(old < new);
+        assert!(new > old);
+        assert_eq!(old.max(new), new);
+        assert_ne!(old, new);
+    }
+
+    #[test]
+    fn ordering_within_minute() {
+        let a = Timestamp::from_calendar(2003, 4, 4, 13, 30, 4).unwrap();
+        let b = Timestamp::from_calendar(2003, 4, 4, 13, 30, 58).unwrap();
+        assert!(a < b);
+        assert_eq!(a.cmp(&a), core::cmp::Ordering::Equal);
+        assert_eq!(a, Timestamp::from_calendar(2003, 4, 4, 13, 30, 4).unwrap());
+    }
+
+    #[test]
+    fn ordering_later_fields_dont_override_earlier_ones() {
+        // A later time of day must not beat an earlier date
+        let a = Timestamp::from_calendar(2003, 4, 4, 23, 59, 59).unwrap();
+        let b = Timestamp::from_calendar(2003, 4, 5, 0, 0, 0).unwrap();
+        assert!(a < b);
+        let c = Timestamp::from_calendar(2003, 3, 31, 0, 0, 0).unwrap();
+        assert!(c < a);
+
+        let mut stamps = [b, a, c];
+        stamps.sort();
+        assert_eq!(stamps, [c, a, b]);
+    }
+
+    #[test]
+    fn format_iso8601() {
+        let mut buf = [0u8; 20];
+        // The time the integration tests use, as a FAT timestamp
+        let stamp = Timestamp::from_fat(0x2E84, 0x6BC2);
+        assert_eq!(stamp.format_iso8601(&mut buf), "2003-04-04T13:30:04");
+        let stamp = Timestamp::from_calendar(1970, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(stamp.format_iso8601(&mut buf), "1970-01-01T00:00:00");
+        let stamp = Timestamp::from_calendar(2225, 12, 31, 23, 59, 59).unwrap();
+        assert_eq!(stamp.format_iso8601(&mut buf), "2225-12-31T23:59:59");
+        // Nonsense fields still give two digits each, without overflowing
+        let stamp = Timestamp {
+            year_since_1970: 255,
+            zero_indexed_month: 255,
+            zero_indexed_day: 255,
+            hours: 255,
+            minutes: 255,
+            seconds: 255,
+        };
+        assert_eq!(stamp.format_iso8601(&mut buf), "2225-56-56T55:55:55");
+    }
+
+    #[test]
+    fn date_only() {
+        let stamp = Timestamp::from_calendar(2023, 6, 14, 23, 30, 10).unwrap();
+        assert_eq!(
+            stamp.date_only(),
+            Timestamp::from_calendar(2023, 6, 14, 0, 0, 0).unwrap()
+        );
+    }
+
+    struct FixedTimeSource(Timestamp);
+
+    impl TimeSource for FixedTimeSource {
+        fn get_timestamp(&self) -> Timestamp {
+            self.0
+        }
+    }
+
+    #[test]
+    fn offset_across_midnight() {
+        let source = FixedTimeSource(Timestamp::from_calendar(2023, 6, 14, 23, 30, 0).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, 2 * 3600),
+            Some(Timestamp::from_calendar(2023, 6, 15, 1, 30, 0).unwrap())
+        );
+        let source = FixedTimeSource(Timestamp::from_calendar(2023, 6, 15, 0, 15, 10).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, -(3600 + 20)),
+            Some(Timestamp::from_calendar(2023, 6, 14, 23, 14, 50).unwrap())
+        );
+        assert_eq!(
+            Timestamp::now_from(&source, 0),
+            Some(Timestamp::from_calendar(2023, 6, 15, 0, 15, 10).unwrap())
+        );
+    }
+
+    #[test]
+    fn offset_across_month_boundary() {
+        // Into March in a leap year, and in a non-leap year
+        let source = FixedTimeSource(Timestamp::from_calendar(2024, 2, 28, 22, 0, 0).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, 3 * 3600),
+            Some(Timestamp::from_calendar(2024, 2, 29, 1, 0, 0).unwrap())
+        );
+        let source = FixedTimeSource(Timestamp::from_calendar(2023, 2, 28, 22, 0, 0).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, 3 * 3600),
+            Some(Timestamp::from_calendar(2023, 3, 1, 1, 0, 0).unwrap())
+        );
+        // Back into the previous month, and the previous year
+        let source = FixedTimeSource(Timestamp::from_calendar(2023, 5, 1, 0, 30, 0).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, -3600),
+            Some(Timestamp::from_calendar(2023, 4, 30, 23, 30, 0).unwrap())
+        );
+        let source = FixedTimeSource(Timestamp::from_calendar(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(
+            Timestamp::now_from(&source, -1),
+            Some(Timestamp::from_calendar(2023, 12, 31, 23, 59, 59).unwrap())
+        );
+    }
+
+    #[test]
+    fn offset_out_of_range() {
+        let source = FixedTimeSource(Timestamp::from_calendar(1970, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(Timestamp::now_from(&source, -1), None);
+        let source = FixedTimeSource(Timestamp::from_calendar(2225, 12, 31, 23, 59, 59).unwrap());
+        assert_eq!(Timestamp::now_from(&source, 1), None);
+        assert_eq!(
+            Timestamp::now_from(&source, -59),
+            Some(Timestamp::from_calendar(2225, 12, 31, 23, 59, 0).unwrap())
+        );
+    }
+}
+
 // ****************************************************************************
 //
 // End Of File
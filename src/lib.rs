@@ -51,6 +51,12 @@
 //!   instead.
 //!
 //! You cannot enable both the `log` feature and the `defmt-log` feature.
+//!
+//! ## Supported targets
+//!
+//! The crate works on targets with a 16, 32 or 64-bit `usize`. On a 16-bit
+//! target files can still be up to 4 GiB long, but one `read` or `write` can
+//! only move as many bytes as a slice can hold.
 
 #![cfg_attr(not(test), no_std)]
 #![deny(missing_docs)]
@@ -71,6 +77,8 @@ mod structure;
 pub mod blockdevice;
 pub mod fat;
 pub mod filesystem;
+#[cfg(feature = "embedded-storage")]
+pub mod norflash;
 pub mod sdcard;
 
 use core::fmt::Debug;
@@ -78,19 +86,31 @@ use embedded_io::ErrorKind;
 use filesystem::Handle;
 
 #[doc(inline)]
-pub use crate::blockdevice::{Block, BlockCount, BlockDevice, BlockIdx};
+pub use crate::blockdevice::{
+    Block, BlockCount, BlockDevice, BlockIdx, InstrumentedBlockDevice, IoStats, SubBlockDevice,
+    SubBlockDeviceError,
+};
 
 #[doc(inline)]
-pub use crate::fat::{FatVolume, VolumeName};
+pub use crate::fat::{FatType, FatVolume, FsckReport, VolumeName};
 
 #[doc(inline)]
 pub use crate::filesystem::{
-    Attributes, ClusterId, DirEntry, Directory, File, FilenameError, Mode, RawDirectory, RawFile,
-    ShortFileName, TimeSource, Timestamp, MAX_FILE_SIZE,
+    Attributes, ClusterId, CodePage, DirEntry, DirScan, Directory, EntryKind, File, FilenameError,
+    FnTimeSource, Mode, NullTimeSource, RawDirectory, RawFile, ShortFileName, ShortFileNameDisplay,
+    ShortFileNamePattern, TimeSource, Timestamp, WalkControl, MAX_FILE_SIZE,
 };
 
 use filesystem::DirectoryInfo;
 
+#[cfg(feature = "embedded-storage")]
+#[doc(inline)]
+pub use crate::norflash::Error as NorFlashBlockDeviceError;
+
+#[cfg(feature = "embedded-storage")]
+#[doc(inline)]
+pub use crate::norflash::NorFlashBlockDevice;
+
 #[doc(inline)]
 pub use crate::sdcard::Error as SdCardError;
 
@@ -99,7 +119,7 @@ pub use crate::sdcard::SdCard;
 
 mod volume_mgr;
 #[doc(inline)]
-pub use volume_mgr::VolumeManager;
+pub use volume_mgr::{FileOpts, VolumeManager, VolumeManagerBuilder, VolumeOpts};
 
 #[cfg(all(feature = "defmt-log", feature = "log"))]
 compile_error!("Cannot enable both log and defmt-log");
@@ -189,6 +209,10 @@ where
     /// Cluster was not properly allocated by the library
     AllocationError,
     /// Jumped to free space during FAT traversing
+    ///
+    /// The cluster chain of a file or directory ran into a free cluster before
+    /// it ended, so the filesystem is corrupt. Reading a file stops at the end
+    /// of the last good cluster, and then gives this error.
     UnterminatedFatChain,
     /// Tried to open Read-Only file with write mode
     ReadOnly,
@@ -207,6 +231,16 @@ where
     /// This is either a bug in the filesystem, or you tried to access the
     /// filesystem API from inside a directory iterator (that isn't allowed).
     LockError,
+    /// Searching or listing a directory needed more block reads than
+    /// [`VolumeOpts::max_dir_scan_blocks`] allows.
+    DirectoryScanLimitExceeded,
+    /// The copies of the FAT don't match, starting at this block of the first
+    /// FAT.
+    FatMirrorMismatch(BlockIdx),
+    /// The write would make the file longer than
+    /// [`MAX_FILE_SIZE`](crate::filesystem::MAX_FILE_SIZE) bytes, which is the
+    /// most a FAT directory entry can record. Nothing was written.
+    FileTooLarge,
 }
 
 impl<E: Debug> embedded_io::Error for Error<E> {
@@ -220,6 +254,7 @@ impl<E: Debug> embedded_io::Error for Error<E> {
             | Error::VolumeAlreadyOpen
             | Error::EndOfFile
             | Error::DiskFull
+            | Error::FileTooLarge
             | Error::NotEnoughSpace
             | Error::AllocationError
             | Error::LockError => ErrorKind::Other,
@@ -236,20 +271,109 @@ impl<E: Debug> embedded_io::Error for Error<E> {
             | Error::DeleteDirAsFile
             | Error::BadCluster
             | Error::ConversionError
-            | Error::UnterminatedFatChain => ErrorKind::InvalidData,
+            | Error::UnterminatedFatChain
+            | Error::FatMirrorMismatch(_) => ErrorKind::InvalidData,
             Error::Unsupported | Error::BadBlockSize(_) => ErrorKind::Unsupported,
             Error::ReadOnly => ErrorKind::PermissionDenied,
             Error::FileAlreadyExists | Error::DirAlreadyExists => ErrorKind::AlreadyExists,
+            Error::DirectoryScanLimitExceeded => ErrorKind::TimedOut,
         }
     }
 }
 
+impl<E: Debug> core::fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::DeviceError(e) => write!(f, "block device error: {:?}", e),
+            Error::FormatError(s) => write!(f, "bad filesystem format: {}", s),
+            Error::NoSuchVolume => write!(f, "no such volume"),
+            Error::FilenameError(e) => write!(f, "bad filename: {}", e),
+            Error::TooManyOpenVolumes => write!(f, "too many open volumes"),
+            Error::TooManyOpenDirs => write!(f, "too many open directories"),
+            Error::TooManyOpenFiles => write!(f, "too many open files"),
+            Error::BadHandle => write!(f, "bad handle"),
+            Error::NotFound => write!(f, "file or directory not found"),
+            Error::FileAlreadyOpen => write!(f, "file already open"),
+            Error::DirAlreadyOpen => write!(f, "directory already open"),
+            Error::OpenedDirAsFile => write!(f, "tried to open a directory as a file"),
+            Error::OpenedFileAsDir => write!(f, "tried to open a file as a directory"),
+            Error::DeleteDirAsFile => write!(f, "tried to delete a directory as a file"),
+            Error::VolumeStillInUse => write!(f, "volume still has open files or directories"),
+            Error::VolumeAlreadyOpen => write!(f, "volume already open"),
+            Error::Unsupported => write!(f, "unsupported operation"),
+            Error::EndOfFile => write!(f, "end of file"),
+            Error::BadCluster => write!(f, "bad cluster"),
+            Error::ConversionError => write!(f, "type conversion failed"),
+            Error::NotEnoughSpace => write!(f, "not enough space"),
+            Error::AllocationError => write!(f, "cluster not allocated"),
+            Error::UnterminatedFatChain => write!(f, "cluster chain ran into free space"),
+            Error::ReadOnly => write!(f, "file is read-only"),
+            Error::FileAlreadyExists => write!(f, "file already exists"),
+            Error::BadBlockSize(size) => write!(f, "bad block size {} (only 512 supported)", size),
+            Error::InvalidOffset => write!(f, "invalid offset"),
+            Error::DiskFull => write!(f, "disk full"),
+            Error::DirAlreadyExists => write!(f, "directory already exists"),
+            Error::LockError => write!(f, "filesystem already locked"),
+            Error::DirectoryScanLimitExceeded => write!(f, "directory scan limit exceeded"),
+            Error::FatMirrorMismatch(block) => write!(f, "FAT copies differ at block {}", block),
+            Error::FileTooLarge => write!(f, "file would be too large"),
+        }
+    }
+}
+
+/// Needs the `core-error` feature, and Rust 1.81 or later.
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::DeviceError(e) => Some(e),
+            Error::FilenameError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl<E> Error<E>
+where
+    E: core::fmt::Debug,
+{
+    /// Wrap an error from the block device.
+    ///
+    /// This is the same as `Error::DeviceError(error)`, but it reads better
+    /// when you pass it to `map_err`. If your own code calls the block device
+    /// directly and returns `Error<D::Error>`, `?` will do this conversion
+    /// for you:
+    ///
+    /// ```
+    /// use embedded_sdmmc::{Block, BlockDevice, BlockIdx, Error};
+    ///
+    /// fn read_first_byte<D>(block_device: &D) -> Result<u8, Error<D::Error>>
+    /// where
+    ///     D: BlockDevice,
+    /// {
+    ///     let mut blocks = [Block::new()];
+    ///     block_device.read(&mut blocks, BlockIdx(0))?;
+    ///     Ok(blocks[0][0])
+    /// }
+    /// ```
+    ///
+    /// If the compiler can't work out which error type you want (because
+    /// the `?` is inside a closure, say), use
+    /// `.map_err(Error::from_device)?` instead.
+    pub fn from_device(error: E) -> Self {
+        Error::DeviceError(error)
+    }
+}
+
 impl<E> From<E> for Error<E>
 where
     E: core::fmt::Debug,
 {
     fn from(value: E) -> Error<E> {
-        Error::DeviceError(value)
+        Error::from_device(value)
     }
 }
 
@@ -275,10 +399,11 @@ impl RawVolume {
         const MAX_DIRS: usize,
         const MAX_FILES: usize,
         const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
     >(
         self,
-        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> Volume<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+        volume_mgr: &VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> Volume<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
     where
         D: crate::BlockDevice,
         T: crate::TimeSource,
@@ -295,17 +420,31 @@ impl RawVolume {
 /// If you drop a value of this type, it closes the volume automatically, but
 /// any error that may occur will be ignored. To handle potential errors, use
 /// the [`Volume::close`] method.
-pub struct Volume<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-where
+pub struct Volume<
+    'a,
+    D,
+    T,
+    const MAX_DIRS: usize,
+    const MAX_FILES: usize,
+    const MAX_VOLUMES: usize,
+    const MAX_CHECKPOINTS: usize = 0,
+> where
     D: crate::BlockDevice,
     T: crate::TimeSource,
 {
     raw_volume: RawVolume,
-    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
+    volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -313,8 +452,8 @@ where
     /// Create a new `Volume` from a `RawVolume`
     pub fn new(
         raw_volume: RawVolume,
-        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>,
-    ) -> Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
+        volume_mgr: &'a VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+    ) -> Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
         Volume {
             raw_volume,
             volume_mgr,
@@ -327,11 +466,55 @@ where
     /// use `open_file_in_dir`.
     pub fn open_root_dir(
         &self,
-    ) -> Result<crate::Directory<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, Error<D::Error>> {
+    ) -> Result<
+        crate::Directory<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>,
+        Error<D::Error>,
+    > {
         let d = self.volume_mgr.open_root_dir(self.raw_volume)?;
         Ok(d.to_directory(self.volume_mgr))
     }
 
+    /// Read a whole file, given its path from the root of the volume.
+    ///
+    /// See [`VolumeManager::read_file_to`] for details.
+    pub fn read_file_to(&self, path: &str, buffer: &mut [u8]) -> Result<usize, Error<D::Error>> {
+        self.volume_mgr.read_file_to(self.raw_volume, path, buffer)
+    }
+
+    /// Write a whole file, given its path from the root of the volume.
+    ///
+    /// See [`VolumeManager::write_file_from`] for details.
+    pub fn write_file_from(
+        &self,
+        path: &str,
+        buffer: &[u8],
+        mode: Mode,
+    ) -> Result<usize, Error<D::Error>> {
+        self.volume_mgr
+            .write_file_from(self.raw_volume, path, buffer, mode)
+    }
+
+    /// Get which kind of FAT this volume uses.
+    pub fn fat_type(&self) -> FatType {
+        self.volume_mgr
+            .fat_type(self.raw_volume)
+            .expect("Corrupt volume ID")
+    }
+
+    /// Get the number of bytes in each cluster of this volume.
+    pub fn bytes_per_cluster(&self) -> u32 {
+        self.volume_mgr
+            .bytes_per_cluster(self.raw_volume)
+            .expect("Corrupt volume ID")
+    }
+
+    /// Get the total number of data clusters in this volume.
+    pub fn cluster_count(&self) -> u32 {
+        self.volume_mgr
+            .cluster_count(self.raw_volume)
+            .expect("Corrupt volume ID")
+    }
+
     /// Convert back to a raw volume
     pub fn to_raw_volume(self) -> RawVolume {
         let v = self.raw_volume;
@@ -350,8 +533,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize> Drop
-    for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > Drop for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -361,8 +551,15 @@ where
     }
 }
 
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    core::fmt::Debug for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > core::fmt::Debug for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -373,8 +570,15 @@ where
 }
 
 #[cfg(feature = "defmt-log")]
-impl<'a, D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    defmt::Format for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        'a,
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > defmt::Format for Volume<'a, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: crate::BlockDevice,
     T: crate::TimeSource,
@@ -394,6 +598,12 @@ pub(crate) struct VolumeInfo {
     idx: VolumeIdx,
     /// What kind of volume this is
     volume_type: VolumeType,
+    /// Whether we refuse to write to this volume
+    read_only: bool,
+    /// How to turn short file names on this volume into text
+    code_page: CodePage,
+    /// Whether to cache the volume's details when it is closed
+    cache_geometry: bool,
 }
 
 /// This enum holds the data for the various different types of filesystems we
@@ -408,6 +618,8 @@ pub enum VolumeType {
 /// A number which identifies a volume (or partition) on a disk.
 ///
 /// `VolumeIdx(0)` is the first primary partition on an MBR partitioned disk.
+/// `VolumeIdx(4)` and above are the logical partitions inside the disk's
+/// extended partition, if it has one.
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub struct VolumeIdx(pub usize);
@@ -423,6 +635,10 @@ const PARTITION_ID_FAT16: u8 = 0x06;
 /// Marker for a FAT32 partition. What Macosx disk utility (and also SD-Card formatter?)
 /// use.
 const PARTITION_ID_FAT32_CHS_LBA: u8 = 0x0B;
+/// Marker for an extended partition, which holds logical partitions.
+const PARTITION_ID_EXTENDED: u8 = 0x05;
+/// Marker for an extended partition with LBA.
+const PARTITION_ID_EXTENDED_LBA: u8 = 0x0F;
 
 // ****************************************************************************
 //
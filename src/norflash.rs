@@ -0,0 +1,448 @@
+//! Implements the BlockDevice trait for NOR flash, using the traits from
+//! `embedded-storage`.
+//!
+//! Only available with the `embedded-storage` feature enabled.
+
+use core::cell::RefCell;
+
+use embedded_storage::nor_flash::NorFlash;
+
+use crate::{trace, Block, BlockCount, BlockDevice, BlockIdx};
+
+// ****************************************************************************
+// Types and Implementations
+// ****************************************************************************
+
+/// A [`BlockDevice`] on top of some NOR flash.
+///
+/// NOR flash can only be written once after it has been erased, and can only
+/// be erased in large sectors. So to write a block, we read the whole sector
+/// into a buffer, put the new block in, erase the sector, and write the
+/// buffer back. If the blocks being written were already erased, we skip the
+/// erase and just write them, and if they already hold the given data, we
+/// don't touch the flash at all.
+///
+/// `SECTOR` is the size of that buffer in bytes, which must be a multiple of
+/// both the flash's erase size and the 512 byte block size. The default of
+/// 4 KiB suits most SPI NOR flash. The block size must also be a multiple of
+/// the flash's read and write sizes. These are checked when the device is
+/// created (at compile time).
+///
+/// Note that repeatedly writing the FAT and directory blocks will wear out a
+/// sector much faster than the rest of the flash, as there is no wear
+/// levelling.
+pub struct NorFlashBlockDevice<F, const SECTOR: usize = 4096>
+where
+    F: NorFlash,
+{
+    inner: RefCell<Inner<F, SECTOR>>,
+}
+
+/// The parts of a [`NorFlashBlockDevice`] which need `&mut` access.
+struct Inner<F, const SECTOR: usize> {
+    flash: F,
+    buffer: [u8; SECTOR],
+}
+
+impl<F, const SECTOR: usize> NorFlashBlockDevice<F, SECTOR>
+where
+    F: NorFlash,
+{
+    /// Fails to compile if the flash and the buffer don't fit together.
+    const GEOMETRY_OK: () = {
+        assert!(SECTOR > 0 && SECTOR.is_multiple_of(F::ERASE_SIZE));
+        assert!(SECTOR.is_multiple_of(Block::LEN));
+        assert!(Block::LEN.is_multiple_of(F::READ_SIZE));
+        assert!(Block::LEN.is_multiple_of(F::WRITE_SIZE));
+    };
+
+    const BLOCKS_PER_SECTOR: u32 = (SECTOR / Block::LEN) as u32;
+
+    /// Create a new block device using the given flash.
+    ///
+    /// Any partial block at the end of the flash is not used.
+    pub fn new(flash: F) -> NorFlashBlockDevice<F, SECTOR> {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::GEOMETRY_OK;
+        NorFlashBlockDevice {
+            inner: RefCell::new(Inner {
+                flash,
+                buffer: [0xFF; SECTOR],
+            }),
+        }
+    }
+
+    /// Get a temporary borrow on the underlying flash.
+    ///
+    /// The given closure will be called exactly once, and will be passed a
+    /// mutable reference to the underlying flash object.
+    pub fn flash<T, FUNC>(&self, func: FUNC) -> T
+    where
+        FUNC: FnOnce(&mut F) -> T,
+    {
+        let mut inner = self.inner.borrow_mut();
+        func(&mut inner.flash)
+    }
+
+    /// Give back the underlying flash.
+    pub fn free(self) -> F {
+        self.inner.into_inner().flash
+    }
+
+    /// Check that `count` blocks starting at `start` are on the flash.
+    fn check_range(&self, start: BlockIdx, count: usize) -> Result<(), Error<F::Error>> {
+        let count = u32::try_from(count).map_err(|_| Error::OutOfBounds)?;
+        let end = start
+            .checked_add(BlockCount(count))
+            .ok_or(Error::OutOfBounds)?;
+        let num_blocks = self.inner.borrow().num_blocks();
+        if end.0 > num_blocks.0 {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(())
+    }
+}
+
+impl<F, const SECTOR: usize> Inner<F, SECTOR>
+where
+    F: NorFlash,
+{
+    fn num_blocks(&self) -> BlockCount {
+        let blocks = self.flash.capacity() / Block::LEN;
+        BlockCount(u32::try_from(blocks).unwrap_or(u32::MAX))
+    }
+
+    /// Write some blocks which all live in the sector starting at
+    /// `sector_offset`.
+    fn write_sector(
+        &mut self,
+        sector_offset: u32,
+        blocks: &[Block],
+        first_block_offset: u32,
+    ) -> Result<(), F::Error> {
+        self.flash.read(sector_offset, &mut self.buffer)?;
+        let start = (first_block_offset - sector_offset) as usize;
+        let end = start + blocks.len() * Block::LEN;
+        let old = &self.buffer[start..end];
+        let new = blocks.iter().flat_map(|b| b.contents.iter());
+
+        if old.iter().zip(new.clone()).all(|(o, n)| o == n) {
+            trace!("Flash already holds data @ {}", first_block_offset);
+            return Ok(());
+        }
+
+        if old.iter().all(|b| *b == 0xFF) {
+            // Already erased, so we can just program the new data
+            trace!("Writing erased flash @ {}", first_block_offset);
+            for (i, block) in blocks.iter().enumerate() {
+                let offset = first_block_offset + (i * Block::LEN) as u32;
+                self.flash.write(offset, &block.contents)?;
+            }
+            return Ok(());
+        }
+
+        trace!("Erasing and re-writing flash sector @ {}", sector_offset);
+        for (dest, src) in self.buffer[start..end].iter_mut().zip(new) {
+            *dest = *src;
+        }
+        self.flash
+            .erase(sector_offset, sector_offset + SECTOR as u32)?;
+        self.flash.write(sector_offset, &self.buffer)
+    }
+}
+
+impl<F, const SECTOR: usize> BlockDevice for NorFlashBlockDevice<F, SECTOR>
+where
+    F: NorFlash,
+{
+    type Error = Error<F::Error>;
+
+    /// Read one or more blocks, starting at the given block index.
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.check_range(start_block_idx, blocks.len())?;
+        let mut inner = self.inner.borrow_mut();
+        for (block, idx) in blocks.iter_mut().zip(start_block_idx.0..) {
+            inner
+                .flash
+                .read(idx * Block::LEN_U32, &mut block.contents)
+                .map_err(Error::Flash)?;
+        }
+        Ok(())
+    }
+
+    /// Write one or more blocks, starting at the given block index.
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.check_range(start_block_idx, blocks.len())?;
+        let mut inner = self.inner.borrow_mut();
+        let mut blocks = blocks;
+        let mut block_idx = start_block_idx.0;
+        while !blocks.is_empty() {
+            // Do every block that lives in this sector in one go
+            let sector_idx = block_idx / Self::BLOCKS_PER_SECTOR;
+            let in_sector = Self::BLOCKS_PER_SECTOR - (block_idx % Self::BLOCKS_PER_SECTOR);
+            let count = blocks.len().min(in_sector as usize);
+            let (these, rest) = blocks.split_at(count);
+            inner
+                .write_sector(
+                    sector_idx * SECTOR as u32,
+                    these,
+                    block_idx * Block::LEN_U32,
+                )
+                .map_err(Error::Flash)?;
+            blocks = rest;
+            block_idx += count as u32;
+        }
+        Ok(())
+    }
+
+    /// Determine how many blocks this device can hold.
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        Ok(self.inner.borrow().num_blocks())
+    }
+
+    /// One sector's worth of blocks, so a burst can be written with a single
+    /// erase.
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        BlockCount(Self::BLOCKS_PER_SECTOR)
+    }
+}
+
+/// The possible errors a [`NorFlashBlockDevice`] can generate.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error<E> {
+    /// The flash gave us an error
+    Flash(E),
+    /// The blocks requested are beyond the end of the flash
+    OutOfBounds,
+}
+
+// ****************************************************************************
+//
+// Unit Tests
+//
+// ****************************************************************************
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_storage::nor_flash::{
+        check_erase, check_read, check_write, ErrorType, NorFlashErrorKind, ReadNorFlash,
+    };
+
+    /// Something the mock flash was asked to do.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum FlashOp {
+        Read { offset: u32, len: usize },
+        Erase { from: u32, to: u32 },
+        Write { offset: u32, len: usize },
+    }
+
+    /// Flash which behaves like NOR flash - writes can only clear bits, and
+    /// erasing a 4 KiB sector sets them again.
+    struct MockFlash {
+        data: Vec<u8>,
+        ops: Vec<FlashOp>,
+    }
+
+    impl MockFlash {
+        fn new(sectors: usize) -> MockFlash {
+            MockFlash {
+                data: vec![0xFF; sectors * 4096],
+                ops: Vec::new(),
+            }
+        }
+    }
+
+    impl ErrorType for MockFlash {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for MockFlash {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            check_read(self, offset, bytes.len())?;
+            self.ops.push(FlashOp::Read {
+                offset,
+                len: bytes.len(),
+            });
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for MockFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = 4096;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            check_erase(self, from, to)?;
+            self.ops.push(FlashOp::Erase { from, to });
+            self.data[from as usize..to as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            check_write(self, offset, bytes.len())?;
+            self.ops.push(FlashOp::Write {
+                offset,
+                len: bytes.len(),
+            });
+            let offset = offset as usize;
+            for (dest, src) in self.data[offset..offset + bytes.len()]
+                .iter_mut()
+                .zip(bytes)
+            {
+                *dest &= *src;
+            }
+            Ok(())
+        }
+    }
+
+    fn filled(value: u8) -> Block {
+        Block::from_array([value; Block::LEN])
+    }
+
+    #[test]
+    fn partial_sector_write() {
+        let device: NorFlashBlockDevice<_> = NorFlashBlockDevice::new(MockFlash::new(4));
+        assert_eq!(device.num_blocks().unwrap(), BlockCount(32));
+        device
+            .write(&[filled(0x11), filled(0x22)], BlockIdx(8))
+            .unwrap();
+        device.flash(|f| f.ops.clear());
+
+        // Overwrite the second block of the second sector
+        device.write(&[filled(0x33)], BlockIdx(9)).unwrap();
+        device.flash(|f| {
+            assert_eq!(
+                f.ops,
+                [
+                    FlashOp::Read {
+                        offset: 4096,
+                        len: 4096
+                    },
+                    FlashOp::Erase {
+                        from: 4096,
+                        to: 8192
+                    },
+                    FlashOp::Write {
+                        offset: 4096,
+                        len: 4096
+                    },
+                ]
+            );
+        });
+
+        // The rest of the sector survived the erase
+        let mut blocks = [Block::new(), Block::new(), Block::new()];
+        device.read(&mut blocks, BlockIdx(8)).unwrap();
+        assert_eq!(blocks[0].contents, [0x11; Block::LEN]);
+        assert_eq!(blocks[1].contents, [0x33; Block::LEN]);
+        assert_eq!(blocks[2].contents, [0xFF; Block::LEN]);
+        device.flash(|f| assert!(f.data[..4096].iter().all(|b| *b == 0xFF)));
+    }
+
+    #[test]
+    fn erased_write_skips_erase() {
+        let device: NorFlashBlockDevice<_> = NorFlashBlockDevice::new(MockFlash::new(2));
+        device.write(&[filled(0x44)], BlockIdx(3)).unwrap();
+        device.flash(|f| {
+            assert_eq!(
+                f.ops,
+                [
+                    FlashOp::Read {
+                        offset: 0,
+                        len: 4096
+                    },
+                    FlashOp::Write {
+                        offset: 1536,
+                        len: 512
+                    },
+                ]
+            );
+            f.ops.clear();
+        });
+
+        // Writing the same data again doesn't touch the flash
+        device.write(&[filled(0x44)], BlockIdx(3)).unwrap();
+        device.flash(|f| {
+            assert_eq!(
+                f.ops,
+                [FlashOp::Read {
+                    offset: 0,
+                    len: 4096
+                }]
+            );
+        });
+    }
+
+    #[test]
+    fn write_across_sectors() {
+        let device: NorFlashBlockDevice<_> = NorFlashBlockDevice::new(MockFlash::new(2));
+        let blocks: Vec<Block> = (0..4).map(filled).collect();
+        device.write(&blocks, BlockIdx(6)).unwrap();
+        device.flash(|f| {
+            let writes: Vec<_> = f
+                .ops
+                .iter()
+                .filter(|op| matches!(op, FlashOp::Write { .. }))
+                .copied()
+                .collect();
+            assert_eq!(
+                writes,
+                [
+                    FlashOp::Write {
+                        offset: 3072,
+                        len: 512
+                    },
+                    FlashOp::Write {
+                        offset: 3584,
+                        len: 512
+                    },
+                    FlashOp::Write {
+                        offset: 4096,
+                        len: 512
+                    },
+                    FlashOp::Write {
+                        offset: 4608,
+                        len: 512
+                    },
+                ]
+            );
+        });
+        let mut read_back = [Block::new(), Block::new(), Block::new(), Block::new()];
+        device.read(&mut read_back, BlockIdx(6)).unwrap();
+        for (i, block) in read_back.iter().enumerate() {
+            assert_eq!(block.contents, [i as u8; Block::LEN]);
+        }
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let device: NorFlashBlockDevice<_> = NorFlashBlockDevice::new(MockFlash::new(1));
+        let mut blocks = [Block::new(), Block::new()];
+        assert_eq!(device.read(&mut blocks, BlockIdx(6)), Ok(()));
+        assert_eq!(
+            device.read(&mut blocks, BlockIdx(7)),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(device.write(&blocks, BlockIdx(7)), Err(Error::OutOfBounds));
+        assert_eq!(
+            device.write(&blocks, BlockIdx(u32::MAX)),
+            Err(Error::OutOfBounds)
+        );
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
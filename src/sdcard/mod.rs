@@ -4,11 +4,14 @@
 //! performance.
 
 pub mod proto;
+mod spi_bus;
 
 use crate::{trace, Block, BlockCount, BlockDevice, BlockIdx};
 use core::cell::RefCell;
 use proto::*;
 
+pub use spi_bus::{SpiBusDevice, SpiBusDeviceError};
+
 // ****************************************************************************
 // Imports
 // ****************************************************************************
@@ -27,22 +30,33 @@ use crate::{debug, warn};
 /// the SPI Clock line, at 400 kHz, with no chip-select asserted (or at least,
 /// not the chip-select of the SD Card).
 ///
-/// This kind of breaks the embedded-hal model, so how to do this is left to
-/// the caller. You could drive the SpiBus directly, or use an SpiDevice with
+/// This kind of breaks the embedded-hal model. If the card has an SPI bus to
+/// itself, construct the driver with [`SdCard::new_spi_bus`], which drives
+/// chip-select itself and sends those clocks for you. Otherwise it is left to
+/// the caller: you could drive the SpiBus directly, or use an SpiDevice with
 /// a dummy chip-select pin. Or you could try just not doing the 74 clocks and
 /// see if your card works anyway - some do, some don't.
 ///
 /// All the APIs take `&self` - mutability is handled using an inner `RefCell`.
 ///
+/// If the card's power supply can be switched by a GPIO pin, construct the
+/// driver with [`SdCard::new_spi_with_power`] and you can then use
+/// [`SdCard::power_cycle`] to recover a card which has stopped responding.
+///
 /// [`SpiDevice`]: embedded_hal::spi::SpiDevice
-pub struct SdCard<SPI, DELAYER>
+pub struct SdCard<SPI, DELAYER, POWER = NoPowerPin>
 where
     SPI: embedded_hal::spi::SpiDevice<u8>,
     DELAYER: embedded_hal::delay::DelayNs,
 {
     inner: RefCell<SdCardInner<SPI, DELAYER>>,
+    power: RefCell<POWER>,
 }
 
+/// The power pin type for an [`SdCard`] whose power supply can't be switched.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct NoPowerPin;
+
 impl<SPI, DELAYER> SdCard<SPI, DELAYER>
 where
     SPI: embedded_hal::spi::SpiDevice<u8>,
@@ -75,11 +89,149 @@ where
                 spi,
                 delayer,
                 card_type: None,
+                num_blocks: None,
+                au_blocks: None,
+                deselect: None,
                 options,
             }),
+            power: RefCell::new(NoPowerPin),
+        }
+    }
+
+    /// Destroy the driver, giving back the SPI device and the delayer.
+    ///
+    /// Handy if you want to use the SPI peripheral for something else once
+    /// you've finished with the card. The card is left as it is, so you can
+    /// use [`SdCard::mark_card_as_init`] if you make a new driver for it
+    /// later.
+    pub fn free(self) -> (SPI, DELAYER) {
+        let inner = self.inner.into_inner();
+        (inner.spi, inner.delayer)
+    }
+}
+
+impl<BUS, CS, DELAYER> SdCard<SpiBusDevice<BUS, CS>, DELAYER>
+where
+    BUS: embedded_hal::spi::SpiBus<u8>,
+    CS: embedded_hal::digital::OutputPin,
+    DELAYER: embedded_hal::delay::DelayNs,
+{
+    /// Create a new SD/MMC Card driver which has sole use of an SPI bus, and
+    /// drives the card's chip-select pin itself.
+    ///
+    /// Chip-select is held asserted for each whole command sequence - the
+    /// command, its response and any data - and the clocks the card needs
+    /// with chip-select high - at power-up, and after each sequence - are
+    /// sent for you. See [`SpiBusDevice`] for the details.
+    ///
+    /// The card will not be initialised at this time. Initialisation is
+    /// deferred until a method is called on the object.
+    ///
+    /// Uses the default options.
+    pub fn new_spi_bus(
+        bus: BUS,
+        cs: CS,
+        delayer: DELAYER,
+    ) -> SdCard<SpiBusDevice<BUS, CS>, DELAYER> {
+        Self::new_spi_bus_with_options(bus, cs, delayer, AcquireOpts::default())
+    }
+
+    /// Create a new SD/MMC Card driver which has sole use of an SPI bus, and
+    /// drives the card's chip-select pin itself, with the given options.
+    ///
+    /// See [`SdCard::new_spi_bus`].
+    pub fn new_spi_bus_with_options(
+        bus: BUS,
+        cs: CS,
+        delayer: DELAYER,
+        options: AcquireOpts,
+    ) -> SdCard<SpiBusDevice<BUS, CS>, DELAYER> {
+        let card = Self::new_with_options(SpiBusDevice::new(bus, cs), delayer, options);
+        card.inner.borrow_mut().deselect = Some(SpiBusDevice::deselect);
+        card
+    }
+}
+
+impl<SPI, DELAYER, POWER> SdCard<SPI, DELAYER, POWER>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+    DELAYER: embedded_hal::delay::DelayNs,
+    POWER: embedded_hal::digital::OutputPin,
+{
+    /// How long we hold the power off for, in milliseconds.
+    ///
+    /// The spec wants the supply below 0.5V for at least 1 ms, but the
+    /// capacitors on a typical board take a little while to discharge.
+    const POWER_OFF_MS: u32 = 10;
+
+    /// How long we wait for the supply to settle after power on, in
+    /// milliseconds.
+    const POWER_ON_MS: u32 = 1;
+
+    /// Create a new SD/MMC Card driver using a raw SPI interface and a GPIO
+    /// pin which switches the card's power supply.
+    ///
+    /// Driving the pin high must power the card, and driving it low must cut
+    /// the power. The pin is not touched until you call
+    /// [`SdCard::power_cycle`], so make sure the card is powered before using
+    /// it.
+    ///
+    /// The card will not be initialised at this time. Initialisation is
+    /// deferred until a method is called on the object.
+    ///
+    /// Uses the default options.
+    pub fn new_spi_with_power(
+        spi: SPI,
+        delayer: DELAYER,
+        power_pin: POWER,
+    ) -> SdCard<SPI, DELAYER, POWER> {
+        SdCard {
+            inner: RefCell::new(SdCardInner {
+                spi,
+                delayer,
+                card_type: None,
+                num_blocks: None,
+                au_blocks: None,
+                deselect: None,
+                options: AcquireOpts::default(),
+            }),
+            power: RefCell::new(power_pin),
         }
     }
 
+    /// Turn the card off and on again.
+    ///
+    /// The card is marked as requiring a reset, so the next operation will go
+    /// through the initialisation sequence again. Gives `Error::GpioError` if
+    /// the power pin can't be set.
+    pub fn power_cycle(&self) -> Result<(), Error> {
+        let mut inner = self.inner.borrow_mut();
+        let mut power = self.power.borrow_mut();
+        inner.card_type = None;
+        debug!("Power cycling card");
+        power.set_low().map_err(|_| Error::GpioError)?;
+        inner.delayer.delay_ms(Self::POWER_OFF_MS);
+        power.set_high().map_err(|_| Error::GpioError)?;
+        inner.delayer.delay_ms(Self::POWER_ON_MS);
+        Ok(())
+    }
+
+    /// Destroy the driver, giving back the SPI device, the delayer and the
+    /// power pin.
+    ///
+    /// Like [`SdCard::free`], for a driver made with
+    /// [`SdCard::new_spi_with_power`].
+    pub fn free_with_power(self) -> (SPI, DELAYER, POWER) {
+        let inner = self.inner.into_inner();
+        (inner.spi, inner.delayer, self.power.into_inner())
+    }
+}
+
+impl<SPI, DELAYER, POWER> SdCard<SPI, DELAYER, POWER>
+where
+    SPI: embedded_hal::spi::SpiDevice<u8>,
+    DELAYER: embedded_hal::delay::DelayNs,
+{
     /// Get a temporary borrow on the underlying SPI device.
     ///
     /// The given closure will be called exactly once, and will be passed a
@@ -100,8 +252,52 @@ where
     /// This will trigger card (re-)initialisation.
     pub fn num_bytes(&self) -> Result<u64, Error> {
         let mut inner = self.inner.borrow_mut();
-        inner.check_init()?;
-        inner.num_bytes()
+        let result = inner.check_init().and_then(|_| inner.num_bytes());
+        inner.end_sequence(result)
+    }
+
+    /// Work out which capacity class this card is in, from its size.
+    ///
+    /// Returns the class, and the usable size of the card in bytes. Unlike
+    /// [`SdCard::get_card_type`], this tells SDHC and SDXC cards apart.
+    ///
+    /// This will trigger card (re-)initialisation.
+    pub fn card_size_class(&self) -> Result<(SizeClass, u64), Error> {
+        let num_bytes = self.num_bytes()?;
+        Ok((SizeClass::from_num_bytes(num_bytes), num_bytes))
+    }
+
+    /// Get the fastest SPI clock this card supports, in Hz, from the
+    /// `TRAN_SPEED` field of its Card Specific Data.
+    ///
+    /// Once the card is initialised, you can use [`SdCard::spi`] to re-clock
+    /// your SPI bus to this speed.
+    ///
+    /// This will trigger card (re-)initialisation.
+    pub fn max_clock_hz(&self) -> Result<u32, Error> {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner.check_init().and_then(|_| inner.max_clock_hz());
+        inner.end_sequence(result)
+    }
+
+    /// Read the card's SD Configuration Register, which says which versions
+    /// of the SD specification, bus widths and commands it supports.
+    ///
+    /// This will trigger card (re-)initialisation.
+    pub fn read_scr(&self) -> Result<Scr, Error> {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner.check_init().and_then(|_| inner.read_scr());
+        inner.end_sequence(result)
+    }
+
+    /// Read the card's SD Status register, which says (amongst other
+    /// things) how big its Allocation Unit is.
+    ///
+    /// This will trigger card (re-)initialisation.
+    pub fn read_sd_status(&self) -> Result<SdStatus, Error> {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner.check_init().and_then(|_| inner.read_sd_status());
+        inner.end_sequence(result)
     }
 
     /// Can this card erase single blocks?
@@ -109,8 +305,26 @@ where
     /// This will trigger card (re-)initialisation.
     pub fn erase_single_block_enabled(&self) -> Result<bool, Error> {
         let mut inner = self.inner.borrow_mut();
-        inner.check_init()?;
-        inner.erase_single_block_enabled()
+        let result = inner
+            .check_init()
+            .and_then(|_| inner.erase_single_block_enabled());
+        inner.end_sequence(result)
+    }
+
+    /// Wait until the card is no longer busy (say, still programming the
+    /// blocks from a long write), checking up to `max_retries` times.
+    ///
+    /// We wait about 10us between checks. Gives `Error::TimeoutWaitNotBusy`
+    /// if the card is still busy after that. Handy before you power the card
+    /// down.
+    ///
+    /// This will trigger card (re-)initialisation.
+    pub fn wait_ready(&self, max_retries: u32) -> Result<(), Error> {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner
+            .check_init()
+            .and_then(|_| inner.wait_not_busy(Delay::new(max_retries)));
+        inner.end_sequence(result)
     }
 
     /// Mark the card as requiring a reset.
@@ -126,7 +340,8 @@ where
     /// This will trigger card (re-)initialisation.
     pub fn get_card_type(&self) -> Option<CardType> {
         let mut inner = self.inner.borrow_mut();
-        inner.check_init().ok()?;
+        let result = inner.check_init();
+        inner.end_sequence(result).ok()?;
         inner.card_type
     }
 
@@ -149,10 +364,12 @@ where
     pub unsafe fn mark_card_as_init(&self, card_type: CardType) {
         let mut inner = self.inner.borrow_mut();
         inner.card_type = Some(card_type);
+        inner.num_blocks = None;
+        inner.au_blocks = None;
     }
 }
 
-impl<SPI, DELAYER> BlockDevice for SdCard<SPI, DELAYER>
+impl<SPI, DELAYER, POWER> BlockDevice for SdCard<SPI, DELAYER, POWER>
 where
     SPI: embedded_hal::spi::SpiDevice<u8>,
     DELAYER: embedded_hal::delay::DelayNs,
@@ -165,8 +382,10 @@ where
     fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
         let mut inner = self.inner.borrow_mut();
         debug!("Read {} blocks @ {}", blocks.len(), start_block_idx.0,);
-        inner.check_init()?;
-        inner.read(blocks, start_block_idx)
+        let result = inner
+            .check_init()
+            .and_then(|_| inner.read(blocks, start_block_idx));
+        inner.end_sequence(result)
     }
 
     /// Write one or more blocks, starting at the given block index.
@@ -175,8 +394,10 @@ where
     fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
         let mut inner = self.inner.borrow_mut();
         debug!("Writing {} blocks @ {}", blocks.len(), start_block_idx.0);
-        inner.check_init()?;
-        inner.write(blocks, start_block_idx)
+        let result = inner
+            .check_init()
+            .and_then(|_| inner.write(blocks, start_block_idx));
+        inner.end_sequence(result)
     }
 
     /// Determine how many blocks this device can hold.
@@ -184,11 +405,28 @@ where
     /// This will trigger card (re-)initialisation.
     fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
         let mut inner = self.inner.borrow_mut();
-        inner.check_init()?;
-        inner.num_blocks()
+        let result = inner.check_init().and_then(|_| inner.num_blocks());
+        inner.end_sequence(result)
+    }
+
+    /// The size of the card's Allocation Unit, which is what a card is
+    /// fastest at writing in one go.
+    ///
+    /// We ask the card the first time, and remember the answer until the
+    /// card is next initialised. If the card can't tell us, we say one block.
+    ///
+    /// This will trigger card (re-)initialisation.
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        let mut inner = self.inner.borrow_mut();
+        let result = inner.check_init().map(|_| inner.au_blocks());
+        inner.end_sequence(result).unwrap_or(BlockCount(1))
     }
 }
 
+/// Lets go of the card's chip-select, for an SPI device that leaves that to
+/// us.
+type DeselectFn<SPI> = fn(&mut SPI) -> Result<(), <SPI as embedded_hal::spi::ErrorType>::Error>;
+
 /// Inner details for the SD Card driver.
 ///
 /// All the APIs required `&mut self`.
@@ -200,7 +438,15 @@ where
     spi: SPI,
     delayer: DELAYER,
     card_type: Option<CardType>,
+    /// How many blocks the card holds, once we have asked it.
+    num_blocks: Option<BlockCount>,
+    /// How many blocks are in the card's Allocation Unit, once we have
+    /// asked it.
+    au_blocks: Option<BlockCount>,
     options: AcquireOpts,
+    /// Called at the end of each command sequence, for an SPI device that
+    /// leaves driving chip-select to us.
+    deselect: Option<DeselectFn<SPI>>,
 }
 
 impl<SPI, DELAYER> SdCardInner<SPI, DELAYER>
@@ -209,12 +455,29 @@ where
     DELAYER: embedded_hal::delay::DelayNs,
 {
     /// Read one or more blocks, starting at the given block index.
+    ///
+    /// Reads are split into bursts of at most
+    /// [`AcquireOpts::max_multiblock`] blocks.
     fn read(&mut self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Error> {
-        let start_idx = match self.card_type {
-            Some(CardType::SD1 | CardType::SD2) => start_block_idx.0 * 512,
-            Some(CardType::SDHC) => start_block_idx.0,
-            None => return Err(Error::CardNotFound),
+        self.check_bounds(start_block_idx, blocks.len())?;
+        let burst_len = match self.options.max_multiblock {
+            Some(max) => max.max(1),
+            None => blocks.len().max(1),
         };
+        for (i, burst) in blocks.chunks_mut(burst_len).enumerate() {
+            let offset = u32::try_from(i * burst_len).map_err(|_| Error::BadState)?;
+            let block_idx = start_block_idx
+                .checked_add(BlockCount(offset))
+                .ok_or(Error::BadState)?;
+            self.read_burst(burst, block_idx)?;
+        }
+        Ok(())
+    }
+
+    /// Read one or more blocks with a single command, starting at the given
+    /// block index.
+    fn read_burst(&mut self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Error> {
+        let start_idx = self.block_address(start_block_idx)?;
 
         if blocks.len() == 1 {
             // Start a single-block read
@@ -234,11 +497,8 @@ where
 
     /// Write one or more blocks, starting at the given block index.
     fn write(&mut self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Error> {
-        let start_idx = match self.card_type {
-            Some(CardType::SD1 | CardType::SD2) => start_block_idx.0 * 512,
-            Some(CardType::SDHC) => start_block_idx.0,
-            None => return Err(Error::CardNotFound),
-        };
+        self.check_bounds(start_block_idx, blocks.len())?;
+        let start_idx = self.block_address(start_block_idx)?;
         if blocks.len() == 1 {
             // Start a single-block write
             self.card_command(CMD24, start_idx)?;
@@ -268,10 +528,81 @@ where
             self.wait_not_busy(Delay::new_write())?;
             self.write_byte(STOP_TRAN_TOKEN)?;
         }
+        if self.options.verify_writes {
+            self.verify(blocks, start_idx)?;
+        }
+        Ok(())
+    }
+
+    /// Read back blocks we have just written, and check the card holds what
+    /// we sent it.
+    fn verify(&mut self, blocks: &[Block], start_idx: u32) -> Result<(), Error> {
+        let mut readback = Block::new();
+        if blocks.len() == 1 {
+            self.card_command(CMD17, start_idx)?;
+            self.read_data(&mut readback.contents)?;
+            if readback.contents != blocks[0].contents {
+                warn!("Write verify failed");
+                return Err(Error::WriteError);
+            }
+        } else {
+            self.card_command(CMD18, start_idx)?;
+            let mut result = Ok(());
+            for block in blocks.iter() {
+                self.read_data(&mut readback.contents)?;
+                if readback.contents != block.contents {
+                    warn!("Write verify failed");
+                    result = Err(Error::WriteError);
+                    break;
+                }
+            }
+            // Stop the read, even if we found a mismatch
+            self.card_command(CMD12, 0)?;
+            result?;
+        }
         Ok(())
     }
 
+    /// Finish off a command sequence, by sending a trailing dummy byte if the
+    /// options ask for one, and letting go of chip-select if that's up to us.
+    ///
+    /// The result of the command sequence is passed through, unless it was OK
+    /// and finishing off failed.
+    fn end_sequence<T>(&mut self, result: Result<T, Error>) -> Result<T, Error> {
+        let dummy_result = if self.options.trailing_dummy_byte {
+            self.write_bytes(&[0xFF])
+        } else {
+            Ok(())
+        };
+        let deselect_result = match self.deselect {
+            Some(deselect) => deselect(&mut self.spi).map_err(|_| Error::Transport),
+            None => Ok(()),
+        };
+        result.and_then(|value| dummy_result.and(deselect_result).map(|_| value))
+    }
+
+    /// Convert a block index into the address argument for a read or write
+    /// command.
+    ///
+    /// Standard-capacity cards are byte-addressed, so the block index is
+    /// scaled up by the block size. If the resulting byte address does not fit
+    /// in the 32-bit command argument, we return `Error::BadState` instead of
+    /// letting the address wrap and touching the wrong part of the card.
+    fn block_address(&self, block_idx: BlockIdx) -> Result<u32, Error> {
+        match self.card_type {
+            Some(CardType::SD1 | CardType::SD2) => {
+                let byte_address = u64::from(block_idx.0) * u64::from(Block::LEN_U32);
+                u32::try_from(byte_address).map_err(|_| Error::BadState)
+            }
+            Some(CardType::SDHC) => Ok(block_idx.0),
+            None => Err(Error::CardNotFound),
+        }
+    }
+
     /// Determine how many blocks this device can hold.
+    ///
+    /// This always asks the card, and remembers the answer for
+    /// `check_bounds`.
     fn num_blocks(&mut self) -> Result<BlockCount, Error> {
         let csd = self.read_csd()?;
         debug!("CSD: {:?}", csd);
@@ -279,9 +610,35 @@ where
             Csd::V1(ref contents) => contents.card_capacity_blocks(),
             Csd::V2(ref contents) => contents.card_capacity_blocks(),
         };
+        self.num_blocks = Some(BlockCount(num_blocks));
         Ok(BlockCount(num_blocks))
     }
 
+    /// Check that `count` blocks starting at `start_block_idx` are all on
+    /// the card, giving `Error::OutOfBounds` if they aren't.
+    ///
+    /// We ask the card how big it is the first time, and remember the
+    /// answer until the card is next initialised.
+    fn check_bounds(&mut self, start_block_idx: BlockIdx, count: usize) -> Result<(), Error> {
+        let num_blocks = match self.num_blocks {
+            Some(num_blocks) => num_blocks,
+            None => self.num_blocks()?,
+        };
+        let in_bounds = u32::try_from(count)
+            .ok()
+            .and_then(|count| start_block_idx.0.checked_add(count))
+            .is_some_and(|end| end <= num_blocks.0);
+        if in_bounds {
+            Ok(())
+        } else {
+            warn!(
+                "{} blocks @ {} is beyond the end of the card",
+                count, start_block_idx.0
+            );
+            Err(Error::OutOfBounds(start_block_idx))
+        }
+    }
+
     /// Return the usable size of this SD card in bytes.
     fn num_bytes(&mut self) -> Result<u64, Error> {
         let csd = self.read_csd()?;
@@ -292,6 +649,15 @@ where
         }
     }
 
+    /// Get the fastest clock this card supports, in Hz.
+    fn max_clock_hz(&mut self) -> Result<u32, Error> {
+        let tran_speed = match self.read_csd()? {
+            Csd::V1(ref contents) => contents.max_data_transfer_rate(),
+            Csd::V2(ref contents) => contents.max_data_transfer_rate(),
+        };
+        tran_speed_hz(tran_speed).ok_or(Error::RegisterReadError)
+    }
+
     /// Can this card erase single blocks?
     pub fn erase_single_block_enabled(&mut self) -> Result<bool, Error> {
         let csd = self.read_csd()?;
@@ -324,9 +690,67 @@ where
         }
     }
 
+    /// Read the SD Configuration Register.
+    ///
+    /// The SCR comes back as an 8 byte data block, with a start token and a
+    /// CRC just like a 512 byte block of data.
+    fn read_scr(&mut self) -> Result<Scr, Error> {
+        let mut scr = Scr::new();
+        if self.card_acmd(ACMD51, 0)? != 0 {
+            return Err(Error::RegisterReadError);
+        }
+        self.read_data(&mut scr.data)?;
+        Ok(scr)
+    }
+
+    /// Read the SD Status register.
+    ///
+    /// ACMD13 gets an R2 response in SPI mode, so there's a second status
+    /// byte to read before the 64 byte data block.
+    fn read_sd_status(&mut self) -> Result<SdStatus, Error> {
+        let mut status = SdStatus::new();
+        if self.card_acmd(ACMD13, 0)? != 0 {
+            return Err(Error::RegisterReadError);
+        }
+        if self.read_byte()? != 0 {
+            return Err(Error::RegisterReadError);
+        }
+        self.read_data(&mut status.data)?;
+        Ok(status)
+    }
+
+    /// How many blocks are in the card's Allocation Unit.
+    ///
+    /// Asks the card the first time, then remembers the answer - which is
+    /// one block if the card doesn't say.
+    fn au_blocks(&mut self) -> BlockCount {
+        if let Some(au_blocks) = self.au_blocks {
+            return au_blocks;
+        }
+        // If the card won't tell us, remember that too, so we don't keep
+        // asking
+        let au_blocks = match self.read_sd_status() {
+            Ok(status) => {
+                debug!("SD Status: {:?}", status);
+                status
+                    .au_size_bytes()
+                    .map_or(BlockCount(1), |bytes| BlockCount(bytes / Block::LEN_U32))
+            }
+            Err(_e) => {
+                warn!("Can't read SD Status: {:?}", _e);
+                BlockCount(1)
+            }
+        };
+        self.au_blocks = Some(au_blocks);
+        au_blocks
+    }
+
     /// Read an arbitrary number of bytes from the card using the SD Card
     /// protocol and an optional CRC. Always fills the given buffer, so make
     /// sure it's the right size.
+    ///
+    /// Register reads use this too, so the buffer needn't be a whole block -
+    /// the CRC covers however many bytes the card sends.
     fn read_data(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
         // Get first non-FF byte.
         let mut delay = Delay::new_read();
@@ -365,12 +789,17 @@ where
     /// an optional CRC.
     fn write_data(&mut self, token: u8, buffer: &[u8]) -> Result<(), Error> {
         self.write_byte(token)?;
-        self.write_bytes(buffer)?;
+        // We can't work the CRC out as the block goes out on the bus. An
+        // `SpiDevice` is given all the operations of a transaction up front,
+        // so there's nowhere to update the CRC between pieces of the block,
+        // and sending each piece in its own transaction would be slower than
+        // the pass over the data it saves.
         let crc_bytes = if self.options.use_crc {
             crc16(buffer).to_be_bytes()
         } else {
             [0xFF, 0xFF]
         };
+        self.write_bytes(buffer)?;
         // These two bytes are always sent. They are either a valid CRC, or
         // junk, depending on whether CRC mode was enabled.
         self.write_bytes(&crc_bytes)?;
@@ -471,6 +900,8 @@ where
             }
             debug!("Card version: {:?}", card_type);
             s.card_type = Some(card_type);
+            s.num_blocks = None;
+            s.au_blocks = None;
             Ok(())
         };
         let result = f(self);
@@ -581,6 +1012,38 @@ pub struct AcquireOpts {
     /// Sets the number of times we will retry to acquire the card before giving up and returning
     /// `Err(Error::CardNotFound)`. By default, card acquisition will be retried 50 times.
     pub acquire_retries: u32,
+
+    /// Set to true to read back every block after it has been written, and
+    /// return `Err(Error::WriteError)` if the card doesn't hold the data we
+    /// sent it.
+    ///
+    /// This roughly doubles the cost of a write, so it is off by default, but
+    /// it will catch cards which acknowledge a write they didn't actually
+    /// commit.
+    pub verify_writes: bool,
+
+    /// Set to true to send an extra `0xFF` byte, in a transaction of its own,
+    /// at the end of each command sequence.
+    ///
+    /// SD cards keep driving MISO until they see one more clock after their
+    /// chip-select goes high, which can upset other devices on a shared SPI
+    /// bus. The extra byte gives the card that clock. This only fully works
+    /// if your [`SpiDevice`](embedded_hal::spi::SpiDevice) de-asserts
+    /// chip-select between transactions.
+    ///
+    /// Off by default, as it isn't needed if the card is the only device on
+    /// the bus.
+    pub trailing_dummy_byte: bool,
+
+    /// The most blocks to read with a single multi-block read (CMD18).
+    ///
+    /// Longer reads are split into several bursts. `Some(1)` reads every
+    /// block with its own single-block read (CMD17), for cards which misbehave
+    /// with multi-block reads, or hosts which can't meet the timing of the
+    /// stop command (CMD12) that ends one. `Some(0)` is treated as `Some(1)`.
+    ///
+    /// `None` (the default) reads any number of blocks in one burst.
+    pub max_multiblock: Option<usize>,
 }
 
 impl Default for AcquireOpts {
@@ -588,10 +1051,82 @@ impl Default for AcquireOpts {
         AcquireOpts {
             use_crc: true,
             acquire_retries: 50,
+            verify_writes: false,
+            trailing_dummy_byte: false,
+            max_multiblock: None,
+        }
+    }
+}
+
+impl AcquireOpts {
+    /// Start building a set of options, beginning from the defaults.
+    ///
+    /// See [`AcquireOptsBuilder`] for an example.
+    pub fn builder() -> AcquireOptsBuilder {
+        AcquireOptsBuilder {
+            opts: AcquireOpts::default(),
         }
     }
 }
 
+/// Builds an [`AcquireOpts`].
+///
+/// Any option you don't set keeps its [`Default`] value.
+///
+/// ```
+/// use embedded_sdmmc::sdcard::AcquireOpts;
+///
+/// let options = AcquireOpts::builder()
+///     .acquire_retries(100)
+///     .verify_writes(true)
+///     .build();
+/// assert_eq!(options.acquire_retries, 100);
+/// assert!(options.verify_writes);
+/// assert!(options.use_crc);
+/// ```
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug)]
+pub struct AcquireOptsBuilder {
+    opts: AcquireOpts,
+}
+
+impl AcquireOptsBuilder {
+    /// Set [`AcquireOpts::use_crc`].
+    pub fn use_crc(mut self, use_crc: bool) -> Self {
+        self.opts.use_crc = use_crc;
+        self
+    }
+
+    /// Set [`AcquireOpts::acquire_retries`].
+    pub fn acquire_retries(mut self, acquire_retries: u32) -> Self {
+        self.opts.acquire_retries = acquire_retries;
+        self
+    }
+
+    /// Set [`AcquireOpts::verify_writes`].
+    pub fn verify_writes(mut self, verify_writes: bool) -> Self {
+        self.opts.verify_writes = verify_writes;
+        self
+    }
+
+    /// Set [`AcquireOpts::trailing_dummy_byte`].
+    pub fn trailing_dummy_byte(mut self, trailing_dummy_byte: bool) -> Self {
+        self.opts.trailing_dummy_byte = trailing_dummy_byte;
+        self
+    }
+
+    /// Set [`AcquireOpts::max_multiblock`].
+    pub fn max_multiblock(mut self, max_multiblock: Option<usize>) -> Self {
+        self.opts.max_multiblock = max_multiblock;
+        self
+    }
+
+    /// Create the options.
+    pub fn build(self) -> AcquireOpts {
+        self.opts
+    }
+}
+
 /// The possible errors this crate can generate.
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, Copy, Clone)]
@@ -624,8 +1159,46 @@ pub enum Error {
     CardNotFound,
     /// Couldn't set a GPIO pin
     GpioError,
+    /// The blocks asked for, starting at this one, run past the end of the
+    /// card
+    OutOfBounds(BlockIdx),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Transport => write!(f, "SPI transport error"),
+            Error::CantEnableCRC => write!(f, "failed to enable CRC checking"),
+            Error::TimeoutReadBuffer => write!(f, "timed out reading data"),
+            Error::TimeoutWaitNotBusy => write!(f, "timed out waiting for card to be ready"),
+            Error::TimeoutCommand(cmd) => match command_name(*cmd) {
+                Some(name) => write!(f, "timed out on {}", name),
+                None => write!(f, "timed out on CMD{}", cmd),
+            },
+            Error::TimeoutACommand(cmd) => match app_command_name(*cmd) {
+                Some(name) => write!(f, "timed out on {}", name),
+                None => write!(f, "timed out on ACMD{}", cmd),
+            },
+            Error::Cmd58Error => write!(f, "bad response to CMD58"),
+            Error::RegisterReadError => write!(f, "failed to read card register"),
+            Error::CrcError(card, ours) => write!(
+                f,
+                "CRC mismatch (card sent {:#06x}, we calculated {:#06x})",
+                card, ours
+            ),
+            Error::ReadError => write!(f, "read error"),
+            Error::WriteError => write!(f, "write error"),
+            Error::BadState => write!(f, "card in wrong state"),
+            Error::CardNotFound => write!(f, "card not found"),
+            Error::GpioError => write!(f, "GPIO error"),
+            Error::OutOfBounds(idx) => write!(f, "block {} is beyond the end of the card", idx),
+        }
+    }
 }
 
+#[cfg(feature = "core-error")]
+impl core::error::Error for Error {}
+
 /// The different types of card we support.
 #[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -644,6 +1217,39 @@ pub enum CardType {
     SDHC,
 }
 
+/// The capacity classes of SD card.
+///
+/// See [`SdCard::card_size_class`].
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SizeClass {
+    /// A standard-capacity card, of up to 2 GiB.
+    Sdsc,
+    /// A high-capacity card, of more than 2 GiB but less than 32 GiB.
+    Sdhc,
+    /// An extended-capacity card, of 32 GiB or more.
+    Sdxc,
+}
+
+impl SizeClass {
+    /// The largest standard-capacity card.
+    const MAX_SDSC_BYTES: u64 = 2 << 30;
+
+    /// The smallest extended-capacity card.
+    const MIN_SDXC_BYTES: u64 = 32 << 30;
+
+    /// Work out the class of a card with this many bytes.
+    pub fn from_num_bytes(num_bytes: u64) -> SizeClass {
+        if num_bytes <= Self::MAX_SDSC_BYTES {
+            SizeClass::Sdsc
+        } else if num_bytes < Self::MIN_SDXC_BYTES {
+            SizeClass::Sdhc
+        } else {
+            SizeClass::Sdxc
+        }
+    }
+}
+
 /// This an object you can use to busy-wait with a timeout.
 ///
 /// Will let you call `delay` up to `max_retries` times before `delay` returns
@@ -715,6 +1321,975 @@ impl Delay {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_hal::spi::{ErrorType, Operation, SpiDevice};
+    use std::collections::{HashMap, VecDeque};
+
+    /// What the emulated card is doing with the bytes the host sends it.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum MockState {
+        /// Waiting for a command frame.
+        Idle,
+        /// Streaming out blocks until the host sends CMD12.
+        ReadMultiple,
+        /// Waiting for a data token during a single or multi-block write.
+        WaitToken { multiple: bool },
+        /// Receiving a block of data (plus CRC) from the host.
+        Receiving { multiple: bool, data: Vec<u8> },
+    }
+
+    /// Make a version 1.0 CSD for a card of
+    /// `(c_size + 1) << (c_size_mult + read_bl_len - 7)` blocks.
+    fn csd_v1(c_size: u32, c_size_mult: u8, read_bl_len: u8) -> [u8; 16] {
+        let mut csd = [0u8; 16];
+        csd[5] = read_bl_len & 0x0F;
+        csd[6] = ((c_size >> 10) & 0x03) as u8;
+        csd[7] = (c_size >> 2) as u8;
+        csd[8] = ((c_size & 0x03) as u8) << 6;
+        csd[9] = (c_size_mult >> 1) & 0x03;
+        csd[10] = (c_size_mult & 0x01) << 7;
+        csd
+    }
+
+    /// Make a version 2.0 CSD for a card of `(c_size + 1) * 1024` blocks.
+    fn csd_v2(c_size: u32) -> [u8; 16] {
+        let mut csd = [0u8; 16];
+        csd[0] = 0x40;
+        csd[5] = 9;
+        csd[7] = ((c_size >> 16) & 0x3F) as u8;
+        csd[8] = (c_size >> 8) as u8;
+        csd[9] = c_size as u8;
+        csd
+    }
+
+    /// Emulates just enough of the SD Card SPI protocol to exercise the
+    /// driver, with a sparse RAM backing store.
+    struct MockCard {
+        card_type: CardType,
+        blocks: HashMap<u32, [u8; Block::LEN]>,
+        /// Every command the card has received, with its argument.
+        commands: Vec<(u8, u32)>,
+        miso: VecDeque<u8>,
+        frame: Vec<u8>,
+        state: MockState,
+        next_block: u32,
+        idle: bool,
+        /// Flip the first byte of every block we send back to the host.
+        corrupt_reads: bool,
+        /// What we send back for ACMD51.
+        scr: [u8; 8],
+        /// The AU_SIZE we report in the SD Status for ACMD13.
+        au_size: u8,
+        /// Do we support ACMD13 at all?
+        sd_status: bool,
+        /// Was the last command CMD55, making this one an app command?
+        app_command: bool,
+        /// What we send back for CMD9. If not set, we claim to be bigger
+        /// than any real card of our type, so it's the driver's own address
+        /// checks which stop it going too far.
+        csd: Option<[u8; 16]>,
+        /// How many more bytes to hold MISO low for, once we've nothing
+        /// else to send, as if busy programming.
+        busy_bytes: usize,
+        /// The operations in the most recent transaction, and the bytes the
+        /// host sent in each.
+        last_transaction: Vec<(MockOp, Vec<u8>)>,
+    }
+
+    /// The kinds of SPI operation the host can perform.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum MockOp {
+        Read,
+        Write,
+        Transfer,
+        TransferInPlace,
+    }
+
+    impl MockCard {
+        fn new(card_type: CardType) -> MockCard {
+            MockCard {
+                card_type,
+                blocks: HashMap::new(),
+                commands: Vec::new(),
+                miso: VecDeque::new(),
+                frame: Vec::new(),
+                state: MockState::Idle,
+                next_block: 0,
+                idle: true,
+                corrupt_reads: false,
+                scr: [0x02, 0x35, 0x84, 0x83, 0x00, 0x00, 0x00, 0x00],
+                au_size: 0x9,
+                sd_status: true,
+                app_command: false,
+                csd: None,
+                busy_bytes: 0,
+                last_transaction: Vec::new(),
+            }
+        }
+
+        /// Convert a command argument into a block number.
+        fn block_number(&self, arg: u32) -> u32 {
+            match self.card_type {
+                CardType::SD1 | CardType::SD2 => arg / Block::LEN_U32,
+                CardType::SDHC => arg,
+            }
+        }
+
+        /// Queue up a data token, a block of data and its CRC.
+        fn queue_block(&mut self, block_number: u32) {
+            let mut data = self
+                .blocks
+                .get(&block_number)
+                .copied()
+                .unwrap_or([0u8; Block::LEN]);
+            if self.corrupt_reads {
+                data[0] ^= 0xFF;
+            }
+            self.miso.push_back(DATA_START_BLOCK);
+            self.miso.extend(data.iter());
+            self.miso.extend(crc16(&data).to_be_bytes().iter());
+        }
+
+        fn handle_command(&mut self, command: u8, arg: u32) {
+            self.commands.push((command, arg));
+            let app_command = core::mem::replace(&mut self.app_command, command == CMD55);
+            let r1 = if self.idle {
+                R1_IDLE_STATE
+            } else {
+                R1_READY_STATE
+            };
+            match command {
+                CMD0 => {
+                    self.idle = true;
+                    self.state = MockState::Idle;
+                    self.miso.push_back(R1_IDLE_STATE);
+                }
+                CMD8 if self.card_type == CardType::SD1 => {
+                    self.miso.push_back(R1_ILLEGAL_COMMAND | R1_IDLE_STATE);
+                }
+                CMD8 => {
+                    self.miso.extend([r1, 0x00, 0x00, 0x01, 0xAA]);
+                }
+                ACMD41 => {
+                    self.idle = false;
+                    self.miso.push_back(R1_READY_STATE);
+                }
+                CMD58 => {
+                    let ocr = if self.card_type == CardType::SDHC {
+                        0xC0
+                    } else {
+                        0x80
+                    };
+                    self.miso.extend([r1, ocr, 0xFF, 0x80, 0x00]);
+                }
+                CMD12 => {
+                    self.state = MockState::Idle;
+                    self.miso.clear();
+                    // Stuff byte, then the R1 response
+                    self.miso.extend([0xFF, r1]);
+                }
+                ACMD13 if app_command && !self.sd_status => {
+                    self.miso.push_back(r1 | R1_ILLEGAL_COMMAND);
+                }
+                ACMD13 if app_command => {
+                    // An R2 response
+                    self.miso.extend([r1, 0x00]);
+                    let mut status = [0u8; 64];
+                    status[10] = self.au_size << 4;
+                    self.miso.push_back(DATA_START_BLOCK);
+                    self.miso.extend(status.iter());
+                    self.miso.extend(crc16(&status).to_be_bytes().iter());
+                }
+                CMD13 => {
+                    self.miso.extend([r1, 0x00]);
+                }
+                CMD17 => {
+                    self.miso.push_back(r1);
+                    let block_number = self.block_number(arg);
+                    self.queue_block(block_number);
+                }
+                CMD18 => {
+                    self.miso.push_back(r1);
+                    self.next_block = self.block_number(arg);
+                    self.state = MockState::ReadMultiple;
+                }
+                CMD24 | CMD25 => {
+                    self.miso.push_back(r1);
+                    self.next_block = self.block_number(arg);
+                    self.state = MockState::WaitToken {
+                        multiple: command == CMD25,
+                    };
+                }
+                CMD9 => {
+                    self.miso.push_back(r1);
+                    let csd = self.csd.unwrap_or(match self.card_type {
+                        CardType::SD1 => csd_v1(4095, 7, 15),
+                        CardType::SD2 | CardType::SDHC => csd_v2(0xFFFF),
+                    });
+                    self.miso.push_back(DATA_START_BLOCK);
+                    self.miso.extend(csd.iter());
+                    self.miso.extend(crc16(&csd).to_be_bytes().iter());
+                }
+                ACMD51 => {
+                    self.miso.push_back(r1);
+                    let scr = self.scr;
+                    self.miso.push_back(DATA_START_BLOCK);
+                    self.miso.extend(scr.iter());
+                    self.miso.extend(crc16(&scr).to_be_bytes().iter());
+                }
+                CMD55 | CMD59 | ACMD23 => {
+                    self.miso.push_back(r1);
+                }
+                _ => {
+                    self.miso.push_back(r1 | R1_ILLEGAL_COMMAND);
+                }
+            }
+        }
+
+        /// Clock one byte in each direction.
+        fn exchange(&mut self, mosi: u8) -> u8 {
+            if self.miso.is_empty() && self.state == MockState::ReadMultiple {
+                self.queue_block(self.next_block);
+                self.next_block += 1;
+            }
+            let miso = match self.miso.pop_front() {
+                Some(miso) => miso,
+                None if self.busy_bytes > 0 => {
+                    self.busy_bytes -= 1;
+                    0x00
+                }
+                None => 0xFF,
+            };
+            match &mut self.state {
+                MockState::Receiving { multiple, data } => {
+                    data.push(mosi);
+                    // Block plus two CRC bytes
+                    if data.len() == Block::LEN + 2 {
+                        let mut contents = [0u8; Block::LEN];
+                        contents.copy_from_slice(&data[0..Block::LEN]);
+                        let multiple = *multiple;
+                        self.blocks.insert(self.next_block, contents);
+                        self.next_block += 1;
+                        self.miso.push_back(DATA_RES_ACCEPTED);
+                        self.state = if multiple {
+                            MockState::WaitToken { multiple }
+                        } else {
+                            MockState::Idle
+                        };
+                    }
+                }
+                MockState::WaitToken { multiple } => match mosi {
+                    DATA_START_BLOCK | WRITE_MULTIPLE_TOKEN => {
+                        self.state = MockState::Receiving {
+                            multiple: *multiple,
+                            data: Vec::new(),
+                        };
+                    }
+                    STOP_TRAN_TOKEN => {
+                        self.state = MockState::Idle;
+                    }
+                    _ => {}
+                },
+                MockState::Idle | MockState::ReadMultiple => {
+                    if !self.frame.is_empty() || (mosi & 0xC0) == 0x40 {
+                        self.frame.push(mosi);
+                    }
+                    if self.frame.len() == 6 {
+                        let command = self.frame[0] & 0x3F;
+                        let arg = u32::from_be_bytes([
+                            self.frame[1],
+                            self.frame[2],
+                            self.frame[3],
+                            self.frame[4],
+                        ]);
+                        self.frame.clear();
+                        self.handle_command(command, arg);
+                    }
+                }
+            }
+            miso
+        }
+    }
+
+    impl ErrorType for MockCard {
+        type Error = core::convert::Infallible;
+    }
+
+    impl SpiDevice<u8> for MockCard {
+        fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+            self.last_transaction.clear();
+            for op in operations.iter_mut() {
+                match op {
+                    Operation::Read(buf) => {
+                        for b in buf.iter_mut() {
+                            *b = self.exchange(0xFF);
+                        }
+                        self.last_transaction
+                            .push((MockOp::Read, vec![0xFF; buf.len()]));
+                    }
+                    Operation::Write(buf) => {
+                        for b in buf.iter() {
+                            self.exchange(*b);
+                        }
+                        self.last_transaction.push((MockOp::Write, buf.to_vec()));
+                    }
+                    Operation::Transfer(read, write) => {
+                        for i in 0..read.len().max(write.len()) {
+                            let miso = self.exchange(write.get(i).copied().unwrap_or(0xFF));
+                            if let Some(b) = read.get_mut(i) {
+                                *b = miso;
+                            }
+                        }
+                        self.last_transaction
+                            .push((MockOp::Transfer, write.to_vec()));
+                    }
+                    Operation::TransferInPlace(buf) => {
+                        let mosi = buf.to_vec();
+                        for b in buf.iter_mut() {
+                            *b = self.exchange(*b);
+                        }
+                        self.last_transaction.push((MockOp::TransferInPlace, mosi));
+                    }
+                    Operation::DelayNs(_) => {}
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// A delay that doesn't.
+    struct MockDelay;
+
+    impl embedded_hal::delay::DelayNs for MockDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    fn data_commands(card: &SdCard<MockCard, MockDelay>) -> Vec<(u8, u32)> {
+        card.spi(|spi| {
+            spi.commands
+                .iter()
+                .copied()
+                .filter(|(cmd, _)| matches!(*cmd, CMD17 | CMD18 | CMD24 | CMD25))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn byte_addressed_high_block() {
+        let card = SdCard::new(MockCard::new(CardType::SD2), MockDelay);
+        assert_eq!(card.get_card_type(), Some(CardType::SD2));
+        let mut block = [Block::new()];
+        block[0].contents[0] = 0xA5;
+        // The last block whose byte address fits in 32 bits
+        card.write(&block, BlockIdx(0x007F_FFFF)).unwrap();
+        block[0].contents[0] = 0x00;
+        card.read(&mut block, BlockIdx(0x007F_FFFF)).unwrap();
+        assert_eq!(block[0].contents[0], 0xA5);
+        assert_eq!(
+            data_commands(&card),
+            [(CMD24, 0xFFFF_FE00), (CMD17, 0xFFFF_FE00)]
+        );
+    }
+
+    #[test]
+    fn byte_addressed_overflow() {
+        let card = SdCard::new(MockCard::new(CardType::SD1), MockDelay);
+        assert_eq!(card.get_card_type(), Some(CardType::SD1));
+        let mut blocks = [Block::new(), Block::new()];
+        // This would wrap around to byte address 0 if done in 32 bits
+        assert!(matches!(
+            card.read(&mut blocks, BlockIdx(0x0080_0000)),
+            Err(Error::BadState)
+        ));
+        assert!(matches!(
+            card.write(&blocks[0..1], BlockIdx(0x0080_0000)),
+            Err(Error::BadState)
+        ));
+        assert_eq!(data_commands(&card), []);
+    }
+
+    #[test]
+    fn block_addressed_high_block() {
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        let mut blocks = [Block::new(), Block::new()];
+        card.read(&mut blocks, BlockIdx(0x0080_0000)).unwrap();
+        card.write(&blocks, BlockIdx(0x0080_0000)).unwrap();
+        assert_eq!(
+            data_commands(&card),
+            [(CMD18, 0x0080_0000), (CMD25, 0x0080_0000)]
+        );
+    }
+
+    #[test]
+    fn max_multiblock() {
+        let mut blocks = [Block::new(), Block::new(), Block::new()];
+        let options = AcquireOpts {
+            max_multiblock: Some(1),
+            ..Default::default()
+        };
+        let card = SdCard::new_with_options(MockCard::new(CardType::SDHC), MockDelay, options);
+        card.read(&mut blocks, BlockIdx(10)).unwrap();
+        assert_eq!(
+            data_commands(&card),
+            [(CMD17, 10), (CMD17, 11), (CMD17, 12)]
+        );
+
+        let options = AcquireOpts {
+            max_multiblock: Some(2),
+            ..Default::default()
+        };
+        let card = SdCard::new_with_options(MockCard::new(CardType::SDHC), MockDelay, options);
+        card.read(&mut blocks, BlockIdx(10)).unwrap();
+        assert_eq!(data_commands(&card), [(CMD18, 10), (CMD17, 12)]);
+    }
+
+    #[test]
+    fn free() {
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        let mut blocks = [Block::new()];
+        card.read(&mut blocks, BlockIdx(7)).unwrap();
+        let (spi, _delay) = card.free();
+        // We got back the same SPI device, which saw the read
+        assert!(spi.commands.contains(&(CMD17, 7)));
+
+        // The card is still initialised, so a new driver can pick it up
+        let card = SdCard::new(spi, MockDelay);
+        unsafe { card.mark_card_as_init(CardType::SDHC) };
+        card.spi(|spi| spi.commands.clear());
+        card.read(&mut blocks, BlockIdx(8)).unwrap();
+        assert_eq!(data_commands(&card), [(CMD17, 8)]);
+        card.spi(|spi| assert!(!spi.commands.iter().any(|(c, _)| *c == CMD0)));
+    }
+
+    #[test]
+    fn read_scr() {
+        // CRCs are on by default, so this also checks the CRC over just the
+        // 8 bytes of the register
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        let scr = card.read_scr().unwrap();
+        assert_eq!(scr.data, [0x02, 0x35, 0x84, 0x83, 0x00, 0x00, 0x00, 0x00]);
+        assert!(scr.supports_4bit_bus());
+        card.spi(|spi| {
+            assert_eq!(
+                spi.commands[spi.commands.len() - 2..],
+                [(CMD55, 0), (ACMD51, 0)]
+            )
+        });
+    }
+
+    #[test]
+    fn optimal_transfer_blocks() {
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        let status = card.read_sd_status().unwrap();
+        assert_eq!(status.au_size_bytes(), Some(4 * 1024 * 1024));
+        // 4 MiB of 512 byte blocks
+        assert_eq!(card.optimal_transfer_blocks(), BlockCount(8192));
+        // We only ask the card once
+        assert_eq!(card.optimal_transfer_blocks(), BlockCount(8192));
+        card.spi(|spi| {
+            let asked = spi.commands.iter().filter(|(c, _)| *c == ACMD13).count();
+            assert_eq!(asked, 2);
+        });
+
+        // A card which doesn't say gets one block
+        let mut mock = MockCard::new(CardType::SDHC);
+        mock.au_size = 0;
+        let card = SdCard::new(mock, MockDelay);
+        assert_eq!(card.optimal_transfer_blocks(), BlockCount(1));
+
+        // Nor does one which won't give its SD Status, and we only ask once
+        let mut mock = MockCard::new(CardType::SDHC);
+        mock.sd_status = false;
+        let card = SdCard::new(mock, MockDelay);
+        assert!(card.read_sd_status().is_err());
+        assert_eq!(card.optimal_transfer_blocks(), BlockCount(1));
+        assert_eq!(card.optimal_transfer_blocks(), BlockCount(1));
+        card.spi(|spi| {
+            let asked = spi.commands.iter().filter(|(c, _)| *c == ACMD13).count();
+            assert_eq!(asked, 2);
+        });
+    }
+
+    #[test]
+    fn wait_ready() {
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+
+        card.spi(|spi| spi.busy_bytes = 5);
+        card.wait_ready(10).unwrap();
+        card.spi(|spi| assert_eq!(spi.busy_bytes, 0));
+
+        card.spi(|spi| spi.busy_bytes = 20);
+        assert!(matches!(
+            card.wait_ready(10),
+            Err(Error::TimeoutWaitNotBusy)
+        ));
+        card.spi(|spi| spi.busy_bytes = 0);
+
+        // An uninitialised card is initialised first
+        card.mark_card_uninit();
+        card.spi(|spi| spi.commands.clear());
+        card.wait_ready(10).unwrap();
+        card.spi(|spi| assert_eq!(spi.commands.first(), Some(&(CMD0, 0))));
+    }
+
+    fn verifying_card(card_type: CardType) -> SdCard<MockCard, MockDelay> {
+        let options = AcquireOpts {
+            verify_writes: true,
+            ..Default::default()
+        };
+        SdCard::new_with_options(MockCard::new(card_type), MockDelay, options)
+    }
+
+    #[test]
+    fn verify_writes() {
+        let card = verifying_card(CardType::SDHC);
+        let mut blocks = [Block::new(), Block::new()];
+        blocks[0].contents.fill(0x11);
+        blocks[1].contents.fill(0x22);
+        card.write(&blocks[0..1], BlockIdx(10)).unwrap();
+        card.write(&blocks, BlockIdx(20)).unwrap();
+        assert_eq!(
+            data_commands(&card),
+            [(CMD24, 10), (CMD17, 10), (CMD25, 20), (CMD18, 20)]
+        );
+        card.spi(|spi| {
+            assert_eq!(spi.blocks[&21], blocks[1].contents);
+            assert_eq!(spi.state, MockState::Idle);
+        });
+    }
+
+    #[test]
+    fn verify_writes_detects_corruption() {
+        let card = verifying_card(CardType::SDHC);
+        let blocks = [Block::new(), Block::new()];
+        card.spi(|spi| spi.corrupt_reads = true);
+        assert!(matches!(
+            card.write(&blocks[0..1], BlockIdx(10)),
+            Err(Error::WriteError)
+        ));
+        assert!(matches!(
+            card.write(&blocks, BlockIdx(20)),
+            Err(Error::WriteError)
+        ));
+        // Without verification, the corruption goes unnoticed
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        card.spi(|spi| spi.corrupt_reads = true);
+        card.write(&blocks, BlockIdx(20)).unwrap();
+    }
+
+    #[test]
+    fn mock_csd() {
+        assert_eq!(
+            CsdV1 {
+                data: csd_v1(4095, 7, 15)
+            }
+            .card_capacity_blocks(),
+            4096 << 15
+        );
+        assert_eq!(
+            CsdV2 {
+                data: csd_v2(0xFFFF)
+            }
+            .card_capacity_blocks(),
+            0x0400_0000
+        );
+    }
+
+    #[test]
+    fn size_classes() {
+        assert_eq!(SizeClass::from_num_bytes(0), SizeClass::Sdsc);
+        assert_eq!(SizeClass::from_num_bytes(1 << 30), SizeClass::Sdsc);
+        assert_eq!(SizeClass::from_num_bytes(2 << 30), SizeClass::Sdsc);
+        assert_eq!(SizeClass::from_num_bytes((2 << 30) + 512), SizeClass::Sdhc);
+        assert_eq!(SizeClass::from_num_bytes(16 << 30), SizeClass::Sdhc);
+        assert_eq!(SizeClass::from_num_bytes((32 << 30) - 512), SizeClass::Sdhc);
+        assert_eq!(SizeClass::from_num_bytes(32 << 30), SizeClass::Sdxc);
+        assert_eq!(SizeClass::from_num_bytes(2 << 40), SizeClass::Sdxc);
+    }
+
+    #[test]
+    fn card_size_class() {
+        // The biggest SDHC card has a C_SIZE of 0xFF5F
+        for (c_size, class) in [
+            (0x1FFF, SizeClass::Sdhc),
+            (0xFF5F, SizeClass::Sdhc),
+            (0xFFFF, SizeClass::Sdxc),
+        ] {
+            let mut mock = MockCard::new(CardType::SDHC);
+            mock.csd = Some(csd_v2(c_size));
+            let card = SdCard::new(mock, MockDelay);
+            let num_bytes = (u64::from(c_size) + 1) * 512 * 1024;
+            assert_eq!(card.card_size_class().unwrap(), (class, num_bytes));
+        }
+        let mut mock = MockCard::new(CardType::SD1);
+        mock.csd = Some(csd_v1(4095, 7, 9));
+        let card = SdCard::new(mock, MockDelay);
+        assert_eq!(card.card_size_class().unwrap(), (SizeClass::Sdsc, 1 << 30));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        for card_type in [CardType::SD1, CardType::SD2, CardType::SDHC] {
+            let mut mock = MockCard::new(card_type);
+            // 1024 blocks
+            mock.csd = Some(match card_type {
+                CardType::SD1 => csd_v1(255, 0, 9),
+                CardType::SD2 | CardType::SDHC => csd_v2(0),
+            });
+            let card = SdCard::new(mock, MockDelay);
+            assert_eq!(card.num_blocks().unwrap(), BlockCount(1024));
+            let mut blocks = [Block::new(), Block::new()];
+
+            // The last block is fine
+            card.read(&mut blocks[0..1], BlockIdx(1023)).unwrap();
+            card.write(&blocks[0..1], BlockIdx(1023)).unwrap();
+
+            // One block past the end isn't, nor is running over the end
+            assert!(matches!(
+                card.read(&mut blocks[0..1], BlockIdx(1024)),
+                Err(Error::OutOfBounds(BlockIdx(1024)))
+            ));
+            assert!(matches!(
+                card.read(&mut blocks, BlockIdx(1023)),
+                Err(Error::OutOfBounds(BlockIdx(1023)))
+            ));
+            assert!(matches!(
+                card.write(&blocks, BlockIdx(1023)),
+                Err(Error::OutOfBounds(BlockIdx(1023)))
+            ));
+            assert!(matches!(
+                card.read(&mut blocks[0..1], BlockIdx(u32::MAX)),
+                Err(Error::OutOfBounds(BlockIdx(u32::MAX)))
+            ));
+
+            // None of those got as far as the card
+            let expected_address = match card_type {
+                CardType::SD1 | CardType::SD2 => 1023 * Block::LEN_U32,
+                CardType::SDHC => 1023,
+            };
+            assert_eq!(
+                data_commands(&card),
+                [(CMD17, expected_address), (CMD24, expected_address)]
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_dummy_byte() {
+        let dummy = vec![(MockOp::Write, vec![0xFF])];
+        let mut blocks = [Block::new(), Block::new()];
+
+        let card = SdCard::new(MockCard::new(CardType::SDHC), MockDelay);
+        card.read(&mut blocks, BlockIdx(0)).unwrap();
+        card.spi(|spi| assert_ne!(spi.last_transaction, dummy));
+
+        let options = AcquireOpts {
+            trailing_dummy_byte: true,
+            ..Default::default()
+        };
+        let card = SdCard::new_with_options(MockCard::new(CardType::SDHC), MockDelay, options);
+        card.read(&mut blocks[0..1], BlockIdx(0)).unwrap();
+        card.spi(|spi| assert_eq!(spi.last_transaction, dummy));
+        card.read(&mut blocks, BlockIdx(0)).unwrap();
+        card.spi(|spi| assert_eq!(spi.last_transaction, dummy));
+        card.write(&blocks, BlockIdx(0)).unwrap();
+        card.spi(|spi| assert_eq!(spi.last_transaction, dummy));
+        // Also sent when the command sequence fails
+        card.mark_card_uninit();
+        card.spi(|spi| spi.card_type = CardType::SD1);
+        assert!(matches!(
+            card.read(&mut blocks, BlockIdx(0x0080_0000)),
+            Err(Error::BadState)
+        ));
+        card.spi(|spi| assert_eq!(spi.last_transaction, dummy));
+    }
+
+    /// Something that happened to the power pin, or a delay.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum PowerEvent {
+        Low,
+        High,
+        DelayMs(u32),
+    }
+
+    type PowerLog = std::rc::Rc<std::cell::RefCell<Vec<PowerEvent>>>;
+
+    /// A power pin which records what it was set to.
+    struct MockPin {
+        log: PowerLog,
+        fail: bool,
+    }
+
+    impl embedded_hal::digital::ErrorType for MockPin {
+        type Error = embedded_hal::digital::ErrorKind;
+    }
+
+    impl embedded_hal::digital::OutputPin for MockPin {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err(embedded_hal::digital::ErrorKind::Other);
+            }
+            self.log.borrow_mut().push(PowerEvent::Low);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            if self.fail {
+                return Err(embedded_hal::digital::ErrorKind::Other);
+            }
+            self.log.borrow_mut().push(PowerEvent::High);
+            Ok(())
+        }
+    }
+
+    /// A delay which records millisecond delays in the same log as the pin.
+    struct RecordingDelay {
+        log: PowerLog,
+    }
+
+    impl embedded_hal::delay::DelayNs for RecordingDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+
+        fn delay_ms(&mut self, ms: u32) {
+            self.log.borrow_mut().push(PowerEvent::DelayMs(ms));
+        }
+    }
+
+    #[test]
+    fn power_cycle() {
+        let log = PowerLog::default();
+        let pin = MockPin {
+            log: log.clone(),
+            fail: false,
+        };
+        let delay = RecordingDelay { log: log.clone() };
+        let card = SdCard::new_spi_with_power(MockCard::new(CardType::SDHC), delay, pin);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        log.borrow_mut().clear();
+
+        card.power_cycle().unwrap();
+        assert_eq!(
+            *log.borrow(),
+            [
+                PowerEvent::Low,
+                PowerEvent::DelayMs(10),
+                PowerEvent::High,
+                PowerEvent::DelayMs(1)
+            ]
+        );
+
+        // The next operation initialises the card again
+        let count_cmd0 = |card: &SdCard<_, _, _>| {
+            card.spi(|spi: &mut MockCard| spi.commands.iter().filter(|(c, _)| *c == CMD0).count())
+        };
+        let before = count_cmd0(&card);
+        let mut blocks = [Block::new()];
+        card.read(&mut blocks, BlockIdx(0)).unwrap();
+        assert!(count_cmd0(&card) > before);
+    }
+
+    #[test]
+    fn free_with_power() {
+        let log = PowerLog::default();
+        let pin = MockPin {
+            log: log.clone(),
+            fail: false,
+        };
+        let delay = RecordingDelay { log: log.clone() };
+        let card = SdCard::new_spi_with_power(MockCard::new(CardType::SDHC), delay, pin);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        let (spi, mut delay, mut pin) = card.free_with_power();
+        assert!(spi.commands.contains(&(CMD0, 0)));
+        log.borrow_mut().clear();
+        embedded_hal::digital::OutputPin::set_low(&mut pin).unwrap();
+        embedded_hal::delay::DelayNs::delay_ms(&mut delay, 5);
+        assert_eq!(*log.borrow(), [PowerEvent::Low, PowerEvent::DelayMs(5)]);
+    }
+
+    #[test]
+    fn power_cycle_pin_error() {
+        let log = PowerLog::default();
+        let pin = MockPin {
+            log: log.clone(),
+            fail: true,
+        };
+        let delay = RecordingDelay { log: log.clone() };
+        let card = SdCard::new_spi_with_power(MockCard::new(CardType::SDHC), delay, pin);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        assert!(matches!(card.power_cycle(), Err(Error::GpioError)));
+        // We don't know what state the card is in now
+        card.spi(|spi| spi.commands.clear());
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        card.spi(|spi| assert_eq!(spi.commands.first(), Some(&(CMD0, 0))));
+    }
+
+    /// Something that happened on a bus with its own chip-select pin.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    enum BusEvent {
+        Select,
+        Deselect,
+        /// The bytes sent with one bus call, and whether the card was
+        /// selected at the time.
+        Bytes {
+            selected: bool,
+            mosi: Vec<u8>,
+        },
+    }
+
+    #[derive(Default)]
+    struct BusLog {
+        selected: bool,
+        events: Vec<BusEvent>,
+    }
+
+    type SharedBusLog = std::rc::Rc<std::cell::RefCell<BusLog>>;
+
+    /// An SPI bus with a card on it, which only listens when selected.
+    struct MockBus {
+        card: MockCard,
+        log: SharedBusLog,
+    }
+
+    impl MockBus {
+        fn exchange(&mut self, mosi: &[u8], miso: &mut [u8]) {
+            let mut log = self.log.borrow_mut();
+            let selected = log.selected;
+            for (i, b) in mosi.iter().enumerate() {
+                let out = if selected {
+                    self.card.exchange(*b)
+                } else {
+                    0xFF
+                };
+                if let Some(m) = miso.get_mut(i) {
+                    *m = out;
+                }
+            }
+            log.events.push(BusEvent::Bytes {
+                selected,
+                mosi: mosi.to_vec(),
+            });
+        }
+    }
+
+    impl ErrorType for MockBus {
+        type Error = core::convert::Infallible;
+    }
+
+    impl embedded_hal::spi::SpiBus<u8> for MockBus {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+            self.exchange(&vec![0xFF; words.len()], words);
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+            self.exchange(words, &mut []);
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+            let mut mosi = write.to_vec();
+            mosi.resize(read.len().max(write.len()), 0xFF);
+            self.exchange(&mosi, read);
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+            let mosi = words.to_vec();
+            self.exchange(&mosi, words);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    /// A chip-select pin which records what it was set to.
+    struct MockCs {
+        log: SharedBusLog,
+    }
+
+    impl embedded_hal::digital::ErrorType for MockCs {
+        type Error = core::convert::Infallible;
+    }
+
+    impl embedded_hal::digital::OutputPin for MockCs {
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            let mut log = self.log.borrow_mut();
+            log.selected = true;
+            log.events.push(BusEvent::Select);
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            let mut log = self.log.borrow_mut();
+            log.selected = false;
+            log.events.push(BusEvent::Deselect);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn spi_bus() {
+        let log = SharedBusLog::default();
+        let bus = MockBus {
+            card: MockCard::new(CardType::SDHC),
+            log: log.clone(),
+        };
+        let cs = MockCs { log: log.clone() };
+        let card = SdCard::new_spi_bus(bus, cs, MockDelay);
+        assert_eq!(card.get_card_type(), Some(CardType::SDHC));
+        let mut blocks = [Block::new(), Block::new()];
+        card.read(&mut blocks, BlockIdx(3)).unwrap();
+        card.write(&blocks, BlockIdx(5)).unwrap();
+
+        let events = core::mem::take(&mut log.borrow_mut().events);
+        // Power-up clocks first, with the card deselected
+        assert_eq!(events[0], BusEvent::Deselect);
+        assert_eq!(
+            events[1],
+            BusEvent::Bytes {
+                selected: false,
+                mosi: vec![0xFF; 10]
+            }
+        );
+        // Then each command sequence - getting the card type (which
+        // initialises the card), the read and the write - is one assertion of
+        // chip-select, followed by one clock with the card deselected
+        let mut rest = &events[2..];
+        let mut sequences = 0;
+        while !rest.is_empty() {
+            assert_eq!(rest[0], BusEvent::Select);
+            let end = rest.iter().position(|e| *e == BusEvent::Deselect).unwrap();
+            assert!(end > 1);
+            assert!(rest[1..end]
+                .iter()
+                .all(|e| matches!(e, BusEvent::Bytes { selected: true, .. })));
+            assert_eq!(
+                rest[end + 1],
+                BusEvent::Bytes {
+                    selected: false,
+                    mosi: vec![0xFF]
+                }
+            );
+            rest = &rest[end + 2..];
+            sequences += 1;
+        }
+        assert_eq!(sequences, 3);
+
+        // The card saw everything that was sent while it was selected
+        let (device, _delay) = card.free();
+        let (bus, _cs) = device.free();
+        assert_eq!(bus.card.commands.first(), Some(&(CMD0, 0)));
+        assert!(bus.card.commands.contains(&(CMD18, 3)));
+        assert!(bus.card.commands.contains(&(CMD25, 5)));
+    }
+}
+
 // ****************************************************************************
 //
 // End Of File
@@ -60,6 +60,8 @@ pub const CMD55: u8 = 0x37;
 pub const CMD58: u8 = 0x3A;
 /// CRC_ON_OFF - enable or disable CRC checking
 pub const CMD59: u8 = 0x3B;
+/// SD_STATUS - read the SD Status register
+pub const ACMD13: u8 = 0x0D;
 /// Pre-erased before writing
 ///
 /// > It is recommended using this command preceding CMD25, some of the cards will be faster for Multiple
@@ -69,6 +71,46 @@ pub const ACMD23: u8 = 0x17;
 /// SD_SEND_OP_COMD - Sends host capacity support information and activates
 /// the card's initialization process
 pub const ACMD41: u8 = 0x29;
+/// SEND_SCR - read the SD Configuration Register (SCR)
+pub const ACMD51: u8 = 0x33;
+
+/// Get the number and name of a command we send, like `CMD17
+/// (READ_SINGLE_BLOCK)`, for logs and error messages.
+///
+/// Returns `None` for commands we don't use.
+pub fn command_name(command: u8) -> Option<&'static str> {
+    let name = match command {
+        CMD0 => "CMD0 (GO_IDLE_STATE)",
+        CMD8 => "CMD8 (SEND_IF_COND)",
+        CMD9 => "CMD9 (SEND_CSD)",
+        CMD12 => "CMD12 (STOP_TRANSMISSION)",
+        CMD13 => "CMD13 (SEND_STATUS)",
+        CMD17 => "CMD17 (READ_SINGLE_BLOCK)",
+        CMD18 => "CMD18 (READ_MULTIPLE_BLOCK)",
+        CMD24 => "CMD24 (WRITE_BLOCK)",
+        CMD25 => "CMD25 (WRITE_MULTIPLE_BLOCK)",
+        CMD55 => "CMD55 (APP_CMD)",
+        CMD58 => "CMD58 (READ_OCR)",
+        CMD59 => "CMD59 (CRC_ON_OFF)",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Get the number and name of an application-specific command we send
+/// (one which follows CMD55), like `ACMD41 (SD_SEND_OP_COND)`.
+///
+/// Returns `None` for commands we don't use.
+pub fn app_command_name(command: u8) -> Option<&'static str> {
+    let name = match command {
+        ACMD13 => "ACMD13 (SD_STATUS)",
+        ACMD23 => "ACMD23 (SET_WR_BLK_ERASE_COUNT)",
+        ACMD41 => "ACMD41 (SD_SEND_OP_COND)",
+        ACMD51 => "ACMD51 (SEND_SCR)",
+        _ => return None,
+    };
+    Some(name)
+}
 
 //==============================================================================
 
@@ -213,6 +255,111 @@ impl CsdV2 {
     }
 }
 
+/// SD Configuration Register
+///
+/// Says which versions of the SD specification, bus widths and commands the
+/// card supports.
+#[derive(Default, Debug)]
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+pub struct Scr {
+    /// The 8-bytes of data in this SD Configuration Register
+    pub data: [u8; 8],
+}
+
+impl Scr {
+    /// Create a new, empty, SCR
+    pub fn new() -> Scr {
+        Scr::default()
+    }
+
+    define_field!(scr_structure, u8, 0, 4, 4);
+    define_field!(sd_spec, u8, 0, 0, 4);
+    define_field!(data_stat_after_erase, bool, 1, 7);
+    define_field!(sd_security, u8, 1, 4, 3);
+    define_field!(sd_bus_widths, u8, 1, 0, 4);
+    define_field!(sd_spec3, bool, 2, 7);
+    define_field!(ex_security, u8, 2, 3, 4);
+    define_field!(sd_spec4, bool, 2, 2);
+    define_field!(sd_specx, u8, [(2, 0, 2), (3, 6, 2)]);
+    define_field!(cmd_support, u8, 3, 0, 4);
+
+    /// Does the card support the 4-bit wide SD bus?
+    pub fn supports_4bit_bus(&self) -> bool {
+        (self.sd_bus_widths() & 0b0100) != 0
+    }
+}
+
+/// SD Status register
+///
+/// Describes the card's current state and its performance characteristics,
+/// such as the size of its Allocation Unit.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+pub struct SdStatus {
+    /// The 64-bytes of data in this SD Status register
+    pub data: [u8; 64],
+}
+
+impl Default for SdStatus {
+    fn default() -> SdStatus {
+        SdStatus { data: [0; 64] }
+    }
+}
+
+impl SdStatus {
+    /// Create a new, empty, SD Status
+    pub fn new() -> SdStatus {
+        SdStatus::default()
+    }
+
+    define_field!(dat_bus_width, u8, 0, 6, 2);
+    define_field!(secured_mode, bool, 0, 5);
+    define_field!(speed_class, u8, 8);
+    define_field!(performance_move, u8, 9);
+    define_field!(au_size, u8, 10, 4, 4);
+
+    /// The size of the card's Allocation Unit, in bytes.
+    ///
+    /// Returns `None` if the card doesn't say.
+    pub fn au_size_bytes(&self) -> Option<u32> {
+        const KIB: u32 = 1024;
+        const MIB: u32 = 1024 * KIB;
+        let bytes = match self.au_size() {
+            0x0 => return None,
+            // 16 KiB to 4 MiB go up in powers of two
+            n @ 0x1..=0x9 => (16 * KIB) << (n - 1),
+            0xA => 8 * MIB,
+            0xB => 12 * MIB,
+            0xC => 16 * MIB,
+            0xD => 24 * MIB,
+            0xE => 32 * MIB,
+            _ => 64 * MIB,
+        };
+        Some(bytes)
+    }
+}
+
+/// Decode the `TRAN_SPEED` field of a CSD into the maximum clock frequency,
+/// in Hz.
+///
+/// Bits 2:0 give the unit and bits 6:3 give a multiplier. Returns `None` if
+/// either uses a reserved value.
+pub fn tran_speed_hz(tran_speed: u8) -> Option<u32> {
+    // The units, divided by ten so the multipliers can be whole numbers
+    const UNITS: [u32; 4] = [10_000, 100_000, 1_000_000, 10_000_000];
+    // The multipliers, times ten
+    const MULTIPLIERS: [u32; 16] = [
+        0, 10, 12, 13, 15, 20, 25, 30, 35, 40, 45, 50, 55, 60, 70, 80,
+    ];
+    let unit = UNITS.get(usize::from(tran_speed & 0x07))?;
+    let multiplier = MULTIPLIERS[usize::from((tran_speed >> 3) & 0x0F)];
+    if multiplier == 0 {
+        None
+    } else {
+        Some(unit * multiplier)
+    }
+}
+
 /// Perform the 7-bit CRC used on the SD card
 pub fn crc7(data: &[u8]) -> u8 {
     let mut crc = 0u8;
@@ -230,7 +377,14 @@ pub fn crc7(data: &[u8]) -> u8 {
 
 /// Perform the X25 CRC calculation, as used for data blocks.
 pub fn crc16(data: &[u8]) -> u16 {
-    let mut crc = 0u16;
+    crc16_update(0, data)
+}
+
+/// Continue an X25 CRC calculation with some more data.
+///
+/// Start with a `crc` of zero. Feeding the data in through any number of
+/// calls gives the same result as passing it all to [`crc16`] at once.
+pub fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
     for &byte in data {
         crc = ((crc >> 8) & 0xFF) | (crc << 8);
         crc ^= u16::from(byte);
@@ -264,6 +418,94 @@ mod test {
         assert_eq!(crc16(&DATA), 0x9fc5);
     }
 
+    #[test]
+    fn test_crc16_incremental() {
+        // Some pseudo-random data, from a simple LCG
+        let mut data = [0u8; 512];
+        let mut seed = 0x1234_5678u32;
+        for b in data.iter_mut() {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            *b = (seed >> 16) as u8;
+        }
+        let expected = crc16(&data);
+        for chunk_len in [1, 7, 64, 100, 511, 512] {
+            let crc = data.chunks(chunk_len).fold(0, crc16_update);
+            assert_eq!(crc, expected, "chunk_len = {}", chunk_len);
+        }
+        assert_eq!(crc16_update(0, &[]), 0);
+    }
+
+    #[test]
+    fn test_tran_speed() {
+        assert_eq!(tran_speed_hz(0x32), Some(25_000_000));
+        assert_eq!(tran_speed_hz(0x5A), Some(50_000_000));
+        assert_eq!(tran_speed_hz(0x0B), Some(100_000_000));
+        assert_eq!(tran_speed_hz(0x48), Some(400_000));
+        // Reserved multiplier
+        assert_eq!(tran_speed_hz(0x02), None);
+        // Reserved unit
+        assert_eq!(tran_speed_hz(0x34), None);
+    }
+
+    #[test]
+    fn test_command_names() {
+        assert_eq!(command_name(17), Some("CMD17 (READ_SINGLE_BLOCK)"));
+        assert_eq!(command_name(CMD0), Some("CMD0 (GO_IDLE_STATE)"));
+        assert_eq!(command_name(CMD25), Some("CMD25 (WRITE_MULTIPLE_BLOCK)"));
+        assert_eq!(command_name(CMD58), Some("CMD58 (READ_OCR)"));
+        assert_eq!(command_name(99), None);
+        // The same numbers mean something else after CMD55
+        assert_eq!(command_name(13), Some("CMD13 (SEND_STATUS)"));
+        assert_eq!(app_command_name(13), Some("ACMD13 (SD_STATUS)"));
+        assert_eq!(app_command_name(ACMD41), Some("ACMD41 (SD_SEND_OP_COND)"));
+        assert_eq!(app_command_name(CMD17), None);
+    }
+
+    #[test]
+    fn test_scr() {
+        // An example SCR from an SDHC card
+        const EXAMPLE: Scr = Scr {
+            data: hex!("02 35 84 83 00 00 00 00"),
+        };
+        assert_eq!(EXAMPLE.scr_structure(), 0);
+        // Physical Layer Specification version 6.xx
+        assert_eq!(EXAMPLE.sd_spec(), 2);
+        assert!(EXAMPLE.sd_spec3());
+        assert!(EXAMPLE.sd_spec4());
+        assert_eq!(EXAMPLE.sd_specx(), 2);
+        assert!(!EXAMPLE.data_stat_after_erase());
+        // SDHC security (CPRM version 2.00)
+        assert_eq!(EXAMPLE.sd_security(), 3);
+        // 1-bit and 4-bit buses
+        assert_eq!(EXAMPLE.sd_bus_widths(), 0b0101);
+        assert!(EXAMPLE.supports_4bit_bus());
+        assert_eq!(EXAMPLE.ex_security(), 0);
+        // CMD20 and CMD23
+        assert_eq!(EXAMPLE.cmd_support(), 0b0011);
+    }
+
+    #[test]
+    fn test_sd_status() {
+        let mut status = SdStatus::new();
+        assert_eq!(status.au_size(), 0);
+        assert_eq!(status.au_size_bytes(), None);
+        // 4-bit bus, Class 10, 4 MiB AU
+        status.data[0] = 0x80;
+        status.data[8] = 0x04;
+        status.data[10] = 0x90;
+        assert_eq!(status.dat_bus_width(), 2);
+        assert!(!status.secured_mode());
+        assert_eq!(status.speed_class(), 4);
+        assert_eq!(status.au_size(), 9);
+        assert_eq!(status.au_size_bytes(), Some(4 * 1024 * 1024));
+        status.data[10] = 0x10;
+        assert_eq!(status.au_size_bytes(), Some(16 * 1024));
+        status.data[10] = 0xB0;
+        assert_eq!(status.au_size_bytes(), Some(12 * 1024 * 1024));
+        status.data[10] = 0xF0;
+        assert_eq!(status.au_size_bytes(), Some(64 * 1024 * 1024));
+    }
+
     #[test]
     fn test_csdv1b() {
         const EXAMPLE: CsdV1 = CsdV1 {
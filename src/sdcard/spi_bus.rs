@@ -0,0 +1,172 @@
+//! An [`SpiDevice`] made from an [`SpiBus`] and a chip-select pin, which
+//! drives chip-select the way an SD Card wants.
+//!
+//! [`SpiDevice`]: embedded_hal::spi::SpiDevice
+//! [`SpiBus`]: embedded_hal::spi::SpiBus
+
+use embedded_hal::digital::OutputPin;
+use embedded_hal::spi::{Operation, SpiBus};
+
+use crate::trace;
+
+/// How many `0xFF` bytes make up the power-up clocks.
+const INIT_CLOCK_BYTES: usize = 10;
+
+/// Gives an SD Card driver sole use of an [`SpiBus`], with its own
+/// chip-select pin.
+///
+/// The first transaction asserts chip-select (drives it low), and it stays
+/// asserted through every transaction after that, until
+/// [`SpiBusDevice::deselect`] is called. That way, a command, its response
+/// and any data that goes with it all happen within one assertion of
+/// chip-select. An [`SdCard`](crate::SdCard) made with
+/// [`SdCard::new_spi_bus`](crate::SdCard::new_spi_bus) calls `deselect` at
+/// the end of each command sequence, which de-asserts chip-select and then
+/// clocks out one `0xFF` byte with chip-select high, because a card keeps
+/// driving MISO until it sees a clock after chip-select goes high.
+///
+/// Before the first transaction, it also sends the 74 (well, 80) clock
+/// cycles with chip-select high that a card needs after power-up. Make sure
+/// your bus is clocked at 400 kHz or less until the card is initialised. If
+/// you power cycle the card, call [`SpiBusDevice::send_init_clocks`] (through
+/// [`SdCard::spi`](crate::SdCard::spi)) before using it again.
+///
+/// Nothing in the SD Card driver asks for `Operation::DelayNs`, so there is
+/// no delay here to perform one with - you get
+/// [`SpiBusDeviceError::DelayUnsupported`] instead.
+///
+/// [`SpiBus`]: embedded_hal::spi::SpiBus
+#[derive(Debug)]
+pub struct SpiBusDevice<BUS, CS> {
+    bus: BUS,
+    cs: CS,
+    init_clocks_due: bool,
+    selected: bool,
+}
+
+impl<BUS, CS> SpiBusDevice<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    /// Wrap an SPI bus and a chip-select pin.
+    ///
+    /// Nothing is sent until the first transaction.
+    pub fn new(bus: BUS, cs: CS) -> SpiBusDevice<BUS, CS> {
+        SpiBusDevice {
+            bus,
+            cs,
+            init_clocks_due: true,
+            selected: false,
+        }
+    }
+
+    /// Send the power-up clock cycles, with chip-select high, right now.
+    pub fn send_init_clocks(&mut self) -> Result<(), SpiBusDeviceError<BUS::Error, CS::Error>> {
+        trace!("Sending init clocks");
+        self.init_clocks_due = false;
+        self.selected = false;
+        self.cs.set_high().map_err(SpiBusDeviceError::ChipSelect)?;
+        self.bus
+            .write(&[0xFF; INIT_CLOCK_BYTES])
+            .map_err(SpiBusDeviceError::Spi)?;
+        self.bus.flush().map_err(SpiBusDeviceError::Spi)
+    }
+
+    /// De-assert chip-select, if it is asserted, and then clock out one
+    /// `0xFF` byte so the card lets go of MISO.
+    pub fn deselect(&mut self) -> Result<(), SpiBusDeviceError<BUS::Error, CS::Error>> {
+        if !self.selected {
+            return Ok(());
+        }
+        self.selected = false;
+        self.cs.set_high().map_err(SpiBusDeviceError::ChipSelect)?;
+        self.bus.write(&[0xFF]).map_err(SpiBusDeviceError::Spi)?;
+        self.bus.flush().map_err(SpiBusDeviceError::Spi)
+    }
+
+    /// Get back the bus and the chip-select pin.
+    pub fn free(self) -> (BUS, CS) {
+        (self.bus, self.cs)
+    }
+
+    /// Perform the operations, with chip-select already low.
+    fn run(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), SpiBusDeviceError<BUS::Error, CS::Error>> {
+        for op in operations {
+            match op {
+                Operation::Read(buf) => self.bus.read(buf),
+                Operation::Write(buf) => self.bus.write(buf),
+                Operation::Transfer(read, write) => self.bus.transfer(read, write),
+                Operation::TransferInPlace(buf) => self.bus.transfer_in_place(buf),
+                Operation::DelayNs(_) => return Err(SpiBusDeviceError::DelayUnsupported),
+            }
+            .map_err(SpiBusDeviceError::Spi)?;
+        }
+        self.bus.flush().map_err(SpiBusDeviceError::Spi)
+    }
+}
+
+impl<BUS, CS> embedded_hal::spi::ErrorType for SpiBusDevice<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    type Error = SpiBusDeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS> embedded_hal::spi::SpiDevice<u8> for SpiBusDevice<BUS, CS>
+where
+    BUS: SpiBus<u8>,
+    CS: OutputPin,
+{
+    fn transaction(&mut self, operations: &mut [Operation<'_, u8>]) -> Result<(), Self::Error> {
+        if self.init_clocks_due {
+            self.send_init_clocks()?;
+        }
+        if !self.selected {
+            self.cs.set_low().map_err(SpiBusDeviceError::ChipSelect)?;
+            self.selected = true;
+        }
+        let result = self.run(operations);
+        if result.is_err() {
+            // Let go of the card, as whatever it was doing has gone wrong
+            let _ = self.deselect();
+        }
+        result
+    }
+}
+
+/// The errors an [`SpiBusDevice`] can give.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpiBusDeviceError<BUS, CS> {
+    /// The SPI bus gave an error
+    Spi(BUS),
+    /// The chip-select pin gave an error
+    ChipSelect(CS),
+    /// A transaction asked for a delay, which we can't do
+    DelayUnsupported,
+}
+
+impl<BUS, CS> embedded_hal::spi::Error for SpiBusDeviceError<BUS, CS>
+where
+    BUS: embedded_hal::spi::Error,
+    CS: core::fmt::Debug,
+{
+    fn kind(&self) -> embedded_hal::spi::ErrorKind {
+        match self {
+            SpiBusDeviceError::Spi(e) => e.kind(),
+            SpiBusDeviceError::ChipSelect(_) => embedded_hal::spi::ErrorKind::ChipSelectFault,
+            SpiBusDeviceError::DelayUnsupported => embedded_hal::spi::ErrorKind::Other,
+        }
+    }
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
@@ -4,27 +4,139 @@
 
 use core::cell::RefCell;
 use core::convert::TryFrom;
+use core::ops::ControlFlow;
 
 use byteorder::{ByteOrder, LittleEndian};
 use heapless::Vec;
 
-use crate::fat::{self, BlockCache, FatType, OnDiskDirEntry, RESERVED_ENTRIES};
+use crate::fat::{self, BlockCache, DirPosition, OnDiskDirEntry, RESERVED_ENTRIES};
 
 use crate::filesystem::{
-    Attributes, ClusterId, DirEntry, DirectoryInfo, FileInfo, HandleGenerator, Mode, RawDirectory,
-    RawFile, TimeSource, ToShortFileName, MAX_FILE_SIZE,
+    Attributes, ChainCheckpoints, ClusterId, CodePage, DirEntry, DirScan, DirectoryInfo, EntryKind,
+    FileInfo, FileMeta, FilenameError, HandleGenerator, Mode, RawDirectory, RawFile,
+    ShortFileNameDisplay, ShortFileNamePattern, TimeSource, Timestamp, ToShortFileName,
+    WalkControl, MAX_FILE_SIZE,
 };
 use crate::{
-    debug, trace, Block, BlockCount, BlockDevice, BlockIdx, Error, RawVolume, ShortFileName,
-    Volume, VolumeIdx, VolumeInfo, VolumeType, PARTITION_ID_FAT16, PARTITION_ID_FAT16_LBA,
+    debug, trace, warn, Block, BlockCount, BlockDevice, BlockIdx, Error, FsckReport, RawVolume,
+    ShortFileName, Volume, VolumeIdx, VolumeInfo, VolumeType, PARTITION_ID_EXTENDED,
+    PARTITION_ID_EXTENDED_LBA, PARTITION_ID_FAT16, PARTITION_ID_FAT16_LBA,
     PARTITION_ID_FAT32_CHS_LBA, PARTITION_ID_FAT32_LBA,
 };
 
+/// Options for opening a volume.
+///
+/// Use `Default::default()` for the standard behaviour.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolumeOpts {
+    /// On a FAT16 volume, scan the whole FAT when the volume is opened to
+    /// count the free clusters. The count is then kept up to date as clusters
+    /// are allocated and freed.
+    ///
+    /// FAT32 volumes record the count in their info sector, so this only
+    /// affects FAT16 volumes. The scan reads every block of the FAT, so it is
+    /// off by default.
+    pub count_fat16_free_clusters: bool,
+    /// On a FAT32 volume, use the free cluster count and next free cluster
+    /// recorded in the info sector.
+    ///
+    /// Some cards have a stale or corrupt info sector. Turning this off
+    /// ignores it, and counts the free clusters by scanning the whole FAT
+    /// when the volume is opened instead. The corrected count is written back
+    /// to the info sector the next time a file is flushed. It is on by
+    /// default.
+    pub trust_info_sector: bool,
+    /// Refuse every operation which would write to the volume.
+    ///
+    /// Opening a file in any mode other than [`Mode::ReadOnly`], or creating,
+    /// deleting or renaming anything, fails with `Error::ReadOnly`. Nothing
+    /// on the volume (not even the info sector) is written when files are
+    /// flushed or closed, so the card is never modified. It is off by
+    /// default.
+    pub read_only: bool,
+    /// The most blocks to read when looking something up in, or listing, a
+    /// directory.
+    ///
+    /// A huge, fragmented directory can take a long time to search. With a
+    /// limit set, a search which would read more blocks than this stops and
+    /// gives `Error::DirectoryScanLimitExceeded` instead, so you can bound
+    /// how long each call blocks for. It is unlimited (`None`) by default.
+    pub max_dir_scan_blocks: Option<u32>,
+    /// Check that the copies of the FAT all match when the volume is opened,
+    /// and fail with `Error::FatMirrorMismatch` if they don't.
+    ///
+    /// See [`VolumeManager::verify_fats`]. This reads every block of every
+    /// copy of the FAT, so it is off by default.
+    pub verify_fat_mirror: bool,
+    /// The code page to use for the bytes above `0x7F` in short file names,
+    /// when the volume manager turns them into text.
+    ///
+    /// See [`VolumeManager::code_page`]. It is [`CodePage::Cp437`] by
+    /// default.
+    pub code_page: CodePage,
+    /// Keep what we know about the volume when it is closed, so that opening
+    /// it again (with this option set) doesn't have to read the partition
+    /// table and boot sector again.
+    ///
+    /// This is for closing and re-opening a volume often, when nothing else
+    /// touches the card in between. If something else might have changed the
+    /// card - you've let a USB host at it, or it was swapped and you called
+    /// `SdCard::mark_card_uninit` - call
+    /// [`VolumeManager::forget_cached_volumes`] before opening it again. It
+    /// is off by default.
+    pub cache_geometry: bool,
+}
+
+impl Default for VolumeOpts {
+    fn default() -> Self {
+        VolumeOpts {
+            count_fat16_free_clusters: false,
+            trust_info_sector: true,
+            read_only: false,
+            max_dir_scan_blocks: None,
+            verify_fat_mirror: false,
+            code_page: CodePage::Cp437,
+            cache_geometry: false,
+        }
+    }
+}
+
+/// Options for opening a file.
+///
+/// Use `Default::default()` for the standard behaviour.
+#[cfg_attr(feature = "defmt-log", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FileOpts {
+    /// Write the directory entry to disk (and flush the block device) at the
+    /// end of every `write` call.
+    ///
+    /// Normally the directory entry is only updated when the file is flushed
+    /// or closed, so if power is lost in between, the file's new length is
+    /// lost even though the data blocks were written. Setting this makes each
+    /// write take longer, but leaves the file consistent on disk after every
+    /// write. It is off by default.
+    pub sync_entry_on_write: bool,
+    /// The attributes to give the file, if opening it creates it.
+    ///
+    /// These are ignored when opening an existing file. The directory and
+    /// volume label attributes can't be used for a file, and give
+    /// `Error::Unsupported`.
+    pub attributes: Attributes,
+}
+
 /// Wraps a block device and gives access to the FAT-formatted volumes within
 /// it.
 ///
 /// Tracks which files and directories are open, to prevent you from deleting
 /// a file or directory you currently have open.
+///
+/// Each open file can also remember where it is in its FAT chain at every
+/// 64th cluster, up to `MAX_CHECKPOINTS` places. Seeking backwards (or a long
+/// way forwards) then starts walking the chain from the nearest checkpoint
+/// before the new offset, instead of from the start of the file. Each
+/// checkpoint costs eight bytes per open file. The default of zero keeps no
+/// checkpoints at all.
 #[derive(Debug)]
 pub struct VolumeManager<
     D,
@@ -32,6 +144,7 @@ pub struct VolumeManager<
     const MAX_DIRS: usize = 4,
     const MAX_FILES: usize = 4,
     const MAX_VOLUMES: usize = 1,
+    const MAX_CHECKPOINTS: usize = 0,
 > where
     D: BlockDevice,
     T: TimeSource,
@@ -39,7 +152,7 @@ pub struct VolumeManager<
 {
     pub(crate) block_device: D,
     pub(crate) time_source: T,
-    data: RefCell<VolumeManagerData<MAX_DIRS, MAX_FILES, MAX_VOLUMES>>,
+    data: RefCell<VolumeManagerData<MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>>,
 }
 
 impl<D, T> VolumeManager<D, T, 4, 4>
@@ -53,17 +166,156 @@ where
     /// files.
     ///
     /// This creates a `VolumeManager` with default values
-    /// MAX_DIRS = 4, MAX_FILES = 4, MAX_VOLUMES = 1. Call `VolumeManager::new_with_limits(block_device, time_source)`
+    /// MAX_DIRS = 4, MAX_FILES = 4, MAX_VOLUMES = 1. Call `VolumeManager::builder(block_device, time_source)`
     /// if you need different limits.
     pub fn new(block_device: D, time_source: T) -> VolumeManager<D, T, 4, 4, 1> {
         // Pick a random starting point for the IDs that's not zero, because
         // zero doesn't stand out in the logs.
         Self::new_with_limits(block_device, time_source, 5000)
     }
+
+    /// Start building a Volume Manager, so you can choose the limits on open
+    /// volumes, directories and files without spelling out the whole type.
+    ///
+    /// See [`VolumeManagerBuilder`] for an example.
+    pub fn builder(block_device: D, time_source: T) -> VolumeManagerBuilder<D, T> {
+        VolumeManagerBuilder {
+            block_device,
+            time_source,
+            id_offset: 5000,
+        }
+    }
 }
 
-impl<D, T, const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+/// Builds a [`VolumeManager`], with a choice of limits.
+///
+/// Each limit defaults to the same value as [`VolumeManager::new`] uses. Each
+/// method that sets a limit gives you a new builder, carrying that limit in
+/// its type.
+///
+/// ```
+/// use embedded_sdmmc::{BlockDevice, NullTimeSource, VolumeManager};
+///
+/// fn make_volume_manager<D>(block_device: D) -> VolumeManager<D, NullTimeSource, 8, 16, 2>
+/// where
+///     D: BlockDevice,
+///     D::Error: core::fmt::Debug,
+/// {
+///     VolumeManager::builder(block_device, NullTimeSource)
+///         .max_dirs::<8>()
+///         .max_files::<16>()
+///         .max_volumes::<2>()
+///         .build()
+/// }
+/// ```
+///
+/// If you don't set any limits, you get the defaults:
+///
+/// ```
+/// use embedded_sdmmc::{BlockDevice, NullTimeSource, VolumeManager};
+///
+/// fn make_volume_manager<D>(block_device: D) -> VolumeManager<D, NullTimeSource>
+/// where
+///     D: BlockDevice,
+///     D::Error: core::fmt::Debug,
+/// {
+///     VolumeManager::builder(block_device, NullTimeSource).build()
+/// }
+/// ```
+#[derive(Debug)]
+pub struct VolumeManagerBuilder<
+    D,
+    T,
+    const MAX_DIRS: usize = 4,
+    const MAX_FILES: usize = 4,
+    const MAX_VOLUMES: usize = 1,
+    const MAX_CHECKPOINTS: usize = 0,
+> {
+    block_device: D,
+    time_source: T,
+    id_offset: u32,
+}
+
+impl<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > VolumeManagerBuilder<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
+where
+    D: BlockDevice,
+    T: TimeSource,
+    <D as BlockDevice>::Error: core::fmt::Debug,
+{
+    /// Set how many directories can be open at once.
+    pub fn max_dirs<const N: usize>(
+        self,
+    ) -> VolumeManagerBuilder<D, T, N, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
+        VolumeManagerBuilder {
+            block_device: self.block_device,
+            time_source: self.time_source,
+            id_offset: self.id_offset,
+        }
+    }
+
+    /// Set how many files can be open at once.
+    pub fn max_files<const N: usize>(
+        self,
+    ) -> VolumeManagerBuilder<D, T, MAX_DIRS, N, MAX_VOLUMES, MAX_CHECKPOINTS> {
+        VolumeManagerBuilder {
+            block_device: self.block_device,
+            time_source: self.time_source,
+            id_offset: self.id_offset,
+        }
+    }
+
+    /// Set how many volumes can be open at once.
+    pub fn max_volumes<const N: usize>(
+        self,
+    ) -> VolumeManagerBuilder<D, T, MAX_DIRS, MAX_FILES, N, MAX_CHECKPOINTS> {
+        VolumeManagerBuilder {
+            block_device: self.block_device,
+            time_source: self.time_source,
+            id_offset: self.id_offset,
+        }
+    }
+
+    /// Set how many FAT chain checkpoints each open file keeps.
+    ///
+    /// See [`VolumeManager`] for what these are for.
+    pub fn max_checkpoints<const N: usize>(
+        self,
+    ) -> VolumeManagerBuilder<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, N> {
+        VolumeManagerBuilder {
+            block_device: self.block_device,
+            time_source: self.time_source,
+            id_offset: self.id_offset,
+        }
+    }
+
+    /// Set the offset for all the IDs the Volume Manager generates.
+    ///
+    /// See [`VolumeManager::new_with_limits`].
+    pub fn id_offset(self, id_offset: u32) -> Self {
+        Self { id_offset, ..self }
+    }
+
+    /// Create the Volume Manager.
+    pub fn build(self) -> VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
+        VolumeManager::new_with_limits(self.block_device, self.time_source, self.id_offset)
+    }
+}
+
+impl<
+        D,
+        T,
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 where
     D: BlockDevice,
     T: TimeSource,
@@ -80,7 +332,7 @@ where
         block_device: D,
         time_source: T,
         id_offset: u32,
-    ) -> VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES> {
+    ) -> VolumeManager<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS> {
         debug!("Creating new embedded-sdmmc::VolumeManager");
         VolumeManager {
             block_device,
@@ -90,6 +342,7 @@ where
                 open_volumes: Vec::new(),
                 open_dirs: Vec::new(),
                 open_files: Vec::new(),
+                cached_volumes: Vec::new(),
             }),
         }
     }
@@ -111,11 +364,40 @@ where
     pub fn open_volume(
         &self,
         volume_idx: VolumeIdx,
-    ) -> Result<Volume<D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES>, Error<D::Error>> {
+    ) -> Result<Volume<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>, Error<D::Error>>
+    {
         let v = self.open_raw_volume(volume_idx)?;
         Ok(v.to_volume(self))
     }
 
+    /// Get a volume (or partition) based on entries in the Master Boot Record,
+    /// using the given options.
+    pub fn open_volume_with_options(
+        &self,
+        volume_idx: VolumeIdx,
+        options: VolumeOpts,
+    ) -> Result<Volume<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>, Error<D::Error>>
+    {
+        let v = self.open_raw_volume_with_options(volume_idx, options)?;
+        Ok(v.to_volume(self))
+    }
+
+    /// Get a volume (or partition) based on entries in the Master Boot Record,
+    /// refusing any operation which would write to it.
+    ///
+    /// See [`VolumeOpts::read_only`].
+    pub fn open_volume_read_only(
+        &self,
+        volume_idx: VolumeIdx,
+    ) -> Result<Volume<'_, D, T, MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>, Error<D::Error>>
+    {
+        let options = VolumeOpts {
+            read_only: true,
+            ..Default::default()
+        };
+        self.open_volume_with_options(volume_idx, options)
+    }
+
     /// Get a volume (or partition) based on entries in the Master Boot Record.
     ///
     /// We do not support GUID Partition Table disks. Nor do we support any
@@ -124,18 +406,19 @@ where
     /// This function gives you a `RawVolume` and you must close the volume by
     /// calling `VolumeManager::close_volume`.
     pub fn open_raw_volume(&self, volume_idx: VolumeIdx) -> Result<RawVolume, Error<D::Error>> {
-        const PARTITION1_START: usize = 446;
-        const PARTITION2_START: usize = PARTITION1_START + PARTITION_INFO_LENGTH;
-        const PARTITION3_START: usize = PARTITION2_START + PARTITION_INFO_LENGTH;
-        const PARTITION4_START: usize = PARTITION3_START + PARTITION_INFO_LENGTH;
-        const FOOTER_START: usize = 510;
-        const FOOTER_VALUE: u16 = 0xAA55;
-        const PARTITION_INFO_LENGTH: usize = 16;
-        const PARTITION_INFO_STATUS_INDEX: usize = 0;
-        const PARTITION_INFO_TYPE_INDEX: usize = 4;
-        const PARTITION_INFO_LBA_START_INDEX: usize = 8;
-        const PARTITION_INFO_NUM_BLOCKS_INDEX: usize = 12;
+        self.open_raw_volume_with_options(volume_idx, VolumeOpts::default())
+    }
 
+    /// Get a volume (or partition) based on entries in the Master Boot Record,
+    /// using the given options.
+    ///
+    /// This function gives you a `RawVolume` and you must close the volume by
+    /// calling `VolumeManager::close_volume`.
+    pub fn open_raw_volume_with_options(
+        &self,
+        volume_idx: VolumeIdx,
+        options: VolumeOpts,
+    ) -> Result<RawVolume, Error<D::Error>> {
         let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
 
         if data.open_volumes.is_full() {
@@ -148,62 +431,74 @@ where
             }
         }
 
-        let (part_type, lba_start, num_blocks) = {
-            let mut blocks = [Block::new()];
-            trace!("Reading partition table");
-            self.block_device
-                .read(&mut blocks, BlockIdx(0))
-                .map_err(Error::DeviceError)?;
-            let block = &blocks[0];
-            // We only support Master Boot Record (MBR) partitioned cards, not
-            // GUID Partition Table (GPT)
-            if LittleEndian::read_u16(&block[FOOTER_START..FOOTER_START + 2]) != FOOTER_VALUE {
-                return Err(Error::FormatError("Invalid MBR signature"));
-            }
-            let partition = match volume_idx {
-                VolumeIdx(0) => {
-                    &block[PARTITION1_START..(PARTITION1_START + PARTITION_INFO_LENGTH)]
-                }
-                VolumeIdx(1) => {
-                    &block[PARTITION2_START..(PARTITION2_START + PARTITION_INFO_LENGTH)]
-                }
-                VolumeIdx(2) => {
-                    &block[PARTITION3_START..(PARTITION3_START + PARTITION_INFO_LENGTH)]
+        // Whatever we do with it, a cached copy would be out of date once the
+        // volume is open
+        let cached = data
+            .cached_volumes
+            .iter()
+            .position(|(idx, _)| *idx == volume_idx)
+            .map(|pos| data.cached_volumes.remove(pos).1);
+        if options.cache_geometry {
+            if let Some(mut volume) = cached {
+                debug!("Using cached volume {:?}", volume_idx);
+                // Everything else we found out is still up to date
+                let VolumeType::Fat(fat) = &mut volume;
+                fat.max_dir_scan_blocks = options.max_dir_scan_blocks;
+                if options.verify_fat_mirror {
+                    debug!("Checking FAT copies match");
+                    fat.verify_fats(&self.block_device)?;
                 }
-                VolumeIdx(3) => {
-                    &block[PARTITION4_START..(PARTITION4_START + PARTITION_INFO_LENGTH)]
-                }
-                _ => {
-                    return Err(Error::NoSuchVolume);
-                }
-            };
-            // Only 0x80 and 0x00 are valid (bootable, and non-bootable)
-            if (partition[PARTITION_INFO_STATUS_INDEX] & 0x7F) != 0x00 {
-                return Err(Error::FormatError("Invalid partition status"));
+                let id = RawVolume(data.id_generator.generate());
+                let info = VolumeInfo {
+                    raw_volume: id,
+                    idx: volume_idx,
+                    volume_type: volume,
+                    read_only: options.read_only,
+                    code_page: options.code_page,
+                    cache_geometry: true,
+                };
+                // We already checked for space
+                data.open_volumes.push(info).unwrap();
+                return Ok(id);
             }
-            let lba_start = LittleEndian::read_u32(
-                &partition[PARTITION_INFO_LBA_START_INDEX..(PARTITION_INFO_LBA_START_INDEX + 4)],
-            );
-            let num_blocks = LittleEndian::read_u32(
-                &partition[PARTITION_INFO_NUM_BLOCKS_INDEX..(PARTITION_INFO_NUM_BLOCKS_INDEX + 4)],
-            );
-            (
-                partition[PARTITION_INFO_TYPE_INDEX],
-                BlockIdx(lba_start),
-                BlockCount(num_blocks),
-            )
-        };
+        }
+
+        let (part_type, lba_start, num_blocks) = self.find_partition(volume_idx)?;
         match part_type {
             PARTITION_ID_FAT32_CHS_LBA
             | PARTITION_ID_FAT32_LBA
             | PARTITION_ID_FAT16_LBA
             | PARTITION_ID_FAT16 => {
-                let volume = fat::parse_volume(&self.block_device, lba_start, num_blocks)?;
+                let mut volume = fat::parse_volume(&self.block_device, lba_start, num_blocks)?;
+                match &mut volume {
+                    VolumeType::Fat(fat) => {
+                        let count_free_clusters = match fat.get_fat_type() {
+                            fat::FatType::Fat16 => options.count_fat16_free_clusters,
+                            fat::FatType::Fat32 => !options.trust_info_sector,
+                        };
+                        if count_free_clusters {
+                            debug!("Counting free clusters");
+                            fat.free_clusters_count =
+                                Some(fat.count_free_clusters(&self.block_device)?);
+                        }
+                        if !options.trust_info_sector {
+                            fat.next_free_cluster = None;
+                        }
+                        fat.max_dir_scan_blocks = options.max_dir_scan_blocks;
+                        if options.verify_fat_mirror {
+                            debug!("Checking FAT copies match");
+                            fat.verify_fats(&self.block_device)?;
+                        }
+                    }
+                }
                 let id = RawVolume(data.id_generator.generate());
                 let info = VolumeInfo {
                     raw_volume: id,
                     idx: volume_idx,
                     volume_type: volume,
+                    read_only: options.read_only,
+                    code_page: options.code_page,
+                    cache_geometry: options.cache_geometry,
                 };
                 // We already checked for space
                 data.open_volumes.push(info).unwrap();
@@ -213,6 +508,76 @@ where
         }
     }
 
+    /// Find the type, first block and size of a partition.
+    ///
+    /// Indices 0 to 3 are the primary partitions in the Master Boot Record.
+    /// Higher indices are the logical partitions inside the first extended
+    /// partition, in the order they appear in its chain of Extended Boot
+    /// Records, so `VolumeIdx(4)` is the first logical partition.
+    fn find_partition(
+        &self,
+        volume_idx: VolumeIdx,
+    ) -> Result<(u8, BlockIdx, BlockCount), Error<D::Error>> {
+        const PRIMARY_PARTITIONS: usize = 4;
+        // In case the chain of Extended Boot Records loops
+        const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+        let mut blocks = [Block::new()];
+        trace!("Reading partition table");
+        self.block_device
+            .read(&mut blocks, BlockIdx(0))
+            .map_err(Error::DeviceError)?;
+        // We only support Master Boot Record (MBR) partitioned cards, not
+        // GUID Partition Table (GPT)
+        check_boot_signature(&blocks[0], "Invalid MBR signature")?;
+        if volume_idx.0 < PRIMARY_PARTITIONS {
+            let (part_type, lba_start, num_blocks) = partition_entry(&blocks[0], volume_idx.0)?;
+            return Ok((part_type, BlockIdx(lba_start), BlockCount(num_blocks)));
+        }
+
+        let extended_start = (0..PRIMARY_PARTITIONS)
+            .find_map(|idx| match partition_entry::<D::Error>(&blocks[0], idx) {
+                Ok((PARTITION_ID_EXTENDED | PARTITION_ID_EXTENDED_LBA, lba_start, _)) => {
+                    Some(BlockIdx(lba_start))
+                }
+                _ => None,
+            })
+            .ok_or(Error::NoSuchVolume)?;
+
+        // Each EBR describes one logical partition (relative to the EBR) and
+        // links to the next EBR (relative to the start of the extended
+        // partition).
+        let mut ebr_idx = extended_start;
+        let mut logical_idx = volume_idx.0 - PRIMARY_PARTITIONS;
+        for _ in 0..MAX_LOGICAL_PARTITIONS {
+            trace!("Reading extended boot record");
+            self.block_device
+                .read(&mut blocks, ebr_idx)
+                .map_err(Error::DeviceError)?;
+            check_boot_signature(&blocks[0], "Invalid EBR signature")?;
+            let (part_type, lba_start, num_blocks) = partition_entry(&blocks[0], 0)?;
+            if part_type == 0 {
+                return Err(Error::NoSuchVolume);
+            }
+            if logical_idx == 0 {
+                let lba_start = ebr_idx
+                    .checked_add(BlockCount(lba_start))
+                    .ok_or(Error::FormatError("Logical partition beyond end of disk"))?;
+                return Ok((part_type, lba_start, BlockCount(num_blocks)));
+            }
+            logical_idx -= 1;
+            match partition_entry(&blocks[0], 1)? {
+                (PARTITION_ID_EXTENDED | PARTITION_ID_EXTENDED_LBA, next_start, _) => {
+                    ebr_idx = extended_start.checked_add(BlockCount(next_start)).ok_or(
+                        Error::FormatError("Extended boot record beyond end of disk"),
+                    )?;
+                }
+                _ => return Err(Error::NoSuchVolume),
+            }
+        }
+        Err(Error::FormatError("Too many logical partitions"))
+    }
+
     /// Open the volume's root directory.
     ///
     /// You can then read the directory entries with `iterate_dir`, or you can
@@ -244,6 +609,9 @@ where
     /// You can then read the directory entries with `iterate_dir` and `open_file_in_dir`.
     ///
     /// Passing "." as the name results in opening the `parent_dir` a second time.
+    /// Passing ".." opens the parent of `parent_dir`, as
+    /// [`VolumeManager::open_parent_dir`] does - so the root directory's `..`
+    /// is the root directory itself.
     pub fn open_dir<N>(
         &self,
         parent_dir: RawDirectory,
@@ -261,34 +629,58 @@ where
         // Find dir by ID
         let parent_dir_idx = data.get_dir_by_id(parent_dir)?;
         let volume_idx = data.get_volume_by_id(data.open_dirs[parent_dir_idx].raw_volume)?;
-        let short_file_name = name.to_short_filename().map_err(Error::FilenameError)?;
-
-        // Open the directory
+        let code_page = data.open_volumes[volume_idx].code_page;
+
+        // Names too long for 8.3 might be the long file name of an entry
+        let dir_entry = if let Some(long_name) = name
+            .long_name()
+            .filter(|n| ShortFileName::is_too_long(n, code_page))
+        {
+            match &data.open_volumes[volume_idx].volume_type {
+                VolumeType::Fat(fat) => fat.find_lfn_directory_entry(
+                    &self.block_device,
+                    &data.open_dirs[parent_dir_idx],
+                    long_name,
+                )?,
+            }
+        } else {
+            let short_file_name = name
+                .to_short_filename_with_code_page(code_page)
+                .map_err(Error::FilenameError)?;
+
+            // Open the directory
+
+            // Should we short-cut? (root dir doesn't have ".")
+            if short_file_name == ShortFileName::this_dir() {
+                let directory_id = RawDirectory(data.id_generator.generate());
+                let dir_info = DirectoryInfo {
+                    raw_directory: directory_id,
+                    raw_volume: data.open_volumes[volume_idx].raw_volume,
+                    cluster: data.open_dirs[parent_dir_idx].cluster,
+                };
 
-        // Should we short-cut? (root dir doesn't have ".")
-        if short_file_name == ShortFileName::this_dir() {
-            let directory_id = RawDirectory(data.id_generator.generate());
-            let dir_info = DirectoryInfo {
-                raw_directory: directory_id,
-                raw_volume: data.open_volumes[volume_idx].raw_volume,
-                cluster: data.open_dirs[parent_dir_idx].cluster,
-            };
+                data.open_dirs
+                    .push(dir_info)
+                    .map_err(|_| Error::TooManyOpenDirs)?;
 
-            data.open_dirs
-                .push(dir_info)
-                .map_err(|_| Error::TooManyOpenDirs)?;
+                return Ok(directory_id);
+            }
 
-            return Ok(directory_id);
-        }
+            // The root directory doesn't have ".." either
+            if short_file_name == ShortFileName::parent_dir() {
+                drop(data);
+                return self.open_parent_dir(parent_dir);
+            }
 
-        // ok we'll actually look for the directory then
+            // ok we'll actually look for the directory then
 
-        let dir_entry = match &data.open_volumes[volume_idx].volume_type {
-            VolumeType::Fat(fat) => fat.find_directory_entry(
-                &self.block_device,
-                &data.open_dirs[parent_dir_idx],
-                &short_file_name,
-            )?,
+            match &data.open_volumes[volume_idx].volume_type {
+                VolumeType::Fat(fat) => fat.find_directory_entry(
+                    &self.block_device,
+                    &data.open_dirs[parent_dir_idx],
+                    &short_file_name,
+                )?,
+            }
         };
 
         debug!("Found dir entry: {:?}", dir_entry);
@@ -315,6 +707,101 @@ where
         Ok(directory_id)
     }
 
+    /// Open a directory using a directory entry you have already found.
+    ///
+    /// The entry must have come from `parent_dir`, or from another directory
+    /// on the same volume, for example by using `iterate_dir` or
+    /// `find_directory_entry`. This saves scanning the parent directory again
+    /// to find the entry by name.
+    pub fn open_dir_from_entry(
+        &self,
+        parent_dir: RawDirectory,
+        entry: &DirEntry,
+    ) -> Result<RawDirectory, Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+
+        if data.open_dirs.is_full() {
+            return Err(Error::TooManyOpenDirs);
+        }
+
+        if !entry.attributes.is_directory() {
+            return Err(Error::OpenedFileAsDir);
+        }
+
+        let parent_dir_idx = data.get_dir_by_id(parent_dir)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[parent_dir_idx].raw_volume)?;
+
+        // Remember this open directory.
+        let directory_id = RawDirectory(data.id_generator.generate());
+        let dir_info = DirectoryInfo {
+            raw_directory: directory_id,
+            raw_volume: data.open_volumes[volume_idx].raw_volume,
+            cluster: entry.cluster,
+        };
+
+        data.open_dirs
+            .push(dir_info)
+            .map_err(|_| Error::TooManyOpenDirs)?;
+
+        debug!("Opened {:?} from entry, got {:?}", entry.name, directory_id);
+
+        Ok(directory_id)
+    }
+
+    /// Open the parent of a directory.
+    ///
+    /// This reads the `..` entry of the given directory. The parent of a
+    /// top-level directory is the root directory, and the parent of the root
+    /// directory is the root directory itself.
+    ///
+    /// The given directory remains open, and you must close both handles.
+    pub fn open_parent_dir(
+        &self,
+        directory: RawDirectory,
+    ) -> Result<RawDirectory, Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+
+        if data.open_dirs.is_full() {
+            return Err(Error::TooManyOpenDirs);
+        }
+
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+
+        let parent_cluster = if data.open_dirs[directory_idx].cluster == ClusterId::ROOT_DIR {
+            // The root directory has no ".." entry - it is its own parent
+            ClusterId::ROOT_DIR
+        } else {
+            let dir_entry = match &data.open_volumes[volume_idx].volume_type {
+                VolumeType::Fat(fat) => fat.find_directory_entry(
+                    &self.block_device,
+                    &data.open_dirs[directory_idx],
+                    &ShortFileName::parent_dir(),
+                )?,
+            };
+            debug!("Found parent entry: {:?}", dir_entry);
+            if !dir_entry.attributes.is_directory() {
+                return Err(Error::FormatError("'..' entry is not a directory"));
+            }
+            // A cluster of `0` (meaning 'root directory') has already been
+            // mapped to `ClusterId::ROOT_DIR` when the entry was parsed.
+            dir_entry.cluster
+        };
+
+        let directory_id = RawDirectory(data.id_generator.generate());
+        let dir_info = DirectoryInfo {
+            raw_directory: directory_id,
+            raw_volume: data.open_volumes[volume_idx].raw_volume,
+            cluster: parent_cluster,
+        };
+
+        data.open_dirs
+            .push(dir_info)
+            .map_err(|_| Error::TooManyOpenDirs)?;
+
+        Ok(directory_id)
+    }
+
     /// Close a directory. You cannot perform operations on an open directory
     /// and so must close it if you want to do something with it.
     pub fn close_dir(&self, directory: RawDirectory) -> Result<(), Error<D::Error>> {
@@ -350,8 +837,29 @@ where
 
         let volume_idx = data.get_volume_by_id(volume)?;
 
-        data.open_volumes.swap_remove(volume_idx);
+        self.block_device.flush().map_err(Error::DeviceError)?;
+
+        let info = data.open_volumes.swap_remove(volume_idx);
+        if info.cache_geometry {
+            debug!("Caching volume {:?}", info.idx);
+            // Make room by forgetting the oldest one
+            if data.cached_volumes.is_full() {
+                data.cached_volumes.remove(0);
+            }
+            // We just made room
+            let _ = data.cached_volumes.push((info.idx, info.volume_type));
+        }
+
+        Ok(())
+    }
 
+    /// Forget everything cached about closed volumes.
+    ///
+    /// See [`VolumeOpts::cache_geometry`]. Volumes which are open are not
+    /// affected.
+    pub fn forget_cached_volumes(&self) -> Result<(), Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        data.cached_volumes.clear();
         Ok(())
     }
 
@@ -368,14 +876,117 @@ where
 
         let directory_idx = data.get_dir_by_id(directory)?;
         let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        let dir_info = &data.open_dirs[directory_idx];
+        let code_page = data.open_volumes[volume_idx].code_page;
         match &data.open_volumes[volume_idx].volume_type {
             VolumeType::Fat(fat) => {
-                let sfn = name.to_short_filename().map_err(Error::FilenameError)?;
-                fat.find_directory_entry(&self.block_device, &data.open_dirs[directory_idx], &sfn)
+                // Names too long for 8.3 might be the long file name of an entry
+                if let Some(long_name) = name
+                    .long_name()
+                    .filter(|n| ShortFileName::is_too_long(n, code_page))
+                {
+                    return fat.find_lfn_directory_entry(&self.block_device, dir_info, long_name);
+                }
+                let sfn = name
+                    .to_short_filename_with_code_page(code_page)
+                    .map_err(Error::FilenameError)?;
+                fat.find_directory_entry(&self.block_device, dir_info, &sfn)
+            }
+        }
+    }
+
+    /// Get the directory entry for an open directory itself.
+    ///
+    /// For a sub-directory this is its `.` entry, which lives in its first
+    /// cluster and points back at it, so it has the directory's attributes
+    /// and timestamps (and `.` for a name). The root directory doesn't have
+    /// an entry, so you get a made-up one, with a time of midnight on
+    /// 1980-01-01 and `ClusterId::ROOT_DIR` as its cluster.
+    pub fn dir_metadata(&self, directory: RawDirectory) -> Result<DirEntry, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        let dir_info = &data.open_dirs[directory_idx];
+        if dir_info.cluster == ClusterId::ROOT_DIR {
+            let epoch = Timestamp::from_fat(0, 0);
+            return Ok(DirEntry {
+                name: ShortFileName::this_dir(),
+                mtime: epoch,
+                ctime: epoch,
+                atime: epoch,
+                attributes: Attributes::new().directory(),
+                cluster: ClusterId::ROOT_DIR,
+                size: 0,
+                entry_block: BlockIdx(0),
+                entry_offset: 0,
+            });
+        }
+        let VolumeType::Fat(fat) = &data.open_volumes[volume_idx].volume_type;
+        match fat.find_directory_entry(&self.block_device, dir_info, &ShortFileName::this_dir()) {
+            Err(Error::NotFound) => Err(Error::FormatError("Directory has no '.' entry")),
+            result => result,
+        }
+    }
+
+    /// Pick a short file name for a new entry in a directory.
+    ///
+    /// If `name` is free it is returned as-is. Otherwise it gets the lowest
+    /// free `~N` numeric tail, so a second `REPORT.TXT` becomes
+    /// `REPORT~1.TXT`.
+    pub fn generate_unique_short_name<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+    ) -> Result<ShortFileName, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        let dir_info = &data.open_dirs[directory_idx];
+        let sfn = name
+            .to_short_filename_with_code_page(data.open_volumes[volume_idx].code_page)
+            .map_err(Error::FilenameError)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.generate_unique_short_name(&self.block_device, dir_info, &sfn)
             }
         }
     }
 
+    /// Does a directory contain an entry with the given name?
+    ///
+    /// Returns `Ok(false)` if the entry isn't found, and passes on any other
+    /// error. This doesn't tell you whether the entry is a file or a
+    /// directory; use [`VolumeManager::entry_kind`] for that.
+    pub fn exists<N>(&self, directory: RawDirectory, name: N) -> Result<bool, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        Ok(self.entry_kind(directory, name)?.is_some())
+    }
+
+    /// Is the named entry in a directory a file or a directory?
+    ///
+    /// Returns `Ok(None)` if the entry isn't found, and passes on any other
+    /// error.
+    pub fn entry_kind<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+    ) -> Result<Option<EntryKind>, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        match self.find_directory_entry(directory, name) {
+            Ok(dir_entry) => Ok(Some(dir_entry.kind())),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Call a callback function for each directory entry in a directory.
     ///
     /// <div class="warning">
@@ -400,6 +1011,261 @@ where
         }
     }
 
+    /// Copy directory entries into `out`, in the order `iterate_dir` would
+    /// give them, until `out` is full or we reach the end of the directory.
+    ///
+    /// Returns how many entries were copied; any slots after those are left
+    /// alone. This avoids the restrictions on calling the VolumeManager from
+    /// inside an `iterate_dir` callback, without needing an allocator. We
+    /// stop reading the directory as soon as `out` is full.
+    pub fn collect_entries(
+        &self,
+        directory: RawDirectory,
+        out: &mut [Option<DirEntry>],
+    ) -> Result<usize, Error<D::Error>> {
+        let data = self.data.borrow();
+
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        if out.is_empty() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.iterate_dir_until(
+                    &self.block_device,
+                    &data.open_dirs[directory_idx],
+                    |entry| {
+                        out[count] = Some(entry.clone());
+                        count += 1;
+                        if count == out.len() {
+                            ControlFlow::Break(())
+                        } else {
+                            ControlFlow::Continue(())
+                        }
+                    },
+                )?;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Call a callback function for each directory entry whose name matches
+    /// a wildcard pattern, such as `*.TXT`.
+    ///
+    /// See [`ShortFileNamePattern`] for the pattern syntax. Volume labels are
+    /// never passed to the callback.
+    ///
+    /// <div class="warning">
+    ///
+    /// As with `iterate_dir`, do not call any methods on the VolumeManager
+    /// from inside the callback.
+    ///
+    /// </div>
+    pub fn iterate_dir_glob<F>(
+        &self,
+        directory: RawDirectory,
+        pattern: &str,
+        mut func: F,
+    ) -> Result<(), Error<D::Error>>
+    where
+        F: FnMut(&DirEntry),
+    {
+        let pattern = ShortFileNamePattern::create_from_str_with_code_page(
+            pattern,
+            self.dir_code_page(directory)?,
+        )
+        .map_err(Error::FilenameError)?;
+        self.iterate_dir(directory, |entry| {
+            if !entry.attributes.is_volume() && pattern.matches(&entry.name) {
+                func(entry)
+            }
+        })
+    }
+
+    /// Start a search for entries in a directory whose name matches a
+    /// wildcard pattern, such as `*.DAT`.
+    ///
+    /// Gives you the first matching entry (if there is one), and a
+    /// [`DirScan`] to pass to [`VolumeManager::find_next`] for the rest. Unlike
+    /// with `iterate_dir_glob`, you can use the VolumeManager between calls.
+    ///
+    /// See [`ShortFileNamePattern`] for the pattern syntax. Volume labels
+    /// never match. The directory must stay open until the search is done.
+    pub fn find_first(
+        &self,
+        directory: RawDirectory,
+        pattern: &str,
+    ) -> Result<(DirScan, Option<DirEntry>), Error<D::Error>> {
+        let pattern = ShortFileNamePattern::create_from_str_with_code_page(
+            pattern,
+            self.dir_code_page(directory)?,
+        )
+        .map_err(Error::FilenameError)?;
+        let cluster = {
+            let data = self.data.borrow();
+            data.open_dirs[data.get_dir_by_id(directory)?].cluster
+        };
+        let mut scan = DirScan {
+            directory,
+            pattern,
+            next: Some(DirPosition::start(cluster)),
+        };
+        let entry = self.find_next(&mut scan)?;
+        Ok((scan, entry))
+    }
+
+    /// Get the next entry matching a search started with
+    /// [`VolumeManager::find_first`], or `None` if there are no more.
+    ///
+    /// Each call carries on reading the directory from where the last one
+    /// stopped. If entries are added to or removed from the directory during
+    /// the search, entries may be skipped or given twice.
+    pub fn find_next(&self, scan: &mut DirScan) -> Result<Option<DirEntry>, Error<D::Error>> {
+        let Some(from) = scan.next else {
+            return Ok(None);
+        };
+        let data = self.data.borrow();
+
+        let directory_idx = data.get_dir_by_id(scan.directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        let found = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.iterate_dir_from(&self.block_device, from, |entry| {
+                if !entry.attributes.is_volume() && scan.pattern.matches(&entry.name) {
+                    ControlFlow::Break(entry.clone())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            })?,
+        };
+        match found {
+            Some((entry, next)) => {
+                scan.next = Some(next);
+                Ok(Some(entry))
+            }
+            None => {
+                scan.next = None;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Walk a directory and its sub-directories, depth first.
+    ///
+    /// The callback is given the path to the directory holding each entry
+    /// (relative to `directory`) and the entry itself. The `.` and `..`
+    /// entries are skipped. The callback's return value decides whether we
+    /// descend into a sub-directory, skip it, or stop walking altogether.
+    ///
+    /// We descend at most `max_depth` levels below `directory`, so a
+    /// `max_depth` of zero just visits the entries in `directory`. Each level
+    /// holds one directory handle open, which is closed again when we come
+    /// back up, so at most `max_depth` extra handles are open at once.
+    /// Opening a handle beyond the `MAX_DIRS` limit is an error.
+    ///
+    /// Any error stops the walk and is returned, after closing any handles
+    /// this function opened. The directory is scanned again from the start
+    /// after visiting each sub-directory, trading speed for memory.
+    ///
+    /// <div class="warning">
+    ///
+    /// As with `iterate_dir`, do not call any methods on the VolumeManager
+    /// from inside the callback.
+    ///
+    /// </div>
+    pub fn walk_dir<F>(
+        &self,
+        directory: RawDirectory,
+        max_depth: usize,
+        mut func: F,
+    ) -> Result<(), Error<D::Error>>
+    where
+        F: FnMut(&[ShortFileName], &DirEntry) -> WalkControl,
+    {
+        // Each level holds a directory handle and the index of the next entry
+        // to visit in that directory.
+        let mut stack: Vec<(RawDirectory, usize), MAX_DIRS> = Vec::new();
+        let mut path: Vec<ShortFileName, MAX_DIRS> = Vec::new();
+        stack
+            .push((directory, 0))
+            .map_err(|_| Error::TooManyOpenDirs)?;
+
+        let result = loop {
+            let Some(&(dir, resume_idx)) = stack.last() else {
+                break Ok(());
+            };
+            let can_descend = stack.len() <= max_depth;
+            let mut entry_idx = 0;
+            let mut stop = false;
+            let mut descend_into: Option<(usize, DirEntry)> = None;
+            let iterate_result = self.iterate_dir(dir, |entry| {
+                let this_idx = entry_idx;
+                entry_idx += 1;
+                if stop || descend_into.is_some() || this_idx < resume_idx {
+                    return;
+                }
+                if entry.name == ShortFileName::this_dir()
+                    || entry.name == ShortFileName::parent_dir()
+                {
+                    return;
+                }
+                match func(&path, entry) {
+                    WalkControl::Continue => {
+                        if can_descend && entry.attributes.is_directory() {
+                            descend_into = Some((this_idx, entry.clone()));
+                        }
+                    }
+                    WalkControl::SkipDir => {}
+                    WalkControl::Stop => stop = true,
+                }
+            });
+            if let Err(e) = iterate_result {
+                break Err(e);
+            }
+            if stop {
+                break Ok(());
+            }
+            match descend_into {
+                Some((idx, entry)) => {
+                    if let Some(last) = stack.last_mut() {
+                        last.1 = idx + 1;
+                    }
+                    let sub_dir = match self.open_dir_from_entry(dir, &entry) {
+                        Ok(d) => d,
+                        Err(e) => break Err(e),
+                    };
+                    if let Err((sub_dir, _)) = stack.push((sub_dir, 0)) {
+                        let _ = self.close_dir(sub_dir);
+                        break Err(Error::TooManyOpenDirs);
+                    }
+                    // The new handle is on the stack now, so the clean-up
+                    // below closes it if this fails
+                    if path.push(entry.name).is_err() {
+                        break Err(Error::TooManyOpenDirs);
+                    }
+                }
+                None => {
+                    // Finished this directory, so go back up
+                    stack.pop();
+                    path.pop();
+                    if !stack.is_empty() {
+                        if let Err(e) = self.close_dir(dir) {
+                            break Err(e);
+                        }
+                    }
+                }
+            }
+        };
+
+        // Close any handles we opened, leaving the caller's one alone
+        for (dir, _) in stack.iter().skip(1) {
+            let _ = self.close_dir(*dir);
+        }
+
+        result
+    }
+
     /// Open a file with the given full path. A file can only be opened once.
     pub fn open_file_in_dir<N>(
         &self,
@@ -410,6 +1276,46 @@ where
     where
         N: ToShortFileName,
     {
+        self.open_file_in_dir_with_opts(directory, name, mode, FileOpts::default())
+    }
+
+    /// Open a file with the given full path, giving it the given attributes
+    /// if opening it creates it. A file can only be opened once.
+    ///
+    /// See [`FileOpts::attributes`] for details.
+    pub fn open_file_in_dir_with_attributes<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+        mode: Mode,
+        attributes: Attributes,
+    ) -> Result<RawFile, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        let options = FileOpts {
+            attributes,
+            ..Default::default()
+        };
+        self.open_file_in_dir_with_opts(directory, name, mode, options)
+    }
+
+    /// Open a file with the given full path, using the given options. A file
+    /// can only be opened once.
+    pub fn open_file_in_dir_with_opts<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+        mode: Mode,
+        options: FileOpts,
+    ) -> Result<RawFile, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        if options.attributes.is_directory() || options.attributes.is_volume() {
+            return Err(Error::Unsupported);
+        }
+
         let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
 
         // This check is load-bearing - we do an unchecked push later.
@@ -421,11 +1327,32 @@ where
         let volume_id = data.open_dirs[directory_idx].raw_volume;
         let volume_idx = data.get_volume_by_id(volume_id)?;
         let volume_info = &data.open_volumes[volume_idx];
-        let sfn = name.to_short_filename().map_err(Error::FilenameError)?;
+        let dir_info = &data.open_dirs[directory_idx];
+        let dir_cluster = dir_info.cluster;
+
+        if volume_info.read_only && mode != Mode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
 
-        let dir_entry = match &volume_info.volume_type {
+        // Names too long for 8.3 might be the long file name of an entry, but
+        // we can't create a file with one.
+        let code_page = volume_info.code_page;
+        let (sfn, dir_entry) = match &volume_info.volume_type {
             VolumeType::Fat(fat) => {
-                fat.find_directory_entry(&self.block_device, &data.open_dirs[directory_idx], &sfn)
+                if let Some(long_name) = name
+                    .long_name()
+                    .filter(|n| ShortFileName::is_too_long(n, code_page))
+                {
+                    let dir_entry =
+                        fat.find_lfn_directory_entry(&self.block_device, dir_info, long_name);
+                    (None, dir_entry)
+                } else {
+                    let sfn = name
+                        .to_short_filename_with_code_page(code_page)
+                        .map_err(Error::FilenameError)?;
+                    let dir_entry = fat.find_directory_entry(&self.block_device, dir_info, &sfn);
+                    (Some(sfn), dir_entry)
+                }
             }
         };
 
@@ -449,9 +1376,10 @@ where
             }
         };
 
-        // Check if it's open already
+        // Check if it's open already. This is the only check - whatever mode
+        // we were given, an existing file can only be opened once.
         if let Some(dir_entry) = &dir_entry {
-            if data.file_is_open(volume_info.raw_volume, dir_entry) {
+            if data.file_is_open(volume_id, dir_entry) {
                 return Err(Error::FileAlreadyOpen);
             }
         }
@@ -463,8 +1391,9 @@ where
                 if dir_entry.is_some() {
                     return Err(Error::FileAlreadyExists);
                 }
+                let sfn = sfn.ok_or(Error::FilenameError(FilenameError::TooLongForShortName))?;
                 let cluster = data.open_dirs[directory_idx].cluster;
-                let att = Attributes::create_from_fat(0);
+                let att = options.attributes;
                 let volume_idx = data.get_volume_by_id(volume_id)?;
                 let entry = match &mut data.open_volumes[volume_idx].volume_type {
                     VolumeType::Fat(fat) => fat.write_new_directory_entry(
@@ -486,6 +1415,10 @@ where
                     mode,
                     entry,
                     dirty: false,
+                    sync_entry_on_write: options.sync_entry_on_write,
+                    dir_cluster,
+                    zeros_from: None,
+                    checkpoints: ChainCheckpoints::default(),
                 };
 
                 // Remember this open file - can't be full as we checked already
@@ -507,12 +1440,6 @@ where
                     return Err(Error::OpenedDirAsFile);
                 }
 
-                // Check it's not already open
-                if data.file_is_open(volume_id, &dir_entry) {
-                    return Err(Error::FileAlreadyOpen);
-                }
-
-                let mode = solve_mode_variant(mode, true);
                 let raw_file = RawFile(data.id_generator.generate());
 
                 let file = match mode {
@@ -524,6 +1451,10 @@ where
                         mode,
                         entry: dir_entry,
                         dirty: false,
+                        sync_entry_on_write: options.sync_entry_on_write,
+                        dir_cluster,
+                        zeros_from: None,
+                        checkpoints: ChainCheckpoints::default(),
                     },
                     Mode::ReadWriteAppend => {
                         let mut file = FileInfo {
@@ -534,6 +1465,10 @@ where
                             mode,
                             entry: dir_entry,
                             dirty: false,
+                            sync_entry_on_write: options.sync_entry_on_write,
+                            dir_cluster,
+                            zeros_from: None,
+                            checkpoints: ChainCheckpoints::default(),
                         };
                         // seek_from_end with 0 can't fail
                         file.seek_from_end(0).ok();
@@ -548,6 +1483,10 @@ where
                             mode,
                             entry: dir_entry,
                             dirty: false,
+                            sync_entry_on_write: options.sync_entry_on_write,
+                            dir_cluster,
+                            zeros_from: None,
+                            checkpoints: ChainCheckpoints::default(),
                         };
                         match &mut data.open_volumes[volume_idx].volume_type {
                             VolumeType::Fat(fat) => {
@@ -557,7 +1496,9 @@ where
                         file.update_length(0);
                         match &data.open_volumes[volume_idx].volume_type {
                             VolumeType::Fat(fat) => {
-                                file.entry.mtime = self.time_source.get_timestamp();
+                                let now = self.time_source.get_timestamp();
+                                file.entry.mtime = now;
+                                file.entry.atime = now.date_only();
                                 fat.write_entry_to_disk(&self.block_device, &file.entry)?;
                             }
                         };
@@ -577,6 +1518,87 @@ where
         }
     }
 
+    /// Create several empty files in a directory at once.
+    ///
+    /// This is much quicker than opening (and closing) each new file with
+    /// [`VolumeManager::open_file_in_dir`]. The directory is scanned once to
+    /// check the names, and once more to find free entries, and new entries
+    /// that share a block are written with a single block write.
+    ///
+    /// None of the files can exist already, and no name can be given twice -
+    /// either gives `Error::FileAlreadyExists` before anything is written. If
+    /// the directory can't grow to hold them all, you get
+    /// `Error::NotEnoughSpace` and the files that did fit are left behind.
+    pub fn create_files<N>(
+        &self,
+        directory: RawDirectory,
+        files: &[(N, Attributes)],
+    ) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName + Clone,
+    {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+
+        if data.open_volumes[volume_idx].read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        // Check every name before we write anything
+        let code_page = data.open_volumes[volume_idx].code_page;
+        for (idx, (name, attributes)) in files.iter().enumerate() {
+            if attributes.is_directory() || attributes.is_volume() {
+                return Err(Error::Unsupported);
+            }
+            let sfn = name
+                .clone()
+                .to_short_filename_with_code_page(code_page)
+                .map_err(Error::FilenameError)?;
+            if files[..idx]
+                .iter()
+                .any(|(other, _)| matches!(other.clone().to_short_filename_with_code_page(code_page), Ok(o) if o == sfn))
+            {
+                return Err(Error::FileAlreadyExists);
+            }
+        }
+        let mut exists = false;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.iterate_dir(
+                &self.block_device,
+                &data.open_dirs[directory_idx],
+                |entry| {
+                    exists |= files.iter().any(|(name, _)| {
+                        matches!(name.clone().to_short_filename_with_code_page(code_page), Ok(n) if n == entry.name)
+                    });
+                },
+            )?,
+        }
+        if exists {
+            return Err(Error::FileAlreadyExists);
+        }
+
+        let cluster = data.open_dirs[directory_idx].cluster;
+        match &mut data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.write_new_directory_entries(
+                &self.block_device,
+                &self.time_source,
+                cluster,
+                files.len(),
+                |idx| {
+                    let (name, attributes) = &files[idx];
+                    // Safe to unwrap, since we converted every name above
+                    (
+                        name.clone()
+                            .to_short_filename_with_code_page(code_page)
+                            .unwrap(),
+                        *attributes,
+                    )
+                },
+            ),
+        }
+    }
+
     /// Delete a closed file with the given filename, if it exists.
     pub fn delete_file_in_dir<N>(
         &self,
@@ -591,7 +1613,12 @@ where
         let dir_idx = data.get_dir_by_id(directory)?;
         let dir_info = &data.open_dirs[dir_idx];
         let volume_idx = data.get_volume_by_id(dir_info.raw_volume)?;
-        let sfn = name.to_short_filename().map_err(Error::FilenameError)?;
+        if data.open_volumes[volume_idx].read_only {
+            return Err(Error::ReadOnly);
+        }
+        let sfn = name
+            .to_short_filename_with_code_page(data.open_volumes[volume_idx].code_page)
+            .map_err(Error::FilenameError)?;
 
         let dir_entry = match &data.open_volumes[volume_idx].volume_type {
             VolumeType::Fat(fat) => fat.find_directory_entry(&self.block_device, dir_info, &sfn),
@@ -615,6 +1642,29 @@ where
         Ok(())
     }
 
+    /// Release any clusters at the end of a directory which only hold deleted
+    /// or unused entries.
+    ///
+    /// Directories grow when they fill up, but don't shrink when files are
+    /// deleted. This gives the space back. The first cluster of the directory
+    /// is always kept, as are any clusters between entries still in use.
+    /// Returns the number of clusters released.
+    pub fn compact_dir(&self, directory: RawDirectory) -> Result<u32, Error<D::Error>> {
+        use core::ops::DerefMut;
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let data = data.deref_mut();
+
+        let dir_idx = data.get_dir_by_id(directory)?;
+        let dir_info = &data.open_dirs[dir_idx];
+        let volume_idx = data.get_volume_by_id(dir_info.raw_volume)?;
+        if data.open_volumes[volume_idx].read_only {
+            return Err(Error::ReadOnly);
+        }
+        match &mut data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.compact_dir(&self.block_device, dir_info),
+        }
+    }
+
     /// Get the volume label
     ///
     /// Will look in the BPB for a volume label, and if nothing is found, will
@@ -659,7 +1709,137 @@ where
         Ok(maybe_volume_name)
     }
 
+    /// Read a whole file, given its path from the root of the volume.
+    ///
+    /// The path uses `/` to separate directories, such as `/CONFIG/NET.TXT`,
+    /// and the leading `/` is optional. The file is opened, read into
+    /// `buffer`, and closed again. If the file is longer than `buffer`, only
+    /// as much as fits is read.
+    ///
+    /// Returns how many bytes were read, or an error.
+    pub fn read_file_to(
+        &self,
+        volume: RawVolume,
+        path: &str,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error<D::Error>> {
+        let file = self.open_file_by_path(volume, path, Mode::ReadOnly)?;
+        let mut read = 0;
+        let result = loop {
+            match self.read(file, &mut buffer[read..]) {
+                Ok(0) => break Ok(read),
+                Ok(n) => read += n,
+                Err(e) => break Err(e),
+            }
+        };
+        let close_result = self.close_file(file);
+        let read = result?;
+        close_result?;
+        Ok(read)
+    }
+
+    /// Write a whole file, given its path from the root of the volume.
+    ///
+    /// The path is given as for [`VolumeManager::read_file_to`]. The file is
+    /// opened with the given `mode`, `buffer` is written to it, and it is
+    /// closed again. As with `write`, a file can't grow beyond
+    /// [`MAX_FILE_SIZE`](crate::filesystem::MAX_FILE_SIZE) bytes, and you get
+    /// `Error::FileTooLarge` if `buffer` would make it any bigger.
+    ///
+    /// Returns how many bytes were written (which is always `buffer.len()`),
+    /// or an error.
+    pub fn write_file_from(
+        &self,
+        volume: RawVolume,
+        path: &str,
+        buffer: &[u8],
+        mode: Mode,
+    ) -> Result<usize, Error<D::Error>> {
+        let file = self.open_file_by_path(volume, path, mode)?;
+        let result = self.file_offset(file).and_then(|start| {
+            self.write(file, buffer)?;
+            let end = self.file_offset(file)?;
+            usize::try_from(end - start).map_err(|_| Error::ConversionError)
+        });
+        let close_result = self.close_file(file);
+        let written = result?;
+        close_result?;
+        Ok(written)
+    }
+
+    /// Open a file for appending, creating it if it doesn't exist.
+    ///
+    /// This is the same as calling [`VolumeManager::open_file_in_dir`] with
+    /// [`Mode::ReadWriteCreateOrAppend`]. The handle stays open, so you can
+    /// keep writing to it, and you must flush and close it yourself. Use
+    /// [`VolumeManager::append_to_file`] to do it all in one go.
+    pub fn append_file_in_dir<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+    ) -> Result<RawFile, Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        self.open_file_in_dir(directory, name, Mode::ReadWriteCreateOrAppend)
+    }
+
+    /// Append some bytes to the end of a file, creating it if it doesn't
+    /// exist.
+    ///
+    /// The file is opened, written to, and closed (which flushes it) again.
+    /// As with `write`, a file can't grow beyond
+    /// [`MAX_FILE_SIZE`](crate::filesystem::MAX_FILE_SIZE) bytes.
+    pub fn append_to_file<N>(
+        &self,
+        directory: RawDirectory,
+        name: N,
+        buffer: &[u8],
+    ) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName,
+    {
+        let file = self.append_file_in_dir(directory, name)?;
+        let result = self.write(file, buffer);
+        let close_result = self.close_file(file);
+        result?;
+        close_result
+    }
+
+    /// Open a file, given its path from the root of the volume.
+    ///
+    /// Each directory along the way is opened and closed again in turn, so
+    /// this needs at most two free directory handles.
+    fn open_file_by_path(
+        &self,
+        volume: RawVolume,
+        path: &str,
+        mode: Mode,
+    ) -> Result<RawFile, Error<D::Error>> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let (dirs, name) = path.rsplit_once('/').unwrap_or(("", path));
+        if name.is_empty() {
+            return Err(Error::FilenameError(FilenameError::FilenameEmpty));
+        }
+        let mut directory = self.open_root_dir(volume)?;
+        for dir_name in dirs.split('/').filter(|s| !s.is_empty()) {
+            let child = self.open_dir(directory, dir_name);
+            self.close_dir(directory)?;
+            directory = child?;
+        }
+        let file = self.open_file_in_dir(directory, name, mode);
+        self.close_dir(directory)?;
+        file
+    }
+
     /// Read from an open file.
+    ///
+    /// Returns how many bytes were read, or an error.
+    ///
+    /// If the file's cluster chain runs into a free cluster before the end of
+    /// the file, the filesystem is corrupt. We stop at the end of the last
+    /// good cluster and return what we have read so far, and the next read
+    /// gives `Error::UnterminatedFatChain`.
     pub fn read(&self, file: RawFile, buffer: &mut [u8]) -> Result<usize, Error<D::Error>> {
         let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
 
@@ -672,37 +1852,178 @@ where
         let mut space = buffer.len();
         let mut read = 0;
         while space > 0 && !data.open_files[file_idx].eof() {
+            let current_offset = data.open_files[file_idx].current_offset;
+            let zeros_from = data.open_files[file_idx].zeros_from;
+            if zeros_from.is_some_and(|z| current_offset >= z) {
+                // Space from `file_extend` which hasn't been written yet
+                let to_copy = space.min(saturating_usize(data.open_files[file_idx].left()));
+                buffer[read..read + to_copy].fill(0);
+                read += to_copy;
+                space -= to_copy;
+                // This is at most what was left of the file, so it fits
+                let to_copy = u32::try_from(to_copy).map_err(|_| Error::ConversionError)?;
+                data.open_files[file_idx]
+                    .seek_from_start(current_offset + to_copy)
+                    .unwrap();
+                continue;
+            }
             let mut current_cluster = data.open_files[file_idx].current_cluster;
-            let (block_idx, block_offset, block_avail) = data.find_data_on_disk(
+            let mut checkpoints = core::mem::take(&mut data.open_files[file_idx].checkpoints);
+            let result = data.find_data_on_disk(
                 &self.block_device,
                 volume_idx,
                 &mut current_cluster,
                 data.open_files[file_idx].entry.cluster,
                 data.open_files[file_idx].current_offset,
-            )?;
+                &mut checkpoints,
+            );
+            data.open_files[file_idx].checkpoints = checkpoints;
+            let (block_idx, block_offset, block_avail) = match result {
+                Err(Error::UnterminatedFatChain) if read > 0 => {
+                    warn!(
+                        "File {:?} has a free cluster after cluster {:?}",
+                        file, current_cluster.1
+                    );
+                    break;
+                }
+                result => result?,
+            };
             data.open_files[file_idx].current_cluster = current_cluster;
+            let wanted = space
+                .min(saturating_usize(data.open_files[file_idx].left()))
+                .min(zeros_from.map_or(usize::MAX, |z| saturating_usize(z - current_offset)));
+            let whole_blocks = wanted / Block::LEN;
+            let to_copy = if block_offset == 0 && whole_blocks > 0 {
+                // Block aligned, so read as many whole blocks as we can
+                // straight into the buffer, up to the end of this cluster,
+                // or as many as the device likes to take at once.
+                let VolumeType::Fat(fat) = &data.open_volumes[volume_idx].volume_type;
+                let offset_in_cluster = current_offset - current_cluster.0;
+                let blocks_left_in_cluster =
+                    ((fat.bytes_per_cluster() - offset_in_cluster) / Block::LEN_U32) as usize;
+                let mut burst = whole_blocks.min(blocks_left_in_cluster);
+                let optimal = self.block_device.optimal_transfer_blocks().0;
+                if optimal > 1 {
+                    burst = burst.min(saturating_usize(optimal));
+                }
+                let to_copy = burst * Block::LEN;
+                trace!("Reading {} blocks of file ID {:?}", burst, file);
+                self.block_device
+                    .read(
+                        Block::slice_from_bytes_mut(&mut buffer[read..read + to_copy]),
+                        block_idx,
+                    )
+                    .map_err(Error::DeviceError)?;
+                to_copy
+            } else {
+                let mut blocks = [Block::new()];
+                trace!("Reading file ID {:?}", file);
+                self.block_device
+                    .read(&mut blocks, block_idx)
+                    .map_err(Error::DeviceError)?;
+                let block = &blocks[0];
+                let to_copy = block_avail.min(wanted);
+                assert!(to_copy != 0);
+                buffer[read..read + to_copy]
+                    .copy_from_slice(&block[block_offset..block_offset + to_copy]);
+                to_copy
+            };
+            read += to_copy;
+            space -= to_copy;
+            data.open_files[file_idx]
+                .seek_from_current(to_copy as i32)
+                .unwrap();
+        }
+        Ok(read)
+    }
+
+    /// Read from an open file, starting at the given offset.
+    ///
+    /// Unlike [`VolumeManager::read`], this does not use or change the file's
+    /// current position, so readers working on different parts of the same
+    /// file don't get in each other's way. Reading at or beyond the end of the
+    /// file reads nothing. Like [`VolumeManager::read`], this stops early if
+    /// the file's cluster chain is broken.
+    ///
+    /// Returns how many bytes were read, or an error.
+    pub fn read_at(
+        &self,
+        file: RawFile,
+        offset: u32,
+        buffer: &mut [u8],
+    ) -> Result<usize, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+
+        let file_idx = data.get_file_by_id(file)?;
+        let volume_idx = data.get_volume_by_id(data.open_files[file_idx].raw_volume)?;
+        let file_info = &data.open_files[file_idx];
+
+        // Start from the file's cached cluster, which find_data_on_disk will
+        // ignore if it is past the offset we want.
+        let mut current_cluster = file_info.current_cluster;
+        let mut checkpoints = file_info.checkpoints.clone();
+        let mut current_offset = offset;
+        let mut read = 0;
+        while read < buffer.len() && current_offset < file_info.entry.size {
+            if file_info.zeros_from.is_some_and(|z| current_offset >= z) {
+                // Space from `file_extend` which hasn't been written yet
+                let to_copy = (buffer.len() - read)
+                    .min(saturating_usize(file_info.entry.size - current_offset));
+                buffer[read..read + to_copy].fill(0);
+                read += to_copy;
+                break;
+            }
+            let (block_idx, block_offset, block_avail) = match data.find_data_on_disk(
+                &self.block_device,
+                volume_idx,
+                &mut current_cluster,
+                file_info.entry.cluster,
+                current_offset,
+                &mut checkpoints,
+            ) {
+                Err(Error::UnterminatedFatChain) if read > 0 => {
+                    warn!(
+                        "File {:?} has a free cluster after cluster {:?}",
+                        file, current_cluster.1
+                    );
+                    break;
+                }
+                result => result?,
+            };
             let mut blocks = [Block::new()];
-            trace!("Reading file ID {:?}", file);
+            trace!("Reading file ID {:?} at {}", file, current_offset);
             self.block_device
                 .read(&mut blocks, block_idx)
                 .map_err(Error::DeviceError)?;
             let block = &blocks[0];
             let to_copy = block_avail
-                .min(space)
-                .min(data.open_files[file_idx].left() as usize);
-            assert!(to_copy != 0);
+                .min(buffer.len() - read)
+                .min(saturating_usize(file_info.entry.size - current_offset))
+                .min(
+                    file_info
+                        .zeros_from
+                        .map_or(usize::MAX, |z| saturating_usize(z - current_offset)),
+                );
             buffer[read..read + to_copy]
                 .copy_from_slice(&block[block_offset..block_offset + to_copy]);
             read += to_copy;
-            space -= to_copy;
-            data.open_files[file_idx]
-                .seek_from_current(to_copy as i32)
-                .unwrap();
+            current_offset += to_copy as u32;
         }
+        // Keep any checkpoints we found on the way, for next time
+        drop(data);
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        data.open_files[file_idx].checkpoints = checkpoints;
         Ok(read)
     }
 
     /// Write to a open file.
+    ///
+    /// Whole blocks at a block-aligned offset are written straight from
+    /// `buffer`, up to a cluster at a time, with one multi-block write.
+    ///
+    /// A file can't grow beyond [`MAX_FILE_SIZE`] bytes. If `buffer` won't
+    /// fit before that, nothing is written and you get
+    /// `Error::FileTooLarge`.
     pub fn write(&self, file: RawFile, buffer: &[u8]) -> Result<(), Error<D::Error>> {
         #[cfg(feature = "defmt-log")]
         debug!("write(file={:?}, buffer={:x}", file, buffer);
@@ -710,6 +2031,65 @@ where
         #[cfg(feature = "log")]
         debug!("write(file={:?}, buffer={:x?}", file, buffer);
 
+        if !buffer.is_empty() {
+            // Any space from `file_extend` that we skip over must be zeroed
+            let offset = self.file_offset(file)?;
+            self.write_pending_zeros(file, Some(offset))?;
+        }
+        self.write_inner(file, buffer)?;
+
+        let sync_entry_on_write = {
+            let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+            let file_idx = data.get_file_by_id(file)?;
+            data.open_files[file_idx].sync_entry_on_write
+        };
+        if sync_entry_on_write && !buffer.is_empty() {
+            self.flush_file(file)?;
+        }
+        Ok(())
+    }
+
+    /// Append a record to the end of an open file, so that a torn write
+    /// can't leave half a record in it.
+    ///
+    /// The record is written as its length, as a little-endian `u32`,
+    /// followed by the bytes of `record`. The directory entry - and with it
+    /// the file's length - is only updated once the data has been written
+    /// and the block device flushed. So if the write fails, or the power goes
+    /// part way through, the file on disk ends after the last complete
+    /// record. If the write fails, the file's length and offset also go back
+    /// to where they were, so the next record overwrites what was left.
+    ///
+    /// This always leaves the file's offset at the end of the file.
+    pub fn write_record(&self, file: RawFile, record: &[u8]) -> Result<(), Error<D::Error>> {
+        let record_len = u32::try_from(record.len()).map_err(|_| Error::FileTooLarge)?;
+        self.write_pending_zeros(file, None)?;
+        let old_length = self.file_length(file)?;
+        self.file_seek_from_start(file, old_length)?;
+
+        let result = self
+            .write_inner(file, &record_len.to_le_bytes())
+            .and_then(|_| self.write_inner(file, record))
+            .and_then(|_| self.block_device.flush().map_err(Error::DeviceError));
+        if let Err(e) = result {
+            warn!("Record write to {:?} failed; dropping it", file);
+            {
+                let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+                let file_idx = data.get_file_by_id(file)?;
+                data.open_files[file_idx].update_length(old_length);
+            }
+            self.file_seek_from_start(file, old_length)?;
+            return Err(e);
+        }
+        self.flush_file(file)
+    }
+
+    /// Write to an open file, at its current offset.
+    ///
+    /// This is [`VolumeManager::write`] without writing any zeros that space
+    /// from `file_extend` is waiting for, and without syncing the directory
+    /// entry.
+    fn write_inner(&self, file: RawFile, buffer: &[u8]) -> Result<(), Error<D::Error>> {
         let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
 
         // Clone this so we can touch our other structures. Need to ensure we
@@ -721,6 +2101,19 @@ where
             return Err(Error::ReadOnly);
         }
 
+        if buffer.is_empty() {
+            // Don't give an empty file a cluster it has nothing to put in
+            return Ok(());
+        }
+
+        // The offset can't be past MAX_FILE_SIZE, so this can't wrap
+        let bytes_until_max = MAX_FILE_SIZE - data.open_files[file_idx].current_offset;
+        if u32::try_from(buffer.len()).map_or(true, |len| len > bytes_until_max) {
+            return Err(Error::FileTooLarge);
+        }
+        let bytes_to_write = buffer.len();
+        let start_offset = data.open_files[file_idx].current_offset;
+
         data.open_files[file_idx].dirty = true;
 
         if data.open_files[file_idx].entry.cluster.0 < RESERVED_ENTRIES {
@@ -745,10 +2138,9 @@ where
             data.open_files[file_idx].current_cluster =
                 (0, data.open_files[file_idx].entry.cluster);
         }
-        let bytes_until_max =
-            usize::try_from(MAX_FILE_SIZE - data.open_files[file_idx].current_offset)
-                .map_err(|_| Error::ConversionError)?;
-        let bytes_to_write = core::cmp::min(buffer.len(), bytes_until_max);
+        let bytes_per_cluster = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.bytes_per_cluster(),
+        };
         let mut written = 0;
 
         while written < bytes_to_write {
@@ -758,13 +2150,16 @@ where
                 written, bytes_to_write, current_cluster
             );
             let current_offset = data.open_files[file_idx].current_offset;
-            let (block_idx, block_offset, block_avail) = match data.find_data_on_disk(
+            let mut checkpoints = core::mem::take(&mut data.open_files[file_idx].checkpoints);
+            let result = data.find_data_on_disk(
                 &self.block_device,
                 volume_idx,
                 &mut current_cluster,
                 data.open_files[file_idx].entry.cluster,
                 current_offset,
-            ) {
+                &mut checkpoints,
+            );
+            let (block_idx, block_offset, block_avail) = match result {
                 Ok(vars) => {
                     debug!(
                         "Found block_idx={:?}, block_offset={:?}, block_avail={}",
@@ -774,46 +2169,84 @@ where
                 }
                 Err(Error::EndOfFile) => {
                     debug!("Extending file");
-                    match data.open_volumes[volume_idx].volume_type {
-                        VolumeType::Fat(ref mut fat) => {
-                            if fat
-                                .alloc_cluster(&self.block_device, Some(current_cluster.1), false)
-                                .is_err()
-                            {
-                                return Err(Error::DiskFull);
-                            }
-                            debug!("Allocated new FAT cluster, finding offsets...");
-                            let new_offset = data
-                                .find_data_on_disk(
-                                    &self.block_device,
-                                    volume_idx,
-                                    &mut current_cluster,
-                                    data.open_files[file_idx].entry.cluster,
-                                    data.open_files[file_idx].current_offset,
-                                )
-                                .map_err(|_| Error::AllocationError)?;
+                    // Allocate everything the rest of the buffer needs in
+                    // one go, so the FAT blocks are written once each rather
+                    // than once per cluster
+                    let bytes_left = (bytes_to_write - written) as u32;
+                    let VolumeType::Fat(fat) = &mut data.open_volumes[volume_idx].volume_type;
+                    // The free cluster count is only a hint, so don't trust it
+                    // to say how many we can have - the search will find out
+                    let clusters = bytes_left.div_ceil(bytes_per_cluster);
+                    let allocated = fat
+                        .alloc_clusters(&self.block_device, clusters, Some(current_cluster.1))
+                        .is_ok();
+                    debug!("Allocated new FAT clusters, finding offsets...");
+                    // If we ran out of space part way through, we can still
+                    // use the clusters we did get
+                    match data.find_data_on_disk(
+                        &self.block_device,
+                        volume_idx,
+                        &mut current_cluster,
+                        data.open_files[file_idx].entry.cluster,
+                        data.open_files[file_idx].current_offset,
+                        &mut checkpoints,
+                    ) {
+                        Ok(new_offset) => {
                             debug!("New offset {:?}", new_offset);
                             new_offset
                         }
+                        Err(_) if !allocated => return Err(Error::DiskFull),
+                        Err(_) => return Err(Error::AllocationError),
                     }
                 }
                 Err(e) => return Err(e),
             };
-            let mut blocks = [Block::new()];
-            let to_copy = core::cmp::min(block_avail, bytes_to_write - written);
-            if block_offset != 0 {
-                debug!("Reading for partial block write");
+            data.open_files[file_idx].checkpoints = checkpoints;
+            let whole_blocks = (bytes_to_write - written) / Block::LEN;
+            let to_copy = if block_offset == 0 && whole_blocks > 0 {
+                // Block aligned, so write as many whole blocks as we can
+                // straight from the buffer, up to the end of this cluster,
+                // or as many as the device likes to take at once.
+                let offset_in_cluster =
+                    data.open_files[file_idx].current_offset - current_cluster.0;
+                let blocks_left_in_cluster =
+                    ((bytes_per_cluster - offset_in_cluster) / Block::LEN_U32) as usize;
+                let mut burst = whole_blocks.min(blocks_left_in_cluster);
+                let optimal = self.block_device.optimal_transfer_blocks().0;
+                if optimal > 1 {
+                    burst = burst.min(saturating_usize(optimal));
+                }
+                let to_copy = burst * Block::LEN;
+                debug!(
+                    "Writing {} blocks from {:?}",
+                    to_copy / Block::LEN,
+                    block_idx
+                );
                 self.block_device
-                    .read(&mut blocks, block_idx)
+                    .write(
+                        Block::slice_from_bytes(&buffer[written..written + to_copy]),
+                        block_idx,
+                    )
                     .map_err(Error::DeviceError)?;
-            }
-            let block = &mut blocks[0];
-            block[block_offset..block_offset + to_copy]
-                .copy_from_slice(&buffer[written..written + to_copy]);
-            debug!("Writing block {:?}", block_idx);
-            self.block_device
-                .write(&blocks, block_idx)
-                .map_err(Error::DeviceError)?;
+                to_copy
+            } else {
+                let mut blocks = [Block::new()];
+                let to_copy = core::cmp::min(block_avail, bytes_to_write - written);
+                if block_offset != 0 {
+                    debug!("Reading for partial block write");
+                    self.block_device
+                        .read(&mut blocks, block_idx)
+                        .map_err(Error::DeviceError)?;
+                }
+                let block = &mut blocks[0];
+                block[block_offset..block_offset + to_copy]
+                    .copy_from_slice(&buffer[written..written + to_copy]);
+                debug!("Writing block {:?}", block_idx);
+                self.block_device
+                    .write(&blocks, block_idx)
+                    .map_err(Error::DeviceError)?;
+                to_copy
+            };
             written += to_copy;
             data.open_files[file_idx].current_cluster = current_cluster;
 
@@ -828,8 +2261,125 @@ where
                 .unwrap();
             // Entry update deferred to file close, for performance.
         }
-        data.open_files[file_idx].entry.attributes.set_archive(true);
-        data.open_files[file_idx].entry.mtime = self.time_source.get_timestamp();
+        let file_info = &mut data.open_files[file_idx];
+        if let Some(zeros_from) = file_info.zeros_from {
+            let end_offset = file_info.current_offset;
+            if start_offset <= zeros_from && end_offset > zeros_from {
+                // We've written over the start of the space
+                file_info.zeros_from = Some(end_offset).filter(|&z| z < file_info.entry.size);
+            }
+        }
+        file_info.entry.attributes.set_archive(true);
+        let now = self.time_source.get_timestamp();
+        file_info.entry.mtime = now;
+        file_info.entry.atime = now.date_only();
+        Ok(())
+    }
+
+    /// Make a file `length` bytes long, without writing anything in the new
+    /// space.
+    ///
+    /// The clusters to hold the file are allocated (see
+    /// [`VolumeManager::file_preallocate`]) but not written to, so this call
+    /// is quick even for a big file. The new space reads as zeros. The file's
+    /// offset doesn't change.
+    ///
+    /// FAT has no way to record a hole in a file, so the zeros do have to be
+    /// written to the disk eventually. That happens when you write something
+    /// after them, or when the file is flushed or closed - at which point
+    /// any new space you haven't written to yourself is filled with zeros, a
+    /// block at a time. So this saves time when you go on to fill the file
+    /// from the start (nothing is written twice), but extending a file by a
+    /// lot and closing it straight away still writes all of those zeros.
+    ///
+    /// The file can't be made shorter, so a `length` less than the file's
+    /// length gives `Error::InvalidOffset`.
+    pub fn file_extend(&self, file: RawFile, length: u32) -> Result<(), Error<D::Error>> {
+        let old_length = self.file_length(file)?;
+        if length < old_length {
+            return Err(Error::InvalidOffset);
+        }
+        if length == old_length {
+            return Ok(());
+        }
+        self.file_preallocate(file, length)?;
+
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        let file_info = &mut data.open_files[file_idx];
+        file_info.zeros_from.get_or_insert(old_length);
+        file_info.update_length(length);
+        file_info.dirty = true;
+        let now = self.time_source.get_timestamp();
+        file_info.entry.mtime = now;
+        file_info.entry.atime = now.date_only();
+        Ok(())
+    }
+
+    /// Write the zeros that space from [`VolumeManager::file_extend`] has
+    /// been waiting for, up to `until` (or the end of the file).
+    ///
+    /// The file's offset is put back afterwards.
+    fn write_pending_zeros(
+        &self,
+        file: RawFile,
+        until: Option<u32>,
+    ) -> Result<(), Error<D::Error>> {
+        let (start, end, position) = {
+            let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+            let file_idx = data.get_file_by_id(file)?;
+            let file_info = &data.open_files[file_idx];
+            let Some(start) = file_info.zeros_from else {
+                return Ok(());
+            };
+            let end = until.map_or(file_info.entry.size, |u| u.min(file_info.entry.size));
+            (start, end, file_info.current_offset)
+        };
+        if start >= end {
+            return Ok(());
+        }
+        debug!("Writing zeros from {} to {}", start, end);
+        self.file_seek_from_start(file, start)?;
+        let zeros = [0u8; Block::LEN];
+        let mut offset = start;
+        while offset < end {
+            // Stay block-aligned, so we never have to read a block first
+            let len = (Block::LEN_U32 - offset % Block::LEN_U32).min(end - offset);
+            self.write_inner(file, &zeros[..len as usize])?;
+            offset += len;
+        }
+        self.file_seek_from_start(file, position)
+    }
+
+    /// Make sure a file has enough clusters to hold `bytes` bytes, so later
+    /// writes up to that size don't have to allocate.
+    ///
+    /// The clusters are taken in one pass over the FAT and are contiguous
+    /// wherever the free space allows. The file's length doesn't change -
+    /// the extra clusters sit on the end of the chain until written to.
+    pub fn file_preallocate(&self, file: RawFile, bytes: u32) -> Result<(), Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        let volume_idx = data.get_volume_by_id(data.open_files[file_idx].raw_volume)?;
+
+        if data.open_files[file_idx].mode == Mode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+
+        use core::ops::DerefMut;
+        let data = data.deref_mut();
+        let first_cluster = data.open_files[file_idx].entry.cluster;
+        let new_first_cluster = match data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(ref mut fat) => {
+                let clusters = bytes.div_ceil(fat.bytes_per_cluster());
+                fat.preallocate(&self.block_device, first_cluster, clusters)?
+            }
+        };
+        if new_first_cluster != first_cluster {
+            debug!("Alloc first cluster {:?}", new_first_cluster);
+            data.open_files[file_idx].entry.cluster = new_first_cluster;
+            data.open_files[file_idx].dirty = true;
+        }
         Ok(())
     }
 
@@ -843,7 +2393,20 @@ where
     }
 
     /// Flush (update the entry) for a file with the given raw file handle.
+    ///
+    /// This also calls [`BlockDevice::flush`] on the block device.
     pub fn flush_file(&self, file: RawFile) -> Result<(), Error<D::Error>> {
+        self.write_file_entry(file)?;
+        self.block_device.flush().map_err(Error::DeviceError)
+    }
+
+    /// Write the directory entry for a file to disk, if it has changed.
+    ///
+    /// Any zeros that space from [`VolumeManager::file_extend`] is waiting
+    /// for are written first, so the disk matches what we've said is in the
+    /// file.
+    fn write_file_entry(&self, file: RawFile) -> Result<(), Error<D::Error>> {
+        self.write_pending_zeros(file, None)?;
         use core::ops::DerefMut;
         let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
         let data = data.deref_mut();
@@ -852,6 +2415,11 @@ where
 
         if data.open_files[file_id].dirty {
             let volume_idx = data.get_volume_by_id(data.open_files[file_id].raw_volume)?;
+            if data.open_volumes[volume_idx].read_only {
+                // Files on a read-only volume can't be written to, so should
+                // never be dirty - but don't risk touching the disk.
+                return Ok(());
+            }
             match &mut data.open_volumes[volume_idx].volume_type {
                 VolumeType::Fat(fat) => {
                     debug!("Updating FAT info sector");
@@ -868,10 +2436,213 @@ where
         Ok(())
     }
 
+    /// Flush every open file, so that all their directory entries are up to
+    /// date on disk, and then flush the block device.
+    ///
+    /// If flushing a file fails, we carry on and flush the remaining files,
+    /// and then return the first error we saw.
+    pub fn sync(&self) -> Result<(), Error<D::Error>> {
+        let open_files: Vec<RawFile, MAX_FILES> = {
+            let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+            data.open_files.iter().map(|f| f.raw_file).collect()
+        };
+        let mut result = Ok(());
+        for file in open_files {
+            let flush_result = self.write_file_entry(file);
+            if result.is_ok() {
+                result = flush_result;
+            }
+        }
+        let flush_result = self.block_device.flush().map_err(Error::DeviceError);
+        result.and(flush_result)
+    }
+
+    /// Close every open file, directory and volume, so you can start again
+    /// from a clean slate (after an error, say).
+    ///
+    /// Open files are flushed first, as with [`VolumeManager::sync`]. The
+    /// handle tables are cleared even if that fails, and the first error is
+    /// returned. Any handles you still hold become invalid, and using (or
+    /// dropping) them gives `Error::BadHandle`.
+    pub fn close_all_handles(&self) -> Result<(), Error<D::Error>> {
+        let result = self.sync();
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        data.open_files.clear();
+        data.open_dirs.clear();
+        data.open_volumes.clear();
+        result
+    }
+
+    /// Get the number of free clusters on a volume.
+    ///
+    /// If the volume has a free cluster count (from the FAT32 info sector, or
+    /// because [`VolumeOpts::count_fat16_free_clusters`] was set), that count
+    /// is returned. Otherwise we scan the FAT, which can be slow.
+    pub fn free_cluster_count(&self, volume: RawVolume) -> Result<u32, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => match fat.free_clusters_count {
+                Some(count) => Ok(count),
+                None => fat.count_free_clusters(&self.block_device),
+            },
+        }
+    }
+
+    /// Count the clusters in the chain starting at `first_cluster`.
+    ///
+    /// Gives `Error::FormatError` if the chain has more clusters than the
+    /// volume, which means the FAT is corrupt.
+    pub fn cluster_chain_len(
+        &self,
+        volume: RawVolume,
+        first_cluster: ClusterId,
+    ) -> Result<u32, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.cluster_chain_len(&self.block_device, first_cluster),
+        }
+    }
+
+    /// Check whether every cluster in the chain starting at `first_cluster` is
+    /// immediately followed by the next cluster on disk.
+    ///
+    /// A contiguous file can be read without looking at the FAT.
+    pub fn is_contiguous(
+        &self,
+        volume: RawVolume,
+        first_cluster: ClusterId,
+    ) -> Result<bool, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.is_contiguous(&self.block_device, first_cluster),
+        }
+    }
+
+    /// Check that every copy of the FAT on a volume matches the first one.
+    ///
+    /// FAT volumes usually keep two copies of the FAT, and a card which lost
+    /// power while the FAT was being updated may leave them different. If
+    /// they differ, this gives `Error::FatMirrorMismatch` with the first block
+    /// (of the first FAT) that doesn't match.
+    ///
+    /// This crate writes every change to the FAT to all of its copies, so a
+    /// volume it has written to should still pass this check.
+    pub fn verify_fats(&self, volume: RawVolume) -> Result<(), Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.verify_fats(&self.block_device),
+        }
+    }
+
+    /// Check a volume for damage, without changing anything on disk.
+    ///
+    /// We look for lost cluster chains (allocated in the FAT, but not used by
+    /// any file or directory), cross-linked clusters (used by more than one
+    /// file or directory), and directory entries which point at free
+    /// clusters. See [`FsckReport`] for what we count.
+    ///
+    /// This walks the whole directory tree and reads the whole FAT many
+    /// times over, so it is very slow on a large volume. It's meant for
+    /// debugging cards which have gone wrong in the field.
+    pub fn check_volume(&self, volume: RawVolume) -> Result<FsckReport, Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        // We don't keep this directory open, so it doesn't use up a handle
+        let root = DirectoryInfo {
+            raw_volume: volume,
+            cluster: ClusterId::ROOT_DIR,
+            raw_directory: RawDirectory(data.id_generator.generate()),
+        };
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => fat.check(&self.block_device, &root),
+        }
+    }
+
+    /// Get the code page a volume was opened with.
+    ///
+    /// Use this with [`ShortFileName::display`] to show the names of the
+    /// entries on a volume.
+    pub fn code_page(&self, volume: RawVolume) -> Result<CodePage, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        Ok(data.open_volumes[volume_idx].code_page)
+    }
+
+    /// Get the code page of the volume a directory is on.
+    fn dir_code_page(&self, directory: RawDirectory) -> Result<CodePage, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let directory_idx = data.get_dir_by_id(directory)?;
+        let volume_idx = data.get_volume_by_id(data.open_dirs[directory_idx].raw_volume)?;
+        Ok(data.open_volumes[volume_idx].code_page)
+    }
+
+    /// Get which kind of FAT a volume uses.
+    pub fn fat_type(&self, volume: RawVolume) -> Result<fat::FatType, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => Ok(fat.get_fat_type()),
+        }
+    }
+
+    /// Get the number of bytes in each cluster of a volume.
+    ///
+    /// Writes of whole clusters at a cluster-aligned offset are the fastest.
+    pub fn bytes_per_cluster(&self, volume: RawVolume) -> Result<u32, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => Ok(fat.bytes_per_cluster()),
+        }
+    }
+
+    /// Get the total number of data clusters in a volume.
+    pub fn cluster_count(&self, volume: RawVolume) -> Result<u32, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let volume_idx = data.get_volume_by_id(volume)?;
+        match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => Ok(fat.cluster_count),
+        }
+    }
+
     /// Check if any files or folders are open.
     pub fn has_open_handles(&self) -> bool {
         let data = self.data.borrow();
-        !(data.open_dirs.is_empty() || data.open_files.is_empty())
+        !(data.open_dirs.is_empty() && data.open_files.is_empty())
+    }
+
+    /// Count the open handles, as `(files, dirs, volumes)`.
+    ///
+    /// Compare these with `MAX_FILES`, `MAX_DIRS` and `MAX_VOLUMES` to see
+    /// how close you are to getting `Error::TooManyOpenFiles` (and friends).
+    pub fn open_handle_count(&self) -> Result<(usize, usize, usize), Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        Ok((
+            data.open_files.len(),
+            data.open_dirs.len(),
+            data.open_volumes.len(),
+        ))
+    }
+
+    /// List the open files, with the name of each one.
+    ///
+    /// Useful for finding out which files you forgot to close. The list is
+    /// copied, so you can carry on using the volume manager (and closing the
+    /// files) while you go through it.
+    pub fn open_files_iter(
+        &self,
+    ) -> Result<impl Iterator<Item = (RawFile, ShortFileName)>, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let files: Vec<(RawFile, ShortFileName), MAX_FILES> = data
+            .open_files
+            .iter()
+            .map(|f| (f.raw_file, f.entry.name.clone()))
+            .collect();
+        Ok(files.into_iter())
     }
 
     /// Consume self and return BlockDevice and TimeSource
@@ -920,6 +2691,25 @@ where
         Ok(())
     }
 
+    /// Seek a file with a signed offset relative to the end of the file, like
+    /// `SeekFrom::End`.
+    ///
+    /// An offset of zero seeks to the end of the file, and a negative offset
+    /// seeks back from there. Seeking past the end of the file, or before the
+    /// start, gives `Error::InvalidOffset`.
+    pub fn file_seek_relative_to_end(
+        &self,
+        file: RawFile,
+        offset: i64,
+    ) -> Result<(), Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        data.open_files[file_idx]
+            .seek_relative_to_end(offset)
+            .map_err(|_| Error::InvalidOffset)?;
+        Ok(())
+    }
+
     /// Get the length of a file
     pub fn file_length(&self, file: RawFile) -> Result<u32, Error<D::Error>> {
         let data = self.data.borrow();
@@ -934,6 +2724,106 @@ where
         Ok(data.open_files[file_idx].current_offset)
     }
 
+    /// Get the number of bytes between the current offset of a file and its
+    /// end.
+    ///
+    /// This is never less than zero, even if the offset is past the end.
+    pub fn file_remaining(&self, file: RawFile) -> Result<u32, Error<D::Error>> {
+        let data = self.data.borrow();
+        let file_idx = data.get_file_by_id(file)?;
+        Ok(data.open_files[file_idx].left())
+    }
+
+    /// Get the mode an open file was opened in.
+    pub fn file_mode(&self, file: RawFile) -> Result<Mode, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        Ok(data.open_files[file_idx].mode)
+    }
+
+    /// Get a snapshot of the metadata for an open file.
+    pub fn file_info(&self, file: RawFile) -> Result<FileMeta, Error<D::Error>> {
+        let data = self.data.try_borrow().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        Ok(data.open_files[file_idx].meta())
+    }
+
+    /// Work out the path of an open file, relative to the root directory of
+    /// its volume.
+    ///
+    /// The path is written into `buffer` as the short names of each
+    /// directory and the file, separated by `/` - for example
+    /// `TEST/TEST.DAT`. A file in the root directory just gives its own name.
+    /// The names are converted using the volume's [`VolumeOpts::code_page`].
+    ///
+    /// Directories don't record their own names, so this follows the `..`
+    /// entry of each directory up to the root directory, and then searches
+    /// each parent for the entry that points back down. Expect it to read a
+    /// few blocks for every level.
+    ///
+    /// Returns `Error::NotEnoughSpace` if the path does not fit in `buffer`.
+    pub fn get_file_path<'b>(
+        &self,
+        file: RawFile,
+        buffer: &'b mut [u8],
+    ) -> Result<&'b str, Error<D::Error>> {
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let file_idx = data.get_file_by_id(file)?;
+        let volume_idx = data.get_volume_by_id(data.open_files[file_idx].raw_volume)?;
+        // We don't keep these directories open, so they don't use up handles
+        let mut dir_info = DirectoryInfo {
+            raw_directory: RawDirectory(data.id_generator.generate()),
+            raw_volume: data.open_files[file_idx].raw_volume,
+            cluster: data.open_files[file_idx].dir_cluster,
+        };
+        let code_page = data.open_volumes[volume_idx].code_page;
+        let VolumeType::Fat(fat) = &data.open_volumes[volume_idx].volume_type;
+
+        // We don't know how deep we are, so build the path backwards from the
+        // end of the buffer and move it to the front afterwards.
+        let mut start = buffer.len();
+        prepend_path(
+            buffer,
+            &mut start,
+            data.open_files[file_idx].entry.name.display(code_page),
+        )?;
+        while dir_info.cluster != ClusterId::ROOT_DIR {
+            let parent_entry = fat.find_directory_entry(
+                &self.block_device,
+                &dir_info,
+                &ShortFileName::parent_dir(),
+            )?;
+            let parent_info = DirectoryInfo {
+                cluster: parent_entry.cluster,
+                ..dir_info.clone()
+            };
+            let mut name = None;
+            fat.iterate_dir(&self.block_device, &parent_info, |entry| {
+                if name.is_none()
+                    && entry.attributes.is_directory()
+                    && entry.cluster == dir_info.cluster
+                    && entry.name != ShortFileName::this_dir()
+                    && entry.name != ShortFileName::parent_dir()
+                {
+                    name = Some(entry.name.clone());
+                }
+            })?;
+            let name = name.ok_or(Error::FormatError("Directory missing from its parent"))?;
+            // A corrupt loop of `..` entries will run out of buffer
+            if start == 0 {
+                return Err(Error::NotEnoughSpace);
+            }
+            start -= 1;
+            buffer[start] = b'/';
+            prepend_path(buffer, &mut start, name.display(code_page))?;
+            dir_info = parent_info;
+        }
+
+        let len = buffer.len() - start;
+        buffer.copy_within(start.., 0);
+        core::str::from_utf8(&buffer[..len]).map_err(|_| Error::ConversionError)
+    }
+
     /// Create a directory in a given directory.
     pub fn make_dir_in_dir<N>(
         &self,
@@ -957,7 +2847,12 @@ where
         let volume_id = data.open_dirs[parent_directory_idx].raw_volume;
         let volume_idx = data.get_volume_by_id(volume_id)?;
         let volume_info = &data.open_volumes[volume_idx];
-        let sfn = name.to_short_filename().map_err(Error::FilenameError)?;
+        if volume_info.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let sfn = name
+            .to_short_filename_with_code_page(volume_info.code_page)
+            .map_err(Error::FilenameError)?;
 
         debug!("Creating directory '{}'", sfn);
         debug!(
@@ -988,7 +2883,7 @@ where
             }
         };
 
-        let att = Attributes::create_from_fat(Attributes::DIRECTORY);
+        let att = Attributes::new().directory();
 
         // Need mutable access for this
         match &mut data.open_volumes[volume_idx].volume_type {
@@ -1018,6 +2913,7 @@ where
                     name: crate::ShortFileName::this_dir(),
                     mtime: now,
                     ctime: now,
+                    atime: now.date_only(),
                     attributes: att,
                     // point at ourselves
                     cluster: new_dir_entry_in_parent.cluster,
@@ -1026,34 +2922,30 @@ where
                     entry_offset: 0,
                 };
                 debug!("New dir has {:?}", dot_entry_in_child);
-                let mut offset = 0;
-                blocks[0][offset..offset + OnDiskDirEntry::LEN]
+                blocks[0]
+                    .record_mut(0, OnDiskDirEntry::LEN)
                     .copy_from_slice(&dot_entry_in_child.serialize(fat_type)[..]);
-                offset += OnDiskDirEntry::LEN;
                 // make the ".." entry
                 let dot_dot_entry_in_child = DirEntry {
                     name: crate::ShortFileName::parent_dir(),
                     mtime: now,
                     ctime: now,
+                    atime: now.date_only(),
                     attributes: att,
                     // point at our parent
-                    cluster: match fat_type {
-                        FatType::Fat16 => {
-                            // On FAT16, indicate parent is root using Cluster(0)
-                            if parent_directory_info.cluster == ClusterId::ROOT_DIR {
-                                ClusterId::EMPTY
-                            } else {
-                                parent_directory_info.cluster
-                            }
-                        }
-                        FatType::Fat32 => parent_directory_info.cluster,
+                    cluster: if parent_directory_info.cluster == ClusterId::ROOT_DIR {
+                        // Indicate parent is root using Cluster(0)
+                        ClusterId::EMPTY
+                    } else {
+                        parent_directory_info.cluster
                     },
                     size: 0,
                     entry_block: new_dir_start_block,
                     entry_offset: OnDiskDirEntry::LEN_U32,
                 };
                 debug!("New dir has {:?}", dot_dot_entry_in_child);
-                blocks[0][offset..offset + OnDiskDirEntry::LEN]
+                blocks[0]
+                    .record_mut(1, OnDiskDirEntry::LEN)
                     .copy_from_slice(&dot_dot_entry_in_child.serialize(fat_type)[..]);
 
                 self.block_device
@@ -1077,6 +2969,198 @@ where
 
         Ok(())
     }
+
+    /// Rename a directory, keeping it in the same parent directory.
+    ///
+    /// The directory's contents don't move, and its `.` and `..` entries
+    /// still point at the right places, so only its entry in `directory` is
+    /// rewritten. You can't rename `.` or `..`, or a directory which is open.
+    pub fn rename_dir<N, M>(
+        &self,
+        directory: RawDirectory,
+        old_name: N,
+        new_name: M,
+    ) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName,
+        M: ToShortFileName,
+    {
+        use core::ops::DerefMut;
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let data = data.deref_mut();
+
+        let parent_directory_idx = data.get_dir_by_id(directory)?;
+        let parent_directory_info = &data.open_dirs[parent_directory_idx];
+        let volume_id = parent_directory_info.raw_volume;
+        let volume_idx = data.get_volume_by_id(volume_id)?;
+        if data.open_volumes[volume_idx].read_only {
+            return Err(Error::ReadOnly);
+        }
+        let code_page = data.open_volumes[volume_idx].code_page;
+        let old_sfn = old_name
+            .to_short_filename_with_code_page(code_page)
+            .map_err(Error::FilenameError)?;
+        let new_sfn = new_name
+            .to_short_filename_with_code_page(code_page)
+            .map_err(Error::FilenameError)?;
+
+        let special = [ShortFileName::this_dir(), ShortFileName::parent_dir()];
+        if special.contains(&old_sfn) || special.contains(&new_sfn) {
+            return Err(Error::Unsupported);
+        }
+
+        debug!("Renaming directory '{}' to '{}'", old_sfn, new_sfn);
+
+        let old_entry = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.find_directory_entry(&self.block_device, parent_directory_info, &old_sfn)?
+            }
+        };
+        if !old_entry.attributes.is_directory() {
+            return Err(Error::OpenedFileAsDir);
+        }
+        if data
+            .open_dirs
+            .iter()
+            .any(|d| d.raw_volume == volume_id && d.cluster == old_entry.cluster)
+        {
+            return Err(Error::DirAlreadyOpen);
+        }
+
+        // Is the new name free?
+        let maybe_dir_entry = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.find_directory_entry(&self.block_device, parent_directory_info, &new_sfn)
+            }
+        };
+        match maybe_dir_entry {
+            Ok(entry) if entry.attributes.is_directory() => {
+                return Err(Error::DirAlreadyExists);
+            }
+            Ok(_entry) => {
+                return Err(Error::FileAlreadyExists);
+            }
+            Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        };
+
+        // Write the new entry before removing the old one, so that if we're
+        // interrupted the directory isn't lost.
+        match &mut data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                let mut new_entry = fat.write_new_directory_entry(
+                    &self.block_device,
+                    &self.time_source,
+                    parent_directory_info.cluster,
+                    new_sfn,
+                    old_entry.attributes,
+                )?;
+                new_entry.cluster = old_entry.cluster;
+                new_entry.ctime = old_entry.ctime;
+                new_entry.mtime = old_entry.mtime;
+                new_entry.atime = old_entry.atime;
+                fat.write_entry_to_disk(&self.block_device, &new_entry)?;
+                fat.delete_directory_entry(&self.block_device, parent_directory_info, &old_sfn)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Move a closed file from one directory to another on the same volume,
+    /// giving it a new name on the way.
+    ///
+    /// The file's contents don't move - only its directory entry does, keeping
+    /// its size, attributes and timestamps. You can't move a file which is
+    /// open, or move it onto a name that's already taken. Use
+    /// [`VolumeManager::rename_dir`] for directories.
+    pub fn move_file<N, M>(
+        &self,
+        src_dir: RawDirectory,
+        name: N,
+        dst_dir: RawDirectory,
+        new_name: M,
+    ) -> Result<(), Error<D::Error>>
+    where
+        N: ToShortFileName,
+        M: ToShortFileName,
+    {
+        use core::ops::DerefMut;
+        let mut data = self.data.try_borrow_mut().map_err(|_| Error::LockError)?;
+        let data = data.deref_mut();
+
+        let src_dir_info = &data.open_dirs[data.get_dir_by_id(src_dir)?];
+        let dst_dir_info = &data.open_dirs[data.get_dir_by_id(dst_dir)?];
+        let volume_id = src_dir_info.raw_volume;
+        if dst_dir_info.raw_volume != volume_id {
+            return Err(Error::Unsupported);
+        }
+        let volume_idx = data.get_volume_by_id(volume_id)?;
+        if data.open_volumes[volume_idx].read_only {
+            return Err(Error::ReadOnly);
+        }
+        let code_page = data.open_volumes[volume_idx].code_page;
+        let old_sfn = name
+            .to_short_filename_with_code_page(code_page)
+            .map_err(Error::FilenameError)?;
+        let new_sfn = new_name
+            .to_short_filename_with_code_page(code_page)
+            .map_err(Error::FilenameError)?;
+
+        debug!("Moving file '{}' to '{}'", old_sfn, new_sfn);
+
+        let old_entry = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.find_directory_entry(&self.block_device, src_dir_info, &old_sfn)?
+            }
+        };
+        if old_entry.attributes.is_directory() {
+            return Err(Error::OpenedDirAsFile);
+        }
+        if data.file_is_open(volume_id, &old_entry) {
+            return Err(Error::FileAlreadyOpen);
+        }
+
+        // Is the new name free?
+        let maybe_dir_entry = match &data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                fat.find_directory_entry(&self.block_device, dst_dir_info, &new_sfn)
+            }
+        };
+        match maybe_dir_entry {
+            Ok(entry) if entry.attributes.is_directory() => {
+                return Err(Error::DirAlreadyExists);
+            }
+            Ok(_entry) => {
+                return Err(Error::FileAlreadyExists);
+            }
+            Err(Error::NotFound) => {}
+            Err(e) => return Err(e),
+        };
+
+        // Write the new entry before removing the old one, so that if we're
+        // interrupted the file isn't lost.
+        match &mut data.open_volumes[volume_idx].volume_type {
+            VolumeType::Fat(fat) => {
+                let mut new_entry = fat.write_new_directory_entry(
+                    &self.block_device,
+                    &self.time_source,
+                    dst_dir_info.cluster,
+                    new_sfn,
+                    old_entry.attributes,
+                )?;
+                new_entry.cluster = old_entry.cluster;
+                new_entry.size = old_entry.size;
+                new_entry.ctime = old_entry.ctime;
+                new_entry.mtime = old_entry.mtime;
+                new_entry.atime = old_entry.atime;
+                fat.write_entry_to_disk(&self.block_device, &new_entry)?;
+                fat.delete_directory_entry(&self.block_device, src_dir_info, &old_sfn)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 /// The mutable data the VolumeManager needs to hold
@@ -1088,15 +3172,22 @@ struct VolumeManagerData<
     const MAX_DIRS: usize = 4,
     const MAX_FILES: usize = 4,
     const MAX_VOLUMES: usize = 1,
+    const MAX_CHECKPOINTS: usize = 0,
 > {
     id_generator: HandleGenerator,
     open_volumes: Vec<VolumeInfo, MAX_VOLUMES>,
     open_dirs: Vec<DirectoryInfo, MAX_DIRS>,
-    open_files: Vec<FileInfo, MAX_FILES>,
+    open_files: Vec<FileInfo<MAX_CHECKPOINTS>, MAX_FILES>,
+    /// Volumes closed with [`VolumeOpts::cache_geometry`] set, oldest first.
+    cached_volumes: Vec<(VolumeIdx, VolumeType), MAX_VOLUMES>,
 }
 
-impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
-    VolumeManagerData<MAX_DIRS, MAX_FILES, MAX_VOLUMES>
+impl<
+        const MAX_DIRS: usize,
+        const MAX_FILES: usize,
+        const MAX_VOLUMES: usize,
+        const MAX_CHECKPOINTS: usize,
+    > VolumeManagerData<MAX_DIRS, MAX_FILES, MAX_VOLUMES, MAX_CHECKPOINTS>
 {
     /// Check if a file is open
     ///
@@ -1150,8 +3241,9 @@ impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
     }
 
     /// This function turns `desired_offset` into an appropriate block to be
-    /// read. It either calculates this based on the start of the file, or
-    /// from the given start point - whichever is better.
+    /// read. It either calculates this based on the start of the file, the
+    /// nearest checkpoint, or from the given start point - whichever is
+    /// better. Any checkpoints passed on the way are recorded.
     ///
     /// Returns:
     ///
@@ -1165,6 +3257,7 @@ impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
         start: &mut (u32, ClusterId),
         file_start: ClusterId,
         desired_offset: u32,
+        checkpoints: &mut ChainCheckpoints<MAX_CHECKPOINTS>,
     ) -> Result<(BlockIdx, usize, usize), Error<D::Error>>
     where
         D: BlockDevice,
@@ -1172,6 +3265,12 @@ impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
         let bytes_per_cluster = match &self.open_volumes[volume_idx].volume_type {
             VolumeType::Fat(fat) => fat.bytes_per_cluster(),
         };
+        // is there a checkpoint that gets us closer?
+        if let Some(checkpoint) = checkpoints.nearest(desired_offset) {
+            if desired_offset < start.0 || checkpoint.0 > start.0 {
+                *start = checkpoint;
+            }
+        }
         // do we need to be before our start point?
         if desired_offset < start.0 {
             // user wants to go backwards - start from the beginning of the file
@@ -1191,6 +3290,7 @@ impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
                 }
             };
             start.0 += bytes_per_cluster;
+            checkpoints.record(start.0, start.1, bytes_per_cluster);
         }
         // How many blocks in are we now?
         let offset_from_cluster = desired_offset - start.0;
@@ -1205,6 +3305,38 @@ impl<const MAX_DIRS: usize, const MAX_FILES: usize, const MAX_VOLUMES: usize>
     }
 }
 
+/// Convert a number of bytes in a file to a `usize`, for bounding a copy into
+/// a buffer.
+///
+/// A file can hold more than a 16-bit `usize` can count, but no buffer can,
+/// so anything that doesn't fit becomes `usize::MAX`.
+fn saturating_usize(bytes: u32) -> usize {
+    usize::try_from(bytes).unwrap_or(usize::MAX)
+}
+
+/// Write a displayed short file name into `buffer`, just before `start`, and
+/// move `start` back to the beginning of it.
+fn prepend_path<E>(
+    buffer: &mut [u8],
+    start: &mut usize,
+    name: ShortFileNameDisplay,
+) -> Result<(), Error<E>>
+where
+    E: core::fmt::Debug,
+{
+    use core::fmt::Write;
+    // Each of the 11 bytes might take three bytes of UTF-8, plus the '.'
+    let mut formatted: heapless::String<34> = heapless::String::new();
+    write!(formatted, "{}", name).map_err(|_| Error::ConversionError)?;
+    let len = formatted.len();
+    if len > *start {
+        return Err(Error::NotEnoughSpace);
+    }
+    *start -= len;
+    buffer[*start..*start + len].copy_from_slice(formatted.as_bytes());
+    Ok(())
+}
+
 /// Transform mode variants (ReadWriteCreate_Or_Append) to simple modes ReadWriteAppend or
 /// ReadWriteCreate
 fn solve_mode_variant(mode: Mode, dir_entry_is_some: bool) -> Mode {
@@ -1225,6 +3357,47 @@ fn solve_mode_variant(mode: Mode, dir_entry_is_some: bool) -> Mode {
     mode
 }
 
+/// Check for the 0x55, 0xAA signature at the end of an MBR or EBR.
+fn check_boot_signature<E>(block: &Block, error: &'static str) -> Result<(), Error<E>>
+where
+    E: core::fmt::Debug,
+{
+    const FOOTER_START: usize = 510;
+    const FOOTER_VALUE: u16 = 0xAA55;
+    if LittleEndian::read_u16(&block[FOOTER_START..FOOTER_START + 2]) != FOOTER_VALUE {
+        return Err(Error::FormatError(error));
+    }
+    Ok(())
+}
+
+/// Get the type, first block and size (in blocks) from entry `idx` (0 to 3)
+/// of the partition table in an MBR or EBR.
+fn partition_entry<E>(block: &Block, idx: usize) -> Result<(u8, u32, u32), Error<E>>
+where
+    E: core::fmt::Debug,
+{
+    const PARTITION_TABLE_START: usize = 446;
+    const PARTITION_INFO_LENGTH: usize = 16;
+    const PARTITION_INFO_STATUS_INDEX: usize = 0;
+    const PARTITION_INFO_TYPE_INDEX: usize = 4;
+    const PARTITION_INFO_LBA_START_INDEX: usize = 8;
+    const PARTITION_INFO_NUM_BLOCKS_INDEX: usize = 12;
+
+    let start = PARTITION_TABLE_START + idx * PARTITION_INFO_LENGTH;
+    let partition = &block[start..start + PARTITION_INFO_LENGTH];
+    // Only 0x80 and 0x00 are valid (bootable, and non-bootable)
+    if (partition[PARTITION_INFO_STATUS_INDEX] & 0x7F) != 0x00 {
+        return Err(Error::FormatError("Invalid partition status"));
+    }
+    let lba_start = LittleEndian::read_u32(
+        &partition[PARTITION_INFO_LBA_START_INDEX..(PARTITION_INFO_LBA_START_INDEX + 4)],
+    );
+    let num_blocks = LittleEndian::read_u32(
+        &partition[PARTITION_INFO_NUM_BLOCKS_INDEX..(PARTITION_INFO_NUM_BLOCKS_INDEX + 4)],
+    );
+    Ok((partition[PARTITION_INFO_TYPE_INDEX], lba_start, num_blocks))
+}
+
 // ****************************************************************************
 //
 // Unit Tests
@@ -1486,18 +3659,42 @@ mod tests {
                     blocks_per_cluster: 8,
                     first_data_block: BlockCount(15136),
                     fat_start: BlockCount(32),
+                    fat_size: BlockCount(7552),
+                    num_fats: 2,
                     name: fat::VolumeName::create_from_str("Pictures").unwrap(),
                     free_clusters_count: None,
                     next_free_cluster: None,
                     cluster_count: 965_788,
+                    max_dir_scan_blocks: None,
                     fat_specific_info: fat::FatSpecificInfo::Fat32(fat::Fat32Info {
                         first_root_dir_cluster: ClusterId(2),
                         info_location: BlockIdx(1) + BlockCount(1),
                     })
-                })
+                }),
+                read_only: false,
+                code_page: CodePage::Cp437,
+                cache_geometry: false,
             }
         );
     }
+
+    #[test]
+    fn saturating_usize_bounds() {
+        assert_eq!(saturating_usize(0), 0);
+        assert_eq!(saturating_usize(512), 512);
+        assert_eq!(saturating_usize(0xFFFF), 0xFFFF);
+        // Only a 16-bit target can't hold these
+        #[cfg(target_pointer_width = "16")]
+        {
+            assert_eq!(saturating_usize(0x1_0000), usize::MAX);
+            assert_eq!(saturating_usize(u32::MAX), usize::MAX);
+        }
+        #[cfg(not(target_pointer_width = "16"))]
+        {
+            assert_eq!(saturating_usize(0x1_0000), 0x1_0000);
+            assert_eq!(saturating_usize(u32::MAX), u32::MAX as usize);
+        }
+    }
 }
 
 // ****************************************************************************
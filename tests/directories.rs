@@ -476,6 +476,1077 @@ fn make_directory() {
     volume_mgr.close_file(new_file).expect("close file");
 }
 
+fn open_parent_dir_check(volume_idx: usize) {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    volume_mgr.make_dir_in_dir(root_dir, "A").expect("make A");
+    let dir_a = volume_mgr.open_dir(root_dir, "A").expect("open A");
+    volume_mgr.make_dir_in_dir(dir_a, "B").expect("make B");
+    volume_mgr.close_dir(dir_a).expect("close A");
+    volume_mgr.close_dir(root_dir).expect("close root");
+
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let dir_a = volume_mgr.open_dir(root_dir, "A").expect("open A");
+    let dir_b = volume_mgr.open_dir(dir_a, "B").expect("open B");
+    volume_mgr.close_dir(dir_a).expect("close A");
+    volume_mgr.close_dir(root_dir).expect("close root");
+
+    // Go up from B to A
+    let parent_of_b = volume_mgr.open_parent_dir(dir_b).expect("open parent of B");
+    let mut names = Vec::new();
+    volume_mgr
+        .iterate_dir(parent_of_b, |entry| names.push(entry.name.clone()))
+        .expect("iterate A");
+    assert_eq!(
+        names,
+        [
+            ShortFileName::this_dir(),
+            ShortFileName::parent_dir(),
+            ShortFileName::create_from_str("B").unwrap()
+        ]
+    );
+
+    // Go up from A to the root
+    let parent_of_a = volume_mgr
+        .open_parent_dir(parent_of_b)
+        .expect("open parent of A");
+    let entry = volume_mgr
+        .find_directory_entry(parent_of_a, "README.TXT")
+        .expect("find file in root");
+    assert_eq!(entry.size, 258);
+
+    // The root is its own parent
+    let parent_of_root = volume_mgr
+        .open_parent_dir(parent_of_a)
+        .expect("open parent of root");
+    assert!(volume_mgr
+        .find_directory_entry(parent_of_root, "A")
+        .expect("find A in root")
+        .attributes
+        .is_directory());
+
+    volume_mgr.close_dir(parent_of_root).expect("close dir");
+    volume_mgr.close_dir(parent_of_a).expect("close dir");
+    volume_mgr.close_dir(parent_of_b).expect("close dir");
+    volume_mgr.close_dir(dir_b).expect("close B");
+}
+
+#[test]
+fn fat16_open_parent_dir() {
+    open_parent_dir_check(0);
+}
+
+#[test]
+fn fat32_open_parent_dir() {
+    open_parent_dir_check(1);
+}
+
+#[test]
+fn open_dir_dot_dot() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    for volume_idx in [0, 1] {
+        let volume = volume_mgr
+            .open_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+            .expect("open volume");
+        let root_dir = volume.open_root_dir().expect("open root dir");
+        let test_dir = root_dir.open_dir("TEST").expect("open TEST");
+        assert!(test_dir.exists("TEST.DAT").unwrap());
+
+        // Back up to the root
+        let up = test_dir.open_dir("..").expect("open ..");
+        assert!(up.exists("README.TXT").unwrap());
+        assert!(up.exists("TEST").unwrap());
+        drop(up);
+
+        // The root is its own parent
+        let up = root_dir.open_dir("..").expect("open .. of root");
+        assert!(up.exists("README.TXT").unwrap());
+        let up = up.open_dir("..").expect("open .. of root again");
+        assert!(up.exists("TEST").unwrap());
+    }
+}
+
+#[test]
+fn open_dir_from_entry() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    let test_name = ShortFileName::create_from_str("TEST").unwrap();
+    let mut test_entry = None;
+    let mut file_entry = None;
+    volume_mgr
+        .iterate_dir(root_dir, |d| {
+            if d.name == test_name {
+                test_entry = Some(d.clone());
+            } else if !d.attributes.is_directory() && !d.attributes.is_volume() {
+                file_entry = Some(d.clone());
+            }
+        })
+        .expect("iterate directory");
+    let test_entry = test_entry.expect("find TEST");
+    let file_entry = file_entry.expect("find a file");
+
+    let test_dir = volume_mgr
+        .open_dir_from_entry(root_dir, &test_entry)
+        .expect("open TEST from entry");
+    let dir_entry = volume_mgr
+        .find_directory_entry(test_dir, "TEST.DAT")
+        .expect("find file in TEST");
+    assert_eq!(dir_entry.size, 3500);
+
+    assert!(matches!(
+        volume_mgr.open_dir_from_entry(root_dir, &file_entry),
+        Err(embedded_sdmmc::Error::OpenedFileAsDir)
+    ));
+
+    volume_mgr.close_dir(test_dir).expect("close test dir");
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+}
+
+#[test]
+fn entry_exists() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    assert!(volume_mgr.exists(root_dir, "README.TXT").unwrap());
+    assert!(volume_mgr.exists(root_dir, "TEST").unwrap());
+    assert!(!volume_mgr.exists(root_dir, "MISSING.TXT").unwrap());
+
+    assert_eq!(
+        volume_mgr.entry_kind(root_dir, "README.TXT").unwrap(),
+        Some(embedded_sdmmc::EntryKind::File)
+    );
+    assert_eq!(
+        volume_mgr.entry_kind(root_dir, "TEST").unwrap(),
+        Some(embedded_sdmmc::EntryKind::Directory)
+    );
+    assert_eq!(
+        volume_mgr.entry_kind(root_dir, "MISSING.TXT").unwrap(),
+        None
+    );
+
+    volume_mgr.device().set_fail_reads(true);
+    assert!(matches!(
+        volume_mgr.exists(root_dir, "README.TXT"),
+        Err(embedded_sdmmc::Error::DeviceError(utils::Error::Injected))
+    ));
+    assert!(matches!(
+        volume_mgr.entry_kind(root_dir, "MISSING.TXT"),
+        Err(embedded_sdmmc::Error::DeviceError(utils::Error::Injected))
+    ));
+    volume_mgr.device().set_fail_reads(false);
+
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+
+    // A closed directory is a bad handle, not a missing entry
+    assert!(matches!(
+        volume_mgr.exists(root_dir, "README.TXT"),
+        Err(embedded_sdmmc::Error::BadHandle)
+    ));
+}
+
+#[test]
+fn walk_dir() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: embedded_sdmmc::VolumeManager<_, _, 3, 4, 1> =
+        embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    // Add another level below TEST
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").expect("open TEST");
+    volume_mgr
+        .make_dir_in_dir(test_dir, "SUB")
+        .expect("make TEST/SUB");
+    let sub_dir = volume_mgr.open_dir(test_dir, "SUB").expect("open SUB");
+    let f = volume_mgr
+        .open_file_in_dir(sub_dir, "DEEP.TXT", Mode::ReadWriteCreate)
+        .expect("make DEEP.TXT");
+    volume_mgr.close_file(f).expect("close file");
+    volume_mgr.close_dir(sub_dir).expect("close SUB");
+    volume_mgr.close_dir(test_dir).expect("close TEST");
+
+    let walk = |max_depth, stop_at: Option<&str>, skip: Option<&str>| {
+        let mut found = Vec::new();
+        volume_mgr
+            .walk_dir(root_dir, max_depth, |path, entry| {
+                let mut name = String::new();
+                for component in path {
+                    name.push_str(&format!("{}/", component));
+                }
+                name.push_str(&entry.name.to_string());
+                let control = if Some(name.as_str()) == stop_at {
+                    embedded_sdmmc::WalkControl::Stop
+                } else if Some(name.as_str()) == skip {
+                    embedded_sdmmc::WalkControl::SkipDir
+                } else {
+                    embedded_sdmmc::WalkControl::Continue
+                };
+                found.push(name);
+                control
+            })
+            .map(|_| found)
+    };
+
+    assert_eq!(
+        walk(0, None, None).unwrap(),
+        ["README.TXT", "EMPTY.DAT", "TEST", "64MB.DAT"]
+    );
+    assert_eq!(
+        walk(1, None, None).unwrap(),
+        [
+            "README.TXT",
+            "EMPTY.DAT",
+            "TEST",
+            "TEST/TEST.DAT",
+            "TEST/SUB",
+            "64MB.DAT"
+        ]
+    );
+    assert_eq!(
+        walk(2, None, None).unwrap(),
+        [
+            "README.TXT",
+            "EMPTY.DAT",
+            "TEST",
+            "TEST/TEST.DAT",
+            "TEST/SUB",
+            "TEST/SUB/DEEP.TXT",
+            "64MB.DAT"
+        ]
+    );
+    assert_eq!(
+        walk(2, None, Some("TEST")).unwrap(),
+        ["README.TXT", "EMPTY.DAT", "TEST", "64MB.DAT"]
+    );
+    assert_eq!(
+        walk(2, Some("TEST/TEST.DAT"), None).unwrap(),
+        ["README.TXT", "EMPTY.DAT", "TEST", "TEST/TEST.DAT"]
+    );
+
+    // With only one spare handle, we can't get down to SUB
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").expect("open TEST");
+    assert!(matches!(
+        walk(2, None, None),
+        Err(embedded_sdmmc::Error::TooManyOpenDirs)
+    ));
+    // The walk closed the handle it opened
+    let sub_dir = volume_mgr.open_dir(test_dir, "SUB").expect("open SUB");
+
+    volume_mgr.close_dir(sub_dir).expect("close SUB");
+    volume_mgr.close_dir(test_dir).expect("close TEST");
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+}
+
+#[test]
+fn iterate_dir_glob() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    let glob = |dir, pattern| {
+        let mut names = Vec::new();
+        volume_mgr
+            .iterate_dir_glob(dir, pattern, |entry| names.push(entry.name.to_string()))
+            .expect("iterate dir");
+        names
+    };
+
+    assert_eq!(glob(root_dir, "*.DAT"), ["EMPTY.DAT", "64MB.DAT"]);
+    assert_eq!(glob(root_dir, "README.*"), ["README.TXT"]);
+    assert_eq!(glob(root_dir, "*"), ["TEST"]);
+    assert!(glob(root_dir, "*.BIN").is_empty());
+
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").expect("open test");
+    // `?` also matches the padding after a shorter name
+    assert_eq!(glob(test_dir, "TEST?.DAT"), ["TEST.DAT"]);
+    assert!(glob(test_dir, "TE?.DAT").is_empty());
+
+    assert!(matches!(
+        volume_mgr.iterate_dir_glob(root_dir, "*A.TXT", |_| {}),
+        Err(embedded_sdmmc::Error::FilenameError(_))
+    ));
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(root_dir, "*.TXT", embedded_sdmmc::Mode::ReadOnly),
+        Err(embedded_sdmmc::Error::FilenameError(
+            embedded_sdmmc::FilenameError::ContainsWildcard
+        ))
+    ));
+
+    volume_mgr.close_dir(test_dir).expect("close test dir");
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+}
+
+#[test]
+fn fat32_root_dir_spans_clusters() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat32_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(1))
+        .expect("open volume 1");
+    let root_dir = volume_mgr
+        .open_root_dir(fat32_volume)
+        .expect("open root dir");
+    let root_len = |volume_mgr: &embedded_sdmmc::VolumeManager<_, _>, volume| {
+        volume_mgr
+            .cluster_chain_len(volume, embedded_sdmmc::ClusterId::ROOT_DIR)
+            .expect("chain length")
+    };
+    assert_eq!(root_len(&volume_mgr, fat32_volume), 1);
+
+    let mut initial_entries = 0;
+    volume_mgr
+        .iterate_dir(root_dir, |_| initial_entries += 1)
+        .expect("iterate dir");
+
+    // Fill up the first cluster (128 entries of 32 bytes), so the last file
+    // we create goes in a newly allocated second cluster.
+    let mut created = 0;
+    let mut last_name = String::new();
+    while root_len(&volume_mgr, fat32_volume) == 1 {
+        assert!(created < 256, "root directory didn't grow");
+        last_name = format!("F{}.DAT", created);
+        let f = volume_mgr
+            .open_file_in_dir(
+                root_dir,
+                last_name.as_str(),
+                embedded_sdmmc::Mode::ReadWriteCreate,
+            )
+            .expect("create file");
+        volume_mgr.close_file(f).expect("close file");
+        created += 1;
+    }
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(fat32_volume).expect("close volume");
+
+    // Mount the disk again, and check we can see the entry in the second
+    // cluster.
+    let (disk, time_source) = volume_mgr.free();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let fat32_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(1))
+        .expect("open volume 1");
+    let root_dir = volume_mgr
+        .open_root_dir(fat32_volume)
+        .expect("open root dir");
+    assert_eq!(root_len(&volume_mgr, fat32_volume), 2);
+
+    let mut entries = 0;
+    let mut seen_last = false;
+    volume_mgr
+        .iterate_dir(root_dir, |entry| {
+            entries += 1;
+            seen_last |= entry.name.to_string() == last_name;
+        })
+        .expect("iterate dir");
+    assert_eq!(entries, initial_entries + created);
+    assert!(seen_last);
+
+    let first_entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("find first entry");
+    let last_entry = volume_mgr
+        .find_directory_entry(root_dir, last_name.as_str())
+        .expect("find last entry");
+    // 4 KiB clusters hold 8 blocks
+    assert!(last_entry.entry_block.0 >= first_entry.entry_block.0 + 8);
+
+    volume_mgr
+        .delete_file_in_dir(root_dir, last_name.as_str())
+        .expect("delete last entry");
+    assert!(matches!(
+        volume_mgr.find_directory_entry(root_dir, last_name.as_str()),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(fat32_volume).expect("close volume");
+}
+
+#[test]
+fn names_too_long_for_short_name() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    // Valid long file names that aren't there, so they're just not found
+    for name in [
+        "README.TEXT",
+        "My Notes.txt",
+        "my.config.json",
+        ".gitignore",
+    ] {
+        assert!(matches!(
+            volume_mgr.open_file_in_dir(root_dir, name, embedded_sdmmc::Mode::ReadOnly),
+            Err(embedded_sdmmc::Error::NotFound)
+        ));
+    }
+    assert!(matches!(
+        volume_mgr.open_dir(root_dir, "TEST-DIRECTORY"),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+    assert!(matches!(
+        volume_mgr.find_directory_entry(root_dir, "LONGFILENAME.TXT"),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+
+    // We can't make a short entry for it though
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(
+            root_dir,
+            "LONGFILENAME.TXT",
+            embedded_sdmmc::Mode::ReadWriteCreate
+        ),
+        Err(embedded_sdmmc::Error::FilenameError(
+            embedded_sdmmc::FilenameError::TooLongForShortName
+        ))
+    ));
+
+    // Names that can never exist are still rejected up front
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(root_dir, "LONG|NAME.TXT", embedded_sdmmc::Mode::ReadOnly),
+        Err(embedded_sdmmc::Error::FilenameError(
+            embedded_sdmmc::FilenameError::InvalidCharacter
+        ))
+    ));
+
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+}
+
+#[test]
+fn compact_dir() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    // The FAT16 root directory has a fixed size
+    assert_eq!(volume_mgr.compact_dir(root_dir).unwrap(), 0);
+
+    volume_mgr.make_dir_in_dir(root_dir, "LOGS").unwrap();
+    let logs_dir = volume_mgr.open_dir(root_dir, "LOGS").unwrap();
+    let free_before = volume_mgr.free_cluster_count(fat16_volume).unwrap();
+
+    // A 2 KiB cluster holds 64 entries, so with `.` and `..` this needs three
+    // clusters
+    for i in 0..150 {
+        let name = format!("LOG{}.TXT", i);
+        let f = volume_mgr
+            .open_file_in_dir(
+                logs_dir,
+                name.as_str(),
+                embedded_sdmmc::Mode::ReadWriteCreate,
+            )
+            .unwrap();
+        volume_mgr.close_file(f).unwrap();
+    }
+    assert_eq!(
+        volume_mgr.free_cluster_count(fat16_volume).unwrap(),
+        free_before - 2
+    );
+
+    // Keep one file in the second cluster, so only the third can go
+    for i in (0..150).filter(|i| *i != 70) {
+        let name = format!("LOG{}.TXT", i);
+        volume_mgr
+            .delete_file_in_dir(logs_dir, name.as_str())
+            .unwrap();
+    }
+    assert_eq!(volume_mgr.compact_dir(logs_dir).unwrap(), 1);
+    assert_eq!(
+        volume_mgr.free_cluster_count(fat16_volume).unwrap(),
+        free_before - 1
+    );
+
+    // Now the first cluster is enough
+    volume_mgr
+        .delete_file_in_dir(logs_dir, "LOG70.TXT")
+        .unwrap();
+    assert_eq!(volume_mgr.compact_dir(logs_dir).unwrap(), 1);
+    assert_eq!(volume_mgr.compact_dir(logs_dir).unwrap(), 0);
+    assert_eq!(
+        volume_mgr.free_cluster_count(fat16_volume).unwrap(),
+        free_before
+    );
+
+    // `.` and `..` survive, and the directory still works
+    let mut names = Vec::new();
+    volume_mgr
+        .iterate_dir(logs_dir, |entry| names.push(entry.name.to_string()))
+        .unwrap();
+    assert_eq!(names, [".", ".."]);
+    let parent = volume_mgr.open_dir(logs_dir, "..").unwrap();
+    volume_mgr.close_dir(parent).unwrap();
+    let f = volume_mgr
+        .open_file_in_dir(logs_dir, "NEW.TXT", embedded_sdmmc::Mode::ReadWriteCreate)
+        .unwrap();
+    volume_mgr.close_file(f).unwrap();
+
+    volume_mgr.close_dir(logs_dir).unwrap();
+    volume_mgr.close_dir(root_dir).unwrap();
+}
+
+#[test]
+fn unique_short_names() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat32_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(1))
+        .expect("open volume 1");
+    let root_dir = fat32_volume.open_root_dir().expect("open root dir");
+
+    // Not taken, so unchanged
+    let name = root_dir.generate_unique_short_name("REPORT.TXT").unwrap();
+    assert_eq!(name.to_string(), "REPORT.TXT");
+
+    let mut names = Vec::new();
+    for _ in 0..12 {
+        let name = root_dir.generate_unique_short_name("REPORT.TXT").unwrap();
+        root_dir
+            .open_file_in_dir(&name, Mode::ReadWriteCreate)
+            .expect("create file");
+        names.push(name.to_string());
+    }
+    assert_eq!(
+        names,
+        [
+            "REPORT.TXT",
+            "REPORT~1.TXT",
+            "REPORT~2.TXT",
+            "REPORT~3.TXT",
+            "REPORT~4.TXT",
+            "REPORT~5.TXT",
+            "REPORT~6.TXT",
+            "REPORT~7.TXT",
+            "REPORT~8.TXT",
+            "REPORT~9.TXT",
+            "REPOR~10.TXT",
+            "REPOR~11.TXT",
+        ]
+    );
+
+    // The lowest free tail is re-used
+    root_dir.delete_file_in_dir("REPORT~3.TXT").unwrap();
+    let name = root_dir.generate_unique_short_name("REPORT.TXT").unwrap();
+    assert_eq!(name.to_string(), "REPORT~3.TXT");
+
+    // A different extension doesn't collide
+    let name = root_dir.generate_unique_short_name("REPORT.CSV").unwrap();
+    assert_eq!(name.to_string(), "REPORT.CSV");
+}
+
+#[test]
+fn rename_dir() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = fat16_volume.open_root_dir().expect("open root dir");
+
+    root_dir.make_dir_in_dir("OLD").unwrap();
+    {
+        fat16_volume
+            .write_file_from("OLD/INNER.TXT", b"still here", Mode::ReadWriteCreate)
+            .unwrap();
+        let _old_dir = root_dir.open_dir("OLD").unwrap();
+
+        // Can't rename an open directory
+        assert!(matches!(
+            root_dir.rename_dir("OLD", "NEW"),
+            Err(embedded_sdmmc::Error::DirAlreadyOpen)
+        ));
+    }
+
+    root_dir.rename_dir("OLD", "NEW").unwrap();
+    assert!(!root_dir.exists("OLD").unwrap());
+    assert_eq!(
+        root_dir.entry_kind("NEW").unwrap(),
+        Some(embedded_sdmmc::EntryKind::Directory)
+    );
+
+    let mut buffer = [0u8; 16];
+    let len = fat16_volume
+        .read_file_to("NEW/INNER.TXT", &mut buffer)
+        .unwrap();
+    assert_eq!(&buffer[..len], b"still here");
+    let new_dir = root_dir.open_dir("NEW").unwrap();
+    let parent = new_dir.open_parent_dir().unwrap();
+    assert!(parent.exists("NEW").unwrap());
+    drop(parent);
+    drop(new_dir);
+
+    assert!(matches!(
+        root_dir.rename_dir("README.TXT", "README"),
+        Err(embedded_sdmmc::Error::OpenedFileAsDir)
+    ));
+    assert!(matches!(
+        root_dir.rename_dir("NEW", "TEST"),
+        Err(embedded_sdmmc::Error::DirAlreadyExists)
+    ));
+    assert!(matches!(
+        root_dir.rename_dir("NEW", "README.TXT"),
+        Err(embedded_sdmmc::Error::FileAlreadyExists)
+    ));
+    assert!(matches!(
+        root_dir.rename_dir("MISSING", "OTHER"),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    assert!(matches!(
+        test_dir.rename_dir("..", "UP"),
+        Err(embedded_sdmmc::Error::Unsupported)
+    ));
+}
+
+#[test]
+fn move_file() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = fat16_volume.open_root_dir().expect("open root dir");
+    let test_dir = root_dir.open_dir("TEST").expect("open test dir");
+
+    let mut original = [0u8; 512];
+    let original_len = fat16_volume
+        .read_file_to("README.TXT", &mut original)
+        .unwrap();
+    let original_entry = root_dir.find_directory_entry("README.TXT").unwrap();
+
+    {
+        let _file = root_dir
+            .open_file_in_dir("README.TXT", Mode::ReadOnly)
+            .unwrap();
+        // Can't move an open file
+        assert!(matches!(
+            root_dir.move_file("README.TXT", &test_dir, "MOVED.TXT"),
+            Err(embedded_sdmmc::Error::FileAlreadyOpen)
+        ));
+    }
+    // Can't move onto a name that's taken
+    assert!(matches!(
+        root_dir.move_file("README.TXT", &test_dir, "TEST.DAT"),
+        Err(embedded_sdmmc::Error::FileAlreadyExists)
+    ));
+    assert!(matches!(
+        root_dir.move_file("TEST", &test_dir, "MOVED"),
+        Err(embedded_sdmmc::Error::OpenedDirAsFile)
+    ));
+
+    root_dir
+        .move_file("README.TXT", &test_dir, "MOVED.TXT")
+        .unwrap();
+    assert!(!root_dir.exists("README.TXT").unwrap());
+    let moved_entry = test_dir.find_directory_entry("MOVED.TXT").unwrap();
+    assert_eq!(moved_entry.cluster, original_entry.cluster);
+    assert_eq!(moved_entry.size, original_entry.size);
+    assert_eq!(moved_entry.mtime, original_entry.mtime);
+    assert_eq!(moved_entry.ctime, original_entry.ctime);
+
+    let mut buffer = [0u8; 512];
+    let len = fat16_volume
+        .read_file_to("TEST/MOVED.TXT", &mut buffer)
+        .unwrap();
+    assert_eq!(&buffer[..len], &original[..original_len]);
+}
+
+#[test]
+fn dir_scan_limit() {
+    use embedded_sdmmc::{Error, VolumeIdx, VolumeOpts};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    // Make a directory spanning two blocks: `.`, `..` and 20 files
+    {
+        let volume = volume_mgr.open_volume(VolumeIdx(0)).unwrap();
+        let root_dir = volume.open_root_dir().unwrap();
+        root_dir.make_dir_in_dir("BIG").unwrap();
+        let big_dir = root_dir.open_dir("BIG").unwrap();
+        for i in 0..20 {
+            let name = format!("FILE{:02}.DAT", i);
+            big_dir
+                .open_file_in_dir(name.as_str(), Mode::ReadWriteCreate)
+                .unwrap();
+        }
+    }
+
+    let options = VolumeOpts {
+        max_dir_scan_blocks: Some(1),
+        ..Default::default()
+    };
+    let volume = volume_mgr
+        .open_volume_with_options(VolumeIdx(0), options)
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let big_dir = root_dir.open_dir("BIG").unwrap();
+
+    // Entries in the first block can still be found
+    big_dir.find_directory_entry("FILE00.DAT").unwrap();
+    // But anything needing the second block hits the limit
+    assert!(matches!(
+        big_dir.find_directory_entry("FILE19.DAT"),
+        Err(Error::DirectoryScanLimitExceeded)
+    ));
+    assert!(matches!(
+        big_dir.find_directory_entry("MISSING.DAT"),
+        Err(Error::DirectoryScanLimitExceeded)
+    ));
+    let mut count = 0;
+    assert!(matches!(
+        big_dir.iterate_dir(|_| count += 1),
+        Err(Error::DirectoryScanLimitExceeded)
+    ));
+    assert_eq!(count, 16);
+
+    // Small directories are unaffected
+    assert!(root_dir.exists("README.TXT").unwrap());
+}
+
+#[test]
+fn collect_entries() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = fat16_volume.open_root_dir().expect("open root dir");
+    let test_dir = root_dir.open_dir("TEST").expect("open test dir");
+
+    let mut entries: [Option<embedded_sdmmc::DirEntry>; 8] = Default::default();
+    assert_eq!(test_dir.collect_entries(&mut entries).unwrap(), 3);
+    let names: Vec<String> = entries
+        .iter()
+        .flatten()
+        .map(|e| e.name.to_string())
+        .collect();
+    assert_eq!(names, [".", "..", "TEST.DAT"]);
+    assert_eq!(entries[2].as_ref().unwrap().size, 3500);
+    assert!(entries[3..].iter().all(|e| e.is_none()));
+
+    // We can use the handles straight away, unlike in a callback
+    for entry in entries
+        .iter()
+        .flatten()
+        .filter(|e| !e.attributes.is_directory())
+    {
+        let f = test_dir
+            .open_file_in_dir(&entry.name, Mode::ReadOnly)
+            .unwrap();
+        assert_eq!(f.length(), entry.size);
+    }
+
+    // Stops when the slice is full
+    let mut entries: [Option<embedded_sdmmc::DirEntry>; 2] = Default::default();
+    assert_eq!(root_dir.collect_entries(&mut entries).unwrap(), 2);
+    assert!(entries.iter().all(|e| e.is_some()));
+    assert_eq!(root_dir.collect_entries(&mut []).unwrap(), 0);
+}
+
+#[test]
+fn find_first_and_next() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let fat16_volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr
+        .open_root_dir(fat16_volume)
+        .expect("open root dir");
+
+    let (mut scan, first) = volume_mgr.find_first(root_dir, "*.DAT").expect("find");
+    assert_eq!(first.expect("first match").name.to_string(), "EMPTY.DAT");
+
+    // We can use the Volume Manager in between
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", embedded_sdmmc::Mode::ReadOnly)
+        .expect("open file");
+    let mut buffer = [0u8; 16];
+    volume_mgr.read(f, &mut buffer).expect("read file");
+    volume_mgr.close_file(f).expect("close file");
+
+    let next = volume_mgr.find_next(&mut scan).expect("find next");
+    assert_eq!(next.expect("second match").name.to_string(), "64MB.DAT");
+    assert!(volume_mgr
+        .find_next(&mut scan)
+        .expect("find next")
+        .is_none());
+    // Once we've run out, we stay run out
+    assert!(volume_mgr
+        .find_next(&mut scan)
+        .expect("find next")
+        .is_none());
+
+    let (mut scan, first) = volume_mgr.find_first(root_dir, "*.BIN").expect("find");
+    assert!(first.is_none());
+    assert!(volume_mgr
+        .find_next(&mut scan)
+        .expect("find next")
+        .is_none());
+
+    assert!(matches!(
+        volume_mgr.find_first(root_dir, "*A.TXT"),
+        Err(embedded_sdmmc::Error::FilenameError(_))
+    ));
+
+    volume_mgr.close_dir(root_dir).expect("close root dir");
+}
+
+#[test]
+fn dir_metadata() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    for volume_idx in [0, 1] {
+        let volume = volume_mgr
+            .open_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+            .expect("open volume");
+        let root_dir = volume.open_root_dir().expect("open root dir");
+        root_dir.make_dir_in_dir("NEWDIR").unwrap();
+        let entry = root_dir.find_directory_entry("NEWDIR").unwrap();
+
+        // The "." entry has the directory's own details
+        let new_dir = root_dir.open_dir("NEWDIR").unwrap();
+        let meta = new_dir.metadata().expect("metadata");
+        assert_eq!(meta.name, ShortFileName::this_dir());
+        assert!(meta.attributes.is_directory());
+        assert_eq!(meta.cluster, entry.cluster);
+        assert_eq!(meta.size, 0);
+        assert_eq!(meta.ctime.to_string(), utils::get_time_source_string());
+        assert_eq!(meta.mtime.to_string(), utils::get_time_source_string());
+        drop(new_dir);
+
+        // The root directory gets a made-up entry
+        let meta = root_dir.metadata().expect("root metadata");
+        assert!(meta.attributes.is_directory());
+        assert_eq!(meta.cluster, embedded_sdmmc::ClusterId::ROOT_DIR);
+        assert_eq!(meta.ctime.to_string(), "1980-01-01 00:00:00");
+    }
+}
+
+#[test]
+fn code_page_names() {
+    use embedded_sdmmc::{CodePage, Error, FilenameError, Mode, VolumeIdx, VolumeOpts};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let options = VolumeOpts {
+        code_page: CodePage::Cp850,
+        ..Default::default()
+    };
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), options)
+        .expect("open volume 0");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // Code page 850 has o with stroke, so we can make a file called that
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "\u{00F8}.dat", Mode::ReadWriteCreate)
+        .expect("create file");
+    volume_mgr.write(f, b"hello").expect("write");
+    volume_mgr.close_file(f).expect("close file");
+
+    // It's listed by that name
+    let mut names = Vec::new();
+    volume_mgr
+        .iterate_dir(root_dir, |entry| {
+            names.push(format!("{}", entry.name.display(CodePage::Cp850)))
+        })
+        .expect("iterate dir");
+    assert!(names.iter().any(|n| n == "\u{00F8}.dat"));
+
+    // And it opens by that name, in either case
+    for name in ["\u{00F8}.dat", "\u{00D8}.DAT"] {
+        let f = volume_mgr
+            .open_file_in_dir(root_dir, name, Mode::ReadOnly)
+            .expect("open file");
+        assert_eq!(volume_mgr.file_length(f).unwrap(), 5);
+        volume_mgr.close_file(f).expect("close file");
+    }
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Code page 437 has no such letter, so only a long file name could have
+    // it, and we can't create one
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(root_dir, "\u{00F8}.dat", Mode::ReadOnly),
+        Err(Error::NotFound)
+    ));
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(root_dir, "\u{00F8}.dat", Mode::ReadWriteCreate),
+        Err(Error::FilenameError(FilenameError::TooLongForShortName))
+    ));
+}
+
+#[test]
+fn collect_entries_stops_when_full() {
+    use embedded_sdmmc::InstrumentedBlockDevice;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    volume_mgr
+        .make_dir_in_dir(root_dir, "MANY")
+        .expect("make MANY");
+    let many_dir = volume_mgr.open_dir(root_dir, "MANY").expect("open MANY");
+    // With "." and "..", that's more than one block of entries
+    for i in 0..20 {
+        let file = volume_mgr
+            .open_file_in_dir(
+                many_dir,
+                format!("F{}.TXT", i).as_str(),
+                Mode::ReadWriteCreate,
+            )
+            .expect("create file");
+        volume_mgr.close_file(file).expect("close file");
+    }
+
+    let mut entries: [Option<embedded_sdmmc::DirEntry>; 32] = Default::default();
+    volume_mgr.device().reset_stats();
+    assert_eq!(
+        volume_mgr.collect_entries(many_dir, &mut entries).unwrap(),
+        22
+    );
+    assert_eq!(volume_mgr.device().stats().blocks_read, 2);
+
+    // The first block is enough to fill a short slice
+    volume_mgr.device().reset_stats();
+    assert_eq!(
+        volume_mgr
+            .collect_entries(many_dir, &mut entries[..4])
+            .unwrap(),
+        4
+    );
+    assert_eq!(volume_mgr.device().stats().blocks_read, 1);
+}
+
+#[test]
+fn find_next_resumes() {
+    use embedded_sdmmc::InstrumentedBlockDevice;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    volume_mgr
+        .make_dir_in_dir(root_dir, "MANY")
+        .expect("make MANY");
+    let many_dir = volume_mgr.open_dir(root_dir, "MANY").expect("open MANY");
+    // A cluster on this volume holds 64 entries, so with "." and "..", this
+    // takes two clusters
+    for i in 0..70 {
+        let file = volume_mgr
+            .open_file_in_dir(
+                many_dir,
+                format!("F{}.TXT", i).as_str(),
+                Mode::ReadWriteCreate,
+            )
+            .expect("create file");
+        volume_mgr.close_file(file).expect("close file");
+    }
+
+    let (mut scan, first) = volume_mgr.find_first(many_dir, "*.TXT").expect("find");
+    let mut names = vec![first.expect("first match").name.to_string()];
+    loop {
+        volume_mgr.device().reset_stats();
+        let Some(entry) = volume_mgr.find_next(&mut scan).expect("find next") else {
+            break;
+        };
+        names.push(entry.name.to_string());
+        // We carry on from the last match, rather than reading the directory
+        // from the start. Moving into the next cluster means reading the FAT
+        // too.
+        assert!(volume_mgr.device().stats().blocks_read <= 3);
+    }
+    let expected: Vec<String> = (0..70).map(|i| format!("F{}.TXT", i)).collect();
+    assert_eq!(names, expected);
+}
 // ****************************************************************************
 //
 // End Of File
@@ -0,0 +1,118 @@
+//! Error type related tests
+
+use embedded_sdmmc::{
+    Block, BlockCount, BlockDevice, BlockIdx, Error, FilenameError, NullTimeSource, SdCardError,
+    VolumeIdx, VolumeManager,
+};
+
+#[test]
+fn display_errors() {
+    let e: Error<SdCardError> = Error::DeviceError(SdCardError::TimeoutCommand(17));
+    assert_eq!(e.to_string(), "block device error: TimeoutCommand(17)");
+    let e: Error<SdCardError> = Error::FilenameError(FilenameError::InvalidCharacter);
+    assert_eq!(e.to_string(), "bad filename: invalid character");
+    assert_eq!(
+        SdCardError::TimeoutACommand(41).to_string(),
+        "timed out on ACMD41 (SD_SEND_OP_COND)"
+    );
+    assert_eq!(
+        SdCardError::TimeoutCommand(17).to_string(),
+        "timed out on CMD17 (READ_SINGLE_BLOCK)"
+    );
+    assert_eq!(
+        SdCardError::TimeoutCommand(99).to_string(),
+        "timed out on CMD99"
+    );
+}
+
+#[cfg(feature = "core-error")]
+#[test]
+fn walk_source_chain() {
+    let e: Error<SdCardError> = Error::DeviceError(SdCardError::WriteError);
+    let e: &dyn core::error::Error = &e;
+    let mut chain = Vec::new();
+    let mut next = Some(e);
+    while let Some(e) = next {
+        chain.push(e.to_string());
+        next = e.source();
+    }
+    assert_eq!(chain, ["block device error: WriteError", "write error"]);
+
+    let e: Error<SdCardError> = Error::NotFound;
+    let e: &dyn core::error::Error = &e;
+    assert!(e.source().is_none());
+}
+
+/// A block device which fails every read and write.
+struct BrokenDisk;
+
+#[derive(Debug, PartialEq, Eq)]
+struct BrokenDiskError;
+
+impl BlockDevice for BrokenDisk {
+    type Error = BrokenDiskError;
+
+    fn read(&self, _blocks: &mut [Block], _start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        Err(BrokenDiskError)
+    }
+
+    fn write(&self, _blocks: &[Block], _start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        Err(BrokenDiskError)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        Ok(BlockCount(1))
+    }
+}
+
+/// Mixes calls to the block device and to the volume manager, using `?`
+/// for both.
+fn first_byte_of_volume<D>(
+    volume_mgr: &VolumeManager<D, NullTimeSource>,
+) -> Result<u8, Error<D::Error>>
+where
+    D: BlockDevice,
+{
+    let mut blocks = [Block::new()];
+    volume_mgr.device().read(&mut blocks, BlockIdx(0))?;
+    let _volume = volume_mgr.open_raw_volume(VolumeIdx(0))?;
+    Ok(blocks[0][0])
+}
+
+/// Converts the device error by hand, inside a closure.
+fn count_readable_blocks<D>(block_device: &D, count: u32) -> Result<u32, Error<D::Error>>
+where
+    D: BlockDevice,
+{
+    (0..count).try_fold(0, |total, idx| {
+        let mut blocks = [Block::new()];
+        block_device
+            .read(&mut blocks, BlockIdx(idx))
+            .map_err(Error::from_device)?;
+        Ok(total + 1)
+    })
+}
+
+#[test]
+fn propagate_device_errors() {
+    let volume_mgr = VolumeManager::new(BrokenDisk, NullTimeSource);
+    assert!(matches!(
+        first_byte_of_volume(&volume_mgr),
+        Err(Error::DeviceError(BrokenDiskError))
+    ));
+    assert!(matches!(
+        count_readable_blocks(&BrokenDisk, 4),
+        Err(Error::DeviceError(BrokenDiskError))
+    ));
+    assert!(matches!(count_readable_blocks(&BrokenDisk, 0), Ok(0)));
+    assert!(matches!(
+        Error::from_device(BrokenDiskError),
+        Error::DeviceError(BrokenDiskError)
+    ));
+}
+
+// ****************************************************************************
+//
+// End Of File
+//
+// ****************************************************************************
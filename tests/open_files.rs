@@ -138,6 +138,185 @@ fn open_non_raw() {
     };
 }
 
+#[test]
+fn file_info() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadOnly)
+        .expect("open file");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("find entry");
+
+    let info = volume_mgr.file_info(f).expect("get info");
+    assert_eq!(info.size, 258);
+    assert_eq!(info.mode, Mode::ReadOnly);
+    assert_eq!(info.mtime.to_string(), "2018-12-09 19:22:34");
+    assert_eq!(info.ctime.to_string(), "2018-12-09 19:22:34");
+    assert!(!info.attributes.is_directory());
+    assert_eq!(info.first_cluster, entry.cluster);
+
+    volume_mgr.close_file(f).expect("close file");
+    assert!(matches!(volume_mgr.file_info(f), Err(Error::BadHandle)));
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn close_all_handles() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 2, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").expect("open dir");
+    let f1 = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.TXT", Mode::ReadWriteCreate)
+        .expect("open file");
+    volume_mgr.write(f1, b"Hello").expect("write");
+    let _f2 = volume_mgr
+        .open_file_in_dir(test_dir, "TEST.DAT", Mode::ReadOnly)
+        .expect("open file");
+
+    // Every table is full
+    assert!(matches!(
+        volume_mgr.open_raw_volume(VolumeIdx(1)),
+        Err(Error::TooManyOpenVolumes)
+    ));
+
+    volume_mgr.close_all_handles().expect("close all");
+
+    // The old handles are gone
+    assert!(matches!(volume_mgr.close_file(f1), Err(Error::BadHandle)));
+    assert!(matches!(
+        volume_mgr.close_dir(root_dir),
+        Err(Error::BadHandle)
+    ));
+
+    // We can open everything again straight away, and the write was flushed
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").expect("open dir");
+    let f1 = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.TXT", Mode::ReadOnly)
+        .expect("open file");
+    assert_eq!(volume_mgr.file_length(f1).unwrap(), 5);
+    let f2 = volume_mgr
+        .open_file_in_dir(test_dir, "TEST.DAT", Mode::ReadOnly)
+        .expect("open file");
+    volume_mgr.close_file(f1).unwrap();
+    volume_mgr.close_file(f2).unwrap();
+    volume_mgr.close_dir(test_dir).unwrap();
+    volume_mgr.close_dir(root_dir).unwrap();
+    volume_mgr.close_volume(volume).unwrap();
+}
+
+#[test]
+fn open_twice_any_mode() {
+    const MODES: [Mode; 6] = [
+        Mode::ReadOnly,
+        Mode::ReadWriteAppend,
+        Mode::ReadWriteTruncate,
+        Mode::ReadWriteCreate,
+        Mode::ReadWriteCreateOrTruncate,
+        Mode::ReadWriteCreateOrAppend,
+    ];
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    for first_mode in MODES {
+        // You can't create a file that already exists
+        if first_mode == Mode::ReadWriteCreate {
+            continue;
+        }
+        let f = volume_mgr
+            .open_file_in_dir(root_dir, "README.TXT", first_mode)
+            .expect("open file");
+        for second_mode in MODES {
+            assert!(
+                matches!(
+                    volume_mgr.open_file_in_dir(root_dir, "README.TXT", second_mode),
+                    Err(Error::FileAlreadyOpen)
+                ),
+                "{:?} then {:?}",
+                first_mode,
+                second_mode
+            );
+        }
+        volume_mgr.close_file(f).expect("close file");
+    }
+}
+
+#[test]
+fn find_leaked_handles() {
+    use embedded_sdmmc::ShortFileName;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    assert!(!volume_mgr.has_open_handles());
+    assert_eq!(volume_mgr.open_handle_count().unwrap(), (0, 0, 0));
+
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    assert!(volume_mgr.has_open_handles());
+    let readme = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadOnly)
+        .expect("open file");
+    let empty = volume_mgr
+        .open_file_in_dir(root_dir, "EMPTY.DAT", Mode::ReadOnly)
+        .expect("open file");
+
+    // We've hit the limit, so which files are still open?
+    assert!(matches!(
+        volume_mgr.open_file_in_dir(root_dir, "64MB.DAT", Mode::ReadOnly),
+        Err(Error::TooManyOpenFiles)
+    ));
+    assert_eq!(volume_mgr.open_handle_count().unwrap(), (2, 1, 1));
+    let open: Vec<_> = volume_mgr.open_files_iter().unwrap().collect();
+    assert_eq!(
+        open,
+        [
+            (
+                readme,
+                ShortFileName::create_from_str("README.TXT").unwrap()
+            ),
+            (empty, ShortFileName::create_from_str("EMPTY.DAT").unwrap()),
+        ]
+    );
+
+    // We can close files while going through the list
+    for (file, _name) in volume_mgr.open_files_iter().unwrap() {
+        volume_mgr.close_file(file).expect("close file");
+    }
+    assert_eq!(volume_mgr.open_handle_count().unwrap(), (0, 1, 1));
+    assert_eq!(volume_mgr.open_files_iter().unwrap().count(), 0);
+}
+
 // ****************************************************************************
 //
 // End Of File
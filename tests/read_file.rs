@@ -205,6 +205,560 @@ fn read_file_with_odd_seek() {
     }
 }
 
+#[test]
+fn read_file_bytes() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    let contents: Vec<u8> = f.bytes().collect::<Result<_, _>>().expect("read bytes");
+    assert_eq!(contents.len(), 3500);
+    assert!(f.is_eof());
+    // Nothing left
+    assert!(f.bytes().next().is_none());
+
+    // Dropping the iterator part way through puts back what it read ahead
+    f.seek_from_start(0).unwrap();
+    let start: Vec<u8> = f
+        .bytes()
+        .take(10)
+        .collect::<Result<_, _>>()
+        .expect("read bytes");
+    assert_eq!(start, contents[..10]);
+    assert_eq!(f.offset(), 10);
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(contents);
+    let hash = hasher.finalize();
+    assert_eq!(&hash[..], TEST_DAT_SHA256_SUM);
+}
+
+#[test]
+fn read_file_chunks() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    let mut contents = Vec::new();
+    let mut num_chunks = 0;
+    for chunk in f.chunks::<16>() {
+        let chunk = chunk.expect("read chunk");
+        num_chunks += 1;
+        if contents.len() + 16 <= 3500 {
+            assert_eq!(chunk.len(), 16);
+        } else {
+            // 3500 = (218 * 16) + 12
+            assert_eq!(chunk.len(), 12);
+        }
+        contents.extend_from_slice(&chunk);
+    }
+    assert_eq!(num_chunks, 219);
+    assert!(f.is_eof());
+    assert!(f.chunks::<16>().next().is_none());
+
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(contents);
+    let hash = hasher.finalize();
+    assert_eq!(&hash[..], TEST_DAT_SHA256_SUM);
+}
+
+#[test]
+fn read_file_at() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    let mut contents = vec![0u8; 3500];
+    assert_eq!(f.read(&mut contents).expect("read file"), 3500);
+    f.seek_from_start(1000).expect("seek");
+
+    // Two readers interleaving, one past a cluster boundary and one before
+    // the current position
+    let mut high = [0u8; 700];
+    let mut low = [0u8; 700];
+    for i in 0..3 {
+        let high_offset = 2000 + i * 300;
+        let low_offset = 10 + i * 300;
+        assert_eq!(f.read_at(high_offset, &mut high).expect("read_at"), 700);
+        assert_eq!(f.read_at(low_offset, &mut low).expect("read_at"), 700);
+        assert_eq!(
+            &high[..],
+            &contents[high_offset as usize..high_offset as usize + 700]
+        );
+        assert_eq!(
+            &low[..],
+            &contents[low_offset as usize..low_offset as usize + 700]
+        );
+        assert_eq!(f.offset(), 1000);
+    }
+
+    // Short read at the end, and nothing past it
+    assert_eq!(f.read_at(3400, &mut high).expect("read_at"), 100);
+    assert_eq!(&high[..100], &contents[3400..]);
+    assert_eq!(f.read_at(3500, &mut high).expect("read_at"), 0);
+    assert_eq!(f.read_at(5000, &mut high).expect("read_at"), 0);
+
+    // The file position hasn't moved
+    let mut buffer = [0u8; 10];
+    assert_eq!(f.read(&mut buffer).expect("read"), 10);
+    assert_eq!(&buffer[..], &contents[1000..1010]);
+}
+
+#[test]
+fn read_file_by_path() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+
+    let mut buffer = [0u8; 4096];
+    let len = volume_mgr
+        .read_file_to(volume, "/TEST/TEST.DAT", &mut buffer)
+        .expect("read file");
+    assert_eq!(len, 3500);
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(&buffer[..len]);
+    let hash = hasher.finalize();
+    assert_eq!(&hash[..], TEST_DAT_SHA256_SUM);
+
+    // The leading slash is optional, and a short buffer gets the start
+    let mut short = [0u8; 100];
+    let len = volume_mgr
+        .read_file_to(volume, "test/test.dat", &mut short)
+        .expect("read file");
+    assert_eq!(len, 100);
+    assert_eq!(&short[..], &buffer[..100]);
+
+    let len = volume_mgr
+        .read_file_to(volume, "README.TXT", &mut buffer)
+        .expect("read file");
+    assert_eq!(len, 258);
+
+    assert!(matches!(
+        volume_mgr.read_file_to(volume, "/MISSING/TEST.DAT", &mut buffer),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+    assert!(matches!(
+        volume_mgr.read_file_to(volume, "/TEST/MISSING.DAT", &mut buffer),
+        Err(embedded_sdmmc::Error::NotFound)
+    ));
+    assert!(matches!(
+        volume_mgr.read_file_to(volume, "/TEST", &mut buffer),
+        Err(embedded_sdmmc::Error::OpenedDirAsFile)
+    ));
+    assert!(matches!(
+        volume_mgr.read_file_to(volume, "/TEST/", &mut buffer),
+        Err(embedded_sdmmc::Error::FilenameError(_))
+    ));
+
+    // Every handle was closed again, even on error
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+/// Mark the first cluster of a file as free in the first FAT of the volume
+/// starting at `lba_start`, as if the filesystem were corrupt.
+///
+/// The file's cluster chain then runs into free space after one cluster.
+fn break_cluster_chain<D>(disk: &D, lba_start: u32, entry: &embedded_sdmmc::DirEntry, fat32: bool)
+where
+    D: embedded_sdmmc::BlockDevice,
+    D::Error: core::fmt::Debug,
+{
+    use embedded_sdmmc::{Block, BlockIdx};
+    let mut blocks = [Block::new()];
+    // Find the first cluster in the directory entry
+    disk.read(&mut blocks, entry.entry_block).unwrap();
+    let offset = entry.entry_offset as usize;
+    let cluster_hi = u16::from_le_bytes([blocks[0][offset + 20], blocks[0][offset + 21]]);
+    let cluster_lo = u16::from_le_bytes([blocks[0][offset + 26], blocks[0][offset + 27]]);
+    let cluster = (u32::from(cluster_hi) << 16) | u32::from(cluster_lo);
+    // The FAT comes straight after the reserved blocks
+    disk.read(&mut blocks, BlockIdx(lba_start)).unwrap();
+    let reserved_blocks = u16::from_le_bytes([blocks[0][14], blocks[0][15]]);
+    let entry_len = if fat32 { 4 } else { 2 };
+    let fat_offset = cluster * entry_len;
+    let fat_block = BlockIdx(lba_start + u32::from(reserved_blocks) + fat_offset / 512);
+    let fat_offset = (fat_offset % 512) as usize;
+    disk.read(&mut blocks, fat_block).unwrap();
+    blocks[0][fat_offset..fat_offset + entry_len as usize].fill(0);
+    disk.write(&blocks, fat_block).unwrap();
+}
+
+#[test]
+fn read_broken_cluster_chain() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    // TEST.DAT is 3500 bytes, in two 2048 byte clusters, on the FAT16 volume.
+    // 64MB.DAT has lots of 4096 byte clusters on the FAT32 volume.
+    for (volume_idx, lba_start, path, fat32, cluster_len) in [
+        (0, 2048, "TEST/TEST.DAT", false, 2048),
+        (1, 264192, "64MB.DAT", true, 4096),
+    ] {
+        let volume = volume_mgr
+            .open_raw_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+            .unwrap();
+        let root_dir = volume_mgr.open_root_dir(volume).unwrap();
+        let (dir, name) = match path.split_once('/') {
+            Some((dir, name)) => (volume_mgr.open_dir(root_dir, dir).unwrap(), name),
+            None => (root_dir, path),
+        };
+        let entry = volume_mgr.find_directory_entry(dir, name).unwrap();
+        break_cluster_chain(volume_mgr.device(), lba_start, &entry, fat32);
+
+        let f = volume_mgr
+            .open_file_in_dir(dir, name, embedded_sdmmc::Mode::ReadOnly)
+            .unwrap();
+        let mut buffer = [0u8; 8192];
+        // We get the first cluster, and then stop
+        assert_eq!(volume_mgr.read(f, &mut buffer).unwrap(), cluster_len);
+        assert_eq!(volume_mgr.read_at(f, 0, &mut buffer).unwrap(), cluster_len);
+        // Now there's nothing more to read, but we aren't at the end
+        assert!(!volume_mgr.file_eof(f).unwrap());
+        assert!(matches!(
+            volume_mgr.read(f, &mut buffer),
+            Err(embedded_sdmmc::Error::UnterminatedFatChain)
+        ));
+        volume_mgr.close_file(f).unwrap();
+
+        if dir != root_dir {
+            volume_mgr.close_dir(dir).unwrap();
+        }
+        volume_mgr.close_dir(root_dir).unwrap();
+        volume_mgr.close_volume(volume).unwrap();
+    }
+}
+
+#[test]
+fn seek_relative_to_end() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+    assert_eq!(f.length(), 3500);
+
+    // Seek to the end
+    f.seek_relative_to_end(0).unwrap();
+    assert_eq!(f.offset(), 3500);
+    assert!(f.is_eof());
+
+    // Seek to 10 bytes before the end
+    f.seek_relative_to_end(-10).unwrap();
+    assert_eq!(f.offset(), 3490);
+    let mut buffer = [0u8; 16];
+    assert_eq!(f.read(&mut buffer).unwrap(), 10);
+
+    // Seek to the start
+    f.seek_relative_to_end(-3500).unwrap();
+    assert_eq!(f.offset(), 0);
+
+    // Past the end, or before the start, is refused and doesn't move us
+    f.seek_from_start(100).unwrap();
+    assert!(matches!(
+        f.seek_relative_to_end(10),
+        Err(embedded_sdmmc::Error::InvalidOffset)
+    ));
+    assert!(matches!(
+        f.seek_relative_to_end(-3501),
+        Err(embedded_sdmmc::Error::InvalidOffset)
+    ));
+    assert!(matches!(
+        f.seek_relative_to_end(i64::MIN),
+        Err(embedded_sdmmc::Error::InvalidOffset)
+    ));
+    assert_eq!(f.offset(), 100);
+}
+
+#[test]
+fn read_ready() {
+    use embedded_io::{ReadReady, WriteReady};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let mut f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    assert!(f.read_ready().unwrap());
+    assert!(!f.write_ready().unwrap());
+    f.seek_relative_to_end(-1).unwrap();
+    assert!(f.read_ready().unwrap());
+    let mut buffer = [0u8; 4];
+    assert_eq!(f.read(&mut buffer).unwrap(), 1);
+    assert!(!f.read_ready().unwrap());
+    drop(f);
+
+    let mut f = root_dir
+        .open_file_in_dir("NEW.DAT", embedded_sdmmc::Mode::ReadWriteCreate)
+        .unwrap();
+    assert!(f.write_ready().unwrap());
+    // An empty file has nothing to read
+    assert!(!f.read_ready().unwrap());
+}
+
+#[test]
+fn position_and_remaining() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    assert_eq!(f.position(), 0);
+    assert_eq!(f.remaining(), 3500);
+
+    let mut buffer = [0u8; 100];
+    f.read(&mut buffer).unwrap();
+    assert_eq!(f.position(), 100);
+    assert_eq!(f.remaining(), 3400);
+
+    f.seek_from_start(2048).unwrap();
+    assert_eq!(f.position(), 2048);
+    assert_eq!(f.remaining(), 1452);
+
+    f.seek_from_current(-48).unwrap();
+    assert_eq!(f.position(), 2000);
+    assert_eq!(f.remaining(), 1500);
+
+    f.seek_from_end(1).unwrap();
+    assert_eq!(f.position(), 3499);
+    assert_eq!(f.remaining(), 1);
+
+    f.seek_from_end(0).unwrap();
+    assert_eq!(f.position(), 3500);
+    assert_eq!(f.remaining(), 0);
+}
+
+#[test]
+fn count_reads() {
+    use embedded_sdmmc::{InstrumentedBlockDevice, IoStats};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let test_dir = root_dir.open_dir("TEST").unwrap();
+    let f = test_dir
+        .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    // TEST.DAT is 3500 bytes long, which is 7 blocks, in two clusters. So we
+    // read the whole blocks of each cluster in one go, the partial block at
+    // the end on its own, and one block of the FAT to find the second
+    // cluster.
+    volume_mgr.device().reset_stats();
+    let mut buffer = [0u8; 4096];
+    assert_eq!(f.read(&mut buffer).unwrap(), 3500);
+    assert_eq!(
+        volume_mgr.device().stats(),
+        IoStats {
+            reads: 4,
+            blocks_read: 8,
+            ..Default::default()
+        }
+    );
+
+    // Reading doesn't write anything, even when we close the file
+    f.close().unwrap();
+    assert_eq!(volume_mgr.device().stats().writes, 0);
+}
+
+#[test]
+fn read_bursts_capped_by_device() {
+    use embedded_sdmmc::{BlockCount, InstrumentedBlockDevice};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(utils::FaultyDisk::new(disk));
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let f = root_dir
+        .open_file_in_dir("64MB.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    // FAT16 volume, with 2 KiB (four block) clusters, so 64 KiB is 32
+    // clusters, in two blocks of the FAT
+    let mut buffer = vec![0u8; 65536];
+    volume_mgr.device().reset_stats();
+    assert_eq!(f.read(&mut buffer).unwrap(), 65536);
+    let stats = volume_mgr.device().stats();
+    let fat_reads = stats.blocks_read - 128;
+    assert_eq!(stats.reads, 32 + fat_reads);
+
+    // A device which likes two blocks at a time gets two reads per cluster
+    volume_mgr
+        .device()
+        .device()
+        .set_optimal_transfer_blocks(Some(BlockCount(2)));
+    let mut capped = vec![0u8; 65536];
+    f.seek_from_start(0).unwrap();
+    volume_mgr.device().reset_stats();
+    assert_eq!(f.read(&mut capped).unwrap(), 65536);
+    let stats = volume_mgr.device().stats();
+    assert_eq!(stats.blocks_read, 128 + fat_reads);
+    assert_eq!(stats.reads, 64 + fat_reads);
+    assert_eq!(buffer, capped);
+}
+
+#[test]
+fn file_path() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    for volume_idx in [0, 1] {
+        let volume = volume_mgr
+            .open_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+            .unwrap();
+        let root_dir = volume.open_root_dir().unwrap();
+        let mut buffer = [0u8; 64];
+
+        // A file in the root directory is just its name
+        let f = root_dir
+            .open_file_in_dir("README.TXT", embedded_sdmmc::Mode::ReadOnly)
+            .unwrap();
+        assert_eq!(f.path(&mut buffer).unwrap(), "README.TXT");
+        f.close().unwrap();
+
+        let test_dir = root_dir.open_dir("TEST").unwrap();
+        let f = test_dir
+            .open_file_in_dir("TEST.DAT", embedded_sdmmc::Mode::ReadOnly)
+            .unwrap();
+        assert_eq!(f.path(&mut buffer).unwrap(), "TEST/TEST.DAT");
+        // The path has to fit in the buffer
+        assert!(matches!(
+            f.path(&mut buffer[..12]),
+            Err(embedded_sdmmc::Error::NotEnoughSpace)
+        ));
+        assert_eq!(f.path(&mut buffer[..13]).unwrap(), "TEST/TEST.DAT");
+        f.close().unwrap();
+
+        // A directory whose parent isn't the root directory
+        test_dir.make_dir_in_dir("SUBDIR").unwrap();
+        let sub_dir = test_dir.open_dir("SUBDIR").unwrap();
+        let f = sub_dir
+            .open_file_in_dir("NEW.TXT", embedded_sdmmc::Mode::ReadWriteCreate)
+            .unwrap();
+        assert_eq!(f.path(&mut buffer).unwrap(), "TEST/SUBDIR/NEW.TXT");
+    }
+}
+
+/// Read 16 bytes from each of `offsets` in 64MB.DAT, all with one open file.
+///
+/// Gives back everything read, and how many blocks of the FAT were read to
+/// find it all.
+fn read_64mb_at<const MAX_CHECKPOINTS: usize>(offsets: &[u32]) -> (Vec<u8>, u32) {
+    use embedded_sdmmc::InstrumentedBlockDevice;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::builder(disk, time_source)
+        .max_checkpoints::<MAX_CHECKPOINTS>()
+        .build();
+
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .unwrap();
+    let root_dir = volume.open_root_dir().unwrap();
+    let f = root_dir
+        .open_file_in_dir("64MB.DAT", embedded_sdmmc::Mode::ReadOnly)
+        .unwrap();
+
+    volume_mgr.device().reset_stats();
+    let mut contents = Vec::new();
+    for &offset in offsets {
+        let mut buffer = [0u8; 16];
+        f.seek_from_start(offset).unwrap();
+        assert_eq!(f.read(&mut buffer).unwrap(), 16);
+        contents.extend_from_slice(&buffer);
+    }
+    // Each 16 byte read needs exactly one data block - everything else was
+    // the FAT
+    let fat_reads = volume_mgr.device().stats().reads - offsets.len() as u32;
+    f.close().unwrap();
+    (contents, fat_reads)
+}
+
+#[test]
+fn checkpoints_speed_up_seeking() {
+    // Jump about in the first 16 MiB of the file, which on the FAT16 volume
+    // is 8192 clusters of 2 KiB, or 32 blocks of the FAT
+    let mut offsets = Vec::new();
+    let mut x: u32 = 12345;
+    for _ in 0..50 {
+        x = x.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        offsets.push(((x >> 4) % (16 * 1024 * 1024)) & !15);
+    }
+
+    let (without_contents, without_fat_reads) = read_64mb_at::<0>(&offsets);
+    let (with_contents, with_fat_reads) = read_64mb_at::<128>(&offsets);
+    assert_eq!(without_contents, with_contents);
+    assert!(with_fat_reads * 4 < without_fat_reads);
+}
+
 // ****************************************************************************
 //
 // End Of File
@@ -49,6 +49,8 @@ pub enum Error {
     Decode(flate2::DecompressError),
     /// Asked for a block we don't have
     OutOfBounds(BlockIdx),
+    /// A `FaultyDisk` was told to fail
+    Injected,
 }
 
 impl From<std::io::Error> for Error {
@@ -98,7 +100,7 @@ where
             block
                 .as_mut_slice()
                 .copy_from_slice(&contents[start_offset..end_offset]);
-            block_idx.0 += 1;
+            block_idx += BlockCount(1);
         }
         Ok(())
     }
@@ -114,7 +116,7 @@ where
                 return Err(Error::OutOfBounds(block_idx));
             }
             contents[start_offset..end_offset].copy_from_slice(block.as_slice());
-            block_idx.0 += 1;
+            block_idx += BlockCount(1);
         }
         Ok(())
     }
@@ -130,6 +132,109 @@ where
     }
 }
 
+/// Wraps a block device, and fails reads or writes when told to.
+///
+/// It also counts how many times it has been flushed and written to, and can
+/// claim a different optimal transfer size to the disk it wraps.
+#[allow(unused)]
+pub struct FaultyDisk<D> {
+    inner: D,
+    fail_reads: std::cell::Cell<bool>,
+    writes_until_failure: std::cell::Cell<Option<usize>>,
+    optimal_transfer_blocks: std::cell::Cell<Option<BlockCount>>,
+    flushes: std::cell::Cell<usize>,
+    writes: std::cell::Cell<usize>,
+    written: std::cell::RefCell<Vec<BlockIdx>>,
+}
+
+#[allow(unused)]
+impl<D> FaultyDisk<D> {
+    /// Wrap a block device. Reads succeed until you call `set_fail_reads`.
+    pub fn new(inner: D) -> FaultyDisk<D> {
+        FaultyDisk {
+            inner,
+            fail_reads: std::cell::Cell::new(false),
+            writes_until_failure: std::cell::Cell::new(None),
+            optimal_transfer_blocks: std::cell::Cell::new(None),
+            flushes: std::cell::Cell::new(0),
+            writes: std::cell::Cell::new(0),
+            written: std::cell::RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Set whether reads should fail with `Error::Injected`.
+    pub fn set_fail_reads(&self, fail_reads: bool) {
+        self.fail_reads.set(fail_reads);
+    }
+
+    /// Let `writes` more writes succeed, and fail the ones after that with
+    /// `Error::Injected`. `None` lets every write succeed.
+    pub fn set_writes_until_failure(&self, writes: Option<usize>) {
+        self.writes_until_failure.set(writes);
+    }
+
+    /// Report `blocks` as our optimal transfer size. `None` reports whatever
+    /// the wrapped disk does.
+    pub fn set_optimal_transfer_blocks(&self, blocks: Option<BlockCount>) {
+        self.optimal_transfer_blocks.set(blocks);
+    }
+
+    /// How many times has `flush` been called?
+    pub fn flush_count(&self) -> usize {
+        self.flushes.get()
+    }
+
+    /// How many times has `write` been called?
+    pub fn write_count(&self) -> usize {
+        self.writes.get()
+    }
+
+    /// Get the first block of every write since we were last asked, in order.
+    pub fn take_written_blocks(&self) -> Vec<BlockIdx> {
+        self.written.take()
+    }
+}
+
+impl<D> BlockDevice for FaultyDisk<D>
+where
+    D: BlockDevice<Error = Error>,
+{
+    type Error = Error;
+
+    fn read(&self, blocks: &mut [Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        if self.fail_reads.get() {
+            return Err(Error::Injected);
+        }
+        self.inner.read(blocks, start_block_idx)
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        self.writes.set(self.writes.get() + 1);
+        self.written.borrow_mut().push(start_block_idx);
+        match self.writes_until_failure.get() {
+            Some(0) => return Err(Error::Injected),
+            Some(n) => self.writes_until_failure.set(Some(n - 1)),
+            None => {}
+        }
+        self.inner.write(blocks, start_block_idx)
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        self.inner.num_blocks()
+    }
+
+    fn flush(&self) -> Result<(), Self::Error> {
+        self.flushes.set(self.flushes.get() + 1);
+        self.inner.flush()
+    }
+
+    fn optimal_transfer_blocks(&self) -> BlockCount {
+        self.optimal_transfer_blocks
+            .get()
+            .unwrap_or_else(|| self.inner.optimal_transfer_blocks())
+    }
+}
+
 /// Unpack the fixed, static, disk image.
 fn unpack_disk(gzip_bytes: &[u8]) -> Result<Vec<u8>, Error> {
     let disk_cursor = std::io::Cursor::new(gzip_bytes);
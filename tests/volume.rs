@@ -108,6 +108,677 @@ fn close_volume_too_early() {
     ));
 }
 
+#[test]
+fn fat16_free_cluster_count() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let options = embedded_sdmmc::VolumeOpts {
+        count_fat16_free_clusters: true,
+        ..Default::default()
+    };
+    let volume = volume_mgr
+        .open_raw_volume_with_options(embedded_sdmmc::VolumeIdx(0), options)
+        .expect("open volume 0");
+    let initial_count = volume_mgr
+        .free_cluster_count(volume)
+        .expect("get free count");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // Allocate some clusters
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", embedded_sdmmc::Mode::ReadWriteCreate)
+        .expect("open file");
+    volume_mgr
+        .write(f, &[0xAA; 100 * 1024])
+        .expect("write file");
+    volume_mgr.close_file(f).expect("close file");
+    let after_write = volume_mgr
+        .free_cluster_count(volume)
+        .expect("get free count");
+    assert!(after_write < initial_count);
+
+    // Free some clusters
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", embedded_sdmmc::Mode::ReadWriteTruncate)
+        .expect("open file");
+    volume_mgr.write(f, b"Hello").expect("write file");
+    volume_mgr.close_file(f).expect("close file");
+    let cached_count = volume_mgr
+        .free_cluster_count(volume)
+        .expect("get free count");
+    assert!(cached_count > after_write);
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Without the option, we scan the FAT on demand
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    let scanned_count = volume_mgr
+        .free_cluster_count(volume)
+        .expect("get free count");
+    assert_eq!(cached_count, scanned_count);
+    assert_eq!(initial_count - 1, scanned_count);
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+fn cluster_chain_check(volume_idx: usize, bytes_per_cluster: u32) {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(volume_idx))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "64MB.DAT")
+        .expect("find file");
+    assert_eq!(
+        volume_mgr
+            .cluster_chain_len(volume, entry.cluster)
+            .expect("chain length"),
+        64 * 1024 * 1024 / bytes_per_cluster
+    );
+    assert!(volume_mgr
+        .is_contiguous(volume, entry.cluster)
+        .expect("check contiguous"));
+
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("find file");
+    assert_eq!(
+        volume_mgr
+            .cluster_chain_len(volume, entry.cluster)
+            .expect("chain length"),
+        1
+    );
+
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "EMPTY.DAT")
+        .expect("find file");
+    assert_eq!(
+        volume_mgr
+            .cluster_chain_len(volume, entry.cluster)
+            .expect("chain length"),
+        0
+    );
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn fat16_cluster_chain() {
+    cluster_chain_check(0, 2048);
+}
+
+#[test]
+fn fat32_cluster_chain() {
+    cluster_chain_check(1, 4096);
+}
+
+/// Break the footer of the boot sector at the given block.
+fn corrupt_boot_sector(disk: &utils::RamDisk<Vec<u8>>, block_idx: embedded_sdmmc::BlockIdx) {
+    use embedded_sdmmc::BlockDevice;
+    let mut blocks = [embedded_sdmmc::Block::new()];
+    disk.read(&mut blocks, block_idx).expect("read boot sector");
+    blocks[0][510] = 0x00;
+    disk.write(&blocks, block_idx).expect("write boot sector");
+}
+
+#[test]
+fn fat32_backup_boot_sector() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    // The FAT32 partition starts at block 264192
+    corrupt_boot_sector(&disk, embedded_sdmmc::BlockIdx(264192));
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_raw_volume(embedded_sdmmc::VolumeIdx(1))
+        .expect("open volume 1 using backup boot sector");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("find file");
+    assert_eq!(entry.size, 258);
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn fat16_bad_boot_sector() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    // The FAT16 partition starts at block 2048, and has no backup
+    corrupt_boot_sector(&disk, embedded_sdmmc::BlockIdx(2048));
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    assert!(matches!(
+        volume_mgr.open_raw_volume(embedded_sdmmc::VolumeIdx(0)),
+        Err(embedded_sdmmc::Error::FormatError("Bad BPB footer"))
+    ));
+}
+
+#[test]
+fn mount_sub_block_device() {
+    use embedded_sdmmc::{
+        Block, BlockCount, BlockDevice, BlockIdx, SubBlockDevice, SubBlockDeviceError,
+    };
+
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+
+    // There are unused blocks before the FAT16 partition at block 2048. Put a
+    // smaller disk in the window starting at block 1024, with its own MBR
+    // pointing at that same partition.
+    let mut mbr = [Block::new()];
+    disk.read(&mut mbr, BlockIdx(0)).unwrap();
+    mbr[0][446 + 8..446 + 12].copy_from_slice(&1024u32.to_le_bytes());
+    mbr[0][462..510].fill(0);
+    let sub_disk = SubBlockDevice::new(disk, BlockIdx(1024), BlockCount(1024 + 262144));
+    sub_disk.write(&mbr, BlockIdx(0)).unwrap();
+    assert_eq!(sub_disk.num_blocks().unwrap(), BlockCount(1024 + 262144));
+
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(sub_disk, utils::make_time_source());
+    let mut buffer = [0u8; 512];
+    {
+        let volume = volume_mgr
+            .open_volume(embedded_sdmmc::VolumeIdx(0))
+            .expect("open volume");
+        let len = volume
+            .read_file_to("README.TXT", &mut buffer)
+            .expect("read file");
+        assert_eq!(len, 258);
+    }
+
+    // The same file, via the parent device
+    let (sub_disk, time_source) = volume_mgr.free();
+    let disk = sub_disk.free();
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume");
+    let mut expected = [0u8; 512];
+    volume
+        .read_file_to("README.TXT", &mut expected)
+        .expect("read file");
+    assert_eq!(buffer, expected);
+    drop(volume);
+
+    // We can't get out of the window
+    let (disk, _) = volume_mgr.free();
+    let sub_disk = SubBlockDevice::new(disk, BlockIdx(1024), BlockCount(16));
+    let mut blocks = [Block::new(), Block::new()];
+    assert!(sub_disk.read(&mut blocks, BlockIdx(14)).is_ok());
+    assert!(matches!(
+        sub_disk.read(&mut blocks, BlockIdx(15)),
+        Err(SubBlockDeviceError::OutOfBounds)
+    ));
+    assert!(matches!(
+        sub_disk.write(&blocks, BlockIdx(u32::MAX)),
+        Err(SubBlockDeviceError::OutOfBounds)
+    ));
+}
+
+#[test]
+fn volume_geometry() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: embedded_sdmmc::VolumeManager<
+        utils::RamDisk<Vec<u8>>,
+        utils::TestTimeSource,
+        4,
+        2,
+        2,
+    > = embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0x1000_0000);
+
+    let fat16_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(0))
+        .expect("open volume 0");
+    assert_eq!(fat16_volume.fat_type(), embedded_sdmmc::FatType::Fat16);
+    assert_eq!(fat16_volume.bytes_per_cluster(), 2048);
+    assert_eq!(fat16_volume.cluster_count(), 65399);
+
+    let fat32_volume = volume_mgr
+        .open_volume(embedded_sdmmc::VolumeIdx(1))
+        .expect("open volume 1");
+    assert_eq!(fat32_volume.fat_type(), embedded_sdmmc::FatType::Fat32);
+    assert_eq!(fat32_volume.bytes_per_cluster(), 4096);
+    assert_eq!(fat32_volume.cluster_count(), 97852);
+}
+
+#[test]
+fn ignore_info_sector() {
+    use embedded_sdmmc::{Block, BlockDevice, BlockIdx, Mode, VolumeIdx, VolumeOpts};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+
+    // Corrupt the FAT32 info sector, so it claims there are no free clusters
+    // and that the next free one is past the end of the volume
+    const FAT32_LBA_START: u32 = 264192;
+    let mut blocks = [Block::new()];
+    disk.read(&mut blocks, BlockIdx(FAT32_LBA_START)).unwrap();
+    let info_block =
+        BlockIdx(FAT32_LBA_START + u32::from(u16::from_le_bytes([blocks[0][48], blocks[0][49]])));
+    disk.read(&mut blocks, info_block).unwrap();
+    blocks[0][488..492].copy_from_slice(&0u32.to_le_bytes());
+    blocks[0][492..496].copy_from_slice(&0x0FFF_FFF0u32.to_le_bytes());
+    disk.write(&blocks, info_block).unwrap();
+
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    // By default we believe the info sector
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(1))
+        .expect("open volume 1");
+    assert_eq!(volume_mgr.free_cluster_count(volume).unwrap(), 0);
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    let options = VolumeOpts {
+        trust_info_sector: false,
+        ..Default::default()
+    };
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(1), options)
+        .expect("open volume 1");
+    let free_count = volume_mgr.free_cluster_count(volume).unwrap();
+    assert!(free_count > 0);
+
+    // Allocation works, and keeps the scanned count up to date
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", Mode::ReadWriteCreate)
+        .expect("open file");
+    volume_mgr.write(f, &[0xAA; 10 * 1024]).expect("write file");
+    volume_mgr.close_file(f).expect("close file");
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_count - 3
+    );
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // The corrected count was written back to the info sector
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(1))
+        .expect("open volume 1");
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_count - 3
+    );
+}
+
+#[test]
+fn read_only_volume() {
+    use embedded_sdmmc::{Error, Mode, VolumeIdx};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+
+    let volume = volume_mgr
+        .open_volume_read_only(VolumeIdx(0))
+        .expect("open volume 0");
+    let root_dir = volume.open_root_dir().expect("open root dir");
+
+    // Reading is fine
+    let f = root_dir
+        .open_file_in_dir("README.TXT", Mode::ReadOnly)
+        .expect("open file");
+    let mut buffer = [0u8; 16];
+    f.read(&mut buffer).expect("read file");
+    f.flush().expect("flush file");
+    f.close().expect("close file");
+
+    // Anything which would write is refused
+    for mode in [
+        Mode::ReadWriteAppend,
+        Mode::ReadWriteTruncate,
+        Mode::ReadWriteCreate,
+        Mode::ReadWriteCreateOrTruncate,
+        Mode::ReadWriteCreateOrAppend,
+    ] {
+        assert!(matches!(
+            root_dir.open_file_in_dir("README.TXT", mode),
+            Err(Error::ReadOnly)
+        ));
+    }
+    assert!(matches!(
+        root_dir.open_file_in_dir("NEW.DAT", Mode::ReadWriteCreate),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(
+        root_dir.delete_file_in_dir("README.TXT"),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(
+        root_dir.make_dir_in_dir("NEWDIR"),
+        Err(Error::ReadOnly)
+    ));
+    assert!(matches!(
+        root_dir.rename_dir("TEST", "TEST2"),
+        Err(Error::ReadOnly)
+    ));
+
+    root_dir.close().expect("close dir");
+    volume.close().expect("close volume");
+
+    assert_eq!(volume_mgr.device().write_count(), 0);
+}
+
+/// Write a partition table entry into an MBR or EBR.
+fn write_partition_entry(block: &mut embedded_sdmmc::Block, idx: usize, entry: (u8, u32, u32)) {
+    let start = 446 + (idx * 16);
+    let partition = &mut block[start..start + 16];
+    partition.fill(0);
+    partition[4] = entry.0;
+    partition[8..12].copy_from_slice(&entry.1.to_le_bytes());
+    partition[12..16].copy_from_slice(&entry.2.to_le_bytes());
+}
+
+#[test]
+fn logical_partitions() {
+    use embedded_sdmmc::{Block, BlockDevice, BlockIdx, Error, VolumeIdx};
+
+    const FAT16_START: u32 = 2048;
+    const FAT16_SIZE: u32 = 262144;
+    const FAT32_START: u32 = 264192;
+    const FAT32_SIZE: u32 = 784384;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+
+    // Swap the first primary partition for an extended partition starting
+    // at block 1, holding the FAT16 and FAT32 volumes as logical partitions.
+    // The EBRs go in the unused blocks 1 and 2.
+    let mut blocks = [Block::new()];
+    disk.read(&mut blocks, BlockIdx(0)).unwrap();
+    write_partition_entry(&mut blocks[0], 0, (0x0F, 1, FAT32_START + FAT32_SIZE - 1));
+    disk.write(&blocks, BlockIdx(0)).unwrap();
+
+    let mut ebr = Block::new();
+    ebr[510] = 0x55;
+    ebr[511] = 0xAA;
+    // Logical partition start is relative to this EBR, the link to the next
+    // EBR is relative to the start of the extended partition
+    write_partition_entry(&mut ebr, 0, (0x06, FAT16_START - 1, FAT16_SIZE));
+    write_partition_entry(&mut ebr, 1, (0x05, 1, FAT32_START + FAT32_SIZE - 2));
+    disk.write(&[ebr.clone()], BlockIdx(1)).unwrap();
+    write_partition_entry(&mut ebr, 0, (0x0C, FAT32_START - 2, FAT32_SIZE));
+    write_partition_entry(&mut ebr, 1, (0x00, 0, 0));
+    disk.write(&[ebr], BlockIdx(2)).unwrap();
+
+    let volume_mgr: embedded_sdmmc::VolumeManager<_, _, 4, 4, 2> =
+        embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0x1000_0000);
+
+    // The extended partition itself isn't a volume
+    assert!(matches!(
+        volume_mgr.open_raw_volume(VolumeIdx(0)),
+        Err(Error::FormatError(_))
+    ));
+
+    let fat16_volume = volume_mgr.open_volume(VolumeIdx(4)).expect("open volume 4");
+    assert_eq!(fat16_volume.fat_type(), embedded_sdmmc::FatType::Fat16);
+    let root_dir = fat16_volume.open_root_dir().unwrap();
+    assert!(root_dir.exists("README.TXT").unwrap());
+    drop(root_dir);
+
+    let fat32_volume = volume_mgr.open_volume(VolumeIdx(5)).expect("open volume 5");
+    assert_eq!(fat32_volume.fat_type(), embedded_sdmmc::FatType::Fat32);
+    let root_dir = fat32_volume.open_root_dir().unwrap();
+    assert!(root_dir.exists("README.TXT").unwrap());
+    drop(root_dir);
+    drop(fat32_volume);
+
+    // The chain ends after two logical partitions
+    assert!(matches!(
+        volume_mgr.open_raw_volume(VolumeIdx(6)),
+        Err(Error::NoSuchVolume)
+    ));
+}
+
+#[test]
+fn check_volume() {
+    use embedded_sdmmc::{Block, BlockDevice, DirEntry, FsckReport, VolumeIdx};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: embedded_sdmmc::VolumeManager<
+        utils::RamDisk<Vec<u8>>,
+        utils::TestTimeSource,
+        4,
+        4,
+        2,
+    > = embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0x1000_0000);
+
+    // Both volumes start out clean
+    let fat16_volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume 0");
+    let fat32_volume = volume_mgr
+        .open_raw_volume(VolumeIdx(1))
+        .expect("open volume 1");
+    assert!(volume_mgr.check_volume(fat16_volume).unwrap().is_clean());
+    assert!(volume_mgr.check_volume(fat32_volume).unwrap().is_clean());
+
+    let root_dir = volume_mgr.open_root_dir(fat16_volume).unwrap();
+    let test_dir = volume_mgr.open_dir(root_dir, "TEST").unwrap();
+    let test_dat = volume_mgr
+        .find_directory_entry(test_dir, "TEST.DAT")
+        .unwrap();
+    let readme = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .unwrap();
+    let empty = volume_mgr
+        .find_directory_entry(root_dir, "EMPTY.DAT")
+        .unwrap();
+
+    // Point a directory entry at a different cluster, behind the volume
+    // manager's back
+    let set_cluster = |entry: &DirEntry, cluster: u16| {
+        let mut blocks = [Block::new()];
+        let disk = volume_mgr.device();
+        disk.read(&mut blocks, entry.entry_block).unwrap();
+        let offset = entry.entry_offset as usize;
+        blocks[0][offset + 26..offset + 28].copy_from_slice(&cluster.to_le_bytes());
+        disk.write(&blocks, entry.entry_block).unwrap();
+    };
+
+    // TEST.DAT is 3500 bytes, so forgetting about it loses a chain of two
+    // clusters
+    set_cluster(&test_dat, 0);
+    assert_eq!(
+        volume_mgr.check_volume(fat16_volume).unwrap(),
+        FsckReport {
+            lost_chains: 1,
+            lost_clusters: 2,
+            ..Default::default()
+        }
+    );
+
+    // Giving EMPTY.DAT the cluster that README.TXT uses cross-links it
+    set_cluster(&empty, u32::from(readme.cluster) as u16);
+    assert_eq!(
+        volume_mgr.check_volume(fat16_volume).unwrap(),
+        FsckReport {
+            lost_chains: 1,
+            lost_clusters: 2,
+            cross_linked_clusters: 1,
+            ..Default::default()
+        }
+    );
+
+    // Putting TEST.DAT back on its first cluster finds its chain again
+    set_cluster(&test_dat, u32::from(test_dat.cluster) as u16);
+    assert_eq!(
+        volume_mgr.check_volume(fat16_volume).unwrap(),
+        FsckReport {
+            cross_linked_clusters: 1,
+            ..Default::default()
+        }
+    );
+
+    // The other volume is untouched
+    assert!(volume_mgr.check_volume(fat32_volume).unwrap().is_clean());
+}
+
+#[test]
+fn verify_fat_mirror() {
+    use embedded_sdmmc::{Block, BlockCount, BlockDevice, BlockIdx, Error, VolumeIdx, VolumeOpts};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: embedded_sdmmc::VolumeManager<
+        utils::RamDisk<Vec<u8>>,
+        utils::TestTimeSource,
+        4,
+        4,
+        2,
+    > = embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0x1000_0000);
+    let verify = VolumeOpts {
+        verify_fat_mirror: true,
+        ..Default::default()
+    };
+
+    // The FATs on both volumes match to start with
+    let fat16_volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), verify)
+        .expect("open volume 0");
+    let fat32_volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(1), verify)
+        .expect("open volume 1");
+    volume_mgr.verify_fats(fat16_volume).unwrap();
+    volume_mgr.verify_fats(fat32_volume).unwrap();
+    volume_mgr.close_volume(fat16_volume).unwrap();
+
+    // Find the FATs on the FAT16 volume, which starts at block 2048
+    let disk = volume_mgr.device();
+    let mut blocks = [Block::new()];
+    disk.read(&mut blocks, BlockIdx(2048)).unwrap();
+    let reserved_blocks = u32::from(u16::from_le_bytes([blocks[0][14], blocks[0][15]]));
+    let fat_size = u32::from(u16::from_le_bytes([blocks[0][22], blocks[0][23]]));
+    let first_fat = BlockIdx(2048) + BlockCount(reserved_blocks);
+    let second_fat = first_fat + BlockCount(fat_size);
+
+    // Change one entry in the third block of the second FAT
+    disk.read(&mut blocks, second_fat + BlockCount(2)).unwrap();
+    blocks[0][100] ^= 0xFF;
+    disk.write(&blocks, second_fat + BlockCount(2)).unwrap();
+
+    let expected_block = first_fat + BlockCount(2);
+    assert!(matches!(
+        volume_mgr.open_raw_volume_with_options(VolumeIdx(0), verify),
+        Err(Error::FatMirrorMismatch(block)) if block == expected_block
+    ));
+
+    // We can still open it without checking, and check it later
+    let fat16_volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume 0");
+    assert!(matches!(
+        volume_mgr.verify_fats(fat16_volume),
+        Err(Error::FatMirrorMismatch(block)) if block == expected_block
+    ));
+
+    // The other volume is fine
+    volume_mgr.verify_fats(fat32_volume).unwrap();
+}
+
+#[test]
+fn fat_mirror_kept_after_write() {
+    use embedded_sdmmc::{Mode, VolumeIdx};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: embedded_sdmmc::VolumeManager<
+        utils::RamDisk<Vec<u8>>,
+        utils::TestTimeSource,
+        4,
+        4,
+        2,
+    > = embedded_sdmmc::VolumeManager::new_with_limits(disk, time_source, 0x1000_0000);
+
+    for volume_idx in [VolumeIdx(0), VolumeIdx(1)] {
+        let volume = volume_mgr.open_raw_volume(volume_idx).expect("open volume");
+        let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+        let file = volume_mgr
+            .open_file_in_dir(root_dir, "MIRROR.DAT", Mode::ReadWriteCreate)
+            .expect("open file");
+        // Several clusters, so the FAT chain has to be extended
+        volume_mgr.write(file, &[0x5A; 20000]).expect("write file");
+        volume_mgr.close_file(file).expect("close file");
+        volume_mgr.close_dir(root_dir).expect("close dir");
+        volume_mgr.verify_fats(volume).expect("FATs still match");
+        volume_mgr.close_volume(volume).expect("close volume");
+    }
+}
+
+#[test]
+fn cache_geometry() {
+    use embedded_sdmmc::{InstrumentedBlockDevice, Mode, VolumeIdx, VolumeOpts};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let cached = VolumeOpts {
+        cache_geometry: true,
+        ..Default::default()
+    };
+
+    // The first open has to read the disk
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), cached)
+        .expect("open volume");
+    assert!(volume_mgr.device().stats().reads > 0);
+    volume_mgr
+        .write_file_from(volume, "CACHED.TXT", b"still here", Mode::ReadWriteCreate)
+        .expect("write file");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Opening it again doesn't
+    volume_mgr.device().reset_stats();
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), cached)
+        .expect("open volume");
+    assert_eq!(volume_mgr.device().stats().reads, 0);
+    let mut buffer = [0u8; 16];
+    let len = volume_mgr
+        .read_file_to(volume, "CACHED.TXT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], b"still here");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Until we forget it
+    volume_mgr.forget_cached_volumes().unwrap();
+    volume_mgr.device().reset_stats();
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), cached)
+        .expect("open volume");
+    assert!(volume_mgr.device().stats().reads > 0);
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Opening without the option uses the disk, and throws the cached copy
+    // away
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    volume_mgr.close_volume(volume).expect("close volume");
+    volume_mgr.device().reset_stats();
+    let volume = volume_mgr
+        .open_raw_volume_with_options(VolumeIdx(0), cached)
+        .expect("open volume");
+    assert!(volume_mgr.device().stats().reads > 0);
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
 // ****************************************************************************
 //
 // End Of File
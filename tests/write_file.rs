@@ -1,6 +1,6 @@
 //! File opening related tests
 
-use embedded_sdmmc::{Mode, VolumeIdx, VolumeManager};
+use embedded_sdmmc::{Attributes, FileOpts, Mode, VolumeIdx, VolumeManager};
 
 mod utils;
 
@@ -102,6 +102,923 @@ fn flush_file() {
     assert_eq!(entry.size, 64 * 3);
 }
 
+#[test]
+fn sync_all_files() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    let f1 = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadWriteTruncate)
+        .expect("open file");
+    let f2 = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", Mode::ReadWriteCreate)
+        .expect("open file");
+
+    volume_mgr.write(f1, b"Hello").expect("file write");
+    volume_mgr.write(f2, b"Goodbye, World").expect("file write");
+
+    volume_mgr.sync().expect("sync");
+
+    // Throw away the volume manager without closing anything, and mount the
+    // disk again.
+    let (disk, time_source) = volume_mgr.free();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    for (name, contents) in [
+        ("README.TXT", &b"Hello"[..]),
+        ("NEW.DAT", &b"Goodbye, World"[..]),
+    ] {
+        let f = volume_mgr
+            .open_file_in_dir(root_dir, name, Mode::ReadOnly)
+            .expect("open file");
+        let mut buffer = [0u8; 32];
+        let len = volume_mgr.read(f, &mut buffer).expect("read");
+        assert_eq!(&buffer[..len], contents);
+        volume_mgr.close_file(f).expect("close file");
+    }
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn truncate_updates_timestamps() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    let old_entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("Find entry");
+
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadWriteCreateOrTruncate)
+        .expect("open file");
+
+    // The truncation is written to disk straight away
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("Find entry");
+    assert_eq!(entry.size, 0);
+    assert_eq!(entry.ctime, old_entry.ctime);
+    assert_eq!(entry.mtime.to_string(), utils::get_time_source_string());
+    assert_eq!(entry.atime.to_string(), "2003-04-04 00:00:00");
+
+    volume_mgr.write(f, b"Hello").expect("file write");
+    volume_mgr.close_file(f).expect("close file");
+
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("Find entry");
+    assert_eq!(entry.size, 5);
+    assert_eq!(entry.ctime, old_entry.ctime);
+    assert_ne!(entry.mtime, old_entry.mtime);
+    assert_eq!(entry.mtime.to_string(), utils::get_time_source_string());
+    assert_ne!(entry.atime, old_entry.atime);
+    assert_eq!(entry.atime.to_string(), "2003-04-04 00:00:00");
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn flush_block_device() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    assert_eq!(volume_mgr.device().flush_count(), 0);
+
+    let file = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadWriteAppend)
+        .expect("open file");
+    volume_mgr.write(file, b"Hello").expect("file write");
+    volume_mgr.flush_file(file).expect("flush file");
+    assert_eq!(volume_mgr.device().flush_count(), 1);
+
+    volume_mgr.sync().expect("sync");
+    assert_eq!(volume_mgr.device().flush_count(), 2);
+
+    volume_mgr.close_file(file).expect("close file");
+    assert_eq!(volume_mgr.device().flush_count(), 3);
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    assert_eq!(volume_mgr.device().flush_count(), 3);
+
+    volume_mgr.close_volume(volume).expect("close volume");
+    assert_eq!(volume_mgr.device().flush_count(), 4);
+}
+
+#[test]
+fn sync_entry_on_write() {
+    for sync_entry_on_write in [false, true] {
+        let time_source = utils::make_time_source();
+        let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+        let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+            VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+        let volume = volume_mgr
+            .open_raw_volume(VolumeIdx(0))
+            .expect("open volume");
+        let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+        let options = FileOpts {
+            sync_entry_on_write,
+            ..Default::default()
+        };
+        let file = volume_mgr
+            .open_file_in_dir_with_opts(root_dir, "README.TXT", Mode::ReadWriteAppend, options)
+            .expect("open file");
+        volume_mgr.write(file, b"Hello").expect("file write");
+
+        // Throw away the volume manager without closing the file, and look
+        // at what made it to disk.
+        let (disk, time_source) = volume_mgr.free();
+        let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+            VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+        let volume = volume_mgr
+            .open_raw_volume(VolumeIdx(0))
+            .expect("open volume");
+        let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+        let entry = volume_mgr
+            .find_directory_entry(root_dir, "README.TXT")
+            .expect("find entry");
+        let expected_size = if sync_entry_on_write { 258 + 5 } else { 258 };
+        assert_eq!(entry.size, expected_size);
+    }
+}
+
+#[test]
+fn create_with_attributes() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    assert!(matches!(
+        volume_mgr.open_file_in_dir_with_attributes(
+            root_dir,
+            "BAD.DAT",
+            Mode::ReadWriteCreate,
+            Attributes::new().directory()
+        ),
+        Err(embedded_sdmmc::Error::Unsupported)
+    ));
+
+    let attributes = Attributes::new().hidden().system();
+    let file = volume_mgr
+        .open_file_in_dir_with_attributes(root_dir, "STATE.DAT", Mode::ReadWriteCreate, attributes)
+        .expect("create file");
+    volume_mgr.write(file, b"Hello").expect("file write");
+    volume_mgr.close_file(file).expect("close file");
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+
+    // Mount the disk again, and check the attributes made it to disk
+    let (disk, time_source) = volume_mgr.free();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "STATE.DAT")
+        .expect("find entry");
+    assert!(entry.attributes.is_hidden());
+    assert!(entry.attributes.is_system());
+    assert!(!entry.attributes.is_read_only());
+    assert!(!entry.attributes.is_directory());
+    assert_eq!(entry.size, 5);
+    assert!(!volume_mgr.exists(root_dir, "BAD.DAT").unwrap());
+}
+
+#[test]
+fn write_file_by_path() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+
+    let written = volume_mgr
+        .write_file_from(volume, "/TEST/NEW.TXT", b"Hello", Mode::ReadWriteCreate)
+        .expect("write file");
+    assert_eq!(written, 5);
+    let written = volume_mgr
+        .write_file_from(volume, "/TEST/NEW.TXT", b", World", Mode::ReadWriteAppend)
+        .expect("write file");
+    assert_eq!(written, 7);
+    assert!(matches!(
+        volume_mgr.write_file_from(volume, "/TEST/NEW.TXT", b"Hello", Mode::ReadWriteCreate),
+        Err(embedded_sdmmc::Error::FileAlreadyExists)
+    ));
+    assert!(matches!(
+        volume_mgr.write_file_from(volume, "/TEST/NEW.TXT", b"Hello", Mode::ReadOnly),
+        Err(embedded_sdmmc::Error::ReadOnly)
+    ));
+
+    let mut buffer = [0u8; 64];
+    let len = volume_mgr
+        .read_file_to(volume, "/TEST/NEW.TXT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], b"Hello, World");
+
+    // Every handle was closed again, even on error
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn append_to_file() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    for line in ["one\n", "two\n", "three\n"] {
+        volume_mgr
+            .append_to_file(root_dir, "LOG.TXT", line.as_bytes())
+            .expect("append");
+    }
+
+    // Keeping the handle open is the same as opening in append mode
+    let f = volume_mgr
+        .append_file_in_dir(root_dir, "LOG.TXT")
+        .expect("open for append");
+    assert_eq!(volume_mgr.file_offset(f).unwrap(), 14);
+    volume_mgr.write(f, b"four\n").expect("write");
+    volume_mgr.close_file(f).expect("close");
+
+    let mut buffer = [0u8; 64];
+    let len = volume_mgr
+        .read_file_to(volume, "LOG.TXT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], b"one\ntwo\nthree\nfour\n");
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn preallocate_file() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // FAT16 volume, with 2 KiB clusters
+    let free_before = volume_mgr.free_cluster_count(volume).unwrap();
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "BIG.DAT", Mode::ReadWriteCreate)
+        .expect("create");
+    volume_mgr
+        .file_preallocate(f, 10 * 2048 - 1)
+        .expect("preallocate");
+    let first_cluster = volume_mgr.file_info(f).unwrap().first_cluster;
+    assert_eq!(volume_mgr.file_length(f).unwrap(), 0);
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_before - 10
+    );
+    assert_eq!(
+        volume_mgr.cluster_chain_len(volume, first_cluster).unwrap(),
+        10
+    );
+    assert!(volume_mgr.is_contiguous(volume, first_cluster).unwrap());
+
+    // Asking for less than we have changes nothing
+    volume_mgr.file_preallocate(f, 2048).expect("preallocate");
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_before - 10
+    );
+
+    // Writing into the preallocated space doesn't allocate
+    let contents: Vec<u8> = (0..20000u32).map(|x| x as u8).collect();
+    volume_mgr.write(f, &contents).expect("write");
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_before - 10
+    );
+    assert_eq!(
+        volume_mgr.file_info(f).unwrap().first_cluster,
+        first_cluster
+    );
+    volume_mgr.close_file(f).expect("close");
+
+    let mut buffer = vec![0u8; 25000];
+    let len = volume_mgr
+        .read_file_to(volume, "BIG.DAT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], &contents[..]);
+
+    // Growing a file with clusters appends to the existing chain
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "BIG.DAT", Mode::ReadWriteAppend)
+        .expect("open");
+    volume_mgr
+        .file_preallocate(f, 12 * 2048)
+        .expect("preallocate");
+    volume_mgr.close_file(f).expect("close");
+    assert_eq!(
+        volume_mgr.free_cluster_count(volume).unwrap(),
+        free_before - 12
+    );
+    assert_eq!(
+        volume_mgr.cluster_chain_len(volume, first_cluster).unwrap(),
+        12
+    );
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn write_whole_blocks() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // FAT16 volume, with 2 KiB clusters. Preallocate so that writing the
+    // data doesn't touch the FAT.
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "BULK.DAT", Mode::ReadWriteCreate)
+        .expect("create");
+    volume_mgr
+        .file_preallocate(f, 65536 + 4096)
+        .expect("preallocate");
+    let contents: Vec<u8> = (0..65536 + 4096u32).map(|x| (x / 7) as u8).collect();
+
+    // One multi-block write per cluster
+    let writes_before = volume_mgr.device().write_count();
+    volume_mgr.write(f, &contents[..65536]).expect("write");
+    assert_eq!(volume_mgr.device().write_count() - writes_before, 32);
+
+    // Not cluster aligned: three blocks to finish the cluster, then one
+    // partial block
+    let writes_before = volume_mgr.device().write_count();
+    volume_mgr
+        .write(f, &contents[65536..65536 + 512])
+        .expect("write");
+    volume_mgr
+        .write(f, &contents[65536 + 512..65536 + 2048 + 100])
+        .expect("write");
+    assert_eq!(volume_mgr.device().write_count() - writes_before, 3);
+    volume_mgr.close_file(f).expect("close");
+
+    let mut buffer = vec![0u8; 70000];
+    let len = volume_mgr
+        .read_file_to(volume, "BULK.DAT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], &contents[..65536 + 2048 + 100]);
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn empty_file_round_trip() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let free_before = volume_mgr.free_cluster_count(volume).unwrap();
+
+    // Create a file, and write nothing to it
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NOTHING.DAT", Mode::ReadWriteCreate)
+        .expect("create file");
+    volume_mgr.write(f, &[]).expect("empty write");
+    volume_mgr.flush_file(f).expect("flush file");
+    volume_mgr.close_file(f).expect("close file");
+
+    // It has no clusters, and we didn't allocate any
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "NOTHING.DAT")
+        .expect("find entry");
+    assert_eq!(entry.size, 0);
+    assert!(entry.cluster.is_free());
+    assert_eq!(volume_mgr.free_cluster_count(volume).unwrap(), free_before);
+
+    // Reading it gets us nothing
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NOTHING.DAT", Mode::ReadOnly)
+        .expect("open file");
+    assert!(volume_mgr.file_eof(f).unwrap());
+    let mut buffer = [0u8; 16];
+    assert_eq!(volume_mgr.read(f, &mut buffer).expect("read file"), 0);
+    volume_mgr.close_file(f).expect("close file");
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn write_past_max_file_size() {
+    use embedded_sdmmc::{Block, BlockDevice, Error, MAX_FILE_SIZE};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "README.TXT")
+        .expect("find file");
+
+    // We can't write 4 GiB to a RAM disk, so change the size in the
+    // directory entry instead
+    let set_size = |size: u32| {
+        let mut blocks = [Block::new()];
+        let disk = volume_mgr.device();
+        disk.read(&mut blocks, entry.entry_block).unwrap();
+        let offset = entry.entry_offset as usize;
+        blocks[0][offset + 28..offset + 32].copy_from_slice(&size.to_le_bytes());
+        disk.write(&blocks, entry.entry_block).unwrap();
+    };
+
+    // At exactly the maximum size, there is no room for another byte
+    set_size(MAX_FILE_SIZE);
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadWriteAppend)
+        .expect("open file");
+    assert_eq!(volume_mgr.file_offset(f).unwrap(), MAX_FILE_SIZE);
+    assert_eq!(volume_mgr.file_remaining(f).unwrap(), 0);
+    volume_mgr.write(f, &[]).expect("write nothing");
+    assert!(matches!(
+        volume_mgr.write(f, b"x"),
+        Err(Error::FileTooLarge)
+    ));
+    assert_eq!(volume_mgr.file_offset(f).unwrap(), MAX_FILE_SIZE);
+    volume_mgr.close_file(f).expect("close file");
+
+    // A write which would go one byte too far writes nothing at all
+    set_size(MAX_FILE_SIZE - 1);
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "README.TXT", Mode::ReadWriteAppend)
+        .expect("open file");
+    assert!(matches!(
+        volume_mgr.write(f, b"xy"),
+        Err(Error::FileTooLarge)
+    ));
+    assert_eq!(volume_mgr.file_offset(f).unwrap(), MAX_FILE_SIZE - 1);
+    assert_eq!(volume_mgr.file_length(f).unwrap(), MAX_FILE_SIZE - 1);
+    volume_mgr.close_file(f).expect("close file");
+    assert_eq!(
+        volume_mgr
+            .find_directory_entry(root_dir, "README.TXT")
+            .unwrap()
+            .size,
+        MAX_FILE_SIZE - 1
+    );
+}
+
+#[test]
+fn create_many_files() {
+    use embedded_sdmmc::{Error, InstrumentedBlockDevice};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let volume = volume_mgr.open_volume(VolumeIdx(0)).expect("open volume");
+    let root_dir = volume.open_root_dir().expect("open root dir");
+    root_dir.make_dir_in_dir("LOGS").expect("make dir");
+    let logs_dir = root_dir.open_dir("LOGS").expect("open dir");
+
+    let names: Vec<String> = (0..20).map(|i| format!("LOG{:02}.TXT", i)).collect();
+    let files: Vec<(&str, Attributes)> = names
+        .iter()
+        .map(|name| (name.as_str(), Attributes::new().archive()))
+        .collect();
+
+    // The new directory holds "." and "..", so the 20 new entries fill the
+    // rest of its first block and some of the second - two writes
+    volume_mgr.device().reset_stats();
+    logs_dir.create_files(&files).expect("create files");
+    let stats = volume_mgr.device().stats();
+    assert_eq!(stats.writes, 2);
+    assert_eq!(stats.blocks_written, 2);
+
+    for name in &names {
+        let entry = logs_dir.find_directory_entry(name.as_str()).expect(name);
+        assert_eq!(entry.size, 0);
+        assert!(entry.attributes.is_archive());
+    }
+
+    // Names which exist already, or are given twice, write nothing
+    volume_mgr.device().reset_stats();
+    assert!(matches!(
+        logs_dir.create_files(&[
+            ("NEW.TXT", Attributes::new().archive()),
+            ("LOG07.TXT", Attributes::new().archive())
+        ]),
+        Err(Error::FileAlreadyExists)
+    ));
+    assert!(matches!(
+        logs_dir.create_files(&[
+            ("NEW.TXT", Attributes::new().archive()),
+            ("new.txt", Attributes::new().archive())
+        ]),
+        Err(Error::FileAlreadyExists)
+    ));
+    assert_eq!(volume_mgr.device().stats().writes, 0);
+    assert!(matches!(
+        logs_dir.find_directory_entry("NEW.TXT"),
+        Err(Error::NotFound)
+    ));
+
+    // A cluster holds 64 entries, so these need another one
+    let names: Vec<String> = (20..80).map(|i| format!("LOG{:02}.TXT", i)).collect();
+    let files: Vec<(&str, Attributes)> = names
+        .iter()
+        .map(|name| (name.as_str(), Attributes::new().archive()))
+        .collect();
+    logs_dir.create_files(&files).expect("create more files");
+    let mut count = 0;
+    logs_dir
+        .iterate_dir(|entry| {
+            if entry.name.extension() == b"TXT" {
+                count += 1;
+            }
+        })
+        .unwrap();
+    assert_eq!(count, 80);
+    for name in &names {
+        logs_dir.find_directory_entry(name.as_str()).expect(name);
+    }
+}
+
+#[test]
+fn extend_without_writing() {
+    use embedded_sdmmc::InstrumentedBlockDevice;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = InstrumentedBlockDevice::new(disk);
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let volume = volume_mgr.open_volume(VolumeIdx(0)).expect("open volume");
+    let root_dir = volume.open_root_dir().expect("open root dir");
+    let file = root_dir
+        .open_file_in_dir("SPARSE.DAT", Mode::ReadWriteCreate)
+        .expect("open file");
+    file.write(b"hello").expect("write");
+
+    // Zero-filling 100,000 bytes would take 196 block writes, on top of
+    // linking 48 new clusters into the FAT - extending only does the latter
+    volume_mgr.device().reset_stats();
+    file.extend(100_000).expect("extend");
+    assert!(volume_mgr.device().stats().blocks_written <= 2 * 48);
+    assert_eq!(file.length(), 100_000);
+    assert_eq!(file.offset(), 5);
+    assert!(matches!(
+        file.extend(99_999),
+        Err(embedded_sdmmc::Error::InvalidOffset)
+    ));
+
+    // The new space reads as zeros without going to the disk
+    file.seek_from_start(0).unwrap();
+    let mut buffer = [0xFFu8; 16];
+    assert_eq!(file.read(&mut buffer).expect("read"), 16);
+    assert_eq!(&buffer[..5], b"hello");
+    assert!(buffer[5..].iter().all(|b| *b == 0));
+    volume_mgr.device().reset_stats();
+    let mut buffer = vec![0xFFu8; 99_984];
+    assert_eq!(file.read(&mut buffer).expect("read"), 99_984);
+    assert!(buffer.iter().all(|b| *b == 0));
+    assert!(file.is_eof());
+    assert_eq!(volume_mgr.device().stats().reads, 0);
+
+    // Writing in the middle zeros what comes before it, and closing the
+    // file zeros the rest
+    file.seek_from_start(50_000).unwrap();
+    file.write(b"world").expect("write");
+    file.close().expect("close");
+
+    let file = root_dir
+        .open_file_in_dir("SPARSE.DAT", Mode::ReadOnly)
+        .expect("open file");
+    assert_eq!(file.length(), 100_000);
+    let mut buffer = vec![0xFFu8; 100_000];
+    assert_eq!(file.read(&mut buffer).expect("read"), 100_000);
+    assert_eq!(&buffer[..5], b"hello");
+    assert_eq!(&buffer[50_000..50_005], b"world");
+    assert!(buffer[5..50_000].iter().all(|b| *b == 0));
+    assert!(buffer[50_005..].iter().all(|b| *b == 0));
+}
+
+#[test]
+fn torn_record_write() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "EVENTS.LOG", Mode::ReadWriteCreateOrAppend)
+        .expect("create");
+    volume_mgr.write_record(f, b"first").expect("write record");
+    volume_mgr.write_record(f, b"second").expect("write record");
+    assert_eq!(volume_mgr.file_length(f).unwrap(), 4 + 5 + 4 + 6);
+
+    // The record spans several blocks, and the disk fails after the first
+    let record = vec![0xAAu8; 1500];
+    volume_mgr.device().set_writes_until_failure(Some(1));
+    assert!(matches!(
+        volume_mgr.write_record(f, &record),
+        Err(embedded_sdmmc::Error::DeviceError(utils::Error::Injected))
+    ));
+    assert_eq!(volume_mgr.file_length(f).unwrap(), 4 + 5 + 4 + 6);
+    assert_eq!(volume_mgr.file_offset(f).unwrap(), 4 + 5 + 4 + 6);
+
+    // Pull the power, and mount the disk again: the file ends after the last
+    // complete record
+    volume_mgr.device().set_writes_until_failure(None);
+    let (disk, time_source) = volume_mgr.free();
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "EVENTS.LOG")
+        .expect("find file");
+    assert_eq!(entry.size, 4 + 5 + 4 + 6);
+
+    // Appending carries on from there
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "EVENTS.LOG", Mode::ReadWriteAppend)
+        .expect("open");
+    volume_mgr.write_record(f, b"third").expect("write record");
+    volume_mgr.close_file(f).expect("close");
+    let mut buffer = [0u8; 64];
+    let len = volume_mgr
+        .read_file_to(volume, "EVENTS.LOG", &mut buffer)
+        .expect("read file");
+    assert_eq!(
+        &buffer[..len],
+        b"\x05\0\0\0first\x06\0\0\0second\x05\0\0\0third"
+    );
+}
+
+#[test]
+fn flush_through_embedded_io() {
+    /// Some generic code which writes a line and flushes it
+    fn log_line<W: embedded_io::Write>(writer: &mut W, line: &[u8]) -> Result<(), W::Error> {
+        writer.write_all(line)?;
+        writer.flush()
+    }
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let mut file = volume_mgr
+        .open_file_in_dir(root_dir, "FLUSHED.LOG", Mode::ReadWriteCreate)
+        .expect("create file")
+        .to_file(&volume_mgr);
+    log_line(&mut file, b"first line\n").expect("log line");
+    log_line(&mut file, b"second line\n").expect("log line");
+    // Keep the file open, and throw the volume manager away
+    let _raw_file = file.to_raw_file();
+    let (disk, time_source) = volume_mgr.free();
+
+    let volume_mgr: VolumeManager<utils::RamDisk<Vec<u8>>, utils::TestTimeSource, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let entry = volume_mgr
+        .find_directory_entry(root_dir, "FLUSHED.LOG")
+        .expect("find file");
+    assert_eq!(entry.size, 23);
+    let mut file = volume_mgr
+        .open_file_in_dir(root_dir, "FLUSHED.LOG", Mode::ReadOnly)
+        .expect("open file")
+        .to_file(&volume_mgr);
+    let mut buffer = [0u8; 23];
+    embedded_io::Read::read_exact(&mut file, &mut buffer).expect("read file");
+    assert_eq!(&buffer, b"first line\nsecond line\n");
+}
+
+#[test]
+fn write_bursts_capped_by_device() {
+    use embedded_sdmmc::BlockCount;
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // FAT16 volume, with 2 KiB (four block) clusters
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "BULK.DAT", Mode::ReadWriteCreate)
+        .expect("create");
+    volume_mgr
+        .file_preallocate(f, 2 * 65536)
+        .expect("preallocate");
+    let contents: Vec<u8> = (0..2 * 65536u32).map(|x| (x / 7) as u8).collect();
+
+    // A device which likes two blocks at a time gets two writes per cluster
+    volume_mgr
+        .device()
+        .set_optimal_transfer_blocks(Some(BlockCount(2)));
+    let writes_before = volume_mgr.device().write_count();
+    volume_mgr.write(f, &contents[..65536]).expect("write");
+    assert_eq!(volume_mgr.device().write_count() - writes_before, 64);
+
+    // Bursts still stop at the end of each cluster
+    volume_mgr
+        .device()
+        .set_optimal_transfer_blocks(Some(BlockCount(8)));
+    let writes_before = volume_mgr.device().write_count();
+    volume_mgr.write(f, &contents[65536..]).expect("write");
+    assert_eq!(volume_mgr.device().write_count() - writes_before, 32);
+    volume_mgr.close_file(f).expect("close");
+
+    let mut buffer = vec![0u8; 2 * 65536];
+    let len = volume_mgr
+        .read_file_to(volume, "BULK.DAT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], &contents[..]);
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn fat_writes_batched() {
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+    let disk = utils::FaultyDisk::new(disk);
+    let volume_mgr: VolumeManager<utils::FaultyDisk<utils::RamDisk<Vec<u8>>>, _, 4, 2, 1> =
+        VolumeManager::new_with_limits(disk, time_source, 0xAA00_0000);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(0))
+        .expect("open volume");
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+
+    // How many of these blocks were written more than once?
+    let rewrites = |mut blocks: Vec<embedded_sdmmc::BlockIdx>| {
+        let total = blocks.len();
+        blocks.sort();
+        blocks.dedup();
+        total - blocks.len()
+    };
+
+    // FAT16 blocks hold 256 entries, so 600 contiguous clusters have their
+    // entries in three or four FAT blocks. Preallocating them writes each of
+    // those blocks, and its copy in the second FAT, once.
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "PREALLOC.DAT", Mode::ReadWriteCreate)
+        .expect("create");
+    volume_mgr.device().take_written_blocks();
+    volume_mgr
+        .file_preallocate(f, 600 * 2048)
+        .expect("preallocate");
+    let first = volume_mgr.file_info(f).unwrap().first_cluster;
+    assert!(volume_mgr.is_contiguous(volume, first).unwrap());
+    let written = volume_mgr.device().take_written_blocks();
+    assert!((6..=8).contains(&written.len()), "{:?}", written);
+    assert_eq!(rewrites(written), 0);
+    volume_mgr.close_file(f).expect("close");
+
+    // A big write into an empty file allocates its first cluster, then the
+    // rest in one go, so only the FAT block holding the first cluster's entry
+    // (and its copy) is written twice. With 2 KiB clusters, there's one data
+    // write for each cluster.
+    let contents: Vec<u8> = (0..300 * 2048u32).map(|x| (x / 7) as u8).collect();
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "BIG.DAT", Mode::ReadWriteCreate)
+        .expect("create");
+    volume_mgr.device().take_written_blocks();
+    volume_mgr.write(f, &contents).expect("write");
+    let first = volume_mgr.file_info(f).unwrap().first_cluster;
+    assert!(volume_mgr.is_contiguous(volume, first).unwrap());
+    let written = volume_mgr.device().take_written_blocks();
+    assert!(
+        (300 + 4..=300 + 8).contains(&written.len()),
+        "{:?}",
+        written
+    );
+    assert_eq!(rewrites(written), 2);
+    volume_mgr.close_file(f).expect("close");
+
+    let mut buffer = vec![0u8; contents.len()];
+    let len = volume_mgr
+        .read_file_to(volume, "BIG.DAT", &mut buffer)
+        .expect("read file");
+    assert_eq!(&buffer[..len], &contents[..]);
+    assert!(volume_mgr.check_volume(volume).unwrap().is_clean());
+
+    volume_mgr.close_dir(root_dir).expect("close dir");
+    volume_mgr.close_volume(volume).expect("close volume");
+}
+
+#[test]
+fn stale_free_cluster_count() {
+    use embedded_sdmmc::{Block, BlockDevice, BlockIdx, Mode, VolumeIdx};
+
+    let time_source = utils::make_time_source();
+    let disk = utils::make_block_device(utils::DISK_SOURCE).unwrap();
+
+    // Make the FAT32 info sector claim there is only one free cluster
+    const FAT32_LBA_START: u32 = 264192;
+    let mut blocks = [Block::new()];
+    disk.read(&mut blocks, BlockIdx(FAT32_LBA_START)).unwrap();
+    let info_block =
+        BlockIdx(FAT32_LBA_START + u32::from(u16::from_le_bytes([blocks[0][48], blocks[0][49]])));
+    disk.read(&mut blocks, info_block).unwrap();
+    blocks[0][488..492].copy_from_slice(&1u32.to_le_bytes());
+    disk.write(&blocks, info_block).unwrap();
+
+    let volume_mgr = embedded_sdmmc::VolumeManager::new(disk, time_source);
+    let volume = volume_mgr
+        .open_raw_volume(VolumeIdx(1))
+        .expect("open volume 1");
+    assert_eq!(volume_mgr.free_cluster_count(volume).unwrap(), 1);
+
+    // The count is only a hint, so a write needing more clusters still works
+    let root_dir = volume_mgr.open_root_dir(volume).expect("open root dir");
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", Mode::ReadWriteCreate)
+        .expect("open file");
+    let contents = [0xAA; 10 * 1024];
+    volume_mgr.write(f, &contents).expect("write file");
+    assert_eq!(volume_mgr.file_length(f).unwrap(), 10 * 1024);
+    volume_mgr.close_file(f).expect("close file");
+    assert_eq!(volume_mgr.free_cluster_count(volume).unwrap(), 0);
+
+    let f = volume_mgr
+        .open_file_in_dir(root_dir, "NEW.DAT", Mode::ReadOnly)
+        .expect("open file");
+    let mut buffer = [0u8; 10 * 1024];
+    assert_eq!(volume_mgr.read(f, &mut buffer).unwrap(), 10 * 1024);
+    assert_eq!(buffer, contents);
+    volume_mgr.close_file(f).expect("close file");
+}
+
 // ****************************************************************************
 //
 // End Of File